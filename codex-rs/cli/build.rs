@@ -0,0 +1,63 @@
+use std::path::Path;
+use std::process::Command;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/ambient/v1/ambient.proto");
+
+    // システムにprotocがなくてもビルドできるよう同梱のバイナリを使う
+    let mut config = prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path()?);
+    tonic_build::configure().compile_protos_with_config(
+        config,
+        &["proto/ambient/v1/ambient.proto"],
+        &["proto"],
+    )?;
+
+    embed_version();
+    Ok(())
+}
+
+/// 実行中のバージョンを`AMBIENT_VERSION`として埋め込む
+///
+/// ビルド時に`AMBIENT_VERSION`が指定されていればそれを、なければgitのリリースのタグ
+/// （`v0.3.0`や、タグより後のコミットでは`v0.3.0-5-g1a2b3c4`）を使う。
+/// gitの履歴がないソースからのビルドでは`Cargo.toml`のバージョンになる。
+fn embed_version() {
+    println!("cargo:rerun-if-env-changed=AMBIENT_VERSION");
+    let version = std::env::var("AMBIENT_VERSION")
+        .ok()
+        .filter(|version| !version.trim().is_empty())
+        .or_else(git_version)
+        .unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string());
+    let version = version.trim().trim_start_matches('v');
+    println!("cargo:rustc-env=AMBIENT_VERSION={version}");
+}
+
+fn git_version() -> Option<String> {
+    let version = git(&["describe", "--tags", "--match", "v[0-9]*"])?;
+    // コミットやタグが変わったら埋め込み直す
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        let git_dir = Path::new(&git_dir);
+        let mut watched = vec![
+            git_dir.join("HEAD"),
+            git_dir.join("packed-refs"),
+            git_dir.join("refs").join("tags"),
+        ];
+        if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            watched.push(git_dir.join(branch));
+        }
+        for path in watched.iter().filter(|path| path.exists()) {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+    Some(version)
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|stdout| !stdout.is_empty())
+}
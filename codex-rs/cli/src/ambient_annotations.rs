@@ -0,0 +1,472 @@
+use anyhow::Context;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::ambient_confidence::Severity;
+use crate::ambient_git::run_git_command;
+use crate::ambient_integration::referenced_lines;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EmitEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_server::Finding;
+use crate::ambient_snapshot::new_hunk_ranges;
+use crate::ambient_time::now_stamp;
+
+/// `.ambient`の下の注釈ファイル
+pub const ANNOTATIONS_FILE: &str = "annotations.json";
+
+/// 注釈ファイルの形式のバージョン（互換性のない変更をしたときに上げる）
+pub const ANNOTATIONS_VERSION: u32 = 1;
+
+/// エディタのプラグイン向けの、変更箇所ごとの注釈（`.ambient/annotations.json`）
+///
+/// チェックサイクルが終わるたびに、作業ツリーで変更されているファイルの最新の指摘で書き直す。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct AnnotationsFile {
+    /// 形式のバージョン
+    pub version: u32,
+    /// 最後に反映したチェックサイクルのID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// 書き出した日時（RFC3339、UTC）
+    pub generated_at: String,
+    /// 指摘のあるファイル（パスの順）
+    pub files: Vec<FileAnnotations>,
+}
+
+/// 1つのファイルの注釈
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct FileAnnotations {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    /// `git diff HEAD`のハンク（未追跡のファイルはファイル全体を1つのハンクとする）
+    pub hunks: Vec<HunkAnnotations>,
+    /// どのハンクにも含まれない行への指摘
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+}
+
+/// 1つのハンクとその中の行への指摘
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct HunkAnnotations {
+    /// 変更後の先頭行（1始まり）
+    pub start_line: usize,
+    /// 変更後の行数（削除だけのハンクは0）
+    pub line_count: usize,
+    pub annotations: Vec<Annotation>,
+}
+
+/// ガターに表示する1件の指摘
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Annotation {
+    /// 指摘した行（1始まり、行が分からない指摘はハンクの先頭行）
+    pub line: usize,
+    /// 表示の強さ
+    pub level: AnnotationLevel,
+    /// モデルが自己評価した重大度（`critical`、`high`、`medium`、`low`、評価がなければなし）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    /// レビューの名前
+    pub review: String,
+    /// モデルの回答（Markdown）
+    pub message: String,
+    /// モデルが提案した修正
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+    /// レビューが付けたポリシーのラベル
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// 確信度が低く、人の確認が必要
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub needs_human_review: bool,
+    /// 指摘したファイル分析ジョブのID（イベントの`job_id`と同じ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+}
+
+impl Annotation {
+    /// 指摘から注釈を作る（行が分からない指摘は`fallback_line`に付ける）
+    pub fn from_finding(finding: &Finding, fallback_line: usize) -> Self {
+        Self {
+            line: finding_line(finding).unwrap_or(fallback_line),
+            level: AnnotationLevel::from_severity(finding.severity),
+            severity: finding
+                .severity
+                .map(|severity| severity.as_str().to_string()),
+            review: finding.review.clone(),
+            message: finding.message.trim().to_string(),
+            suggestion: finding.suggestion.clone(),
+            labels: finding.labels.clone(),
+            needs_human_review: finding.needs_human_review,
+            job_id: finding.ids.job_id.map(|id| id.to_string()),
+        }
+    }
+}
+
+/// 注釈の表示の強さ（high以上は`error`、mediumは`warning`、それ以外は`info`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationLevel {
+    Error,
+    Warning,
+    Info,
+}
+
+impl AnnotationLevel {
+    pub fn from_severity(severity: Option<Severity>) -> Self {
+        match severity {
+            Some(Severity::Critical | Severity::High) => AnnotationLevel::Error,
+            Some(Severity::Medium) => AnnotationLevel::Warning,
+            _ => AnnotationLevel::Info,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AnnotationLevel::Error => "error",
+            AnnotationLevel::Warning => "warning",
+            AnnotationLevel::Info => "info",
+        }
+    }
+}
+
+/// 注釈ファイルを書き直したことの通知（UIとクライアントにイベントとして配信する）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnnotationsUpdate {
+    /// リポジトリルートからの注釈ファイルのパス
+    pub path: String,
+    /// 反映したチェックサイクルのID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<Uuid>,
+    /// 指摘のあるファイルの数
+    pub files: usize,
+    /// 指摘の数
+    pub annotations: usize,
+}
+
+/// 指摘した行（行番号のある指摘はその行、なければ回答にある最初の`path:行番号`）
+pub fn finding_line(finding: &Finding) -> Option<usize> {
+    finding.line.map(|line| line as usize).or_else(|| {
+        referenced_lines(&finding.message, &finding.file_path)
+            .into_iter()
+            .next()
+    })
+}
+
+/// ファイルのハンク（変更後の先頭行と行数）に指摘を振り分ける
+pub fn annotate_file(
+    path: &str,
+    hunks: &[(usize, usize)],
+    findings: &[Finding],
+) -> FileAnnotations {
+    let mut file = FileAnnotations {
+        path: path.to_string(),
+        hunks: hunks
+            .iter()
+            .map(|&(start_line, line_count)| HunkAnnotations {
+                start_line,
+                line_count,
+                annotations: Vec::new(),
+            })
+            .collect(),
+        annotations: Vec::new(),
+    };
+    for finding in findings {
+        let annotation =
+            Annotation::from_finding(finding, hunks.first().map_or(1, |&(start, _)| start));
+        let line = annotation.line;
+        let hunk = file.hunks.iter_mut().find(|hunk| {
+            line == hunk.start_line
+                || (hunk.start_line..hunk.start_line + hunk.line_count).contains(&line)
+        });
+        match hunk {
+            Some(hunk) => hunk.annotations.push(annotation),
+            None => file.annotations.push(annotation),
+        }
+    }
+    for hunk in &mut file.hunks {
+        hunk.annotations.sort_by_key(|annotation| annotation.line);
+    }
+    file.annotations.sort_by_key(|annotation| annotation.line);
+    file
+}
+
+impl AnnotationsFile {
+    /// プロジェクトの注釈ファイルのパス
+    pub fn file_path(project_root: &Path) -> PathBuf {
+        project_root.join(".ambient").join(ANNOTATIONS_FILE)
+    }
+
+    /// 書き出した注釈ファイル（なければ、または形式が違えばなし）
+    pub fn read(project_root: &Path) -> Option<Self> {
+        let content = fs::read_to_string(Self::file_path(project_root)).ok()?;
+        let file: Self = serde_json::from_str(&content).ok()?;
+        (file.version == ANNOTATIONS_VERSION).then_some(file)
+    }
+
+    /// 作業ツリーのハンクを読み、ファイルごとの最新の指摘から作る
+    pub fn build(
+        root: &Path,
+        run_id: Option<Uuid>,
+        findings: &BTreeMap<String, Vec<Finding>>,
+    ) -> Self {
+        Self {
+            version: ANNOTATIONS_VERSION,
+            run_id: run_id.map(|id| id.to_string()),
+            generated_at: now_stamp().0,
+            files: findings
+                .iter()
+                .map(|(path, findings)| {
+                    annotate_file(path, &working_tree_hunks(root, path), findings)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn annotation_count(&self) -> usize {
+        self.files
+            .iter()
+            .map(|file| {
+                file.annotations.len()
+                    + file
+                        .hunks
+                        .iter()
+                        .map(|hunk| hunk.annotations.len())
+                        .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// 一時ファイルに書き出してから置き換える（読み込み中のプラグインが途中の内容を見ないように）
+    pub fn write(&self, project_root: &Path) -> Result<PathBuf> {
+        let path = Self::file_path(project_root);
+        if let Some(dir) = path.parent() {
+            ensure_annotations_dir(dir)?;
+        }
+        let temp = path.with_extension("json.tmp");
+        let mut content = serde_json::to_string_pretty(self)?;
+        content.push('\n');
+        fs::write(&temp, content).with_context(|| format!("{}に書き込めません", temp.display()))?;
+        fs::rename(&temp, &path)
+            .with_context(|| format!("{}を置き換えられません", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// `git diff HEAD`のハンク（差分がなければ未追跡のファイルとみなし、ファイル全体）
+fn working_tree_hunks(root: &Path, path: &str) -> Vec<(usize, usize)> {
+    let diff = run_git_command(&["diff", "HEAD", "--", path], root).unwrap_or_default();
+    let hunks = new_hunk_ranges(&diff);
+    if !hunks.is_empty() {
+        return hunks;
+    }
+    match fs::read_to_string(root.join(path)) {
+        Ok(content) if !content.is_empty() => vec![(1, content.lines().count())],
+        _ => Vec::new(),
+    }
+}
+
+/// 注釈ファイルとその一時ファイルがリポジトリの変更として検出されないようにする
+fn ensure_annotations_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let gitignore = dir.join(".gitignore");
+    let current = fs::read_to_string(&gitignore).unwrap_or_default();
+    if !current.lines().any(|line| line.trim() == ANNOTATIONS_FILE) {
+        let mut out = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&gitignore)?;
+        if !current.is_empty() && !current.ends_with('\n') {
+            writeln!(out)?;
+        }
+        writeln!(out, "{ANNOTATIONS_FILE}")?;
+        writeln!(out, "{ANNOTATIONS_FILE}.tmp")?;
+    }
+    Ok(())
+}
+
+/// チェックサイクルが終わるたびに、変更されているファイルの最新の指摘で注釈ファイルを書き直す
+///
+/// 分析したファイルはそのサイクルの指摘で置き換え、変更がなくなったファイルは取り除く。
+/// 内容が変わったときだけ書き直し、`AnnotationsUpdated`イベントで知らせる。
+pub async fn write_annotations(
+    mut rx: broadcast::Receiver<EventEnvelope>,
+    root: PathBuf,
+    tx: broadcast::Sender<EventEnvelope>,
+) {
+    let mut pending: HashMap<Uuid, Vec<Finding>> = HashMap::new();
+    let mut latest: BTreeMap<String, Vec<Finding>> = BTreeMap::new();
+    let mut written: Option<Vec<FileAnnotations>> = None;
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: event @ (AmbientEvent::Finding(_) | AmbientEvent::Findings(_)),
+                project: None,
+                ..
+            }) => {
+                for finding in event.into_findings() {
+                    let Some(run_id) = finding.ids.run_id else {
+                        continue;
+                    };
+                    pending.entry(run_id).or_default().push(finding);
+                }
+            }
+            Ok(EventEnvelope {
+                event: AmbientEvent::CycleSummary(summary),
+                project: None,
+                ..
+            }) => {
+                let mut cycle_findings: BTreeMap<String, Vec<Finding>> = BTreeMap::new();
+                for finding in summary
+                    .run_id
+                    .and_then(|run_id| pending.remove(&run_id))
+                    .unwrap_or_default()
+                {
+                    cycle_findings
+                        .entry(finding.file_path.clone())
+                        .or_default()
+                        .push(finding);
+                }
+                // 分析しなかった（変更が続いている）ファイルだけ前回の指摘を残す
+                latest
+                    .retain(|path, _| summary.skipped.iter().any(|skipped| skipped.path == *path));
+                for path in &summary.analyzed {
+                    if let Some(findings) = cycle_findings.remove(path) {
+                        latest.insert(path.clone(), findings);
+                    }
+                }
+                let annotations = AnnotationsFile::build(&root, summary.run_id, &latest);
+                if written.as_ref() == Some(&annotations.files) {
+                    continue;
+                }
+                match annotations.write(&root) {
+                    Ok(path) => {
+                        let _ = tx.emit(AmbientEvent::AnnotationsUpdated(AnnotationsUpdate {
+                            path: path
+                                .strip_prefix(&root)
+                                .unwrap_or(&path)
+                                .display()
+                                .to_string(),
+                            run_id: summary.run_id,
+                            files: annotations.files.len(),
+                            annotations: annotations.annotation_count(),
+                        }));
+                        written = Some(annotations.files);
+                    }
+                    Err(e) => tracing::warn!("failed to write annotations: {e:#}"),
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::RunIds;
+    use crate::ambient_test_support::TempRepo;
+
+    fn finding(file_path: &str, message: &str, line: Option<u32>) -> Finding {
+        Finding {
+            ids: RunIds::new_run().new_job(),
+            file_path: file_path.to_string(),
+            review: "security".to_string(),
+            message: message.to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity: Some(Severity::High),
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn assigns_findings_to_hunks_by_line() {
+        let file = annotate_file(
+            "src/a.rs",
+            &[(3, 2), (20, 0)],
+            &[
+                finding("src/a.rs", "入力を検証していません", Some(4)),
+                finding(
+                    "src/a.rs",
+                    "`src/a.rs:20`の削除で呼び出しが残っています",
+                    None,
+                ),
+                finding("src/a.rs", "全体的に読みにくい", None),
+                finding("src/a.rs", "定数が重複しています", Some(40)),
+            ],
+        );
+        assert_eq!(
+            file.hunks[0]
+                .annotations
+                .iter()
+                .map(|annotation| annotation.line)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        assert_eq!(file.hunks[1].annotations[0].line, 20);
+        assert_eq!(file.annotations[0].line, 40);
+        assert_eq!(file.annotations[0].level, AnnotationLevel::Error);
+        assert_eq!(file.annotations[0].severity.as_deref(), Some("high"));
+    }
+
+    #[test]
+    fn writes_annotations_for_changed_and_untracked_files() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/lib.rs", "fn a() {}\nfn b() {}\nfn c() {}\n")
+            .unwrap();
+        repo.add(&["src/lib.rs"]).unwrap();
+        repo.commit("init").unwrap();
+        repo.write("src/lib.rs", "fn a() {}\nfn b(x: u8) {}\nfn c() {}\n")
+            .unwrap();
+        repo.write("src/new.rs", "fn d() {}\nfn e() {}\n").unwrap();
+
+        let findings = BTreeMap::from([
+            (
+                "src/lib.rs".to_string(),
+                vec![finding("src/lib.rs", "引数が使われていません", Some(2))],
+            ),
+            (
+                "src/new.rs".to_string(),
+                vec![finding("src/new.rs", "テストがありません", None)],
+            ),
+        ]);
+        let annotations = AnnotationsFile::build(repo.path(), None, &findings);
+        assert_eq!(annotations.annotation_count(), 2);
+        assert_eq!(annotations.files[0].hunks.len(), 1);
+        assert_eq!(annotations.files[0].hunks[0].annotations[0].line, 2);
+        assert_eq!(annotations.files[1].hunks[0].start_line, 1);
+        assert_eq!(annotations.files[1].hunks[0].line_count, 2);
+
+        let path = annotations.write(repo.path()).unwrap();
+        let saved: AnnotationsFile =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, annotations);
+        assert!(
+            fs::read_to_string(repo.path().join(".ambient").join(".gitignore"))
+                .unwrap()
+                .contains(ANNOTATIONS_FILE)
+        );
+    }
+}
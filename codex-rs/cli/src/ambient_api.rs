@@ -0,0 +1,949 @@
+use axum::Extension;
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::Path as UrlPath;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::http::header;
+use axum::response::IntoResponse;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::IntoParams;
+use utoipa::Modify;
+use utoipa::OpenApi;
+use utoipa::ToSchema;
+use utoipa::openapi::security::HttpAuthScheme;
+use utoipa::openapi::security::HttpBuilder;
+use utoipa::openapi::security::SecurityScheme;
+
+use crate::ambient_auth::ClientKey;
+use crate::ambient_check_run::is_rerequest;
+use crate::ambient_confidence::Severity;
+use crate::ambient_cycle::CycleSummary;
+use crate::ambient_cycle::SkipReason;
+use crate::ambient_cycle::SkippedFile;
+use crate::ambient_encoding::read_text;
+use crate::ambient_git::run_git_command;
+use crate::ambient_grpc::QUERY_TIMEOUT;
+use crate::ambient_history::AnalysisRecord;
+use crate::ambient_history::FileFinding;
+use crate::ambient_history::FileHistory;
+use crate::ambient_history::FindingQuery;
+use crate::ambient_history::FindingRecord;
+use crate::ambient_history::Resolution;
+use crate::ambient_history::ResolutionStatus;
+use crate::ambient_history::parse_since;
+use crate::ambient_mention::DEFAULT_PATH_SUGGESTIONS;
+use crate::ambient_mention::list_repo_paths;
+use crate::ambient_mention::match_paths;
+use crate::ambient_output_format::ModelFormatStats;
+use crate::ambient_project_config::ProjectConfig;
+use crate::ambient_safe_path::SafePath;
+use crate::ambient_safe_path::SafePathError;
+use crate::ambient_score::QualityScore;
+use crate::ambient_score::render_badge;
+use crate::ambient_server::ANONYMOUS_CLIENT;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::AppState;
+use crate::ambient_server::ChatMessage;
+use crate::ambient_server::ControlCommand;
+use crate::ambient_server::EmitEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_server::validate_display_name;
+use crate::ambient_symbols::CrossReference;
+use crate::ambient_symbols::SymbolKind;
+use crate::ambient_symbols::SymbolLocation;
+use crate::ambient_version::VersionInfo;
+
+/// APIハンドラーのエラー（ステータスコードとメッセージ）
+pub(crate) type ApiError = (StatusCode, String);
+
+pub const FILE_ENDPOINT: &str = "/api/file";
+pub const DIFF_ENDPOINT: &str = "/api/diff";
+pub const LAST_CYCLE_ENDPOINT: &str = "/api/last-cycle";
+pub const OPENAPI_ENDPOINT: &str = "/api/openapi.json";
+pub const OUTPUT_FORMAT_STATS_ENDPOINT: &str = "/api/output-format-stats";
+pub const VERSION_ENDPOINT: &str = "/api/version";
+pub const PATHS_ENDPOINT: &str = "/api/paths";
+pub const QUALITY_SCORE_ENDPOINT: &str = "/api/quality-score";
+pub const BADGE_ENDPOINT: &str = "/badge.svg";
+pub const HISTORY_ENDPOINT: &str = "/api/history";
+pub const SYMBOLS_ENDPOINT: &str = "/api/symbols";
+pub const GITHUB_WEBHOOK_ENDPOINT: &str = "/api/webhooks/github";
+pub const STATUS_ENDPOINT: &str = "/api/status";
+pub const CONFIG_ENDPOINT: &str = "/api/config";
+pub const QUERY_ENDPOINT: &str = "/api/query";
+pub const CHECK_ENDPOINT: &str = "/api/check";
+pub const PAUSE_ENDPOINT: &str = "/api/pause";
+pub const RESUME_ENDPOINT: &str = "/api/resume";
+/// `/api/files/{path}/history`と`/api/files/{path}/resolutions`のルート（`{path}`は`/`を含む）
+pub const FILES_ROUTE: &str = "/api/files/*rest";
+pub const FILES_ENDPOINT: &str = "/api/files";
+const HISTORY_SUFFIX: &str = "/history";
+
+/// `GET /api/history`で返すレビュー結果の既定の件数
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+const RESOLUTIONS_SUFFIX: &str = "/resolutions";
+
+/// `POST /api/pause`で期間を省略したときに一時停止する分数
+pub const DEFAULT_PAUSE_MINUTES: u64 = 60;
+
+/// `GET /api/symbols`で返す定義の既定の件数
+const DEFAULT_SYMBOL_LIMIT: usize = 20;
+
+/// REST APIのOpenAPI定義（`/api/openapi.json`で配信）
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Ambient Code Watcher API"),
+    paths(
+        file_handler,
+        diff_handler,
+        last_cycle_handler,
+        output_format_stats_handler,
+        quality_score_handler,
+        version_handler,
+        paths_handler,
+        history_handler,
+        symbols_handler,
+        file_history_handler,
+        resolution_handler,
+        status_handler,
+        config_handler,
+        query_handler,
+        check_handler,
+        pause_handler,
+        resume_handler
+    ),
+    components(schemas(
+        FileResponse,
+        DiffResponse,
+        CycleSummary,
+        SkippedFile,
+        SkipReason,
+        FileHistory,
+        FileFinding,
+        FindingRecord,
+        Resolution,
+        ResolutionStatus,
+        AnalysisRecord,
+        ResolutionRequest,
+        ModelFormatStats,
+        QualityScore,
+        VersionInfo,
+        CrossReference,
+        SymbolLocation,
+        SymbolKind,
+        StatusResponse,
+        QueryRequest,
+        QueryAnswer,
+        PauseRequest
+    )),
+    modifiers(&BearerAuth)
+)]
+pub struct ApiDoc;
+
+/// `Authorization: Bearer`によるトークン認証を定義に追加する
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct FileQuery {
+    /// リポジトリルートからの相対パス
+    path: String,
+    /// 取得するリビジョン（省略時は作業ツリー）
+    rev: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct PathsQuery {
+    /// パスの一部（文字を順に含むパスをあいまい一致で探す）
+    #[serde(default)]
+    q: String,
+    /// 返す候補の最大数（省略時は20）
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct HistoryQuery {
+    /// ファイルのパス（globパターン可）
+    file: Option<String>,
+    /// この重大度以上のみ（重大度のない結果は除く）
+    min_severity: Option<Severity>,
+    /// この期間内のみ（`30m`、`12h`、`7d`、`2w`形式）
+    since: Option<String>,
+    /// 返す件数の上限（新しいものから、省略時は100）
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct SymbolsQuery {
+    /// シンボルの名前の一部（大文字小文字を区別しない）
+    #[serde(default)]
+    q: String,
+    /// 返す定義の最大数（省略時は20）
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct DiffQuery {
+    /// リポジトリルートからの相対パス
+    path: String,
+}
+
+/// `GET /api/file`の応答
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FileResponse {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    /// 取得したリビジョン（作業ツリーの場合は`null`）
+    pub rev: Option<String>,
+    /// 検出した言語
+    pub language: String,
+    pub content: String,
+}
+
+/// `GET /api/diff`の応答
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DiffResponse {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    /// 検出した言語
+    pub language: String,
+    /// `git diff HEAD`の出力
+    pub diff: String,
+}
+
+/// `POST /api/files/{path}/resolutions`のリクエスト
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ResolutionRequest {
+    /// 対応するレビュー結果のジョブID
+    #[schema(value_type = String, format = Uuid)]
+    pub job_id: uuid::Uuid,
+    /// 対応するレビュー結果のレビュー名
+    pub review: String,
+    pub status: ResolutionStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// 記録したクライアントの表示名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+/// `GET /api/status`の応答
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct StatusResponse {
+    pub version: VersionInfo,
+    /// 監視しているプロジェクトのルート
+    pub project_root: String,
+    /// 直近のチェックサイクルの集計（まだ完了していなければ`null`）
+    pub last_cycle: Option<CycleSummary>,
+    /// 直近のチェックサイクルの健全性スコア（まだ完了していなければ`null`）
+    pub quality_score: Option<QualityScore>,
+}
+
+/// `POST /api/query`のリクエスト
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct QueryRequest {
+    /// 質問（`@path`でファイルの内容を添える）
+    pub text: String,
+    /// 質問したクライアントの表示名（同時に質問したほかのクライアントの回答と区別する）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+/// `POST /api/query`の応答
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct QueryAnswer {
+    /// モデルの回答
+    pub text: String,
+}
+
+/// `POST /api/pause`のリクエスト（本文は省略できる）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PauseRequest {
+    /// 一時停止する分数（省略すると60分）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minutes: Option<u64>,
+}
+
+/// `GET /api/openapi.json`: OpenAPI定義を返す
+pub(crate) async fn openapi_handler() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// `GET /api/file?path=&rev=`: ファイル全体の内容を返す
+#[utoipa::path(
+    get,
+    path = "/api/file",
+    params(FileQuery),
+    responses(
+        (status = 200, description = "ファイルの内容", body = FileResponse),
+        (status = 400, description = "不正なパスまたはリビジョン"),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 403, description = "リポジトリの外または参照できないディレクトリ"),
+        (status = 404, description = "ファイルが存在しない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn file_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FileQuery>,
+) -> Result<Json<FileResponse>, ApiError> {
+    let root = PathBuf::from(&state.project_root);
+    let safe_path = resolve_repo_path(&root, &query.path)?;
+    if let Some(rev) = &query.rev {
+        validate_rev(rev)?;
+    }
+
+    let relative = safe_path.relative().to_string();
+    let rev = query.rev.clone();
+    let content = tokio::task::spawn_blocking(move || match rev {
+        Some(rev) => run_git_command(&["show", &format!("{rev}:{}", safe_path.relative())], &root),
+        None => read_text(safe_path.absolute()).map_err(anyhow::Error::from),
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    Ok(Json(FileResponse {
+        language: language_for(&state.project_root, &relative),
+        path: relative,
+        rev: query.rev,
+        content,
+    }))
+}
+
+/// `GET /api/diff?path=`: HEADとのdiffを返す
+#[utoipa::path(
+    get,
+    path = "/api/diff",
+    params(DiffQuery),
+    responses(
+        (status = 200, description = "HEADとのdiff", body = DiffResponse),
+        (status = 400, description = "不正なパス"),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 403, description = "リポジトリの外または参照できないディレクトリ"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn diff_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<DiffResponse>, ApiError> {
+    let root = PathBuf::from(&state.project_root);
+    let relative = resolve_repo_path(&root, &query.path)?
+        .relative()
+        .to_string();
+
+    let path = relative.clone();
+    let diff =
+        tokio::task::spawn_blocking(move || run_git_command(&["diff", "HEAD", "--", &path], &root))
+            .await
+            .map_err(internal_error)?
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(DiffResponse {
+        language: language_for(&state.project_root, &relative),
+        path: relative,
+        diff,
+    }))
+}
+
+/// `GET /api/last-cycle`: 直近のチェックサイクルで分析・スキップしたファイルを返す
+#[utoipa::path(
+    get,
+    path = "/api/last-cycle",
+    responses(
+        (status = 200, description = "直近のチェックサイクルの集計", body = CycleSummary),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 404, description = "まだチェックサイクルが完了していない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn last_cycle_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<CycleSummary>, ApiError> {
+    let last_cycle = state.last_cycle.lock().map_err(internal_error)?.clone();
+    last_cycle.map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "まだチェックサイクルが完了していません".to_string(),
+        )
+    })
+}
+
+/// `GET /api/output-format-stats`: JSON形式のレビューの回答を修正した割合をモデルごとに返す
+#[utoipa::path(
+    get,
+    path = "/api/output-format-stats",
+    responses(
+        (status = 200, description = "モデルごとの集計", body = Vec<ModelFormatStats>),
+        (status = 401, description = "トークンが正しくない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn output_format_stats_handler(
+    State(state): State<Arc<AppState>>,
+) -> Json<Vec<ModelFormatStats>> {
+    Json(state.output_format_stats.snapshot())
+}
+
+/// `GET /api/quality-score`: 直近のチェックサイクルの健全性スコアを返す
+#[utoipa::path(
+    get,
+    path = "/api/quality-score",
+    responses(
+        (status = 200, description = "直近のチェックサイクルのスコア", body = QualityScore),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 404, description = "まだチェックサイクルが完了していない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn quality_score_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<QualityScore>, ApiError> {
+    let score = state.quality_score.lock().map_err(internal_error)?.clone();
+    score.map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "まだチェックサイクルが完了していません".to_string(),
+        )
+    })
+}
+
+/// `GET /badge.svg`: 健全性スコアのバッジ（READMEの画像から読めるよう認証しない）
+pub(crate) async fn badge_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let score = state
+        .quality_score
+        .lock()
+        .ok()
+        .and_then(|score| score.as_ref().map(|score| score.score));
+    (
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        render_badge(score),
+    )
+}
+
+/// `POST /api/webhooks/github`: ambientのチェックの再実行が要求されたら分析し直す
+///
+/// 署名（`X-Hub-Signature-256`）が正しい要求だけを受け付け、それ以外のイベントは無視する。
+pub(crate) async fn github_webhook_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, ApiError> {
+    let Some(webhook) = &state.check_run_webhook else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Webhookの秘密が設定されていません".to_string(),
+        ));
+    };
+    let header_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    };
+    if !webhook.verify(&body, header_value("X-Hub-Signature-256")) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "署名が正しくありません".to_string(),
+        ));
+    }
+    let payload: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("JSONではありません: {e}")))?;
+    if !is_rerequest(header_value("X-GitHub-Event"), &payload) {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    webhook.rerequested.notify_one();
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// `GET /api/version`: 実行中のバージョンを返す
+#[utoipa::path(
+    get,
+    path = "/api/version",
+    responses(
+        (status = 200, description = "実行中のバージョン", body = VersionInfo),
+        (status = 401, description = "トークンが正しくない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn version_handler() -> Json<VersionInfo> {
+    Json(VersionInfo::current())
+}
+
+/// `GET /api/paths?q=`: `@file`の補完用に、一致するリポジトリ内のパスを返す
+#[utoipa::path(
+    get,
+    path = "/api/paths",
+    params(PathsQuery),
+    responses(
+        (status = 200, description = "一致の良い順のパス", body = Vec<String>),
+        (status = 401, description = "トークンが正しくない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn paths_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PathsQuery>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let root = PathBuf::from(&state.project_root);
+    let paths = tokio::task::spawn_blocking(move || list_repo_paths(&root))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+    let limit = query.limit.unwrap_or(DEFAULT_PATH_SUGGESTIONS);
+    Ok(Json(match_paths(&paths, &query.q, limit)))
+}
+
+/// `GET /api/history`: 再接続したUIが表示し直せるよう、過去のレビュー結果を古い順に返す
+#[utoipa::path(
+    get,
+    path = "/api/history",
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "条件に一致する新しいレビュー結果（古い順）", body = Vec<FileFinding>),
+        (status = 400, description = "期間の形式が正しくない"),
+        (status = 401, description = "トークンが正しくない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn history_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<FileFinding>>, ApiError> {
+    let since = query
+        .since
+        .as_deref()
+        .map(parse_since)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let filter = FindingQuery {
+        file: query.file,
+        min_severity: query.min_severity,
+        since,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    Ok(Json(state.history.recent_findings(&filter, limit)))
+}
+
+/// `GET /api/symbols?q=`: 名前が一致する定義と、その参照箇所を返す
+#[utoipa::path(
+    get,
+    path = "/api/symbols",
+    params(SymbolsQuery),
+    responses(
+        (status = 200, description = "一致の良い順の定義と参照箇所", body = Vec<CrossReference>),
+        (status = 401, description = "トークンが正しくない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn symbols_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SymbolsQuery>,
+) -> Json<Vec<CrossReference>> {
+    let limit = query.limit.unwrap_or(DEFAULT_SYMBOL_LIMIT);
+    Json(state.symbols.search(&query.q, limit))
+}
+
+/// `GET /api/files/{path}/history`: ファイルの過去のレビュー結果・対応・分析を返す
+#[utoipa::path(
+    get,
+    path = "/api/files/{path}/history",
+    params(("path" = String, Path, description = "リポジトリルートからの相対パス")),
+    responses(
+        (status = 200, description = "ファイルの履歴（記録がなければ空）", body = FileHistory),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 404, description = "不明なエンドポイント"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn file_history_handler(
+    State(state): State<Arc<AppState>>,
+    UrlPath(rest): UrlPath<String>,
+) -> Result<Json<FileHistory>, ApiError> {
+    let path = file_route_path(&rest, HISTORY_SUFFIX)?;
+    let history = state.history.file(&path).unwrap_or(FileHistory {
+        path,
+        ..FileHistory::default()
+    });
+    Ok(Json(history))
+}
+
+/// `POST /api/files/{path}/resolutions`: レビュー結果への対応（誤検知など）を記録する
+#[utoipa::path(
+    post,
+    path = "/api/files/{path}/resolutions",
+    params(("path" = String, Path, description = "リポジトリルートからの相対パス")),
+    request_body = ResolutionRequest,
+    responses(
+        (status = 200, description = "記録した対応", body = Resolution),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 403, description = "`control`スコープのないトークン"),
+        (status = 404, description = "不明なエンドポイント"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn resolution_handler(
+    State(state): State<Arc<AppState>>,
+    UrlPath(rest): UrlPath<String>,
+    Json(request): Json<ResolutionRequest>,
+) -> Result<Json<Resolution>, ApiError> {
+    let path = file_route_path(&rest, RESOLUTIONS_SUFFIX)?;
+    let resolution = Resolution::new(
+        request.job_id,
+        request.review,
+        request.status,
+        request.note,
+        request.user,
+    );
+    state.history.record_resolution(&path, resolution.clone());
+    Ok(Json(resolution))
+}
+
+/// `GET /api/status`: バージョンと、直近のチェックサイクルの集計とスコアを返す
+#[utoipa::path(
+    get,
+    path = "/api/status",
+    responses(
+        (status = 200, description = "サーバーの状態", body = StatusResponse),
+        (status = 401, description = "トークンが正しくない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn status_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StatusResponse>, ApiError> {
+    let last_cycle = state.last_cycle.lock().map_err(internal_error)?.clone();
+    let quality_score = state.quality_score.lock().map_err(internal_error)?.clone();
+    Ok(Json(StatusResponse {
+        version: VersionInfo::current(),
+        project_root: state.project_root.clone(),
+        last_cycle,
+        quality_score,
+    }))
+}
+
+/// `GET /api/config`: プロジェクト設定を返す（アクセストークンは含めない）
+#[utoipa::path(
+    get,
+    path = "/api/config",
+    responses(
+        (status = 200, description = "`.ambient/config.toml`の設定（スキーマは`ambient config schema`）", body = Object),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 403, description = "`admin`スコープのないトークン"),
+        (status = 500, description = "設定を読み込めない"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn config_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ProjectConfig>, ApiError> {
+    let mut config =
+        ProjectConfig::load_from_project(Path::new(&state.project_root)).map_err(internal_error)?;
+    config.access_token = None;
+    config.viewer_token = None;
+    config.api_tokens.clear();
+    Ok(Json(config))
+}
+
+/// `POST /api/query`: 質問を監視ループに送り、モデルの回答を待って返す
+///
+/// `/`で始まるチャットのコマンドは回答の形が異なるため受け付けない（チェックは`POST /api/check`）。
+#[utoipa::path(
+    post,
+    path = "/api/query",
+    request_body = QueryRequest,
+    responses(
+        (status = 200, description = "モデルの回答", body = QueryAnswer),
+        (status = 400, description = "質問が空、コマンド、または表示名が不正"),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 403, description = "`chat`スコープのないトークン"),
+        (status = 429, description = "質問の送信頻度が上限を超えた"),
+        (status = 503, description = "監視ループが停止している"),
+        (status = 504, description = "回答がタイムアウトした"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn query_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(client): Extension<ClientKey>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<QueryAnswer>, ApiError> {
+    let text = request.text.trim();
+    if text.is_empty() || text.starts_with('/') {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "質問が空か、チャットのコマンドです".to_string(),
+        ));
+    }
+    let user = request
+        .user
+        .as_deref()
+        .map(validate_display_name)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if !state
+        .rate_limiter
+        .check(client.0.as_deref().unwrap_or(ANONYMOUS_CLIENT))
+    {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            "質問の送信頻度が上限を超えました".to_string(),
+        ));
+    }
+
+    // 回答を取りこぼさないよう、質問を送る前に購読する
+    let unavailable = || {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "監視ループが停止しています".to_string(),
+        )
+    };
+    let mut rx = state.tx.subscribe();
+    if !state.tx.emit(AmbientEvent::UserQuery(ChatMessage::new(
+        user.clone(),
+        text.to_string(),
+    ))) {
+        return Err(unavailable());
+    }
+    let wait = async {
+        loop {
+            match rx.recv().await {
+                Ok(EventEnvelope {
+                    event: AmbientEvent::QueryResponse(response),
+                    ..
+                }) if response.user == user => return Ok(response.text),
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Err(unavailable()),
+            }
+        }
+    };
+    let text = tokio::time::timeout(QUERY_TIMEOUT, wait)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::GATEWAY_TIMEOUT,
+                "回答がタイムアウトしました".to_string(),
+            )
+        })??;
+    Ok(Json(QueryAnswer { text }))
+}
+
+/// `POST /api/check`: 次の定期チェックを待たずにチェックを実行させる
+///
+/// チェック中であれば、終わってから次のチェックを始める。
+#[utoipa::path(
+    post,
+    path = "/api/check",
+    responses(
+        (status = 202, description = "チェックを要求した"),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 403, description = "`control`スコープのないトークン"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn check_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, ApiError> {
+    state.trigger.notify_one();
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// `POST /api/pause`: 定期チェックとファイルの変更によるチェックを一時停止する
+///
+/// 監視ループは止めないため、質問やチャットのコマンドには一時停止中も回答する。
+#[utoipa::path(
+    post,
+    path = "/api/pause",
+    request_body = PauseRequest,
+    responses(
+        (status = 202, description = "一時停止を指示した"),
+        (status = 400, description = "分数が0"),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 403, description = "`control`スコープのないトークン"),
+        (status = 503, description = "監視ループが停止している"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn pause_handler(
+    State(state): State<Arc<AppState>>,
+    request: Option<Json<PauseRequest>>,
+) -> Result<StatusCode, ApiError> {
+    let minutes = request
+        .and_then(|Json(request)| request.minutes)
+        .unwrap_or(DEFAULT_PAUSE_MINUTES);
+    if minutes == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "一時停止する分数は1以上で指定してください".to_string(),
+        ));
+    }
+    send_control(&state, ControlCommand::Pause { minutes })
+}
+
+/// `POST /api/resume`: 一時停止を解除し、止めている間の変更をすぐに確認する
+#[utoipa::path(
+    post,
+    path = "/api/resume",
+    responses(
+        (status = 202, description = "再開を指示した"),
+        (status = 401, description = "トークンが正しくない"),
+        (status = 403, description = "`control`スコープのないトークン"),
+        (status = 503, description = "監視ループが停止している"),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn resume_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, ApiError> {
+    send_control(&state, ControlCommand::Resume)
+}
+
+/// 監視ループに指示を送る（結果は`System`イベントで配信される）
+fn send_control(state: &AppState, command: ControlCommand) -> Result<StatusCode, ApiError> {
+    if !state.tx.emit(AmbientEvent::Control(command)) {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "監視ループが停止しています".to_string(),
+        ));
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// `/api/files/`以降から、末尾の`suffix`を除いたファイルのパスを取り出す
+fn file_route_path(rest: &str, suffix: &str) -> Result<String, ApiError> {
+    rest.strip_suffix(suffix)
+        .map(|path| path.trim_matches('/').to_string())
+        .filter(|path| !path.is_empty())
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("不明なエンドポイントです: {rest}"),
+            )
+        })
+}
+
+/// リクエストされたパスを`SafePath`で解決し、エラーをHTTPステータスに変換する
+fn resolve_repo_path(root: &Path, requested: &str) -> Result<SafePath, ApiError> {
+    SafePath::resolve(root, requested).map_err(|e| {
+        let status = match e {
+            SafePathError::Empty => StatusCode::BAD_REQUEST,
+            SafePathError::Root(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::FORBIDDEN,
+        };
+        (status, format!("{e}: {requested}"))
+    })
+}
+
+/// `git show`に渡すリビジョンを検証する（オプションとして解釈される値を拒否）
+fn validate_rev(rev: &str) -> Result<(), ApiError> {
+    let valid = !rev.is_empty()
+        && !rev.starts_with('-')
+        && rev
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '.' | '~' | '^'));
+    if valid {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            format!("不正なリビジョンです: {rev}"),
+        ))
+    }
+}
+
+fn language_for(project_root: &str, path: &str) -> String {
+    ProjectConfig::load_from_project(Path::new(project_root))
+        .unwrap_or_default()
+        .language_for(path)
+}
+
+fn internal_error(e: impl std::fmt::Display) -> ApiError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_file_routes() {
+        assert_eq!(
+            file_route_path("src/lib.rs/history", HISTORY_SUFFIX).unwrap(),
+            "src/lib.rs"
+        );
+        assert!(file_route_path("src/lib.rs/history", RESOLUTIONS_SUFFIX).is_err());
+        assert!(file_route_path("/history", HISTORY_SUFFIX).is_err());
+    }
+
+    #[test]
+    fn openapi_documents_every_endpoint() {
+        let doc = ApiDoc::openapi();
+        for endpoint in [
+            FILE_ENDPOINT,
+            DIFF_ENDPOINT,
+            LAST_CYCLE_ENDPOINT,
+            OUTPUT_FORMAT_STATS_ENDPOINT,
+            QUALITY_SCORE_ENDPOINT,
+            VERSION_ENDPOINT,
+            PATHS_ENDPOINT,
+            HISTORY_ENDPOINT,
+            SYMBOLS_ENDPOINT,
+            STATUS_ENDPOINT,
+            CONFIG_ENDPOINT,
+            QUERY_ENDPOINT,
+            CHECK_ENDPOINT,
+            PAUSE_ENDPOINT,
+            RESUME_ENDPOINT,
+            "/api/files/{path}/history",
+            "/api/files/{path}/resolutions",
+        ] {
+            assert!(
+                doc.paths.paths.contains_key(endpoint),
+                "{endpoint} is missing from the OpenAPI document"
+            );
+        }
+    }
+
+    #[test]
+    fn openapi_schemas_match_serialized_fields() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let response = FileResponse {
+            path: "src/lib.rs".to_string(),
+            rev: None,
+            language: "rust".to_string(),
+            content: String::new(),
+        };
+        let serialized = serde_json::to_value(response).unwrap();
+        let properties = &doc["components"]["schemas"]["FileResponse"]["properties"];
+        for key in serialized.as_object().unwrap().keys() {
+            assert!(properties.get(key).is_some(), "{key} is not documented");
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::ambient_api::CHECK_ENDPOINT;
+use crate::ambient_api::CONFIG_ENDPOINT;
+use crate::ambient_api::DIFF_ENDPOINT;
+pub use crate::ambient_api::DiffResponse;
+use crate::ambient_api::FILE_ENDPOINT;
+use crate::ambient_api::FILES_ENDPOINT;
+pub use crate::ambient_api::FileResponse;
+use crate::ambient_api::HISTORY_ENDPOINT;
+use crate::ambient_api::LAST_CYCLE_ENDPOINT;
+use crate::ambient_api::OPENAPI_ENDPOINT;
+use crate::ambient_api::OUTPUT_FORMAT_STATS_ENDPOINT;
+use crate::ambient_api::PATHS_ENDPOINT;
+use crate::ambient_api::PAUSE_ENDPOINT;
+pub use crate::ambient_api::PauseRequest;
+use crate::ambient_api::QUALITY_SCORE_ENDPOINT;
+use crate::ambient_api::QUERY_ENDPOINT;
+pub use crate::ambient_api::QueryAnswer;
+pub use crate::ambient_api::QueryRequest;
+use crate::ambient_api::RESUME_ENDPOINT;
+pub use crate::ambient_api::ResolutionRequest;
+use crate::ambient_api::STATUS_ENDPOINT;
+pub use crate::ambient_api::StatusResponse;
+use crate::ambient_api::VERSION_ENDPOINT;
+pub use crate::ambient_cycle::CycleSummary;
+pub use crate::ambient_history::FileFinding;
+pub use crate::ambient_history::FileHistory;
+pub use crate::ambient_history::Resolution;
+pub use crate::ambient_history::ResolutionStatus;
+pub use crate::ambient_output_format::ModelFormatStats;
+pub use crate::ambient_project_config::ProjectConfig;
+pub use crate::ambient_score::QualityScore;
+pub use crate::ambient_version::VersionInfo;
+
+/// Ambient Code WatcherのREST APIクライアント
+///
+/// 応答の型はサーバーと共有しているため、APIを変更するとこのクライアントの
+/// コンパイルまたはテストが失敗する。
+#[derive(Debug, Clone)]
+pub struct AmbientApiClient {
+    base_url: String,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl AmbientApiClient {
+    /// `base_url`は`http://127.0.0.1:38080`のようなサーバーのURL
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// `Authorization: Bearer`で送るトークンを設定する
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// `GET /api/file`: ファイルの内容（`rev`を省略すると作業ツリー）を取得する
+    pub async fn file(&self, path: &str, rev: Option<&str>) -> Result<FileResponse> {
+        let mut query = vec![("path", path)];
+        if let Some(rev) = rev {
+            query.push(("rev", rev));
+        }
+        self.get(FILE_ENDPOINT, &query).await
+    }
+
+    /// `GET /api/diff`: HEADとのdiffを取得する
+    pub async fn diff(&self, path: &str) -> Result<DiffResponse> {
+        self.get(DIFF_ENDPOINT, &[("path", path)]).await
+    }
+
+    /// `GET /api/last-cycle`: 直近のチェックサイクルの集計を取得する
+    pub async fn last_cycle(&self) -> Result<CycleSummary> {
+        self.get(LAST_CYCLE_ENDPOINT, &[]).await
+    }
+
+    /// `GET /api/output-format-stats`: JSON形式のレビューの回答を修正した割合をモデルごとに取得する
+    pub async fn output_format_stats(&self) -> Result<Vec<ModelFormatStats>> {
+        self.get(OUTPUT_FORMAT_STATS_ENDPOINT, &[]).await
+    }
+
+    /// `GET /api/quality-score`: 直近のチェックサイクルの健全性スコアを取得する
+    pub async fn quality_score(&self) -> Result<QualityScore> {
+        self.get(QUALITY_SCORE_ENDPOINT, &[]).await
+    }
+
+    /// `GET /api/version`: サーバーのバージョンを取得する
+    pub async fn version(&self) -> Result<VersionInfo> {
+        self.get(VERSION_ENDPOINT, &[]).await
+    }
+
+    /// `GET /api/paths`: `@file`の補完候補（一致の良い順のパス）を取得する
+    pub async fn paths(&self, query: &str) -> Result<Vec<String>> {
+        self.get(PATHS_ENDPOINT, &[("q", query)]).await
+    }
+
+    /// `GET /api/history`: 新しいレビュー結果を`limit`件まで古い順に取得する
+    pub async fn history(&self, limit: usize) -> Result<Vec<FileFinding>> {
+        self.get(HISTORY_ENDPOINT, &[("limit", &limit.to_string())])
+            .await
+    }
+
+    /// `GET /api/files/{path}/history`: ファイルの過去のレビュー結果・対応・分析を取得する
+    pub async fn file_history(&self, path: &str) -> Result<FileHistory> {
+        self.get(&format!("{FILES_ENDPOINT}/{path}/history"), &[])
+            .await
+    }
+
+    /// `POST /api/files/{path}/resolutions`: レビュー結果への対応を記録する
+    pub async fn resolve(&self, path: &str, request: &ResolutionRequest) -> Result<Resolution> {
+        self.post(&format!("{FILES_ENDPOINT}/{path}/resolutions"), request)
+            .await
+    }
+
+    /// `GET /api/status`: バージョンと、直近のチェックサイクルの集計とスコアを取得する
+    pub async fn status(&self) -> Result<StatusResponse> {
+        self.get(STATUS_ENDPOINT, &[]).await
+    }
+
+    /// `GET /api/config`: プロジェクト設定（アクセストークンを除く）を取得する
+    pub async fn config(&self) -> Result<ProjectConfig> {
+        self.get(CONFIG_ENDPOINT, &[]).await
+    }
+
+    /// `POST /api/query`: 質問してモデルの回答を待つ
+    pub async fn query(&self, request: &QueryRequest) -> Result<QueryAnswer> {
+        self.post(QUERY_ENDPOINT, request).await
+    }
+
+    /// `POST /api/check`: 次の定期チェックを待たずにチェックを実行させる
+    pub async fn check(&self) -> Result<()> {
+        let url = format!("{}{CHECK_ENDPOINT}", self.base_url);
+        self.send_request(self.http.post(&url), CHECK_ENDPOINT, &url)
+            .await?;
+        Ok(())
+    }
+
+    /// `POST /api/pause`: 分析を一時停止する（`minutes`を省略すると60分）
+    pub async fn pause(&self, minutes: Option<u64>) -> Result<()> {
+        let url = format!("{}{PAUSE_ENDPOINT}", self.base_url);
+        let request = self.http.post(&url).json(&PauseRequest { minutes });
+        self.send_request(request, PAUSE_ENDPOINT, &url).await?;
+        Ok(())
+    }
+
+    /// `POST /api/resume`: 一時停止を解除する
+    pub async fn resume(&self) -> Result<()> {
+        let url = format!("{}{RESUME_ENDPOINT}", self.base_url);
+        self.send_request(self.http.post(&url), RESUME_ENDPOINT, &url)
+            .await?;
+        Ok(())
+    }
+
+    /// `GET /api/openapi.json`: サーバーのOpenAPI定義を取得する
+    pub async fn openapi(&self) -> Result<serde_json::Value> {
+        self.get(OPENAPI_ENDPOINT, &[]).await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, endpoint: &str, query: &[(&str, &str)]) -> Result<T> {
+        let url = format!("{}{endpoint}", self.base_url);
+        self.send(self.http.get(&url).query(query), endpoint, &url)
+            .await
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(&self, endpoint: &str, body: &B) -> Result<T> {
+        let url = format!("{}{endpoint}", self.base_url);
+        self.send(self.http.post(&url).json(body), endpoint, &url)
+            .await
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+        endpoint: &str,
+        url: &str,
+    ) -> Result<T> {
+        self.send_request(request, endpoint, url)
+            .await?
+            .json()
+            .await
+            .with_context(|| format!("{endpoint}の応答を解析できません"))
+    }
+
+    /// トークンを付けて送り、成功以外のステータスをエラーにする
+    async fn send_request(
+        &self,
+        mut request: reqwest::RequestBuilder,
+        endpoint: &str,
+        url: &str,
+    ) -> Result<reqwest::Response> {
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("{url}に接続できません"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("{endpoint}が{status}を返しました: {body}");
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_json;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::matchers::query_param;
+
+    #[tokio::test]
+    async fn fetches_file_with_token() {
+        let server = MockServer::start().await;
+        let expected = FileResponse {
+            path: "src/lib.rs".to_string(),
+            rev: Some("HEAD".to_string()),
+            language: "rust".to_string(),
+            content: "fn main() {}".to_string(),
+        };
+        Mock::given(method("GET"))
+            .and(path(FILE_ENDPOINT))
+            .and(query_param("path", "src/lib.rs"))
+            .and(query_param("rev", "HEAD"))
+            .and(header("authorization", "Bearer secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&expected))
+            .mount(&server)
+            .await;
+
+        let client = AmbientApiClient::new(server.uri()).with_token("secret");
+        let response = client.file("src/lib.rs", Some("HEAD")).await.unwrap();
+        assert_eq!(response, expected);
+    }
+
+    #[tokio::test]
+    async fn records_resolution() {
+        let server = MockServer::start().await;
+        let request = ResolutionRequest {
+            job_id: uuid::Uuid::new_v4(),
+            review: "セキュリティリスクの検出".to_string(),
+            status: ResolutionStatus::FalsePositive,
+            note: None,
+            user: Some("alice".to_string()),
+        };
+        let expected = Resolution::new(
+            request.job_id,
+            request.review.clone(),
+            request.status,
+            None,
+            request.user.clone(),
+        );
+        Mock::given(method("POST"))
+            .and(path("/api/files/src/lib.rs/resolutions"))
+            .and(body_json(&request))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&expected))
+            .mount(&server)
+            .await;
+
+        let client = AmbientApiClient::new(server.uri());
+        let response = client.resolve("src/lib.rs", &request).await.unwrap();
+        assert_eq!(response, expected);
+    }
+
+    #[tokio::test]
+    async fn asks_questions_and_requests_checks() {
+        let server = MockServer::start().await;
+        let request = QueryRequest {
+            text: "@src/lib.rs のエラー処理は妥当ですか".to_string(),
+            user: Some("alice".to_string()),
+        };
+        let expected = QueryAnswer {
+            text: "妥当です".to_string(),
+        };
+        Mock::given(method("POST"))
+            .and(path(QUERY_ENDPOINT))
+            .and(body_json(&request))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&expected))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(CHECK_ENDPOINT))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(PAUSE_ENDPOINT))
+            .and(body_json(serde_json::json!({ "minutes": 30 })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(RESUME_ENDPOINT))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = AmbientApiClient::new(server.uri());
+        assert_eq!(client.query(&request).await.unwrap(), expected);
+        client.check().await.unwrap();
+        client.pause(Some(30)).await.unwrap();
+        client.resume().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_error_status() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(DIFF_ENDPOINT))
+            .respond_with(ResponseTemplate::new(403).set_body_string("forbidden"))
+            .mount(&server)
+            .await;
+
+        let client = AmbientApiClient::new(server.uri());
+        let error = client.diff(".git/config").await.unwrap_err();
+        assert!(error.to_string().contains("403"));
+    }
+}
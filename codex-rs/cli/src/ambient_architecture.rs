@@ -0,0 +1,239 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// アーキテクチャ逸脱チェックのレビュー名
+pub const ARCHITECTURE_REVIEW_NAME: &str = "アーキテクチャ逸脱チェック";
+
+/// `.ambient/architecture.toml`: プロジェクトのレイヤー構成と許可する依存関係
+///
+/// ```toml
+/// [[layers]]
+/// name = "ui"
+/// paths = ["src/ui/**"]
+/// imports = ["crate::ui"]
+/// may_depend_on = ["domain"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ArchitectureRules {
+    /// アーキテクチャの概要（プロンプトにそのまま渡す）
+    #[serde(default)]
+    pub description: String,
+
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+}
+
+/// 1つのレイヤー
+#[derive(Debug, Clone, Deserialize)]
+pub struct Layer {
+    pub name: String,
+
+    /// このレイヤーに属するファイル（globパターン）
+    pub paths: Vec<String>,
+
+    /// 他のファイルからこのレイヤーを参照するときのimportの接頭辞（`crate::db`、`@/db`など）
+    #[serde(default)]
+    pub imports: Vec<String>,
+
+    /// 依存してよいレイヤーの名前（自分自身は常に許可）
+    #[serde(default)]
+    pub may_depend_on: Vec<String>,
+}
+
+/// 許可されていないレイヤーへの依存
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// 依存元のレイヤー
+    pub from: String,
+    /// 依存先のレイヤー
+    pub to: String,
+    /// 該当するimport文
+    pub statement: String,
+}
+
+impl ArchitectureRules {
+    /// `.ambient/architecture.toml`を読み込む（ファイルがなければ`None`）
+    pub fn load_from_project(project_path: &Path) -> Result<Option<Self>> {
+        let path = project_path.join(".ambient").join("architecture.toml");
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        let rules = toml::from_str(&content)
+            .with_context(|| format!("{}を解析できません", path.display()))?;
+        Ok(Some(rules))
+    }
+
+    /// ファイルが属するレイヤー
+    pub fn layer_for(&self, file_path: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| {
+            layer.paths.iter().any(|pattern| {
+                glob::Pattern::new(pattern)
+                    .ok()
+                    .is_some_and(|p| p.matches(file_path))
+            })
+        })
+    }
+
+    /// 追加されたコード`source`のimport文から、許可されていない依存を探す
+    pub fn violations(&self, file_path: &str, source: &str) -> Vec<Violation> {
+        let Some(from) = self.layer_for(file_path) else {
+            return Vec::new();
+        };
+        let forbidden: Vec<&Layer> = self
+            .layers
+            .iter()
+            .filter(|layer| layer.name != from.name && !from.may_depend_on.contains(&layer.name))
+            .collect();
+
+        let mut violations = Vec::new();
+        for statement in source.lines().filter_map(import_statement) {
+            for to in &forbidden {
+                if to
+                    .imports
+                    .iter()
+                    .any(|prefix| imports_prefix(statement, prefix))
+                {
+                    violations.push(Violation {
+                        from: from.name.clone(),
+                        to: to.name.clone(),
+                        statement: statement.to_string(),
+                    });
+                }
+            }
+        }
+        violations
+    }
+
+    /// モデルに渡すプロンプトを作成する
+    pub fn render_prompt(&self, file_path: &str, violations: &[Violation], diff: &str) -> String {
+        let layers: Vec<String> = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let allowed = if layer.may_depend_on.is_empty() {
+                    "なし".to_string()
+                } else {
+                    layer.may_depend_on.join(", ")
+                };
+                format!(
+                    "- {}（{}）: 依存してよいレイヤー: {allowed}",
+                    layer.name,
+                    layer.paths.join(", ")
+                )
+            })
+            .collect();
+        let found: Vec<String> = violations
+            .iter()
+            .map(|v| format!("- {} → {}: `{}`", v.from, v.to, v.statement))
+            .collect();
+        let description = if self.description.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n\n", self.description.trim())
+        };
+        format!(
+            "あなたはソフトウェアアーキテクトです。`{file_path}`の変更が、以下に定義されたアーキテクチャのレイヤー構成に違反していないかを日本語で報告してください。\n\n{description}## レイヤー\n\n{}\n\n## 検出した依存\n\n{}\n\nそれぞれの依存が本当に問題かを判断し、問題であれば依存の向きを正す方法（インターフェースの導入、処理の移動など）を提案してください。\n\n---\n\n{diff}",
+            layers.join("\n"),
+            found.join("\n")
+        )
+    }
+}
+
+/// diffで追加された行だけを取り出す（既存の依存は報告しない）
+pub fn added_lines(diff: &str) -> String {
+    diff.lines()
+        .filter(|line| !line.starts_with("+++"))
+        .filter_map(|line| line.strip_prefix('+'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 行がimport文であれば前後の空白を除いて返す
+fn import_statement(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let is_import = ["use ", "pub use ", "import ", "from ", "#include "]
+        .iter()
+        .any(|keyword| line.starts_with(keyword))
+        || line.contains("require(");
+    is_import.then_some(line)
+}
+
+/// import文が`prefix`で始まるパスを参照しているか（`crate::db`は`crate::dbx`に一致しない）
+fn imports_prefix(statement: &str, prefix: &str) -> bool {
+    statement.match_indices(prefix).any(|(start, _)| {
+        let before = statement[..start].chars().next_back();
+        let after = statement[start + prefix.len()..].chars().next();
+        !before.is_some_and(is_path_char) && !after.is_some_and(is_path_char)
+    })
+}
+
+fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> ArchitectureRules {
+        toml::from_str(
+            r#"
+[[layers]]
+name = "ui"
+paths = ["src/ui/**"]
+imports = ["crate::ui"]
+may_depend_on = ["domain"]
+
+[[layers]]
+name = "domain"
+paths = ["src/domain/**"]
+imports = ["crate::domain"]
+
+[[layers]]
+name = "db"
+paths = ["src/db/**"]
+imports = ["crate::db"]
+may_depend_on = ["domain"]
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn flags_forbidden_imports() {
+        let diff = "+++ b/src/ui/view.rs\n use crate::db::Pool;\n+use crate::domain::User;\n+use crate::db::Connection;\n+let x = 1;\n";
+        assert_eq!(
+            rules().violations("src/ui/view.rs", &added_lines(diff)),
+            vec![Violation {
+                from: "ui".to_string(),
+                to: "db".to_string(),
+                statement: "use crate::db::Connection;".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn ignores_allowed_and_unlayered_files() {
+        let rules = rules();
+        assert!(
+            rules
+                .violations("src/db/pool.rs", "use crate::domain::User;")
+                .is_empty()
+        );
+        assert!(
+            rules
+                .violations("src/main.rs", "use crate::db::Connection;")
+                .is_empty()
+        );
+        // 接頭辞が一致するだけの別モジュールは対象外
+        assert!(
+            rules
+                .violations("src/ui/view.rs", "use crate::dbx::Cache;")
+                .is_empty()
+        );
+    }
+}
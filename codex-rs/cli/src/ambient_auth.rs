@@ -0,0 +1,307 @@
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::Method;
+use axum::http::StatusCode;
+use axum::http::header::AUTHORIZATION;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::sync::Arc;
+use url::form_urlencoded;
+
+use crate::ambient_api::CONFIG_ENDPOINT;
+use crate::ambient_api::QUERY_ENDPOINT;
+use crate::ambient_review_cache::content_hash;
+use crate::ambient_server::AppState;
+
+/// トークンに許可する操作の範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// イベント・指摘・履歴・状態の閲覧
+    Read,
+    /// 質問の送信
+    Chat,
+    /// チェックの実行、一時停止・再開、指摘への対応の記録、チャットのコマンド
+    Control,
+    /// 設定の閲覧を含むすべての操作
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Chat => "chat",
+            Scope::Control => "control",
+            Scope::Admin => "admin",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// 接続したクライアントに許可した操作（`admin`はすべてを許可する）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Role {
+    scopes: u8,
+}
+
+impl Role {
+    /// `access_token`のトークン、またはトークンを設定していない場合
+    pub const OWNER: Role = Role {
+        scopes: 1 << Scope::Admin as u8,
+    };
+    /// `viewer_token`のトークン
+    pub const VIEWER: Role = Role {
+        scopes: 1 << Scope::Read as u8,
+    };
+
+    pub fn with_scopes(scopes: &[Scope]) -> Self {
+        Self {
+            scopes: scopes.iter().fold(0, |bits, scope| bits | scope.bit()),
+        }
+    }
+
+    pub fn allows(self, scope: Scope) -> bool {
+        self.scopes & (scope.bit() | Scope::Admin.bit()) != 0
+    }
+
+    /// スコープが足りない場合の`403`の応答
+    pub fn require(self, scope: Scope) -> Result<(), (StatusCode, String)> {
+        if self.allows(scope) {
+            Ok(())
+        } else {
+            Err((StatusCode::FORBIDDEN, scope_denied(scope)))
+        }
+    }
+}
+
+/// スコープが足りない操作を拒否するときのメッセージ
+pub fn scope_denied(scope: Scope) -> String {
+    format!(
+        "このトークンには`{}`スコープがないため実行できません",
+        scope.as_str()
+    )
+}
+
+/// スコープを指定したアクセストークン（`[[api_tokens]]`）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ApiToken {
+    /// 用途を表す名前（例: `ci`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub token: String,
+    /// 許可する操作（`read`、`chat`、`control`、`admin`）
+    pub scopes: Vec<Scope>,
+}
+
+/// サーバーへのアクセスに使うトークン
+#[derive(Debug, Clone, Default)]
+pub struct AccessTokens {
+    /// 全権限のトークン（どのトークンも未設定の場合だけ、トークンなしの接続を`OWNER`として扱う）
+    pub owner: Option<String>,
+    /// 閲覧専用のトークン
+    pub viewer: Option<String>,
+    /// スコープを指定したトークン
+    pub scoped: Vec<ApiToken>,
+}
+
+impl AccessTokens {
+    /// 提示されたトークンに対応する権限を返す（拒否する場合は`None`）
+    pub fn role_for(&self, token: Option<&str>) -> Option<Role> {
+        if let Some(token) = token {
+            if let Some(scoped) = self
+                .scoped
+                .iter()
+                .find(|scoped| constant_time_eq(token, &scoped.token))
+            {
+                return Some(Role::with_scopes(&scoped.scopes));
+            }
+            if let Some(viewer) = &self.viewer
+                && constant_time_eq(token, viewer)
+            {
+                return Some(Role::VIEWER);
+            }
+        }
+        match (&self.owner, token) {
+            (None, _) if self.viewer.is_none() && self.scoped.is_empty() => Some(Role::OWNER),
+            (Some(owner), Some(token)) if constant_time_eq(token, owner) => Some(Role::OWNER),
+            _ => None,
+        }
+    }
+}
+
+/// 質問の送信頻度を数えるクライアントの区別（提示されたトークンのハッシュ、トークンなしの場合は`None`）
+///
+/// クライアントが選べる表示名ではなく認証に使ったトークンで数えるため、名前を変えても上限を回避できない。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ClientKey(pub(crate) Option<String>);
+
+impl ClientKey {
+    fn for_token(token: Option<&str>) -> Self {
+        Self(token.map(|token| format!("token:{}", &content_hash(token)[..16])))
+    }
+}
+
+/// リクエストに必要なスコープ（設定の閲覧は`admin`、質問は`chat`、それ以外の変更は`control`）
+pub(crate) fn required_scope(method: &Method, path: &str) -> Scope {
+    if path == CONFIG_ENDPOINT {
+        Scope::Admin
+    } else if method == Method::GET || method == Method::HEAD {
+        Scope::Read
+    } else if path == QUERY_ENDPOINT {
+        Scope::Chat
+    } else {
+        Scope::Control
+    }
+}
+
+/// `?token=`または`Authorization: Bearer`のトークンを検証し、スコープを確認して`Role`をリクエストに付与する
+pub(crate) async fn require_token(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let token = bearer_token(&req).or_else(|| query_token(req.uri().query()));
+    let Some(role) = state.tokens.role_for(token.as_deref()) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    if let Err(denied) = role.require(required_scope(req.method(), req.uri().path())) {
+        return denied.into_response();
+    }
+    req.extensions_mut().insert(role);
+    req.extensions_mut()
+        .insert(ClientKey::for_token(token.as_deref()));
+    next.run(req).await
+}
+
+fn bearer_token(req: &Request) -> Option<String> {
+    req.headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|t| t.trim().to_string())
+}
+
+/// クエリ文字列の`token`をパーセントデコードして取り出す（`+`や`%2B`を含むトークンに対応）
+fn query_token(query: Option<&str>) -> Option<String> {
+    form_urlencoded::parse(query?.as_bytes())
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// タイミング攻撃を避けるため長さ以外で早期終了しない比較
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_roles() {
+        let tokens = AccessTokens {
+            owner: Some("owner-secret".to_string()),
+            viewer: Some("viewer-secret".to_string()),
+            scoped: vec![],
+        };
+        assert_eq!(tokens.role_for(Some("owner-secret")), Some(Role::OWNER));
+        assert_eq!(tokens.role_for(Some("viewer-secret")), Some(Role::VIEWER));
+        assert_eq!(tokens.role_for(Some("wrong")), None);
+        assert_eq!(tokens.role_for(None), None);
+
+        // 閲覧専用のトークンだけを設定しても、トークンなしの接続は全権限にならない
+        let viewer_only = AccessTokens {
+            owner: None,
+            viewer: Some("viewer-secret".to_string()),
+            scoped: vec![],
+        };
+        assert_eq!(viewer_only.role_for(None), None);
+        assert_eq!(
+            viewer_only.role_for(Some("viewer-secret")),
+            Some(Role::VIEWER)
+        );
+
+        let open = AccessTokens::default();
+        assert_eq!(open.role_for(None), Some(Role::OWNER));
+    }
+
+    #[test]
+    fn scoped_tokens_allow_only_their_scopes() {
+        let tokens = AccessTokens {
+            owner: None,
+            viewer: None,
+            scoped: vec![
+                ApiToken {
+                    name: Some("ci".to_string()),
+                    token: "ci-secret".to_string(),
+                    scopes: vec![Scope::Read],
+                },
+                ApiToken {
+                    name: None,
+                    token: "bot-secret".to_string(),
+                    scopes: vec![Scope::Read, Scope::Chat],
+                },
+                ApiToken {
+                    name: None,
+                    token: "admin-secret".to_string(),
+                    scopes: vec![Scope::Admin],
+                },
+            ],
+        };
+        // スコープ付きのトークンがあれば、トークンなしの接続は拒否する
+        assert_eq!(tokens.role_for(None), None);
+
+        let ci = tokens.role_for(Some("ci-secret")).unwrap();
+        assert!(ci.allows(Scope::Read));
+        assert!(!ci.allows(Scope::Chat));
+        assert_eq!(
+            ci.require(Scope::Control),
+            Err((StatusCode::FORBIDDEN, scope_denied(Scope::Control)))
+        );
+        let bot = tokens.role_for(Some("bot-secret")).unwrap();
+        assert!(bot.allows(Scope::Chat));
+        assert!(!bot.allows(Scope::Control));
+        let admin = tokens.role_for(Some("admin-secret")).unwrap();
+        assert_eq!(admin, Role::OWNER);
+        assert!(admin.allows(Scope::Control));
+    }
+
+    #[test]
+    fn maps_routes_to_scopes() {
+        assert_eq!(required_scope(&Method::GET, "/api/history"), Scope::Read);
+        assert_eq!(required_scope(&Method::GET, "/ws"), Scope::Read);
+        assert_eq!(required_scope(&Method::POST, QUERY_ENDPOINT), Scope::Chat);
+        assert_eq!(required_scope(&Method::POST, "/api/check"), Scope::Control);
+        assert_eq!(
+            required_scope(&Method::POST, "/api/files/src/a.rs/resolutions"),
+            Scope::Control
+        );
+        assert_eq!(required_scope(&Method::GET, CONFIG_ENDPOINT), Scope::Admin);
+    }
+
+    #[test]
+    fn parses_query_token() {
+        assert_eq!(
+            query_token(Some("path=a.rs&token=abc")),
+            Some("abc".to_string())
+        );
+        assert_eq!(query_token(Some("path=a.rs")), None);
+        assert_eq!(
+            query_token(Some("token=a%2Bb%3D%26c")),
+            Some("a+b=&c".to_string())
+        );
+    }
+}
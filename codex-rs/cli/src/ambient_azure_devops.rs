@@ -0,0 +1,338 @@
+use anyhow::Context;
+use anyhow::Result;
+use base64::Engine;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+use crate::ambient_integration::IntegrationsConfig;
+use crate::ambient_integration::ReviewComment;
+use crate::ambient_policy::PolicyReport;
+use crate::ambient_version::VERSION;
+
+/// Azure DevOps REST APIのバージョン
+const API_VERSION: &str = "7.1";
+
+/// プルリクエストのステータスに付ける名前（`genre/name`で表示される）
+const STATUS_GENRE: &str = "ambient";
+const STATUS_NAME: &str = "review";
+
+/// Azure Reposのプルリクエスト
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzurePullRequest {
+    pub organization: String,
+    pub project: String,
+    pub repository: String,
+    pub id: u64,
+}
+
+impl AzurePullRequest {
+    /// プルリクエストのURLか、`[integrations]`の`organization`・`project`・`repository`と番号から作る
+    ///
+    /// URLは`https://dev.azure.com/<organization>/<project>/_git/<repository>/pullrequest/<id>`と
+    /// 旧形式の`https://<organization>.visualstudio.com/<project>/_git/<repository>/pullrequest/<id>`を受け付ける。
+    pub fn parse(url: &str, config: &IntegrationsConfig) -> Result<Self> {
+        let url = url.trim();
+        if let Ok(id) = url.trim_start_matches('!').parse::<u64>() {
+            let (Some(organization), Some(project), Some(repository)) =
+                (&config.organization, &config.project, &config.repository)
+            else {
+                anyhow::bail!(
+                    "番号だけで指定するには[integrations]にorganization、project、repositoryを設定してください"
+                );
+            };
+            return Ok(Self {
+                organization: organization.clone(),
+                project: project.clone(),
+                repository: repository.clone(),
+                id,
+            });
+        }
+
+        let path = url
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches("https://")
+            .trim_start_matches("http://");
+        let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        let parsed = match parts.as_slice() {
+            [
+                "dev.azure.com",
+                organization,
+                project,
+                "_git",
+                repository,
+                "pullrequest",
+                id,
+                ..,
+            ] => Some((organization.to_string(), project, repository, id)),
+            [host, project, "_git", repository, "pullrequest", id, ..] => host
+                .strip_suffix(".visualstudio.com")
+                .map(|organization| (organization.to_string(), project, repository, id)),
+            _ => None,
+        };
+        let Some((organization, project, repository, id)) = parsed else {
+            anyhow::bail!(
+                "Azure DevOpsのプルリクエストではありません（https://dev.azure.com/<organization>/<project>/_git/<repository>/pullrequest/<id>の形式で指定してください）: {url}"
+            );
+        };
+        Ok(Self {
+            organization,
+            project: project.to_string(),
+            repository: repository.to_string(),
+            id: id
+                .parse()
+                .with_context(|| format!("プルリクエストの番号が不正です: {id}"))?,
+        })
+    }
+
+    fn repository_url(&self) -> String {
+        format!(
+            "https://dev.azure.com/{}/{}/_git/{}",
+            self.organization, self.project, self.repository
+        )
+    }
+
+    fn api_url(&self, resource: &str) -> String {
+        format!(
+            "https://dev.azure.com/{}/{}/_apis/git/repositories/{}/pullRequests/{}/{resource}?api-version={API_VERSION}",
+            self.organization, self.project, self.repository, self.id
+        )
+    }
+
+    fn html_url(&self) -> String {
+        format!("{}/pullrequest/{}", self.repository_url(), self.id)
+    }
+
+    /// プルリクエストのマージ結果（`refs/pull/<id>/merge`）を取得し、マージ先とのdiffを返す
+    ///
+    /// REST APIはdiffを返さないため、`repo_dir`のリポジトリにgitで取得する（チェックアウトはしない）。
+    /// PATはコマンドラインに出ないよう、環境変数でgitに渡す。
+    pub fn fetch_diff(&self, token: Option<&str>, repo_dir: &Path) -> Result<String> {
+        let refspec = format!("refs/pull/{}/merge", self.id);
+        let mut fetch = Command::new("git");
+        fetch
+            .args([
+                "fetch",
+                "--quiet",
+                "--no-tags",
+                &self.repository_url(),
+                &refspec,
+            ])
+            .current_dir(repo_dir);
+        if let Some(token) = token {
+            fetch
+                .env("GIT_CONFIG_COUNT", "1")
+                .env("GIT_CONFIG_KEY_0", "http.extraHeader")
+                .env(
+                    "GIT_CONFIG_VALUE_0",
+                    format!("Authorization: {}", basic_credentials(token)),
+                );
+        }
+        let output = fetch.output().context(
+            "gitを実行できません（Azure DevOpsのプルリクエストはリポジトリ内で実行してください）",
+        )?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{self}を取得できません: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let output = Command::new("git")
+            .args([
+                "-c",
+                "core.quotePath=false",
+                "diff",
+                "FETCH_HEAD^1",
+                "FETCH_HEAD",
+            ])
+            .current_dir(repo_dir)
+            .output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{self}のdiffを作れません: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// レビュー全体と行ごとの指摘をスレッドとして投稿し、ポリシーの判定をステータスに設定する
+    pub async fn publish(
+        &self,
+        client: &reqwest::Client,
+        token: &str,
+        summary: &str,
+        comments: &[&ReviewComment],
+        policy: &PolicyReport,
+    ) -> Result<String> {
+        let url = self.api_url("threads");
+        let threads = std::iter::once(thread_body(summary, None)).chain(
+            comments
+                .iter()
+                .map(|comment| thread_body(&comment.body, Some(*comment))),
+        );
+        for thread in threads {
+            self.post(client, token, &url, &thread).await?;
+        }
+        self.post(
+            client,
+            token,
+            &self.api_url("statuses"),
+            &status_body(policy),
+        )
+        .await?;
+        Ok(self.html_url())
+    }
+
+    async fn post(
+        &self,
+        client: &reqwest::Client,
+        token: &str,
+        url: &str,
+        body: &serde_json::Value,
+    ) -> Result<()> {
+        let response = client
+            .post(url)
+            .header(reqwest::header::USER_AGENT, format!("ambient/{VERSION}"))
+            .header(reqwest::header::AUTHORIZATION, basic_credentials(token))
+            .json(body)
+            .send()
+            .await
+            .with_context(|| format!("{url}に接続できません"))?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("{self}に投稿できません: {status}");
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AzurePullRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}!{}", self.project, self.repository, self.id)
+    }
+}
+
+/// PATのBasic認証（ユーザー名は空）
+fn basic_credentials(token: &str) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(format!(":{token}"));
+    format!("Basic {encoded}")
+}
+
+/// スレッド作成APIの本文（`anchor`があれば変更後のファイルの行に付ける）
+fn thread_body(content: &str, anchor: Option<&ReviewComment>) -> serde_json::Value {
+    let mut thread = serde_json::json!({
+        "comments": [{ "parentCommentId": 0, "content": content, "commentType": 1 }],
+        "status": 1,
+    });
+    if let Some(ReviewComment {
+        path,
+        line: Some(line),
+        ..
+    }) = anchor
+    {
+        thread["threadContext"] = serde_json::json!({
+            "filePath": format!("/{path}"),
+            "rightFileStart": { "line": line, "offset": 1 },
+            "rightFileEnd": { "line": line, "offset": 1 },
+        });
+    }
+    thread
+}
+
+/// ステータスAPIの本文（ブロックする指摘があれば`failed`）
+fn status_body(policy: &PolicyReport) -> serde_json::Value {
+    let state = if policy.passed() {
+        "succeeded"
+    } else {
+        "failed"
+    };
+    let description = policy.describe();
+    serde_json::json!({
+        "state": state,
+        "description": description.lines().next().unwrap_or_default(),
+        "context": { "genre": STATUS_GENRE, "name": STATUS_NAME },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_policy::PolicyViolation;
+
+    fn expected() -> AzurePullRequest {
+        AzurePullRequest {
+            organization: "contoso".to_string(),
+            project: "Fabrikam".to_string(),
+            repository: "web".to_string(),
+            id: 42,
+        }
+    }
+
+    #[test]
+    fn parses_pull_request_urls_and_numbers() {
+        let config = IntegrationsConfig::default();
+        for url in [
+            "https://dev.azure.com/contoso/Fabrikam/_git/web/pullrequest/42",
+            "https://dev.azure.com/contoso/Fabrikam/_git/web/pullrequest/42?_a=files",
+            "https://contoso.visualstudio.com/Fabrikam/_git/web/pullrequest/42",
+        ] {
+            assert_eq!(
+                AzurePullRequest::parse(url, &config).unwrap(),
+                expected(),
+                "{url}"
+            );
+        }
+        assert!(AzurePullRequest::parse("42", &config).is_err());
+        assert!(AzurePullRequest::parse("https://github.com/org/repo/pull/1", &config).is_err());
+
+        let config = IntegrationsConfig {
+            organization: Some("contoso".to_string()),
+            project: Some("Fabrikam".to_string()),
+            repository: Some("web".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(AzurePullRequest::parse("!42", &config).unwrap(), expected());
+        assert_eq!(expected().to_string(), "Fabrikam/web!42");
+        assert_eq!(
+            expected().api_url("threads"),
+            "https://dev.azure.com/contoso/Fabrikam/_apis/git/repositories/web/pullRequests/42/threads?api-version=7.1"
+        );
+    }
+
+    #[test]
+    fn anchors_threads_to_right_file_lines() {
+        let comment = ReviewComment {
+            path: "src/a.rs".to_string(),
+            line: Some(7),
+            body: "問題があります".to_string(),
+        };
+        let thread = thread_body(&comment.body, Some(&comment));
+        assert_eq!(thread["threadContext"]["filePath"], "/src/a.rs");
+        assert_eq!(thread["threadContext"]["rightFileStart"]["line"], 7);
+        assert_eq!(thread["comments"][0]["content"], "問題があります");
+        assert!(thread_body("まとめ", None).get("threadContext").is_none());
+        assert_eq!(basic_credentials("pat"), "Basic OnBhdA==");
+    }
+
+    #[test]
+    fn status_follows_policy() {
+        assert_eq!(status_body(&PolicyReport::default())["state"], "succeeded");
+        let blocked = PolicyReport {
+            violations: vec![PolicyViolation {
+                file_path: "src/a.rs".to_string(),
+                review: "security".to_string(),
+                labels: vec!["security".to_string()],
+            }],
+        };
+        let status = status_body(&blocked);
+        assert_eq!(status["state"], "failed");
+        assert_eq!(
+            status["description"],
+            "ポリシー: ブロックする指摘が1件あります"
+        );
+        assert_eq!(status["context"]["genre"], "ambient");
+    }
+}
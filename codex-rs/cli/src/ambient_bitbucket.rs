@@ -0,0 +1,184 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::fmt;
+
+use crate::ambient_integration::ReviewComment;
+use crate::ambient_version::VERSION;
+
+/// Bitbucket CloudのAPI
+pub const BITBUCKET_API: &str = "https://api.bitbucket.org/2.0";
+
+/// Bitbucket Cloudのプルリクエスト
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitbucketPullRequest {
+    pub workspace: String,
+    pub repo: String,
+    pub id: u64,
+}
+
+impl BitbucketPullRequest {
+    /// `https://bitbucket.org/<workspace>/<repo>/pull-requests/<id>`形式のURLを解析する
+    pub fn parse(url: &str) -> Result<Self> {
+        let path = url
+            .trim()
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("www.");
+        let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        match parts.as_slice() {
+            ["bitbucket.org", workspace, repo, "pull-requests", id, ..] => Ok(Self {
+                workspace: workspace.to_string(),
+                repo: repo.to_string(),
+                id: id
+                    .parse()
+                    .with_context(|| format!("プルリクエストの番号が不正です: {id}"))?,
+            }),
+            _ => anyhow::bail!(
+                "Bitbucketのプルリクエストではありません（https://bitbucket.org/<workspace>/<repo>/pull-requests/<id>の形式で指定してください）: {url}"
+            ),
+        }
+    }
+
+    fn api_url(&self, api_base: &str) -> String {
+        format!(
+            "{api_base}/repositories/{}/{}/pullrequests/{}",
+            self.workspace, self.repo, self.id
+        )
+    }
+
+    fn html_url(&self) -> String {
+        format!(
+            "https://bitbucket.org/{}/{}/pull-requests/{}",
+            self.workspace, self.repo, self.id
+        )
+    }
+
+    /// プルリクエスト全体のdiffを取得する
+    pub async fn fetch_diff(
+        &self,
+        client: &reqwest::Client,
+        token: Option<&str>,
+    ) -> Result<String> {
+        let url = format!("{}/diff", self.api_url(BITBUCKET_API));
+        let response = bitbucket_request(client.get(&url), token)
+            .send()
+            .await
+            .with_context(|| format!("{url}に接続できません"))?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("{self}のdiffを取得できません: {status}");
+        }
+        Ok(response.text().await?)
+    }
+
+    /// レビュー全体の本文と、行ごとのコメントを投稿する
+    ///
+    /// Bitbucketにはまとめて投稿するAPIがないため、コメントごとに1回リクエストする。
+    pub async fn publish(
+        &self,
+        client: &reqwest::Client,
+        token: &str,
+        summary: &str,
+        comments: &[&ReviewComment],
+    ) -> Result<String> {
+        let url = format!("{}/comments", self.api_url(BITBUCKET_API));
+        let bodies = std::iter::once(comment_body(summary, None)).chain(
+            comments
+                .iter()
+                .map(|comment| comment_body(&comment.body, Some(*comment))),
+        );
+        for body in bodies {
+            let response = bitbucket_request(client.post(&url), Some(token))
+                .json(&body)
+                .send()
+                .await
+                .with_context(|| format!("{url}に接続できません"))?;
+            let status = response.status();
+            if !status.is_success() {
+                anyhow::bail!("{self}にコメントを投稿できません: {status}");
+            }
+        }
+        Ok(self.html_url())
+    }
+}
+
+impl fmt::Display for BitbucketPullRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}#{}", self.workspace, self.repo, self.id)
+    }
+}
+
+fn bitbucket_request(
+    builder: reqwest::RequestBuilder,
+    token: Option<&str>,
+) -> reqwest::RequestBuilder {
+    let builder = builder.header(reqwest::header::USER_AGENT, format!("ambient/{VERSION}"));
+    match token {
+        Some(token) => builder.bearer_auth(token),
+        None => builder,
+    }
+}
+
+/// コメント作成APIの本文（`inline`があれば変更後の行に付ける）
+fn comment_body(raw: &str, inline: Option<&ReviewComment>) -> serde_json::Value {
+    let mut body = serde_json::json!({ "content": { "raw": raw } });
+    if let Some(ReviewComment {
+        path,
+        line: Some(line),
+        ..
+    }) = inline
+    {
+        body["inline"] = serde_json::json!({ "path": path, "to": line });
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pull_request_urls() {
+        let pr = BitbucketPullRequest::parse(
+            "https://bitbucket.org/team/service/pull-requests/42/diff#chg-src/a.rs",
+        )
+        .unwrap();
+        assert_eq!(
+            pr,
+            BitbucketPullRequest {
+                workspace: "team".to_string(),
+                repo: "service".to_string(),
+                id: 42,
+            }
+        );
+        assert_eq!(pr.to_string(), "team/service#42");
+        assert_eq!(
+            pr.api_url(BITBUCKET_API),
+            "https://api.bitbucket.org/2.0/repositories/team/service/pullrequests/42"
+        );
+        assert!(BitbucketPullRequest::parse("https://github.com/org/repo/pull/1").is_err());
+    }
+
+    #[test]
+    fn anchors_inline_comments_to_new_lines() {
+        let comment = ReviewComment {
+            path: "src/a.rs".to_string(),
+            line: Some(12),
+            body: "問題があります".to_string(),
+        };
+        assert_eq!(
+            comment_body(&comment.body, Some(&comment)),
+            serde_json::json!({
+                "content": { "raw": "問題があります" },
+                "inline": { "path": "src/a.rs", "to": 12 },
+            })
+        );
+        assert_eq!(
+            comment_body("まとめ", None),
+            serde_json::json!({ "content": { "raw": "まとめ" } })
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::borrow::Cow;
+
+use crate::ambient_plan::CHARS_PER_TOKEN;
+use crate::ambient_plan::estimate_tokens;
+
+/// プロンプトの区分（トークン数を配分する単位）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptSection {
+    /// 固定した文書（`pinned_context`と`AGENTS.md`）
+    Pinned,
+    /// レビューする変更（diffまたはファイル全体）
+    Diff,
+    /// 関連するファイル（シグネチャが変わった関数の呼び出し元、質問の`@path`）
+    Related,
+    /// チャットの質問
+    Conversation,
+}
+
+impl PromptSection {
+    fn label(self) -> &'static str {
+        match self {
+            PromptSection::Pinned => "固定した文書",
+            PromptSection::Diff => "変更",
+            PromptSection::Related => "関連ファイル",
+            PromptSection::Conversation => "質問",
+        }
+    }
+}
+
+/// 1つの区分の取り分
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SectionShare {
+    /// 最低保証を除いた残りのうち、この区分に割り当てる割合（%、使い切らない分は他の区分に回す）
+    pub percent: u8,
+    /// 上限を超えても、この区分に必ず割り当てるトークン数（要求がそれより少なければ要求まで）
+    #[serde(default)]
+    pub min_tokens: usize,
+}
+
+/// `.ambient/config.toml`の`[context_budget]`（`max_prompt_tokens`を超えるときの区分ごとの配分）
+///
+/// 単純に末尾を切り詰めるのではなく、各区分に最低保証を割り当ててから、残りを割合で分ける。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ContextBudget {
+    pub pinned: SectionShare,
+    pub diff: SectionShare,
+    pub related: SectionShare,
+    pub conversation: SectionShare,
+}
+
+impl Default for ContextBudget {
+    fn default() -> Self {
+        Self {
+            pinned: SectionShare {
+                percent: 20,
+                min_tokens: 200,
+            },
+            diff: SectionShare {
+                percent: 55,
+                min_tokens: 1_000,
+            },
+            related: SectionShare {
+                percent: 15,
+                min_tokens: 0,
+            },
+            conversation: SectionShare {
+                percent: 10,
+                min_tokens: 200,
+            },
+        }
+    }
+}
+
+/// 区分の要求と、割り当てたトークン数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionAllocation {
+    pub section: PromptSection,
+    pub requested: usize,
+    pub allocated: usize,
+}
+
+/// 1つのプロンプトの配分の結果（分析のイベントで知らせる）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetAllocation {
+    /// 配分したトークン数の合計の上限（`max_prompt_tokens`、0は無制限）
+    pub budget: usize,
+    pub sections: Vec<SectionAllocation>,
+}
+
+impl ContextBudget {
+    fn share(&self, section: PromptSection) -> SectionShare {
+        match section {
+            PromptSection::Pinned => self.pinned,
+            PromptSection::Diff => self.diff,
+            PromptSection::Related => self.related,
+            PromptSection::Conversation => self.conversation,
+        }
+    }
+
+    /// 各区分の要求（トークン数）を、合計が`budget`に収まるように配分する
+    ///
+    /// 収まる場合と`budget`が0の場合は要求どおり。収まらない場合は最低保証を`requested`の順に割り当て、
+    /// 残りを割合で分ける。要求より多く割り当たった区分の余りは、まだ足りない区分で分け直す。
+    pub fn allocate(
+        &self,
+        budget: usize,
+        requested: &[(PromptSection, usize)],
+    ) -> BudgetAllocation {
+        let mut sections: Vec<SectionAllocation> = requested
+            .iter()
+            .map(|&(section, requested)| SectionAllocation {
+                section,
+                requested,
+                allocated: requested,
+            })
+            .collect();
+        let total: usize = requested.iter().map(|(_, tokens)| tokens).sum();
+        if budget == 0 || total <= budget {
+            return BudgetAllocation { budget, sections };
+        }
+
+        let mut left = budget;
+        for allocation in &mut sections {
+            let minimum = self.share(allocation.section).min_tokens;
+            allocation.allocated = allocation.requested.min(minimum).min(left);
+            left -= allocation.allocated;
+        }
+        while left > 0 {
+            let hungry: Vec<usize> = (0..sections.len())
+                .filter(|&i| sections[i].allocated < sections[i].requested)
+                .collect();
+            if hungry.is_empty() {
+                break;
+            }
+            // 足りない区分の割合がすべて0なら均等に分ける
+            let percent = |i: usize| usize::from(self.share(sections[i].section).percent);
+            let equal = hungry.iter().all(|&i| percent(i) == 0);
+            let weight = |i: usize| if equal { 1 } else { percent(i) };
+            let total_weight: usize = hungry.iter().map(|&i| weight(i)).sum();
+            let shares: Vec<usize> = hungry
+                .iter()
+                .map(|&i| left * weight(i) / total_weight)
+                .collect();
+            let mut given = 0;
+            for (&i, share) in hungry.iter().zip(shares) {
+                let allocation = &mut sections[i];
+                let give = share.min(allocation.requested - allocation.allocated);
+                allocation.allocated += give;
+                given += give;
+            }
+            if given == 0 {
+                // 割り切れずに残った分は順に割り当てる
+                for &i in &hungry {
+                    let allocation = &mut sections[i];
+                    let give = left.min(allocation.requested - allocation.allocated);
+                    allocation.allocated += give;
+                    left -= give;
+                }
+                break;
+            }
+            left -= given;
+        }
+        BudgetAllocation { budget, sections }
+    }
+}
+
+impl BudgetAllocation {
+    /// 要求より少なく割り当てた区分があるか
+    pub fn is_trimmed(&self) -> bool {
+        self.sections
+            .iter()
+            .any(|allocation| allocation.allocated < allocation.requested)
+    }
+
+    /// 区分に割り当てた文字数（配分がなければなし）
+    pub fn chars(&self, section: PromptSection) -> Option<usize> {
+        self.sections
+            .iter()
+            .find(|allocation| allocation.section == section)
+            .map(|allocation| allocation.allocated * CHARS_PER_TOKEN)
+    }
+
+    /// `固定した文書 300/500、変更 4000/9000`の形式（割り当て/要求、トークン）
+    pub fn describe(&self) -> String {
+        let sections: Vec<String> = self
+            .sections
+            .iter()
+            .map(|allocation| {
+                format!(
+                    "{} {}/{}",
+                    allocation.section.label(),
+                    allocation.allocated,
+                    allocation.requested
+                )
+            })
+            .collect();
+        format!(
+            "[配分] プロンプトが上限（{}トークン）を超えるため、区分ごとに割り当てました: {}",
+            self.budget,
+            sections.join("、")
+        )
+    }
+}
+
+/// 文字数から区分の要求（トークン数）を見積もる
+pub fn requested_tokens(text: &str) -> usize {
+    estimate_tokens(text.chars().count())
+}
+
+/// `max_chars`文字に収まるよう、行の境目で切り詰める（切り詰めた場合は末尾に省略の印を付ける）
+pub fn fit_to_chars(text: &str, max_chars: usize) -> Cow<'_, str> {
+    if text.chars().count() <= max_chars {
+        return Cow::Borrowed(text);
+    }
+    let cut = text
+        .char_indices()
+        .nth(max_chars)
+        .map_or(text.len(), |(index, _)| index);
+    let kept = &text[..cut];
+    let kept = kept.rfind('\n').map_or(kept, |newline| &kept[..=newline]);
+    Cow::Owned(format!("{kept}…（トークン数の配分を超えたため省略）\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocated(allocation: &BudgetAllocation) -> Vec<usize> {
+        allocation
+            .sections
+            .iter()
+            .map(|section| section.allocated)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_requests_that_fit() {
+        let budget = ContextBudget::default();
+        let requested = [(PromptSection::Pinned, 500), (PromptSection::Diff, 3_000)];
+        let allocation = budget.allocate(6_000, &requested);
+        assert_eq!(allocated(&allocation), vec![500, 3_000]);
+        assert!(!allocation.is_trimmed());
+        assert_eq!(allocated(&budget.allocate(0, &requested)), vec![500, 3_000]);
+    }
+
+    #[test]
+    fn guarantees_minimums_and_splits_the_rest_by_percent() {
+        let budget = ContextBudget::default();
+        let allocation = budget.allocate(
+            6_000,
+            &[
+                (PromptSection::Pinned, 2_000),
+                (PromptSection::Diff, 9_000),
+                (PromptSection::Related, 300),
+            ],
+        );
+        // 最低保証（200 + 1000 + 0）の残り4800を20:55:15で分け、関連ファイルの余りを分け直す
+        assert_eq!(allocated(&allocation), vec![1_400, 4_300, 300]);
+        assert_eq!(allocated(&allocation).iter().sum::<usize>(), 6_000);
+        assert!(allocation.is_trimmed());
+        assert_eq!(allocation.chars(PromptSection::Related), Some(1_200));
+        assert_eq!(allocation.chars(PromptSection::Conversation), None);
+        assert!(allocation.describe().contains("固定した文書 1400/2000"));
+
+        // 最低保証だけで上限に達する場合は、先の区分から割り当てる
+        let allocation = budget.allocate(
+            1_100,
+            &[(PromptSection::Pinned, 2_000), (PromptSection::Diff, 9_000)],
+        );
+        assert_eq!(allocated(&allocation), vec![200, 900]);
+    }
+
+    #[test]
+    fn trims_at_line_boundaries() {
+        assert_eq!(fit_to_chars("a\nb\n", 10), "a\nb\n");
+        assert_eq!(
+            fit_to_chars("一行目\n二行目\n三行目\n", 9),
+            "一行目\n二行目\n…（トークン数の配分を超えたため省略）\n"
+        );
+    }
+
+    #[test]
+    fn parses_partial_budgets() {
+        let budget: ContextBudget = toml::from_str("diff = { percent = 70 }").unwrap();
+        assert_eq!(
+            budget.diff,
+            SectionShare {
+                percent: 70,
+                min_tokens: 0,
+            }
+        );
+        assert_eq!(budget.pinned, ContextBudget::default().pinned);
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::Result;
+use std::time::Duration;
+
+use crate::ambient_confidence::Severity;
+use crate::ambient_history::parse_duration;
+
+/// `/help`で表示するコマンドの一覧
+pub const CHAT_COMMAND_HELP: &str = "チャットのコマンド:
+/review <ファイル>  次のチェックで変更の有無にかかわらずレビューする
+/status  監視の状態を表示する
+/pause <期間>  定期チェックを一時停止する（例: /pause 30m）
+/resume  一時停止を解除する
+/findings [重大度]  最近のレビュー結果を表示する（例: /findings high）
+/explain <ファイル>  ファイルの内容を説明する
+/model [モデル名]  使用するモデルを表示・変更する
+/help  この一覧を表示する";
+
+/// モデルに送らずにウォッチャーが処理するチャットのコマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatCommand {
+    /// 指定したファイルを次のチェックでレビューする
+    Review(String),
+    /// 監視の状態を表示する
+    Status,
+    /// 定期チェックを一時停止する
+    Pause(Duration),
+    /// 一時停止を解除する
+    Resume,
+    /// 最近のレビュー結果（指定した重大度以上）
+    Findings(Option<Severity>),
+    /// ファイルの内容を説明する
+    Explain(String),
+    /// 使用するモデルを変更する（`None`の場合は表示のみ）
+    Model(Option<String>),
+    /// コマンドの一覧
+    Help,
+}
+
+/// `/`で始まるメッセージをコマンドとして解析する（それ以外は`None`で、通常の質問として扱う）
+pub fn parse_chat_command(text: &str) -> Option<Result<ChatCommand>> {
+    let text = text.trim();
+    let rest = text.strip_prefix('/')?;
+    let (name, arg) = match rest.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (rest, ""),
+    };
+    let required = |usage: &str| -> Result<String> {
+        if arg.is_empty() {
+            anyhow::bail!("使い方: {usage}");
+        }
+        Ok(arg.to_string())
+    };
+    let command = match name {
+        "review" => required("/review <ファイル>").map(ChatCommand::Review),
+        "status" => Ok(ChatCommand::Status),
+        "pause" => required("/pause <期間>（例: 30m）").and_then(|arg| {
+            let duration = parse_duration(&arg)?
+                .to_std()
+                .map_err(|_| anyhow::anyhow!("期間は正の値で指定してください: {arg}"))?;
+            Ok(ChatCommand::Pause(duration))
+        }),
+        "resume" => Ok(ChatCommand::Resume),
+        "findings" if arg.is_empty() => Ok(ChatCommand::Findings(None)),
+        "findings" => Severity::normalize(arg)
+            .map(|severity| ChatCommand::Findings(Some(severity)))
+            .ok_or_else(|| {
+                anyhow::anyhow!("重大度はlow、medium、high、criticalのいずれかです: {arg}")
+            }),
+        "explain" => required("/explain <ファイル>").map(ChatCommand::Explain),
+        "model" if arg.is_empty() => Ok(ChatCommand::Model(None)),
+        "model" => Ok(ChatCommand::Model(Some(arg.to_string()))),
+        "help" => Ok(ChatCommand::Help),
+        _ => Err(anyhow::anyhow!(
+            "不明なコマンドです: /{name}（/helpで一覧を表示します）"
+        )),
+    };
+    Some(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_with_arguments() {
+        assert_eq!(
+            parse_chat_command(" /review src/lib.rs ").unwrap().unwrap(),
+            ChatCommand::Review("src/lib.rs".to_string())
+        );
+        assert_eq!(
+            parse_chat_command("/pause 30m").unwrap().unwrap(),
+            ChatCommand::Pause(Duration::from_secs(30 * 60))
+        );
+        assert_eq!(
+            parse_chat_command("/findings high").unwrap().unwrap(),
+            ChatCommand::Findings(Some(Severity::High))
+        );
+        assert_eq!(
+            parse_chat_command("/model").unwrap().unwrap(),
+            ChatCommand::Model(None)
+        );
+        assert_eq!(
+            parse_chat_command("/status").unwrap().unwrap(),
+            ChatCommand::Status
+        );
+    }
+
+    #[test]
+    fn leaves_questions_to_the_model() {
+        assert!(parse_chat_command("この関数は何をしていますか？").is_none());
+        assert!(parse_chat_command("src/lib.rs /review").is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_or_incomplete_commands() {
+        assert!(parse_chat_command("/deploy").unwrap().is_err());
+        assert!(parse_chat_command("/review").unwrap().is_err());
+        assert!(parse_chat_command("/pause soon").unwrap().is_err());
+        assert!(parse_chat_command("/findings urgent").unwrap().is_err());
+    }
+}
@@ -0,0 +1,579 @@
+use anyhow::Context;
+use anyhow::Result;
+use hmac::Hmac;
+use hmac::Mac;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::ambient_annotations::finding_line;
+use crate::ambient_confidence::Severity;
+use crate::ambient_cycle::CycleSummary;
+use crate::ambient_git::run_git_command;
+use crate::ambient_integration::IntegrationKind;
+use crate::ambient_integration::IntegrationsConfig;
+use crate::ambient_policy::PolicyConfig;
+use crate::ambient_policy::PolicyReport;
+use crate::ambient_pull_request::GITHUB_API;
+use crate::ambient_pull_request::github_request;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_server::Finding;
+
+/// GitHubのチェック一覧に表示する名前（再実行の要求もこの名前のものだけを受け付ける）
+pub const CHECK_NAME: &str = "ambient";
+
+/// Webhookの署名の秘密を読む既定の環境変数
+pub const WEBHOOK_SECRET_ENV: &str = "GITHUB_WEBHOOK_SECRET";
+
+/// 1回のリクエストで送れる注釈の数（GitHubの上限）
+const ANNOTATIONS_PER_REQUEST: usize = 50;
+
+/// チェックランを作るGitHubのリポジトリ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GithubRepo {
+    pub owner: String,
+    pub repo: String,
+}
+
+impl GithubRepo {
+    /// `origin`のURL（`git@github.com:<owner>/<repo>.git`や`https://github.com/<owner>/<repo>`）から作る
+    pub fn from_remote(url: &str) -> Option<Self> {
+        let path = url
+            .trim()
+            .trim_start_matches("ssh://")
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("git@")
+            .strip_prefix("github.com")?
+            .trim_start_matches([':', '/']);
+        let mut parts = path.trim_end_matches('/').split('/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(owner), Some(repo), None) if !owner.is_empty() && !repo.is_empty() => {
+                Some(Self {
+                    owner: owner.to_string(),
+                    repo: repo.trim_end_matches(".git").to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// `root`のリポジトリの`origin`から作る
+    pub fn discover(root: &Path) -> Result<Self> {
+        let url = run_git_command(&["remote", "get-url", "origin"], root)?;
+        Self::from_remote(&url)
+            .with_context(|| format!("originがGitHubのリポジトリではありません: {}", url.trim()))
+    }
+
+    fn api_url(&self, api_base: &str) -> String {
+        format!("{api_base}/repos/{}/{}/check-runs", self.owner, self.repo)
+    }
+}
+
+impl fmt::Display for GithubRepo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.repo)
+    }
+}
+
+/// 行に付ける注釈（行番号のある指摘と、回答に`path:行番号`がある指摘だけ）
+pub fn annotations(findings: &[Finding]) -> Vec<serde_json::Value> {
+    findings
+        .iter()
+        .filter_map(|finding| {
+            let line = finding_line(finding)?;
+            Some(serde_json::json!({
+                "path": finding.file_path,
+                "start_line": line,
+                "end_line": line,
+                "annotation_level": annotation_level(finding.severity),
+                "title": finding.review,
+                "message": finding.message.trim(),
+            }))
+        })
+        .collect()
+}
+
+/// 重大度を注釈のレベルにする（high以上は`failure`、mediumは`warning`、それ以外は`notice`）
+fn annotation_level(severity: Option<Severity>) -> &'static str {
+    match severity {
+        Some(Severity::Critical | Severity::High) => "failure",
+        Some(Severity::Medium) => "warning",
+        Some(Severity::Low) | None => "notice",
+    }
+}
+
+/// チェックランの結論（ブロックする指摘があれば`failure`、指摘があれば`neutral`）
+pub fn conclusion(findings: &[Finding], policy: &PolicyReport) -> &'static str {
+    if !policy.passed() {
+        "failure"
+    } else if findings.is_empty() {
+        "success"
+    } else {
+        "neutral"
+    }
+}
+
+/// チェックランの本文（サイクルの要約とファイルごとの指摘のMarkdown）
+pub fn render_check_summary(
+    summary: &CycleSummary,
+    findings: &[Finding],
+    policy: &PolicyReport,
+) -> String {
+    let mut body = format!(
+        "{}件のファイルを分析し、{}件の指摘がありました。\n",
+        summary.analyzed.len(),
+        findings.len()
+    );
+    if !summary.text.is_empty() {
+        body.push_str(&format!("\n{}\n", summary.text.trim()));
+    }
+    if !policy.passed() {
+        body.push_str(&format!("\n{}\n", policy.describe().trim()));
+    }
+    let mut current = None;
+    for finding in findings {
+        if current != Some(&finding.file_path) {
+            body.push_str(&format!("\n### `{}`\n", finding.file_path));
+            current = Some(&finding.file_path);
+        }
+        let severity = finding
+            .severity
+            .map(|severity| format!("（{}）", severity.as_str()))
+            .unwrap_or_default();
+        body.push_str(&format!(
+            "\n**{}**{severity}\n\n{}\n",
+            finding.review,
+            finding.message.trim()
+        ));
+    }
+    body
+}
+
+/// 完了したチェックランを作り、確認できるページのURLを返す
+///
+/// 注釈は1回に50件までしか送れないため、残りはチェックランの更新で追加する。
+#[allow(clippy::too_many_arguments)]
+pub async fn publish_check_run(
+    client: &reqwest::Client,
+    api_base: &str,
+    repo: &GithubRepo,
+    token: &str,
+    head_sha: &str,
+    external_id: Option<Uuid>,
+    title: &str,
+    summary: &str,
+    conclusion: &str,
+    annotations: &[serde_json::Value],
+) -> Result<String> {
+    let mut batches = annotations.chunks(ANNOTATIONS_PER_REQUEST);
+    let output = |batch: Option<&[serde_json::Value]>| {
+        serde_json::json!({
+            "title": title,
+            "summary": summary,
+            "annotations": batch.unwrap_or_default(),
+        })
+    };
+    let url = repo.api_url(api_base);
+    let mut body = serde_json::json!({
+        "name": CHECK_NAME,
+        "head_sha": head_sha,
+        "status": "completed",
+        "conclusion": conclusion,
+        "output": output(batches.next()),
+    });
+    if let Some(external_id) = external_id {
+        body["external_id"] = serde_json::Value::String(external_id.to_string());
+    }
+    let response = github_request(
+        client.post(&url),
+        "application/vnd.github+json",
+        Some(token),
+    )
+    .json(&body)
+    .send()
+    .await
+    .with_context(|| format!("{url}に接続できません"))?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("{repo}にチェックランを作成できません: {status}");
+    }
+    let check_run: serde_json::Value = response.json().await?;
+    let id = check_run["id"]
+        .as_u64()
+        .context("チェックランのIDがありません")?;
+
+    let update_url = format!("{url}/{id}");
+    for batch in batches {
+        let response = github_request(
+            client.patch(&update_url),
+            "application/vnd.github+json",
+            Some(token),
+        )
+        .json(&serde_json::json!({ "output": output(Some(batch)) }))
+        .send()
+        .await
+        .with_context(|| format!("{update_url}に接続できません"))?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("{repo}のチェックランに注釈を追加できません: {status}");
+        }
+    }
+    Ok(check_run["html_url"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// `[integrations]`でGitHubのチェックランが有効か
+pub fn check_runs_enabled(integrations: &IntegrationsConfig) -> bool {
+    integrations.check_runs && integrations.kind == IntegrationKind::Github
+}
+
+/// チェックランが有効なら、チェックサイクルの結果をHEADのコミットに付けるタスクを始める
+pub fn spawn_check_runs(
+    root: PathBuf,
+    integrations: &IntegrationsConfig,
+    policy: &PolicyConfig,
+    client: reqwest::Client,
+    tx: &broadcast::Sender<EventEnvelope>,
+) {
+    if !check_runs_enabled(integrations) {
+        return;
+    }
+    match (GithubRepo::discover(&root), integrations.token()) {
+        (Ok(repo), Some(token)) => {
+            println!("チェックランを{repo}に作成します");
+            tokio::spawn(publish_check_runs(
+                tx.subscribe(),
+                root,
+                client,
+                repo,
+                token,
+                policy.clone(),
+            ));
+        }
+        (Err(e), _) => eprintln!("チェックランを作成しません: {e:#}"),
+        (_, None) => eprintln!(
+            "チェックランを作成しません: {}にトークンを設定してください",
+            integrations.token_hint()
+        ),
+    }
+}
+
+/// チェックサイクルが終わるたびに、その指摘でHEADのコミットにチェックランを作る
+async fn publish_check_runs(
+    mut rx: broadcast::Receiver<EventEnvelope>,
+    root: PathBuf,
+    client: reqwest::Client,
+    repo: GithubRepo,
+    token: String,
+    policy: PolicyConfig,
+) {
+    let mut findings: HashMap<Uuid, Vec<Finding>> = HashMap::new();
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: event @ (AmbientEvent::Finding(_) | AmbientEvent::Findings(_)),
+                project: None,
+                ..
+            }) => {
+                for finding in event.into_findings() {
+                    let Some(run_id) = finding.ids.run_id else {
+                        continue;
+                    };
+                    findings.entry(run_id).or_default().push(finding);
+                }
+            }
+            Ok(EventEnvelope {
+                event: AmbientEvent::CycleSummary(summary),
+                project: None,
+                ..
+            }) => {
+                let mut cycle_findings = summary
+                    .run_id
+                    .and_then(|run_id| findings.remove(&run_id))
+                    .unwrap_or_default();
+                cycle_findings.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+                let (Ok(head_sha), Ok(branch)) = (
+                    run_git_command(&["rev-parse", "HEAD"], &root),
+                    run_git_command(&["rev-parse", "--abbrev-ref", "HEAD"], &root),
+                ) else {
+                    continue;
+                };
+                let report = policy.evaluate(&cycle_findings);
+                let result = publish_check_run(
+                    &client,
+                    GITHUB_API,
+                    &repo,
+                    &token,
+                    head_sha.trim(),
+                    summary.run_id,
+                    &format!("{CHECK_NAME}: {}", branch.trim()),
+                    &render_check_summary(&summary, &cycle_findings, &report),
+                    conclusion(&cycle_findings, &report),
+                    &annotations(&cycle_findings),
+                )
+                .await;
+                match result {
+                    Ok(url) => println!("チェックランを作成しました: {url}"),
+                    Err(e) => eprintln!("{e:#}"),
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// `/api/webhooks/github`で受け取るチェックの再実行の要求
+#[derive(Clone)]
+pub struct CheckRunWebhook {
+    /// 署名（`X-Hub-Signature-256`）を検証する秘密
+    pub secret: String,
+    /// 再実行が要求されたら通知する
+    pub rerequested: Arc<Notify>,
+}
+
+impl std::fmt::Debug for CheckRunWebhook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 秘密はログに出さない
+        f.debug_struct("CheckRunWebhook")
+            .field("secret", &"<redacted>")
+            .finish_non_exhaustive()
+    }
+}
+
+impl CheckRunWebhook {
+    /// `X-Hub-Signature-256`の`sha256=<hex>`が本文のHMAC-SHA256と一致するか
+    pub fn verify(&self, body: &[u8], signature: &str) -> bool {
+        let Some(hex) = signature.trim().strip_prefix("sha256=") else {
+            return false;
+        };
+        let Some(expected) = decode_hex(hex) else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(body);
+        mac.verify_slice(&expected).is_ok()
+    }
+}
+
+/// `check_run`（ambientのもの）または`check_suite`の`rerequested`か
+pub fn is_rerequest(event: &str, payload: &serde_json::Value) -> bool {
+    if payload["action"] != "rerequested" {
+        return false;
+    }
+    match event {
+        "check_run" => payload["check_run"]["name"] == CHECK_NAME,
+        "check_suite" => true,
+        _ => false,
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_policy::PolicyViolation;
+    use crate::ambient_server::RunIds;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    fn finding(file_path: &str, message: &str, severity: Option<Severity>) -> Finding {
+        Finding {
+            ids: RunIds::new_run(),
+            file_path: file_path.to_string(),
+            review: "security".to_string(),
+            message: message.to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: None,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn parses_github_remotes() {
+        let expected = GithubRepo {
+            owner: "org".to_string(),
+            repo: "repo".to_string(),
+        };
+        for url in [
+            "git@github.com:org/repo.git\n",
+            "https://github.com/org/repo",
+            "https://github.com/org/repo.git",
+            "ssh://git@github.com/org/repo.git",
+        ] {
+            assert_eq!(
+                GithubRepo::from_remote(url).as_ref(),
+                Some(&expected),
+                "{url}"
+            );
+        }
+        assert_eq!(GithubRepo::from_remote("git@gitlab.com:org/repo.git"), None);
+        assert_eq!(GithubRepo::from_remote("https://github.com/org"), None);
+        assert_eq!(
+            expected.api_url(GITHUB_API),
+            "https://api.github.com/repos/org/repo/check-runs"
+        );
+    }
+
+    #[test]
+    fn annotates_referenced_lines_by_severity() {
+        let annotations = annotations(&[
+            finding(
+                "src/a.rs",
+                "`src/a.rs:12`で入力を検証していません",
+                Some(Severity::High),
+            ),
+            finding("src/a.rs", "全体的に読みにくい", Some(Severity::Low)),
+            finding("src/b.rs", "`src/b.rs:3`", None),
+        ]);
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations[0]["start_line"], 12);
+        assert_eq!(annotations[0]["annotation_level"], "failure");
+        assert_eq!(annotations[0]["title"], "security");
+        assert_eq!(annotations[1]["path"], "src/b.rs");
+        assert_eq!(annotations[1]["annotation_level"], "notice");
+    }
+
+    #[test]
+    fn conclusion_follows_policy_and_findings() {
+        let blocked = PolicyReport {
+            violations: vec![PolicyViolation {
+                file_path: "src/a.rs".to_string(),
+                review: "security".to_string(),
+                labels: vec!["security".to_string()],
+            }],
+        };
+        let findings = vec![finding("src/a.rs", "問題", None)];
+        assert_eq!(conclusion(&[], &PolicyReport::default()), "success");
+        assert_eq!(conclusion(&findings, &PolicyReport::default()), "neutral");
+        assert_eq!(conclusion(&findings, &blocked), "failure");
+
+        let mut summary = CycleSummary::start(None);
+        summary.analyzed("src/a.rs");
+        let body = render_check_summary(&summary, &findings, &blocked);
+        assert!(body.starts_with("1件のファイルを分析し、1件の指摘がありました。\n"));
+        assert!(body.contains("### `src/a.rs`"));
+        assert!(body.contains("ブロックする指摘が1件あります"));
+    }
+
+    #[tokio::test]
+    async fn sends_annotations_in_batches_of_fifty() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/org/repo/check-runs"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "id": 7,
+                "html_url": "https://github.com/org/repo/runs/7",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/repos/org/repo/check-runs/7"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let repo = GithubRepo {
+            owner: "org".to_string(),
+            repo: "repo".to_string(),
+        };
+        let annotations = vec![serde_json::json!({ "path": "src/a.rs" }); 60];
+        let url = publish_check_run(
+            &reqwest::Client::new(),
+            &server.uri(),
+            &repo,
+            "token",
+            "abc123",
+            None,
+            "ambient: main",
+            "まとめ",
+            "neutral",
+            &annotations,
+        )
+        .await
+        .unwrap();
+        assert_eq!(url, "https://github.com/org/repo/runs/7");
+
+        let requests = server.received_requests().await.unwrap();
+        let sizes: Vec<usize> = requests
+            .iter()
+            .map(|request| {
+                let body: serde_json::Value = request.body_json().unwrap();
+                body["output"]["annotations"].as_array().unwrap().len()
+            })
+            .collect();
+        assert_eq!(sizes, vec![50, 10]);
+    }
+
+    #[test]
+    fn verifies_webhook_signatures() {
+        // GitHubのドキュメントの例
+        let webhook = CheckRunWebhook {
+            secret: "It's a Secret to Everybody".to_string(),
+            rerequested: Arc::new(Notify::new()),
+        };
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(webhook.verify(b"Hello, World!", signature));
+        assert!(!webhook.verify(b"Hello, World?", signature));
+        assert!(!webhook.verify(b"Hello, World!", "sha1=757107ea"));
+        assert!(!webhook.verify(b"Hello, World!", "sha256=zz"));
+    }
+
+    #[test]
+    fn accepts_only_rerequests_of_ambient_checks() {
+        let check_run = |action: &str, name: &str| serde_json::json!({ "action": action, "check_run": { "name": name } });
+        assert!(is_rerequest(
+            "check_run",
+            &check_run("rerequested", CHECK_NAME)
+        ));
+        assert!(!is_rerequest("check_run", &check_run("rerequested", "ci")));
+        assert!(!is_rerequest(
+            "check_run",
+            &check_run("created", CHECK_NAME)
+        ));
+        assert!(is_rerequest(
+            "check_suite",
+            &serde_json::json!({ "action": "rerequested" })
+        ));
+        assert!(!is_rerequest(
+            "push",
+            &serde_json::json!({ "action": "rerequested" })
+        ));
+    }
+}
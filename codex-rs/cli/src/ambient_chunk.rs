@@ -0,0 +1,264 @@
+use anyhow::Result;
+
+use crate::ambient_plan::CHARS_PER_TOKEN;
+use crate::ambient_plan::estimate_tokens;
+
+/// 分けたチャンクが小さくなりすぎないようにする、1つのチャンクの最小の文字数
+const MIN_CHUNK_CHARS: usize = 1_000;
+
+/// 付け直すハンクのヘッダーで、行番号の桁が増える分の余裕
+const HUNK_HEADER_SLACK: usize = 8;
+
+/// `content`を入れたプロンプトが`max_tokens`を超える場合は、`content`を分けてチャンクごとのプロンプトにする
+///
+/// `fixed_chars`は、プロンプトのほかに送る文字数（固定した文書や回答形式の指示）。
+/// `max_tokens`が0の場合は分けない。
+pub fn chunk_prompts<F>(
+    content: &str,
+    max_tokens: usize,
+    fixed_chars: usize,
+    prompt_for: F,
+) -> Result<Vec<String>>
+where
+    F: Fn(&str) -> Result<String>,
+{
+    let prompt = prompt_for(content)?;
+    let prompt_chars = prompt.chars().count();
+    if max_tokens == 0 || estimate_tokens(fixed_chars + prompt_chars) <= max_tokens {
+        return Ok(vec![prompt]);
+    }
+    let overhead = fixed_chars + prompt_chars.saturating_sub(content.chars().count());
+    let budget = (max_tokens * CHARS_PER_TOKEN)
+        .saturating_sub(overhead)
+        .max(MIN_CHUNK_CHARS);
+    split_diff(content, budget)
+        .iter()
+        .map(|chunk| prompt_for(chunk.as_str()))
+        .collect()
+}
+
+/// diffを、各チャンクがなるべく`max_chars`文字に収まるようにハンクの境目で分ける
+///
+/// 各チャンクにはファイルのヘッダー（最初のハンクより前の行）を付ける。1つのハンクが収まらない場合は
+/// 行の境目で分け、行番号が合うようにハンクのヘッダーを付け直す。ハンクのない内容（ファイル全体）は
+/// 行の境目で分ける。1行で`max_chars`を超える行は分けない。
+pub fn split_diff(diff: &str, max_chars: usize) -> Vec<String> {
+    if diff.chars().count() <= max_chars {
+        return vec![diff.to_string()];
+    }
+    let lines: Vec<&str> = diff.split_inclusive('\n').collect();
+    let first_hunk = lines
+        .iter()
+        .position(|line| line.starts_with("@@ "))
+        .unwrap_or(0);
+    let (header, body) = lines.split_at(first_hunk);
+    let header = header.concat();
+    let budget = max_chars.saturating_sub(header.chars().count()).max(1);
+
+    let mut hunks: Vec<Vec<&str>> = Vec::new();
+    for line in body {
+        match hunks.last_mut() {
+            Some(hunk) if !line.starts_with("@@ ") => hunk.push(line),
+            _ => hunks.push(vec![line]),
+        }
+    }
+    let pieces = hunks
+        .iter()
+        .flat_map(|hunk| split_hunk(hunk, budget))
+        .collect();
+    pack(pieces, budget)
+        .into_iter()
+        .map(|chunk| format!("{header}{chunk}"))
+        .collect()
+}
+
+/// ハンクを`max_chars`文字以下に分ける（ヘッダーのないハンクは行の境目で分けるだけ）
+fn split_hunk(lines: &[&str], max_chars: usize) -> Vec<String> {
+    let total: usize = lines.iter().map(|line| line.chars().count()).sum();
+    if total <= max_chars {
+        return vec![lines.concat()];
+    }
+    let Some((first, body)) = lines.split_first() else {
+        return vec![];
+    };
+    let Some(header) = HunkHeader::parse(first) else {
+        return pack(
+            lines.iter().map(|line| line.to_string()).collect(),
+            max_chars,
+        );
+    };
+    let budget = max_chars
+        .saturating_sub(first.chars().count() + HUNK_HEADER_SLACK)
+        .max(1);
+    let mut pieces = Vec::new();
+    let mut piece = HunkPiece::new(header.old_start, header.new_start);
+    for line in body {
+        let chars = line.chars().count();
+        if piece.chars > 0 && piece.chars + chars > budget {
+            let next = HunkPiece::new(piece.old_next(), piece.new_next());
+            pieces.push(header.render(&std::mem::replace(&mut piece, next)));
+        }
+        piece.push(line);
+    }
+    if piece.chars > 0 {
+        pieces.push(header.render(&piece));
+    }
+    pieces
+}
+
+/// 前から順に、`max_chars`文字に収まるだけつなげる
+fn pack(pieces: Vec<String>, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_chars = 0;
+    for piece in pieces {
+        let chars = piece.chars().count();
+        if current_chars > 0 && current_chars + chars > max_chars {
+            chunks.push(std::mem::take(&mut current));
+            current_chars = 0;
+        }
+        current.push_str(&piece);
+        current_chars += chars;
+    }
+    if current_chars > 0 || chunks.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// `@@ -12,7 +12,9 @@ fn main()`のハンクのヘッダー
+struct HunkHeader {
+    old_start: usize,
+    new_start: usize,
+    /// 行番号の範囲より後ろ（関数名と改行）
+    section: String,
+}
+
+impl HunkHeader {
+    fn parse(line: &str) -> Option<Self> {
+        let rest = line.strip_prefix("@@ -")?;
+        let (ranges, section) = rest.split_once(" @@")?;
+        let (old, new) = ranges.split_once(" +")?;
+        let start = |range: &str| range.split(',').next()?.parse().ok();
+        Some(Self {
+            old_start: start(old)?,
+            new_start: start(new)?,
+            section: section.to_string(),
+        })
+    }
+
+    fn render(&self, piece: &HunkPiece) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@{}{}",
+            piece.old_start,
+            piece.old_count,
+            piece.new_start,
+            piece.new_count,
+            self.section,
+            piece.text
+        )
+    }
+}
+
+/// 分けたハンクの一部（変更前後の開始行と行数）
+struct HunkPiece {
+    old_start: usize,
+    old_count: usize,
+    new_start: usize,
+    new_count: usize,
+    text: String,
+    chars: usize,
+}
+
+impl HunkPiece {
+    fn new(old_start: usize, new_start: usize) -> Self {
+        Self {
+            old_start,
+            old_count: 0,
+            new_start,
+            new_count: 0,
+            text: String::new(),
+            chars: 0,
+        }
+    }
+
+    fn push(&mut self, line: &str) {
+        match line.chars().next() {
+            Some('+') => self.new_count += 1,
+            Some('-') => self.old_count += 1,
+            Some('\\') => {}
+            _ => {
+                self.old_count += 1;
+                self.new_count += 1;
+            }
+        }
+        self.text.push_str(line);
+        self.chars += line.chars().count();
+    }
+
+    fn old_next(&self) -> usize {
+        self.old_start + self.old_count
+    }
+
+    fn new_next(&self) -> usize {
+        self.new_start + self.new_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str =
+        "diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n";
+
+    #[test]
+    fn splits_diffs_by_hunk_and_renumbers_split_hunks() {
+        let first = "@@ -1,2 +1,3 @@\n a\n+b\n c\n";
+        let second = "@@ -20,3 +21,3 @@ fn main()\n xxxxxxxx\n-yyyyyyyy\n+zzzzzzzz\n wwwwwwww\n";
+        let diff = format!("{HEADER}{first}{second}");
+        assert_eq!(split_diff(&diff, diff.len()), vec![diff.clone()]);
+
+        assert_eq!(
+            split_diff(&diff, HEADER.len() + second.len()),
+            vec![format!("{HEADER}{first}"), format!("{HEADER}{second}")]
+        );
+
+        // 収まらないハンクは行の境目で分け、変更前後の開始行と行数を付け直す
+        assert_eq!(
+            split_diff(&diff, HEADER.len() + 60),
+            vec![
+                format!("{HEADER}{first}"),
+                format!("{HEADER}@@ -20,2 +21,1 @@ fn main()\n xxxxxxxx\n-yyyyyyyy\n"),
+                format!("{HEADER}@@ -22,1 +22,2 @@ fn main()\n+zzzzzzzz\n wwwwwwww\n"),
+            ]
+        );
+
+        let content = "line 1\nline 2\nline 3\n";
+        assert_eq!(
+            split_diff(content, 14),
+            vec!["line 1\nline 2\n", "line 3\n"]
+        );
+    }
+
+    #[test]
+    fn chunks_prompts_only_past_the_token_limit() {
+        let prompt_for = |content: &str| -> Result<String> {
+            Ok(format!("レビューしてください\n\n{content}"))
+        };
+        let diff = format!("{HEADER}@@ -1,1 +1,1 @@\n-a\n+b\n");
+        assert_eq!(chunk_prompts(&diff, 0, 0, prompt_for).unwrap().len(), 1);
+        assert_eq!(chunk_prompts(&diff, 1_000, 0, prompt_for).unwrap().len(), 1);
+
+        let hunks: String = (1..=400)
+            .map(|line| format!("@@ -{line},1 +{line},1 @@\n-old {line}\n+new {line}\n"))
+            .collect();
+        let diff = format!("{HEADER}{hunks}");
+        let prompts = chunk_prompts(&diff, 1_000, 200, prompt_for).unwrap();
+        assert!(prompts.len() > 1);
+        for prompt in &prompts {
+            assert!(prompt.starts_with(&format!("レビューしてください\n\n{HEADER}@@ ")));
+            assert!(estimate_tokens(200 + prompt.chars().count()) <= 1_000);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::ambient_confidence::Severity;
+use crate::ambient_policy::PolicyReport;
+use crate::ambient_server::Finding;
+
+/// `--fail-on`の重大度以上の指摘があったときの終了コード
+pub const SEVERITY_EXIT_CODE: i32 = 2;
+
+/// `ambient --once`の判定結果（重大度の閾値と`[policy]`のラベル）
+#[derive(Debug, Clone)]
+pub struct OnceReport {
+    /// 閾値
+    pub fail_on: Severity,
+    /// 閾値以上の指摘（ファイルとレビューの名前、重大度）
+    pub failing: Vec<(String, String, Severity)>,
+    pub policy: PolicyReport,
+}
+
+impl OnceReport {
+    /// 指摘を判定する（重大度のない指摘は閾値に関係なく通す）
+    pub fn evaluate(findings: &[Finding], fail_on: Severity, policy: PolicyReport) -> Self {
+        let failing = findings
+            .iter()
+            .filter_map(|finding| {
+                let severity = finding.severity.filter(|severity| *severity >= fail_on)?;
+                Some((finding.file_path.clone(), finding.review.clone(), severity))
+            })
+            .collect();
+        Self {
+            fail_on,
+            failing,
+            policy,
+        }
+    }
+
+    /// プロセスの終了コード（通過は0、ポリシーの違反を重大度より優先する）
+    pub fn exit_code(&self) -> i32 {
+        if !self.policy.passed() {
+            self.policy.exit_code()
+        } else if !self.failing.is_empty() {
+            SEVERITY_EXIT_CODE
+        } else {
+            0
+        }
+    }
+
+    /// 判定結果の表示用の文面
+    pub fn describe(&self) -> String {
+        let mut text = if self.failing.is_empty() {
+            format!("重大度: {}以上の指摘はありません", self.fail_on.as_str())
+        } else {
+            format!(
+                "重大度: {}以上の指摘が{}件あります",
+                self.fail_on.as_str(),
+                self.failing.len()
+            )
+        };
+        for (file_path, review, severity) in &self.failing {
+            text.push_str(&format!(
+                "\n  {file_path} {review}（{}）",
+                severity.as_str()
+            ));
+        }
+        text.push('\n');
+        text.push_str(&self.policy.describe());
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_policy::POLICY_BLOCKED_EXIT_CODE;
+    use crate::ambient_policy::PolicyConfig;
+    use crate::ambient_server::RunIds;
+
+    fn finding(severity: Option<Severity>, labels: &[&str]) -> Finding {
+        Finding {
+            ids: RunIds::new_run().new_job(),
+            file_path: "src/lib.rs".to_string(),
+            review: "セキュリティリスクの検出".to_string(),
+            message: "指摘".to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            confidence: None,
+            severity,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: None,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn fails_at_or_above_threshold() {
+        let findings = [
+            finding(Some(Severity::Medium), &[]),
+            finding(Some(Severity::High), &[]),
+            finding(None, &[]),
+        ];
+
+        let report = OnceReport::evaluate(&findings, Severity::High, PolicyReport::default());
+        assert_eq!(report.failing.len(), 1);
+        assert_eq!(report.exit_code(), SEVERITY_EXIT_CODE);
+        assert!(
+            report
+                .describe()
+                .contains("src/lib.rs セキュリティリスクの検出（high）")
+        );
+
+        let report = OnceReport::evaluate(&findings, Severity::Critical, PolicyReport::default());
+        assert_eq!(report.exit_code(), 0);
+    }
+
+    #[test]
+    fn policy_violations_take_precedence() {
+        let findings = [finding(Some(Severity::Critical), &["security"])];
+        let policy: PolicyConfig = toml::from_str("blocking = [\"security\"]").unwrap();
+
+        let report = OnceReport::evaluate(&findings, Severity::High, policy.evaluate(&findings));
+        assert_eq!(report.exit_code(), POLICY_BLOCKED_EXIT_CODE);
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+use std::time::Instant;
+
+/// 連続して失敗したらサーキットを開くモデル呼び出しの回数
+pub const FAILURE_THRESHOLD: u32 = 5;
+
+/// サーキットを開いてから復旧を確認するまでの最初の待ち時間
+const INITIAL_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// 復旧の確認に失敗するたびに倍にする待ち時間の上限
+const MAX_COOLDOWN: Duration = Duration::from_secs(15 * 60);
+
+/// モデル呼び出しを行ってよいかの判定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitDecision {
+    /// 通常どおり呼び出す
+    Allow,
+    /// クールダウン中のため呼び出さない
+    Reject,
+    /// クールダウンが明けたため、軽いリクエストで復旧を確認する
+    Probe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CircuitState {
+    Closed,
+    Open { until: Instant },
+    HalfOpen,
+}
+
+/// プロバイダーへの呼び出しが続けて失敗したときにレビューを止めるサーキットブレーカー
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    cooldown: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            cooldown: INITIAL_COOLDOWN,
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// 呼び出してよいかを判定する（クールダウンが明けていれば半開状態に移る）
+    pub fn check(&mut self, now: Instant) -> CircuitDecision {
+        match self.state {
+            CircuitState::Closed => CircuitDecision::Allow,
+            CircuitState::Open { until } if now < until => CircuitDecision::Reject,
+            CircuitState::Open { .. } => {
+                self.state = CircuitState::HalfOpen;
+                CircuitDecision::Probe
+            }
+            // 確認中は他の呼び出しを止めておく
+            CircuitState::HalfOpen => CircuitDecision::Reject,
+        }
+    }
+
+    /// 呼び出しの成功を記録し、サーキットを閉じる
+    pub fn record_success(&mut self) {
+        self.state = CircuitState::Closed;
+        self.consecutive_failures = 0;
+        self.cooldown = INITIAL_COOLDOWN;
+    }
+
+    /// 呼び出しの失敗を記録する
+    ///
+    /// この失敗でサーキットが新たに開いた場合は、レビューを止める時間を返す。
+    /// 復旧の確認に失敗した場合は待ち時間を倍にして開き直すが、通知は不要なので`None`を返す。
+    pub fn record_failure(&mut self, now: Instant) -> Option<Duration> {
+        match self.state {
+            CircuitState::HalfOpen => {
+                self.cooldown = (self.cooldown * 2).min(MAX_COOLDOWN);
+                self.state = CircuitState::Open {
+                    until: now + self.cooldown,
+                };
+                None
+            }
+            CircuitState::Open { .. } => None,
+            CircuitState::Closed => {
+                self.consecutive_failures += 1;
+                if self.consecutive_failures < FAILURE_THRESHOLD {
+                    return None;
+                }
+                self.state = CircuitState::Open {
+                    until: now + self.cooldown,
+                };
+                Some(self.cooldown)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let mut breaker = CircuitBreaker::default();
+        let now = Instant::now();
+        for _ in 1..FAILURE_THRESHOLD {
+            assert_eq!(breaker.record_failure(now), None);
+        }
+        assert_eq!(breaker.record_failure(now), Some(INITIAL_COOLDOWN));
+        assert_eq!(breaker.check(now), CircuitDecision::Reject);
+    }
+
+    #[test]
+    fn success_resets_failure_count() {
+        let mut breaker = CircuitBreaker::default();
+        let now = Instant::now();
+        for _ in 1..FAILURE_THRESHOLD {
+            breaker.record_failure(now);
+        }
+        breaker.record_success();
+        assert_eq!(breaker.record_failure(now), None);
+        assert_eq!(breaker.check(now), CircuitDecision::Allow);
+    }
+
+    #[test]
+    fn probes_after_cooldown_and_backs_off_on_failure() {
+        let mut breaker = CircuitBreaker::default();
+        let now = Instant::now();
+        for _ in 0..FAILURE_THRESHOLD {
+            breaker.record_failure(now);
+        }
+
+        let after_cooldown = now + INITIAL_COOLDOWN;
+        assert_eq!(breaker.check(after_cooldown), CircuitDecision::Probe);
+        // 確認中は他の呼び出しを止める
+        assert_eq!(breaker.check(after_cooldown), CircuitDecision::Reject);
+
+        // 確認に失敗すると待ち時間が倍になる
+        assert_eq!(breaker.record_failure(after_cooldown), None);
+        assert_eq!(
+            breaker.check(after_cooldown + INITIAL_COOLDOWN),
+            CircuitDecision::Reject
+        );
+        let after_backoff = after_cooldown + INITIAL_COOLDOWN * 2;
+        assert_eq!(breaker.check(after_backoff), CircuitDecision::Probe);
+
+        breaker.record_success();
+        assert_eq!(breaker.check(after_backoff), CircuitDecision::Allow);
+    }
+}
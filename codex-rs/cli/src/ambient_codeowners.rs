@@ -0,0 +1,149 @@
+use glob::MatchOptions;
+use glob::Pattern;
+use std::fs;
+use std::path::Path;
+
+/// GitHubと同じ順序で探すCODEOWNERSの場所
+const CODEOWNERS_PATHS: &[&str] = &[".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// `*`がディレクトリの区切りをまたがないようにする（`**`はまたぐ）
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// `CODEOWNERS`の1行
+#[derive(Debug, Clone)]
+struct Rule {
+    patterns: Vec<Pattern>,
+    owners: Vec<String>,
+}
+
+/// `CODEOWNERS`のルール（後に書かれたルールが優先される）
+#[derive(Debug, Clone, Default)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+impl CodeOwners {
+    /// リポジトリの`CODEOWNERS`を読み込む（見つからなければルールなし）
+    pub fn load(repo_root: &Path) -> Self {
+        CODEOWNERS_PATHS
+            .iter()
+            .find_map(|path| fs::read_to_string(repo_root.join(path)).ok())
+            .map(|content| Self::parse(&content))
+            .unwrap_or_default()
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let rules = content
+            .lines()
+            .filter_map(|line| {
+                let line = line.split_once(" #").map_or(line, |(rule, _)| rule).trim();
+                if line.is_empty() || line.starts_with('#') {
+                    return None;
+                }
+                let mut fields = line.split_whitespace();
+                let pattern = fields.next()?;
+                Some(Rule {
+                    patterns: compile(pattern),
+                    owners: fields.map(str::to_string).collect(),
+                })
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// ファイルの所有者（チームまたはユーザー）
+    pub fn owners_of(&self, file_path: &str) -> Vec<String> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                rule.patterns
+                    .iter()
+                    .any(|p| p.matches_with(file_path, MATCH_OPTIONS))
+            })
+            .map(|rule| rule.owners.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// gitignore形式のパターンをglobに変換する
+///
+/// - 先頭の`/`、または途中に`/`を含むパターンはリポジトリルートからのパス
+/// - `/`を含まないパターンは任意の階層の名前に一致
+/// - ディレクトリに一致した場合はその中のすべてのファイルが対象
+///   （`docs/*`のように最後がワイルドカードの場合は直下のファイルのみ）
+fn compile(pattern: &str) -> Vec<Pattern> {
+    let trimmed = pattern.trim_end_matches('/');
+    let anchored = pattern.starts_with('/') || trimmed.contains('/');
+    let base = trimmed.trim_start_matches('/');
+    let base = if anchored {
+        base.to_string()
+    } else {
+        format!("**/{base}")
+    };
+    let last = base.rsplit('/').next().unwrap_or_default();
+    let mut globs = Vec::new();
+    if !last.contains('*') {
+        globs.push(format!("{base}/**"));
+    }
+    if !pattern.ends_with('/') {
+        globs.push(base);
+    }
+    globs
+        .iter()
+        .filter_map(|glob| Pattern::new(glob).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODEOWNERS: &str = "\
+# 既定の所有者
+*       @org/maintainers
+*.js    @org/frontend
+/auth/  @org/security # 認証まわり
+docs/*  @docs-writer
+build   @org/infra
+";
+
+    #[test]
+    fn later_rules_take_precedence() {
+        let owners = CodeOwners::parse(CODEOWNERS);
+        assert_eq!(owners.owners_of("src/lib.rs"), vec!["@org/maintainers"]);
+        assert_eq!(owners.owners_of("web/app.js"), vec!["@org/frontend"]);
+        assert_eq!(owners.owners_of("auth/login.js"), vec!["@org/security"]);
+        assert_eq!(
+            owners.owners_of("auth/oauth/token.rs"),
+            vec!["@org/security"]
+        );
+    }
+
+    #[test]
+    fn matches_gitignore_style_paths() {
+        let owners = CodeOwners::parse(CODEOWNERS);
+        // `docs/*`は直下のファイルのみ
+        assert_eq!(owners.owners_of("docs/guide.md"), vec!["@docs-writer"]);
+        assert_eq!(
+            owners.owners_of("docs/api/index.md"),
+            vec!["@org/maintainers"]
+        );
+        // `/`を含まないパターンは任意の階層のディレクトリに一致
+        assert_eq!(owners.owners_of("tools/build/run.sh"), vec!["@org/infra"]);
+        // ルート以外の`auth`は対象外
+        assert_eq!(
+            owners.owners_of("src/auth/session.rs"),
+            vec!["@org/maintainers"]
+        );
+    }
+
+    #[test]
+    fn no_codeowners_means_no_owners() {
+        assert!(CodeOwners::default().owners_of("src/lib.rs").is_empty());
+    }
+}
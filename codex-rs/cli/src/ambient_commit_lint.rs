@@ -0,0 +1,365 @@
+use anyhow::Context;
+use anyhow::Result;
+use regex_lite::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::ambient_git::run_git_command;
+
+/// コミットとブランチ名の規約チェックのレビュー名
+pub const COMMIT_LINT_REVIEW_NAME: &str = "コミット規約チェック";
+
+/// 規約違反の指摘に付けるラベル（`[policy]`の`blocking`に加えるとチェックを失敗させる）
+pub const COMMIT_LINT_LABEL: &str = "commit-lint";
+
+/// 1回の確認で調べるコミットの上限（rebaseなどで大量に増えた場合）
+const MAX_NEW_COMMITS: usize = 20;
+
+/// `git commit -v`でメッセージの後ろに付くdiffの区切り
+const SCISSORS_LINE: &str = "# ------------------------ >8 ------------------------";
+
+/// `.ambient/config.toml`の`[commit_lint]`（コミットメッセージとブランチ名の規約）
+///
+/// どれも設定しなければ確認しない。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct CommitLintConfig {
+    /// 件名（1行目）が一致しなければならない正規表現
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_pattern: Option<String>,
+
+    /// Conventional Commitsの型の許可リスト（空の場合は`type(scope): 説明`の形式を求めない）
+    #[serde(default)]
+    pub types: Vec<String>,
+
+    /// ブランチ名が一致しなければならない正規表現
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch_pattern: Option<String>,
+}
+
+/// 規約の対象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintTarget {
+    Message,
+    Branch,
+}
+
+impl LintTarget {
+    fn describe(self) -> &'static str {
+        match self {
+            LintTarget::Message => "コミットメッセージ",
+            LintTarget::Branch => "ブランチ名",
+        }
+    }
+}
+
+impl CommitLintConfig {
+    /// 確認する規約が1つでも設定されているか
+    pub fn is_enabled(&self) -> bool {
+        self.message_pattern.is_some() || !self.types.is_empty() || self.branch_pattern.is_some()
+    }
+
+    /// コミットメッセージが規約に合わない理由（合っていれば空）
+    pub fn lint_message(&self, message: &str) -> Result<Vec<String>> {
+        let subject = subject(message);
+        let mut violations = Vec::new();
+        if subject.is_empty() {
+            violations.push("件名が空です".to_string());
+            return Ok(violations);
+        }
+        if let Some(pattern) = &self.message_pattern
+            && !compile(pattern, "message_pattern")?.is_match(subject)
+        {
+            violations.push(format!("件名が`{pattern}`に一致しません"));
+        }
+        if !self.types.is_empty() {
+            let conventional = compile(r"^([A-Za-z]+)(\([^()]*\))?!?: \S", "types")?;
+            match conventional
+                .captures(subject)
+                .and_then(|captures| captures.get(1))
+            {
+                Some(kind) if self.types.iter().any(|allowed| allowed == kind.as_str()) => {}
+                Some(kind) => violations.push(format!(
+                    "型`{}`は許可されていません（許可: {}）",
+                    kind.as_str(),
+                    self.types.join(", ")
+                )),
+                None => {
+                    violations.push("件名が`type(scope): 説明`の形式ではありません".to_string())
+                }
+            }
+        }
+        Ok(violations)
+    }
+
+    /// ブランチ名が規約に合わない理由（合っていれば空）
+    pub fn lint_branch(&self, branch: &str) -> Result<Vec<String>> {
+        let mut violations = Vec::new();
+        if let Some(pattern) = &self.branch_pattern
+            && !compile(pattern, "branch_pattern")?.is_match(branch)
+        {
+            violations.push(format!("ブランチ名が`{pattern}`に一致しません"));
+        }
+        Ok(violations)
+    }
+
+    /// 規約に合うように書き直してもらうプロンプト
+    pub fn suggestion_prompt(
+        &self,
+        target: LintTarget,
+        text: &str,
+        violations: &[String],
+    ) -> String {
+        let target = target.describe();
+        let mut prompt = format!(
+            "次の{target}はプロジェクトの規約に合っていません。意味を変えずに規約に合うよう書き直した{target}だけを、説明を付けずに答えてください。\n\n規約:\n"
+        );
+        if let Some(pattern) = &self.message_pattern {
+            prompt.push_str(&format!("- 件名は正規表現`{pattern}`に一致する\n"));
+        }
+        if !self.types.is_empty() {
+            prompt.push_str(&format!(
+                "- 件名は`type(scope): 説明`の形式で、typeは{}のいずれか\n",
+                self.types.join(", ")
+            ));
+        }
+        if let Some(pattern) = &self.branch_pattern {
+            prompt.push_str(&format!("- ブランチ名は正規表現`{pattern}`に一致する\n"));
+        }
+        prompt.push_str("\n違反:\n");
+        for violation in violations {
+            prompt.push_str(&format!("- {violation}\n"));
+        }
+        prompt.push_str(&format!("\n{target}:\n\n```\n{text}\n```"));
+        prompt
+    }
+}
+
+fn compile(pattern: &str, key: &str) -> Result<Regex> {
+    Regex::new(pattern).with_context(|| format!("{key}の正規表現が不正です: {pattern}"))
+}
+
+/// メッセージの件名（最初の空でない行）
+fn subject(message: &str) -> &str {
+    message
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+}
+
+/// `commit-msg`フックに渡されたファイルから、gitが付けたコメント行を除いたメッセージ
+pub fn read_message_file(path: &Path) -> Result<String> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("{}を読み込めません", path.display()))?;
+    let message: Vec<&str> = content
+        .lines()
+        .take_while(|line| *line != SCISSORS_LINE)
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    Ok(message.join("\n").trim().to_string())
+}
+
+/// 現在のブランチ名（detached HEADの場合は`None`）
+pub fn current_branch(root: &Path) -> Option<String> {
+    run_git_command(&["symbolic-ref", "--short", "-q", "HEAD"], root)
+        .ok()
+        .map(|branch| branch.trim().to_string())
+        .filter(|branch| !branch.is_empty())
+}
+
+/// 新しく作られたコミット
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    /// 短縮したコミットID
+    pub id: String,
+    pub message: String,
+}
+
+/// 前回の確認からの履歴の変化
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryChange {
+    /// 新しいコミット（古い順）
+    pub commits: Vec<Commit>,
+    /// 新しいコミットがあり、そのブランチをまだ確認していなければブランチ名
+    pub branch: Option<String>,
+}
+
+/// 監視ループが最後に確認したHEADと、確認済みのブランチ
+#[derive(Debug, Default)]
+pub struct CommitWatch {
+    head: Option<String>,
+    checked_branches: BTreeSet<String>,
+}
+
+impl CommitWatch {
+    /// 前回の確認からの新しいコミットと、初めてコミットされたブランチ
+    pub fn poll(&mut self, root: &Path) -> HistoryChange {
+        let commits = self.new_commits(root);
+        // ブランチ名はそのブランチで最初にコミットしたときに確認する
+        let branch = if commits.is_empty() {
+            None
+        } else {
+            self.unchecked_branch(root)
+        };
+        HistoryChange { commits, branch }
+    }
+
+    /// HEADが前回から変わっていれば、新しいコミットを古い順に返す（初回はHEADを記録するだけ）
+    pub fn new_commits(&mut self, root: &Path) -> Vec<Commit> {
+        let Ok(head) = run_git_command(&["rev-parse", "HEAD"], root) else {
+            return vec![];
+        };
+        let head = head.trim().to_string();
+        let Some(previous) = self.head.replace(head.clone()) else {
+            return vec![];
+        };
+        if previous == head {
+            return vec![];
+        }
+        let max_count = format!("--max-count={MAX_NEW_COMMITS}");
+        let format = "--format=%h%x00%B%x1e";
+        let range = format!("{previous}..{head}");
+        // rebaseなどで前回のHEADがなくなっていれば、HEADのコミットだけを確認する
+        let log = run_git_command(&["log", &max_count, format, &range], root)
+            .or_else(|_| run_git_command(&["log", "--max-count=1", format, &head], root))
+            .unwrap_or_default();
+        let mut commits: Vec<Commit> = log
+            .split('\x1e')
+            .filter_map(|entry| {
+                let (id, message) = entry.trim_start().split_once('\0')?;
+                Some(Commit {
+                    id: id.to_string(),
+                    message: message.trim().to_string(),
+                })
+            })
+            .collect();
+        commits.reverse();
+        commits
+    }
+
+    /// 現在のブランチをまだ確認していなければ、その名前を返す
+    pub fn unchecked_branch(&mut self, root: &Path) -> Option<String> {
+        current_branch(root).filter(|branch| self.checked_branches.insert(branch.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_test_support::TempRepo;
+
+    fn conventional() -> CommitLintConfig {
+        toml::from_str(
+            r#"
+message_pattern = "^.{1,50}$"
+types = ["feat", "fix", "docs"]
+branch_pattern = "^(main|(feat|fix)/[a-z0-9-]+)$"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn lints_commit_messages() {
+        let config = conventional();
+        assert!(config.is_enabled());
+        assert!(!CommitLintConfig::default().is_enabled());
+
+        assert!(
+            config
+                .lint_message("feat(cli): add hook mode\n\nbody")
+                .unwrap()
+                .is_empty()
+        );
+        assert!(config.lint_message("fix!: drop flag").unwrap().is_empty());
+        assert_eq!(
+            config.lint_message("chore: bump deps").unwrap(),
+            vec!["型`chore`は許可されていません（許可: feat, fix, docs）".to_string()]
+        );
+        assert_eq!(
+            config.lint_message("Update stuff").unwrap(),
+            vec!["件名が`type(scope): 説明`の形式ではありません".to_string()]
+        );
+        let long = format!("feat: {}", "x".repeat(60));
+        assert_eq!(config.lint_message(&long).unwrap().len(), 1);
+        assert_eq!(
+            config.lint_message("\n").unwrap(),
+            vec!["件名が空です".to_string()]
+        );
+    }
+
+    #[test]
+    fn lints_branch_names_and_rejects_bad_patterns() {
+        let config = conventional();
+        assert!(config.lint_branch("feat/hook-mode").unwrap().is_empty());
+        assert!(config.lint_branch("main").unwrap().is_empty());
+        assert_eq!(config.lint_branch("my_branch").unwrap().len(), 1);
+
+        let broken = CommitLintConfig {
+            branch_pattern: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(broken.lint_branch("main").is_err());
+    }
+
+    #[test]
+    fn builds_suggestion_prompt() {
+        let config = conventional();
+        let violations = config.lint_message("Update stuff").unwrap();
+        let prompt = config.suggestion_prompt(LintTarget::Message, "Update stuff", &violations);
+        assert!(prompt.starts_with("次のコミットメッセージはプロジェクトの規約に合っていません。"));
+        assert!(prompt.contains("typeはfeat, fix, docsのいずれか"));
+        assert!(prompt.ends_with("```\nUpdate stuff\n```"));
+    }
+
+    #[test]
+    fn reads_message_file_without_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("COMMIT_EDITMSG");
+        fs::write(
+            &path,
+            format!(
+                "feat: add hook\n\nbody\n# Please enter the commit message\n{SCISSORS_LINE}\ndiff --git a/x b/x\n"
+            ),
+        )
+        .unwrap();
+        assert_eq!(read_message_file(&path).unwrap(), "feat: add hook\n\nbody");
+    }
+
+    #[test]
+    fn detects_new_commits_and_branches() {
+        let repo = TempRepo::new().unwrap();
+        let mut watch = CommitWatch::default();
+        assert!(watch.new_commits(repo.path()).is_empty());
+
+        repo.write("a.txt", "a").unwrap();
+        repo.add(&["a.txt"]).unwrap();
+        repo.commit("initial").unwrap();
+        // 初回はHEADを記録するだけ
+        assert!(watch.new_commits(repo.path()).is_empty());
+
+        for (file, message) in [("b.txt", "feat: add b"), ("c.txt", "fix: c")] {
+            repo.write(file, file).unwrap();
+            repo.add(&[file]).unwrap();
+            repo.commit(message).unwrap();
+        }
+        let messages: Vec<String> = watch
+            .new_commits(repo.path())
+            .into_iter()
+            .map(|commit| commit.message)
+            .collect();
+        assert_eq!(messages, vec!["feat: add b", "fix: c"]);
+        assert!(watch.new_commits(repo.path()).is_empty());
+
+        repo.git(&["checkout", "-q", "-b", "feat/x"]).unwrap();
+        assert_eq!(
+            watch.unchecked_branch(repo.path()),
+            Some("feat/x".to_string())
+        );
+        assert_eq!(watch.unchecked_branch(repo.path()), None);
+    }
+}
@@ -0,0 +1,252 @@
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// レビューのプロンプトの末尾に付け、指摘の重大度と確からしさを自己評価させる指示
+///
+/// 重大度の選択肢は[`REQUESTED_SEVERITIES`]と同じ順に並べる（番号で答えたモデルの回答をこの順で解釈する）。
+pub const CONFIDENCE_INSTRUCTION: &str = "\n\n回答の最後に、指摘の重大度を`重大度: critical`・`重大度: high`・`重大度: medium`・`重大度: low`のいずれかで1行、確からしさを`確信度: 0〜100の整数`の形式で1行書いてください。指摘がない場合は`重大度: low`、`確信度: 100`としてください。";
+
+/// プロンプトで選択肢として示した順の重大度
+pub const REQUESTED_SEVERITIES: [Severity; 4] = [
+    Severity::Critical,
+    Severity::High,
+    Severity::Medium,
+    Severity::Low,
+];
+
+/// モデルが重大度として書く表現と、対応する重大度（英語・日本語の同義語）
+const SEVERITY_SYNONYMS: &[(&str, Severity)] = &[
+    ("critical", Severity::Critical),
+    ("blocker", Severity::Critical),
+    ("fatal", Severity::Critical),
+    ("severe", Severity::Critical),
+    ("致命的", Severity::Critical),
+    ("緊急", Severity::Critical),
+    ("重大", Severity::Critical),
+    ("深刻", Severity::Critical),
+    ("クリティカル", Severity::Critical),
+    ("high", Severity::High),
+    ("major", Severity::High),
+    ("important", Severity::High),
+    ("error", Severity::High),
+    ("高", Severity::High),
+    ("重要", Severity::High),
+    ("medium", Severity::Medium),
+    ("moderate", Severity::Medium),
+    ("med", Severity::Medium),
+    ("warning", Severity::Medium),
+    ("中", Severity::Medium),
+    ("普通", Severity::Medium),
+    ("low", Severity::Low),
+    ("minor", Severity::Low),
+    ("trivial", Severity::Low),
+    ("info", Severity::Low),
+    ("nit", Severity::Low),
+    ("none", Severity::Low),
+    ("低", Severity::Low),
+    ("軽微", Severity::Low),
+    ("なし", Severity::Low),
+];
+
+/// 自己評価とユーザーの判断を混ぜるときの、自己評価の重み（判断の件数に換算）
+const PRIOR_WEIGHT: f64 = 5.0;
+
+/// モデルが自己評価した指摘の重大度
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    ToSchema,
+    JsonSchema,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+
+    /// モデルが書いた重大度の表現を4段階に揃える
+    ///
+    /// `High risk`や`重大な問題`のように同義語で始まる表現と、選択肢の番号（`2`は`high`）を受け付ける。
+    pub fn normalize(value: &str) -> Option<Self> {
+        let value = value
+            .trim()
+            .trim_start_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        if let Some(position) = value
+            .chars()
+            .next()
+            .and_then(|c| c.to_digit(10))
+            .filter(|_| !value.chars().nth(1).is_some_and(|c| c.is_ascii_digit()))
+        {
+            return REQUESTED_SEVERITIES
+                .get((position as usize).checked_sub(1)?)
+                .copied();
+        }
+        SEVERITY_SYNONYMS
+            .iter()
+            .filter(|(synonym, _)| value.starts_with(synonym))
+            .max_by_key(|(synonym, _)| synonym.len())
+            .map(|(_, severity)| *severity)
+    }
+}
+
+/// 回答の最後の`重大度: …`と`確信度: N`の行を取り出し、それらを除いた回答を返す
+pub fn extract_assessment(message: &str) -> (String, Option<Severity>, Option<u8>) {
+    let (message, confidence) = extract_confidence(message);
+    let (message, severity) = extract_severity(&message);
+    (message, severity, confidence)
+}
+
+/// 回答の最後の`確信度: N`の行を取り出し、その行を除いた回答と確信度（0〜100）を返す
+pub fn extract_confidence(message: &str) -> (String, Option<u8>) {
+    let score = labeled_last_line(message, &["確信度", "confidence", "Confidence"]).and_then(
+        |(body, value)| {
+            let digits: String = value.chars().take_while(char::is_ascii_digit).collect();
+            let score = digits.parse::<u8>().ok().filter(|score| *score <= 100)?;
+            Some((body, score))
+        },
+    );
+    match score {
+        Some((body, score)) => (body.to_string(), Some(score)),
+        None => (message.to_string(), None),
+    }
+}
+
+/// 回答の最後の`重大度: …`の行を取り出し、その行を除いた回答と重大度を返す
+pub fn extract_severity(message: &str) -> (String, Option<Severity>) {
+    let severity = labeled_last_line(message, &["重大度", "severity", "Severity"])
+        .and_then(|(body, value)| Some((body, Severity::normalize(value)?)));
+    match severity {
+        Some((body, severity)) => (body.to_string(), Some(severity)),
+        None => (message.to_string(), None),
+    }
+}
+
+/// 最後の行が`label: 値`の形式であれば、それより前の回答と値を返す（Markdownの強調は無視する）
+fn labeled_last_line<'a>(message: &'a str, labels: &[&str]) -> Option<(&'a str, &'a str)> {
+    let trimmed = message.trim_end();
+    let (body, last) = trimmed.rsplit_once('\n').unwrap_or(("", trimmed));
+    let last = last
+        .trim()
+        .trim_matches(|c| c == '*' || c == '_' || c == '`');
+    let value = labels
+        .iter()
+        .find_map(|label| last.strip_prefix(label))?
+        .trim_start_matches([':', '：', ' '])
+        .trim_end_matches(['*', '_', '`']);
+    Some((body.trim_end(), value))
+}
+
+/// 過去にユーザーが判断したレビュー結果の件数（`fixed`は正しい指摘、`false_positive`は誤検知）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Feedback {
+    pub fixed: usize,
+    pub false_positive: usize,
+}
+
+impl Feedback {
+    /// モデルの自己評価を、このレビューの実際の的中率で補正する
+    ///
+    /// 判断の件数が少ないうちは自己評価をそのまま使い、増えるほど的中率に近づける。
+    pub fn calibrate(&self, raw: u8) -> u8 {
+        if self.fixed + self.false_positive == 0 {
+            return raw;
+        }
+        let judged = (self.fixed + self.false_positive) as f64;
+        let precision = self.fixed as f64 / judged;
+        let blended =
+            (f64::from(raw) / 100.0 * PRIOR_WEIGHT + precision * judged) / (PRIOR_WEIGHT + judged);
+        (blended * 100.0).round().clamp(0.0, 100.0) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_trailing_confidence_line() {
+        assert_eq!(
+            extract_confidence("`src/lib.rs:3`でunwrapしています\n\n**確信度: 35**\n"),
+            ("`src/lib.rs:3`でunwrapしています".to_string(), Some(35))
+        );
+        assert_eq!(
+            extract_confidence("問題は見つかりませんでした"),
+            ("問題は見つかりませんでした".to_string(), None)
+        );
+        assert_eq!(extract_confidence("指摘\n確信度：250").1, None);
+    }
+
+    #[test]
+    fn extracts_severity_before_confidence() {
+        assert_eq!(
+            extract_assessment("APIキーが含まれています\n重大度: High\n確信度: 90"),
+            (
+                "APIキーが含まれています".to_string(),
+                Some(Severity::High),
+                Some(90)
+            )
+        );
+        assert_eq!(extract_assessment("問題ありません\n確信度: 100").1, None);
+    }
+
+    #[test]
+    fn normalizes_severity_vocabulary() {
+        assert_eq!(Severity::normalize("重大"), Some(Severity::Critical));
+        assert_eq!(Severity::normalize("CRITICAL"), Some(Severity::Critical));
+        assert_eq!(Severity::normalize("High risk"), Some(Severity::High));
+        assert_eq!(
+            Severity::normalize("高（認証の迂回）"),
+            Some(Severity::High)
+        );
+        assert_eq!(Severity::normalize("Moderate"), Some(Severity::Medium));
+        assert_eq!(Severity::normalize("medium"), Some(Severity::Medium));
+        assert_eq!(Severity::normalize("minor"), Some(Severity::Low));
+        assert_eq!(Severity::normalize("2"), Some(Severity::High));
+        assert_eq!(Severity::normalize("4. low"), Some(Severity::Low));
+        assert_eq!(Severity::normalize("5"), None);
+        assert_eq!(Severity::normalize("unknown"), None);
+        assert_eq!(
+            extract_severity("指摘\n**Severity: Blocker**").1,
+            Some(Severity::Critical)
+        );
+    }
+
+    #[test]
+    fn calibration_follows_feedback() {
+        assert_eq!(Feedback::default().calibrate(80), 80);
+        let noisy = Feedback {
+            fixed: 1,
+            false_positive: 19,
+        };
+        assert!(noisy.calibrate(90) < 30);
+        let reliable = Feedback {
+            fixed: 20,
+            false_positive: 0,
+        };
+        assert!(reliable.calibrate(40) > 80);
+    }
+}
@@ -1,8 +1,16 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use crate::ambient_desktop_notify::DesktopNotificationsConfig;
+use crate::ambient_terminal_notify::TerminalNotifyConfig;
+
+/// プロファイルを選択する環境変数
+pub const AMBIENT_PROFILE_ENV_VAR: &str = "AMBIENT_PROFILE";
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct AmbientConfig {
     /// ファイル変更の検出間隔（秒）
     #[serde(default = "default_check_interval")]
@@ -15,6 +23,209 @@ pub struct AmbientConfig {
     /// 分析を有効にする拡張子のリスト
     #[serde(default = "default_file_extensions")]
     pub file_extensions: Vec<String>,
+
+    /// 既定のモデルプロバイダーID（codexの`model_providers`のキー。未指定なら`oss`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+
+    /// 既定のモデル名（未指定なら`gpt-oss:20b`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// 既定で使用するプロファイル名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// 名前付きプロファイル（`[profiles.work]`など）
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, AmbientProfile>,
+
+    /// モデルプロバイダーへのHTTP接続の設定
+    #[serde(default)]
+    pub http: HttpClientSettings,
+
+    /// 質問とレビューのモデル呼び出しの順番待ちの設定
+    #[serde(default)]
+    pub scheduler: SchedulerSettings,
+
+    /// 1回のチェックで並行して分析するファイルの数（モデルの同時呼び出し数は`[scheduler]`で制限する）
+    #[serde(default = "default_max_concurrent_analyses")]
+    pub max_concurrent_analyses: usize,
+
+    /// 匿名の利用状況を`~/.codex/ambient-usage.json`に集計する（送信はしない）
+    #[serde(default)]
+    pub telemetry: bool,
+
+    /// 指摘が届いたときに端末のエスケープシーケンスで通知する
+    #[serde(default)]
+    pub terminal_notify: TerminalNotifyConfig,
+
+    /// 指摘が見つかったときのOSのデスクトップ通知（`[notifications]`）
+    #[serde(default)]
+    pub notifications: DesktopNotificationsConfig,
+
+    /// 起動したディレクトリに加えて監視するリポジトリのパス（`~/`はホームディレクトリ）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub projects: Vec<String>,
+}
+
+/// 名前付きプロファイル。未指定の項目は既定値のまま使われる
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct AmbientProfile {
+    /// 使用するモデルプロバイダーID
+    #[serde(default)]
+    pub model_provider: Option<String>,
+
+    /// 使用するモデル名
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// `model_provider = "mock"`のときに回答として使うフィクスチャのディレクトリ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mock_fixtures: Option<PathBuf>,
+
+    /// 分析完了時に通知を受け取るプログラム（JSONが最後の引数として渡される）
+    #[serde(default)]
+    pub notify: Option<Vec<String>>,
+
+    /// 条件に一致したレビュー結果を個別に通知する先（`[[profiles.work.notify_routes]]`）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify_routes: Vec<NotifyRoute>,
+
+    /// モデルに送信する前の秘密情報マスキングの強さ
+    #[serde(default)]
+    pub redaction: RedactionLevel,
+
+    /// HTTP接続の設定（指定した場合はグローバルの`[http]`の代わりに使う）
+    #[serde(default)]
+    pub http: Option<HttpClientSettings>,
+}
+
+/// レビュー結果の通知先
+///
+/// 指定した条件にすべて一致したレビュー結果を`command`に渡す（未指定の条件は常に一致）。
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, JsonSchema)]
+pub struct NotifyRoute {
+    /// 通知を受け取るプログラム（レビュー結果のJSONが最後の引数として渡される）
+    pub command: Vec<String>,
+
+    /// 対象のファイル（globパターン）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+
+    /// 対象の所有者（`CODEOWNERS`の`@org/team`など）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub owners: Vec<String>,
+
+    /// 対象のレビュー（名前の一部で指定）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reviews: Vec<String>,
+}
+
+/// モデルプロバイダーへのHTTP接続の設定
+///
+/// 既定値はローカルのOllamaへのストリーミング向け。遅延の大きいリモートの
+/// エンドポイントを使う場合はタイムアウトを長めにする。
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct HttpClientSettings {
+    /// 使われていない接続を閉じるまでの時間（秒）
+    #[serde(default = "default_pool_idle_timeout")]
+    pub pool_idle_timeout_secs: u64,
+
+    /// ホストごとに保持するアイドル接続の最大数
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+
+    /// TCP_NODELAYを有効にする（ストリーミングの遅延を減らす）
+    #[serde(default = "default_tcp_nodelay")]
+    pub tcp_nodelay: bool,
+
+    /// TCPキープアライブの間隔（秒、0で無効）
+    #[serde(default = "default_tcp_keepalive")]
+    pub tcp_keepalive_secs: u64,
+
+    /// 接続確立のタイムアウト（秒）
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout_secs: u64,
+}
+
+impl Default for HttpClientSettings {
+    fn default() -> Self {
+        Self {
+            pool_idle_timeout_secs: default_pool_idle_timeout(),
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            tcp_nodelay: default_tcp_nodelay(),
+            tcp_keepalive_secs: default_tcp_keepalive(),
+            connect_timeout_secs: default_connect_timeout(),
+        }
+    }
+}
+
+/// 質問とレビューでモデルを共有するときの順番待ちの設定
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct SchedulerSettings {
+    /// モデルへの同時呼び出し数の上限
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+
+    /// 質問とレビューが両方待っているときの質問の割り当て比率
+    #[serde(default = "default_chat_weight")]
+    pub chat_weight: u32,
+
+    /// 質問とレビューが両方待っているときのレビューの割り当て比率（0で質問を常に優先）
+    #[serde(default)]
+    pub review_weight: u32,
+}
+
+impl Default for SchedulerSettings {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: default_max_concurrent_requests(),
+            chat_weight: default_chat_weight(),
+            review_weight: 0,
+        }
+    }
+}
+
+fn default_max_concurrent_requests() -> usize {
+    1 // ローカルのOllamaは1件ずつ処理する
+}
+
+fn default_chat_weight() -> u32 {
+    1
+}
+
+fn default_pool_idle_timeout() -> u64 {
+    90
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    4
+}
+
+fn default_tcp_nodelay() -> bool {
+    true
+}
+
+fn default_tcp_keepalive() -> u64 {
+    60
+}
+
+fn default_connect_timeout() -> u64 {
+    10
+}
+
+/// 秘密情報マスキングの強さ
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactionLevel {
+    /// マスキングしない
+    Off,
+    /// `api_key = "..."`のような代入値をマスキングする
+    #[default]
+    Standard,
+    /// 上記に加えて、長いトークン風の文字列もすべてマスキングする
+    Strict,
 }
 
 impl Default for AmbientConfig {
@@ -23,6 +234,17 @@ impl Default for AmbientConfig {
             check_interval_secs: default_check_interval(),
             port: default_port(),
             file_extensions: default_file_extensions(),
+            provider: None,
+            model: None,
+            profile: None,
+            profiles: HashMap::new(),
+            http: HttpClientSettings::default(),
+            scheduler: SchedulerSettings::default(),
+            max_concurrent_analyses: default_max_concurrent_analyses(),
+            telemetry: false,
+            terminal_notify: TerminalNotifyConfig::default(),
+            notifications: DesktopNotificationsConfig::default(),
+            projects: Vec::new(),
         }
     }
 }
@@ -31,6 +253,10 @@ fn default_check_interval() -> u64 {
     60 // デフォルト60秒
 }
 
+fn default_max_concurrent_analyses() -> usize {
+    4
+}
+
 fn default_port() -> u16 {
     38080
 }
@@ -111,12 +337,112 @@ impl AmbientConfig {
         Ok(())
     }
 
+    /// 使用するプロファイルを決定する
+    ///
+    /// 優先順位は `--profile` > `AMBIENT_PROFILE` > 設定ファイルの`profile`。
+    /// いずれも指定されていない場合は`None`を返す。
+    pub fn resolve_profile(
+        &self,
+        cli_profile: Option<&str>,
+    ) -> anyhow::Result<Option<(String, AmbientProfile)>> {
+        let env_profile = std::env::var(AMBIENT_PROFILE_ENV_VAR)
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+        let name = cli_profile
+            .map(str::to_string)
+            .or(env_profile)
+            .or_else(|| self.profile.clone());
+
+        match name {
+            Some(name) => match self.profiles.get(&name) {
+                Some(profile) => Ok(Some((name, profile.clone()))),
+                None => Err(anyhow::anyhow!("プロファイル`{name}`が見つかりません")),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// 使用するプロバイダーとモデルを決定する
+    ///
+    /// 項目ごとに、プロファイル > プロジェクト設定 > この設定ファイルの順で優先する。
+    /// いずれも指定されていない項目は`None`を返す。
+    pub fn resolve_model(
+        &self,
+        profile: &AmbientProfile,
+        project_provider: Option<&str>,
+        project_model: Option<&str>,
+    ) -> (Option<String>, Option<String>) {
+        let provider = profile
+            .model_provider
+            .clone()
+            .or_else(|| project_provider.map(str::to_string))
+            .or_else(|| self.provider.clone());
+        let model = profile
+            .model
+            .clone()
+            .or_else(|| project_model.map(str::to_string))
+            .or_else(|| self.model.clone());
+        (provider, model)
+    }
+
     /// 設定ファイルのパスを取得
-    fn config_path() -> anyhow::Result<PathBuf> {
-        let home = std::env::var("HOME")
-            .or_else(|_| std::env::var("USERPROFILE"))
-            .map_err(|_| anyhow::anyhow!("ホームディレクトリが見つかりません"))?;
+    pub fn config_path() -> anyhow::Result<PathBuf> {
+        Ok(codex_dir()?.join("ambient.toml"))
+    }
+
+    /// 利用状況の集計ファイルのパスを取得
+    pub fn usage_path() -> anyhow::Result<PathBuf> {
+        Ok(codex_dir()?.join("ambient-usage.json"))
+    }
+
+    /// 履歴とキャッシュを暗号化する鍵の既定のパスを取得
+    pub fn storage_key_path() -> anyhow::Result<PathBuf> {
+        Ok(codex_dir()?.join("ambient-storage.key"))
+    }
+}
+
+fn codex_dir() -> anyhow::Result<PathBuf> {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .map_err(|_| anyhow::anyhow!("ホームディレクトリが見つかりません"))?;
+
+    Ok(PathBuf::from(home).join(".codex"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok(PathBuf::from(home).join(".codex").join("ambient.toml"))
+    #[test]
+    fn profile_overrides_project_and_global_model() {
+        let global = AmbientConfig {
+            provider: Some("openai".to_string()),
+            model: Some("gpt-4o".to_string()),
+            ..Default::default()
+        };
+        let none = AmbientProfile::default();
+        assert_eq!(
+            global.resolve_model(&none, None, None),
+            (Some("openai".to_string()), Some("gpt-4o".to_string()))
+        );
+        assert_eq!(
+            global.resolve_model(&none, Some("anthropic"), None),
+            (Some("anthropic".to_string()), Some("gpt-4o".to_string()))
+        );
+        let profile = AmbientProfile {
+            model: Some("gpt-4o-mini".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            global.resolve_model(&profile, Some("anthropic"), Some("claude")),
+            (
+                Some("anthropic".to_string()),
+                Some("gpt-4o-mini".to_string())
+            )
+        );
+        assert_eq!(
+            AmbientConfig::default().resolve_model(&none, None, None),
+            (None, None)
+        );
     }
 }
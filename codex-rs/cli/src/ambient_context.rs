@@ -0,0 +1,255 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use crate::ambient_project_config::ProjectConfig;
+
+/// 固定できる文書を置くディレクトリ（`.ambient/`からの相対パス）
+pub const CONTEXT_DIR: &str = "context";
+
+/// codexのプロジェクト文書を付けるときの見出し
+pub const PROJECT_DOC_TITLE: &str = "AGENTS.md";
+
+/// トークン数の見積もりに使う1トークンあたりの文字数
+const CHARS_PER_TOKEN: usize = 4;
+
+/// すべてのレビューと質問の前に付ける文書（コーディング規約や用語集など）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PinnedContext {
+    /// ファイル名と内容（`pinned_context`に書いた順）
+    documents: Vec<(String, String)>,
+    /// トークン数の上限を超えて切り詰めた、または省いた文書
+    truncated: Vec<String>,
+}
+
+impl PinnedContext {
+    /// `pinned_context`に一致する`.ambient/context/*.md`を、トークン数の上限まで読み込む
+    pub fn load(project_path: &Path, config: &ProjectConfig) -> Result<Self> {
+        let mut context = Self::default();
+        if config.pinned_context.is_empty() {
+            return Ok(context);
+        }
+        let dir = project_path.join(".ambient").join(CONTEXT_DIR);
+        let mut names = Vec::new();
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("{}を読み込めません", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.is_file()
+                && path.extension().is_some_and(|ext| ext == "md")
+                && let Some(name) = path.file_name().and_then(|name| name.to_str())
+            {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+
+        let mut budget = config.pinned_context_tokens * CHARS_PER_TOKEN;
+        for pattern in &config.pinned_context {
+            let pattern = glob::Pattern::new(pattern)
+                .with_context(|| format!("pinned_contextのパターンが不正です: {pattern}"))?;
+            for name in names.iter().filter(|name| pattern.matches(name)) {
+                if context.documents.iter().any(|(pinned, _)| pinned == name) {
+                    continue;
+                }
+                if budget == 0 {
+                    context.truncated.push(name.clone());
+                    continue;
+                }
+                let path = dir.join(name);
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("{}を読み込めません", path.display()))?;
+                let content = content.trim();
+                let length = content.chars().count();
+                let content: String = content.chars().take(budget).collect();
+                if length > budget {
+                    context.truncated.push(name.clone());
+                }
+                budget -= content.chars().count();
+                context.documents.push((name.clone(), content));
+            }
+        }
+        Ok(context)
+    }
+
+    /// codexのプロジェクト文書（`AGENTS.md`、`project_doc_max_bytes`まで）を先頭に付ける
+    pub fn with_project_doc(mut self, doc: Option<String>) -> Self {
+        if let Some(doc) = doc.as_deref().map(str::trim).filter(|doc| !doc.is_empty()) {
+            self.documents
+                .insert(0, (PROJECT_DOC_TITLE.to_string(), doc.to_string()));
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// 固定した文書のファイル名
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.documents.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// トークン数の上限を超えて切り詰めた、または省いた文書のファイル名
+    pub fn truncated(&self) -> &[String] {
+        &self.truncated
+    }
+
+    /// 固定した文書の文字数の合計（見出しを除く）
+    pub fn chars(&self) -> usize {
+        self.documents
+            .iter()
+            .map(|(_, content)| content.chars().count())
+            .sum()
+    }
+
+    /// 文書の文字数の合計を`max_chars`までにする（`pinned_context`に書いた順に残し、後の文書から切り詰める）
+    pub fn trimmed(&self, max_chars: usize) -> Self {
+        let mut context = Self {
+            documents: Vec::new(),
+            truncated: self.truncated.clone(),
+        };
+        let mut budget = max_chars;
+        for (name, content) in &self.documents {
+            let kept: String = content.chars().take(budget).collect();
+            budget -= kept.chars().count();
+            if kept.len() < content.len() && !context.truncated.contains(name) {
+                context.truncated.push(name.clone());
+            }
+            if !kept.is_empty() {
+                context.documents.push((name.clone(), kept));
+            }
+        }
+        context
+    }
+
+    /// プロンプトの前に固定した文書を付ける
+    pub fn prepend(&self, prompt: &str) -> String {
+        if self.documents.is_empty() {
+            return prompt.to_string();
+        }
+        let mut text = String::from(
+            "以下はこのプロジェクトの規約と前提です。回答はこれに従ってください。\n\n",
+        );
+        for (name, content) in &self.documents {
+            text.push_str(&format!("## {name}\n\n{content}\n\n"));
+        }
+        text.push_str("---\n\n");
+        text.push_str(prompt);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write_context(project: &Path, name: &str, content: &str) {
+        let dir = project.join(".ambient").join(CONTEXT_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(name), content).unwrap();
+    }
+
+    #[test]
+    fn prepends_pinned_documents_in_order() {
+        let dir = tempdir().unwrap();
+        write_context(dir.path(), "coding-standards.md", "unwrapは使わない\n");
+        write_context(dir.path(), "glossary.md", "テナント: 契約単位の顧客");
+        write_context(dir.path(), "draft.md", "未確定のメモ");
+        let config = ProjectConfig {
+            pinned_context: vec!["glossary.md".to_string(), "coding-*.md".to_string()],
+            ..Default::default()
+        };
+
+        let context = PinnedContext::load(dir.path(), &config).unwrap();
+        assert_eq!(
+            context.names().collect::<Vec<_>>(),
+            vec!["glossary.md", "coding-standards.md"]
+        );
+        let prompt = context.prepend("レビューしてください");
+        assert!(prompt.starts_with("以下はこのプロジェクトの規約と前提です。"));
+        assert!(
+            prompt.contains("## glossary.md\n\nテナント: 契約単位の顧客\n\n## coding-standards.md")
+        );
+        assert!(prompt.ends_with("---\n\nレビューしてください"));
+        assert!(!prompt.contains("未確定のメモ"));
+    }
+
+    #[test]
+    fn truncates_to_token_budget() {
+        let dir = tempdir().unwrap();
+        write_context(dir.path(), "a.md", &"あ".repeat(30));
+        write_context(dir.path(), "b.md", "後回し");
+        let config = ProjectConfig {
+            pinned_context: vec!["*.md".to_string()],
+            pinned_context_tokens: 5,
+            ..Default::default()
+        };
+
+        let context = PinnedContext::load(dir.path(), &config).unwrap();
+        assert_eq!(context.names().collect::<Vec<_>>(), vec!["a.md"]);
+        assert_eq!(
+            context.truncated(),
+            ["a.md".to_string(), "b.md".to_string()]
+        );
+        assert!(context.prepend("").contains(&"あ".repeat(20)));
+        assert!(!context.prepend("").contains(&"あ".repeat(21)));
+    }
+
+    #[test]
+    fn trims_to_allocated_chars() {
+        let dir = tempdir().unwrap();
+        write_context(dir.path(), "a.md", "一二三四五");
+        write_context(dir.path(), "b.md", "六七八");
+        let config = ProjectConfig {
+            pinned_context: vec!["*.md".to_string()],
+            ..Default::default()
+        };
+
+        let context = PinnedContext::load(dir.path(), &config).unwrap();
+        assert_eq!(context.chars(), 8);
+        let trimmed = context.trimmed(6);
+        assert_eq!(trimmed.chars(), 6);
+        assert!(trimmed.prepend("").contains("## b.md\n\n六\n\n"));
+        assert_eq!(trimmed.truncated(), ["b.md".to_string()]);
+        assert_eq!(context.trimmed(3).names().collect::<Vec<_>>(), vec!["a.md"]);
+    }
+
+    #[test]
+    fn puts_project_doc_first() {
+        let dir = tempdir().unwrap();
+        write_context(dir.path(), "glossary.md", "テナント: 契約単位の顧客");
+        let config = ProjectConfig {
+            pinned_context: vec!["glossary.md".to_string()],
+            ..Default::default()
+        };
+
+        let context = PinnedContext::load(dir.path(), &config)
+            .unwrap()
+            .with_project_doc(Some("テストは必ず追加する\n".to_string()));
+        assert_eq!(
+            context.names().collect::<Vec<_>>(),
+            vec![PROJECT_DOC_TITLE, "glossary.md"]
+        );
+        assert!(
+            context
+                .prepend("")
+                .contains("## AGENTS.md\n\nテストは必ず追加する\n\n")
+        );
+        assert!(
+            PinnedContext::default()
+                .with_project_doc(Some(" \n".to_string()))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn leaves_prompt_unchanged_without_pins() {
+        let dir = tempdir().unwrap();
+        let context = PinnedContext::load(dir.path(), &ProjectConfig::default()).unwrap();
+        assert!(context.is_empty());
+        assert_eq!(context.prepend("質問"), "質問");
+    }
+}
@@ -0,0 +1,293 @@
+use chrono::DateTime;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::ambient_locale::Locale;
+use crate::ambient_time::now_stamp;
+
+/// ファイルを分析しなかった理由
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// `exclude_patterns`に一致
+    Excluded,
+    /// 拡張子が`file_extensions`にない
+    Extension,
+    /// `max_file_bytes`を超えている
+    TooLarge,
+    /// バイナリファイル
+    Binary,
+    /// 前回分析したときからdiffが変わっていない
+    Unchanged,
+    /// `max_files_per_cycle`に達した
+    BudgetExhausted,
+    /// サブモジュール（`recurse_submodules = false`）
+    Submodule,
+}
+
+impl SkipReason {
+    /// JSONでの表記（`too_large`など）
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SkipReason::Excluded => "excluded",
+            SkipReason::Extension => "extension",
+            SkipReason::TooLarge => "too_large",
+            SkipReason::Binary => "binary",
+            SkipReason::Unchanged => "unchanged",
+            SkipReason::BudgetExhausted => "budget_exhausted",
+            SkipReason::Submodule => "submodule",
+        }
+    }
+
+    /// ログに表示する説明
+    pub fn describe(self) -> &'static str {
+        match self {
+            SkipReason::Excluded => "除外パターンに一致",
+            SkipReason::Extension => "対象外の拡張子",
+            SkipReason::TooLarge => "サイズ上限（max_file_bytes）を超過",
+            SkipReason::Binary => "バイナリファイル",
+            SkipReason::Unchanged => "前回の分析から変更なし",
+            SkipReason::BudgetExhausted => "1回の上限（max_files_per_cycle）に到達",
+            SkipReason::Submodule => "サブモジュール（recurse_submodules = trueで分析）",
+        }
+    }
+
+    /// `locale`の言語での説明
+    pub fn describe_in(self, locale: Locale) -> &'static str {
+        match (locale, self) {
+            (Locale::Ja, _) => self.describe(),
+            (Locale::En, SkipReason::Excluded) => "matches exclude_patterns",
+            (Locale::En, SkipReason::Extension) => "extension not watched",
+            (Locale::En, SkipReason::TooLarge) => "larger than max_file_bytes",
+            (Locale::En, SkipReason::Binary) => "binary file",
+            (Locale::En, SkipReason::Unchanged) => "unchanged since last analysis",
+            (Locale::En, SkipReason::BudgetExhausted) => "max_files_per_cycle reached",
+            (Locale::En, SkipReason::Submodule) => {
+                "submodule (set recurse_submodules = true to analyze)"
+            }
+        }
+    }
+}
+
+/// 分析しなかったファイルとその理由
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SkippedFile {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+/// 1回のチェックサイクルの集計（`GET /api/last-cycle`で取得できる）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct CycleSummary {
+    /// チェックサイクルのID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = Uuid)]
+    pub run_id: Option<Uuid>,
+    /// 開始時刻（RFC3339、UTC）
+    pub started_at: String,
+    /// 終了時刻（RFC3339、UTC）
+    pub finished_at: String,
+    /// 分析したファイル
+    pub analyzed: Vec<String>,
+    /// 分析しなかったファイル
+    pub skipped: Vec<SkippedFile>,
+    /// `max_cycle_duration_secs`を過ぎたため、次のチェックに持ち越したファイル（分析する順）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deferred: Vec<String>,
+    /// 表示用の要約（`language`の書式、UIはこれをそのまま表示する）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+}
+
+impl CycleSummary {
+    /// 現在時刻を開始時刻として集計を始める
+    pub fn start(run_id: Option<Uuid>) -> Self {
+        Self {
+            run_id,
+            started_at: now_stamp().0,
+            ..Self::default()
+        }
+    }
+
+    pub fn analyzed(&mut self, path: &str) {
+        self.analyzed.push(path.to_string());
+    }
+
+    pub fn skip(&mut self, path: &str, reason: SkipReason) {
+        self.skipped.push(SkippedFile {
+            path: path.to_string(),
+            reason,
+        });
+    }
+
+    /// 分析する予定だったファイルを次のチェックに持ち越す
+    pub fn defer(&mut self, path: &str) {
+        self.analyzed.retain(|analyzed| analyzed != path);
+        self.deferred.push(path.to_string());
+    }
+
+    /// 現在時刻を終了時刻として集計を終え、`locale`の書式で要約を付ける
+    pub fn finish(mut self, locale: Locale) -> Self {
+        self.finished_at = now_stamp().0;
+        self.text = self.describe(locale);
+        self
+    }
+
+    /// 開始から終了までの時間（時刻を解析できなければ`None`）
+    pub fn duration(&self) -> Option<Duration> {
+        let started = DateTime::parse_from_rfc3339(&self.started_at).ok()?;
+        let finished = DateTime::parse_from_rfc3339(&self.finished_at).ok()?;
+        (finished - started).to_std().ok()
+    }
+
+    /// 理由ごとの件数
+    pub fn skipped_counts(&self) -> BTreeMap<SkipReason, usize> {
+        let mut counts = BTreeMap::new();
+        for skipped in &self.skipped {
+            *counts.entry(skipped.reason).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// ログに表示する1行の要約
+    pub fn describe(&self, locale: Locale) -> String {
+        let (analyzed, skipped) = (self.analyzed.len(), self.skipped.len());
+        let mut text = match locale {
+            Locale::Ja => format!(
+                "分析 {} / スキップ {}",
+                locale.format_count(analyzed, "件", "file"),
+                locale.format_count(skipped, "件", "file")
+            ),
+            Locale::En => format!(
+                "analyzed {} / skipped {}",
+                locale.format_count(analyzed, "件", "file"),
+                locale.format_count(skipped, "件", "file")
+            ),
+        };
+        let counts = self.skipped_counts();
+        if !counts.is_empty() {
+            let details: Vec<String> = counts
+                .iter()
+                .map(|(reason, count)| format!("{}: {count}", reason.describe_in(locale)))
+                .collect();
+            match locale {
+                Locale::Ja => text.push_str(&format!("（{}）", details.join("、"))),
+                Locale::En => text.push_str(&format!(" ({})", details.join(", "))),
+            }
+        }
+        if !self.deferred.is_empty() {
+            let count = locale.format_count(self.deferred.len(), "件", "file");
+            match locale {
+                Locale::Ja => text.push_str(&format!(" / 持ち越し {count}")),
+                Locale::En => text.push_str(&format!(" / deferred {count}")),
+            }
+        }
+        if let Some(duration) = self.duration() {
+            let label = match locale {
+                Locale::Ja => " / 所要時間 ",
+                Locale::En => " / took ",
+            };
+            text.push_str(label);
+            text.push_str(&locale.format_duration(duration));
+        }
+        text
+    }
+}
+
+/// バイナリのファイルの判定方法（`.ambient/config.toml`の`binary_detection`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryDetection {
+    /// 先頭にNULバイトがあるファイルと、diffがバイナリとして扱われたファイル
+    #[default]
+    Content,
+    /// `content`に加えて、`.gitattributes`で`binary`または`-diff`を指定したファイル（未追跡のファイルも含む）
+    Git,
+}
+
+/// ファイルの先頭にNULバイトがあればバイナリとみなす（gitと同じ判定）
+pub fn looks_binary(content: &[u8]) -> bool {
+    const SNIFF_LEN: usize = 8000;
+    content.iter().take(SNIFF_LEN).any(|&b| b == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_skips_by_reason() {
+        let mut summary = CycleSummary::start(None);
+        summary.analyzed("src/lib.rs");
+        summary.skip("target/out.rs", SkipReason::Excluded);
+        summary.skip("logo.png", SkipReason::Extension);
+        summary.skip("notes.txt", SkipReason::Extension);
+        let mut summary = summary.finish(Locale::Ja);
+
+        assert_eq!(
+            summary.skipped_counts(),
+            BTreeMap::from([(SkipReason::Excluded, 1), (SkipReason::Extension, 2)])
+        );
+        assert!(!summary.finished_at.is_empty());
+        assert!(summary.text.starts_with("分析 1件 / スキップ 3件"));
+
+        summary.started_at = "2026-10-16T00:00:00.000Z".to_string();
+        summary.finished_at = "2026-10-16T00:01:05.000Z".to_string();
+        assert_eq!(
+            summary.describe(Locale::Ja),
+            "分析 1件 / スキップ 3件（除外パターンに一致: 1、対象外の拡張子: 2） / 所要時間 1分5秒"
+        );
+        assert_eq!(
+            summary.describe(Locale::En),
+            "analyzed 1 file / skipped 3 files (matches exclude_patterns: 1, extension not watched: 2) / took 1m 5s"
+        );
+    }
+
+    #[test]
+    fn moves_deferred_files_out_of_analyzed() {
+        let mut summary = CycleSummary::start(None);
+        for path in ["a.rs", "b.rs", "c.rs"] {
+            summary.analyzed(path);
+        }
+        summary.defer("b.rs");
+        summary.defer("c.rs");
+        let summary = summary.finish(Locale::Ja);
+        assert_eq!(summary.analyzed, vec!["a.rs".to_string()]);
+        assert_eq!(
+            summary.deferred,
+            vec!["b.rs".to_string(), "c.rs".to_string()]
+        );
+        assert!(
+            summary
+                .text
+                .starts_with("分析 1件 / スキップ 0件 / 持ち越し 2件")
+        );
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["deferred"][1], "c.rs");
+    }
+
+    #[test]
+    fn serializes_reasons_in_snake_case() {
+        let skipped = SkippedFile {
+            path: "big.json".to_string(),
+            reason: SkipReason::BudgetExhausted,
+        };
+        let json = serde_json::to_value(skipped).unwrap();
+        assert_eq!(json["reason"], "budget_exhausted");
+        assert_eq!(SkipReason::BudgetExhausted.as_str(), "budget_exhausted");
+    }
+
+    #[test]
+    fn detects_binary_content() {
+        assert!(looks_binary(b"\x89PNG\r\n\x1a\n\0\0"));
+        assert!(!looks_binary("fn main() {}".as_bytes()));
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::Context;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_server::Finding;
+
+/// 通知のアプリ名
+const APP_NAME: &str = "Ambient Code Watcher";
+
+/// 1回のチェックでファイルごとに通知する最大数（超えたら1つの通知にまとめる）
+const MAX_FILE_NOTIFICATIONS: usize = 3;
+
+/// 通知の本文の最大文字数
+const MAX_BODY_CHARS: usize = 120;
+
+/// `~/.codex/ambient.toml`の`[notifications]`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct DesktopNotificationsConfig {
+    /// 監視中のチェックで指摘が見つかったら、OSのデスクトップ通知を出す
+    #[serde(default)]
+    pub desktop: bool,
+}
+
+/// デスクトップ通知の見出しと本文
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopNotification {
+    pub summary: String,
+    pub body: String,
+}
+
+impl DesktopNotification {
+    /// OSの通知を出す（LinuxではD-Bus、macOSとWindowsではそれぞれの通知センター）
+    pub fn show(&self) -> Result<()> {
+        notify_rust::Notification::new()
+            .appname(APP_NAME)
+            .summary(&self.summary)
+            .body(&self.body)
+            .show()
+            .map(|_| ())
+            .context("デスクトップ通知を出せません")
+    }
+}
+
+/// チェックで見つかった指摘の通知（ファイルごと、ファイルが多い場合は1つにまとめる）
+///
+/// ファイルごとの通知の本文は、最も重大な指摘の1行目。
+pub fn cycle_notifications(findings: &BTreeMap<String, Vec<Finding>>) -> Vec<DesktopNotification> {
+    let files: Vec<(&String, &Vec<Finding>)> = findings
+        .iter()
+        .filter(|(_, findings)| !findings.is_empty())
+        .collect();
+    if files.len() > MAX_FILE_NOTIFICATIONS {
+        let total: usize = files.iter().map(|(_, findings)| findings.len()).sum();
+        let mut paths: Vec<&str> = files
+            .iter()
+            .take(MAX_FILE_NOTIFICATIONS)
+            .map(|(path, _)| path.as_str())
+            .collect();
+        paths.push("…");
+        return vec![DesktopNotification {
+            summary: format!("{}個のファイルに{total}件の指摘", files.len()),
+            body: paths.join("、"),
+        }];
+    }
+    files
+        .into_iter()
+        .filter_map(|(path, findings)| {
+            // 重大度が同じなら先に届いた指摘
+            let top = findings
+                .iter()
+                .rev()
+                .max_by_key(|finding| finding.severity)?;
+            Some(DesktopNotification {
+                summary: format!("{path}: {}件の指摘", findings.len()),
+                body: summary_line(top),
+            })
+        })
+        .collect()
+}
+
+/// `[high] セキュリティ: 回答の1行目`
+fn summary_line(finding: &Finding) -> String {
+    let first_line = finding
+        .message
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let line = match finding.severity {
+        Some(severity) => format!("[{}] {}: {first_line}", severity.as_str(), finding.review),
+        None => format!("{}: {first_line}", finding.review),
+    };
+    let mut body: String = line.chars().take(MAX_BODY_CHARS).collect();
+    if body.len() < line.len() {
+        body.push('…');
+    }
+    body
+}
+
+/// 指摘として通知するか（指摘の一覧の項目と重大度の付いた回答、人の確認が必要なものは除く）
+fn is_issue(finding: &Finding, itemized: bool) -> bool {
+    !finding.needs_human_review && (itemized || finding.severity.is_some())
+}
+
+/// 通知する指摘をファイルごとに集める
+fn collect(findings: &mut BTreeMap<String, Vec<Finding>>, finding: Finding, itemized: bool) {
+    if is_issue(&finding, itemized) {
+        findings
+            .entry(finding.file_path.clone())
+            .or_default()
+            .push(finding);
+    }
+}
+
+/// `desktop`の場合だけ、デスクトップ通知を始める
+pub fn spawn_desktop_notifier(
+    config: &DesktopNotificationsConfig,
+    tx: &broadcast::Sender<EventEnvelope>,
+) {
+    if config.desktop {
+        tokio::spawn(notify_desktop(tx.subscribe()));
+    }
+}
+
+/// 監視ループのチェックが終わるたびに、見つかった指摘をデスクトップに通知する
+async fn notify_desktop(mut rx: broadcast::Receiver<EventEnvelope>) {
+    let mut findings: BTreeMap<String, Vec<Finding>> = BTreeMap::new();
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: AmbientEvent::Finding(finding),
+                ..
+            }) => collect(&mut findings, finding, false),
+            Ok(EventEnvelope {
+                event: AmbientEvent::Findings(items),
+                ..
+            }) => {
+                for finding in items {
+                    collect(&mut findings, finding, true);
+                }
+            }
+            Ok(EventEnvelope {
+                event: AmbientEvent::CycleSummary(_),
+                ..
+            }) => {
+                let notifications = cycle_notifications(&std::mem::take(&mut findings));
+                if notifications.is_empty() {
+                    continue;
+                }
+                // 通知の呼び出しはブロックするため、監視ループとは別のスレッドで出す
+                tokio::task::spawn_blocking(move || {
+                    for notification in notifications {
+                        if let Err(e) = notification.show() {
+                            tracing::warn!("{e:#}");
+                        }
+                    }
+                });
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_confidence::Severity;
+    use crate::ambient_server::RunIds;
+
+    fn finding(path: &str, review: &str, severity: Option<Severity>, message: &str) -> Finding {
+        Finding {
+            ids: RunIds::default(),
+            file_path: path.to_string(),
+            review: review.to_string(),
+            message: message.to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: None,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn notifies_each_file_with_its_most_severe_finding() {
+        let mut findings = BTreeMap::new();
+        findings.insert(
+            "src/db.rs".to_string(),
+            vec![
+                finding("src/db.rs", "構文", Some(Severity::Low), "未使用の変数"),
+                finding(
+                    "src/db.rs",
+                    "セキュリティ",
+                    Some(Severity::High),
+                    "\nSQLを文字列で組み立てています\n詳細",
+                ),
+            ],
+        );
+        findings.insert(
+            "src/lib.rs".to_string(),
+            vec![finding("src/lib.rs", "構文", None, "括弧が閉じていません")],
+        );
+        assert_eq!(
+            cycle_notifications(&findings),
+            vec![
+                DesktopNotification {
+                    summary: "src/db.rs: 2件の指摘".to_string(),
+                    body: "[high] セキュリティ: SQLを文字列で組み立てています".to_string(),
+                },
+                DesktopNotification {
+                    summary: "src/lib.rs: 1件の指摘".to_string(),
+                    body: "構文: 括弧が閉じていません".to_string(),
+                },
+            ]
+        );
+
+        for path in ["a.rs", "b.rs", "c.rs"] {
+            findings.insert(
+                path.to_string(),
+                vec![finding(path, "構文", Some(Severity::Low), "問題")],
+            );
+        }
+        assert_eq!(
+            cycle_notifications(&findings),
+            vec![DesktopNotification {
+                summary: "5個のファイルに6件の指摘".to_string(),
+                body: "a.rs、b.rs、c.rs、…".to_string(),
+            }]
+        );
+        assert!(cycle_notifications(&BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn notifies_only_findings_that_report_issues() {
+        let mut answer = finding("src/lib.rs", "構文", None, "問題はありません");
+        assert!(!is_issue(&answer, false));
+        assert!(is_issue(&answer, true));
+        answer.severity = Some(Severity::Medium);
+        assert!(is_issue(&answer, false));
+        answer.needs_human_review = true;
+        assert!(!is_issue(&answer, true));
+    }
+}
@@ -0,0 +1,642 @@
+use anyhow::Result;
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::ambient_config::NotifyRoute;
+use crate::ambient_git::run_git_command;
+use crate::ambient_history::DigestRecord;
+use crate::ambient_history::FindingQuery;
+use crate::ambient_history::ReviewHistory;
+use crate::ambient_notify::deliver_digest;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EmitEvent;
+use crate::ambient_server::EventEnvelope;
+
+/// ダイジェストの集計期間（日）
+const PERIOD_DAYS: i64 = 7;
+
+/// 複雑さを比べるファイルの上限（変更の多い週でも時間がかからないように）
+const MAX_COMPLEXITY_FILES: usize = 200;
+
+/// 経過日数を調べるTODOコメントの上限
+const MAX_TODOS: usize = 200;
+
+/// ダイジェストに載せる項目の上限（種類ごと）
+const MAX_LISTED: usize = 5;
+
+/// この日数より古いTODOを「古い」として数える
+const STALE_TODO_DAYS: i64 = 90;
+
+/// 依存関係を宣言するファイル（ロックファイルは差分が大きいので含めない）
+const MANIFESTS: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "requirements.txt",
+    "pyproject.toml",
+    "go.mod",
+    "Gemfile",
+    "composer.json",
+    "pom.xml",
+    "build.gradle",
+];
+
+/// 分岐として数えるキーワード（言語をまたいだおおまかな目安）
+const BRANCH_KEYWORDS: &[&str] = &[
+    "if", "elif", "for", "while", "case", "catch", "except", "when",
+];
+
+/// `.ambient/config.toml`の`[digest]`（週に1回のコード健全性のまとめ）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct DigestConfig {
+    /// 監視中に週次ダイジェストを作成するか
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// 作成したダイジェストをプロファイルの`notify_routes`にも送るか
+    #[serde(default)]
+    pub notify: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            notify: false,
+        }
+    }
+}
+
+/// 期間中のレビュー結果の増減
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FindingTrend {
+    pub opened: usize,
+    pub closed: usize,
+    /// 前の期間に記録されたレビュー結果の数
+    pub previous_opened: usize,
+    /// 前の期間に対応を記録したレビュー結果の数
+    pub previous_closed: usize,
+    /// レビューごとの期間中のレビュー結果の数
+    pub by_review: BTreeMap<String, usize>,
+}
+
+/// ファイルの複雑さの変化
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComplexityChange {
+    pub path: String,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl ComplexityChange {
+    fn delta(&self) -> i64 {
+        self.after as i64 - self.before as i64
+    }
+}
+
+/// 残っているTODOコメント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TodoItem {
+    pub path: String,
+    pub line: usize,
+    pub text: String,
+    /// 書かれてからの日数（コミットされていない行は0）
+    pub age_days: i64,
+}
+
+/// 依存関係ファイルの変更
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependencyChange {
+    pub manifest: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// 期間中のレビュー結果と対応の数
+pub fn finding_trend(history: &ReviewHistory, since: DateTime<Utc>) -> FindingTrend {
+    let previous = since - chrono::Duration::days(PERIOD_DAYS);
+    let in_period = |ts: &str| DateTime::parse_from_rfc3339(ts).is_ok_and(|ts| ts >= since);
+    let mut trend = FindingTrend::default();
+    for finding in history.findings(&FindingQuery {
+        since: Some(previous),
+        ..Default::default()
+    }) {
+        if in_period(&finding.finding.ts) {
+            trend.opened += 1;
+            *trend.by_review.entry(finding.finding.review).or_default() += 1;
+        } else {
+            trend.previous_opened += 1;
+        }
+    }
+    for resolution in history.resolutions(previous) {
+        if in_period(&resolution.ts) {
+            trend.closed += 1;
+        } else {
+            trend.previous_closed += 1;
+        }
+    }
+    trend
+}
+
+/// 分岐の数に1を足した、循環的複雑度のおおまかな目安
+pub fn complexity(source: &str) -> usize {
+    let keywords = source
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| BRANCH_KEYWORDS.contains(word))
+        .count();
+    1 + keywords + source.matches("&&").count() + source.matches("||").count()
+}
+
+/// `since`の時点で最新だったコミット（それより古いコミットがなければ`None`）
+pub fn base_commit(root: &Path, since: DateTime<Utc>) -> Option<String> {
+    let before = format!("--before={}", since.to_rfc3339());
+    let commit = run_git_command(&["rev-list", "-1", &before, "HEAD"], root).ok()?;
+    let commit = commit.trim();
+    (!commit.is_empty()).then(|| commit.to_string())
+}
+
+/// `base`から`HEAD`までに変更されたファイルの複雑さの変化（変化の大きい順）
+pub fn complexity_changes(root: &Path, base: &str) -> Result<Vec<ComplexityChange>> {
+    let range = format!("{base}..HEAD");
+    let files = run_git_command(
+        &[
+            "-c",
+            "core.quotePath=false",
+            "diff",
+            "--name-only",
+            "--diff-filter=AMD",
+            &range,
+        ],
+        root,
+    )?;
+    let mut changes: Vec<ComplexityChange> = files
+        .lines()
+        .filter(|path| !path.is_empty())
+        .take(MAX_COMPLEXITY_FILES)
+        .filter_map(|path| {
+            let measure = |rev: &str| {
+                run_git_command(&["show", &format!("{rev}:{path}")], root)
+                    .ok()
+                    .filter(|content| !content.contains('\0'))
+                    .map_or(0, |content| complexity(&content))
+            };
+            let change = ComplexityChange {
+                path: path.to_string(),
+                before: measure(base),
+                after: measure("HEAD"),
+            };
+            (change.delta() != 0).then_some(change)
+        })
+        .collect();
+    changes.sort_by(|a, b| {
+        b.delta()
+            .abs()
+            .cmp(&a.delta().abs())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    Ok(changes)
+}
+
+/// 追跡しているファイルのTODO・FIXMEコメントと、その行が書かれてからの日数（古い順）
+pub fn todo_ages(root: &Path, now: DateTime<Utc>) -> Result<Vec<TodoItem>> {
+    // 一致しなければ終了コード1になるので、エラーは「TODOなし」とみなす
+    let Ok(output) = run_git_command(
+        &[
+            "-c",
+            "core.quotePath=false",
+            "grep",
+            "-n",
+            "-I",
+            "-w",
+            "-e",
+            "TODO",
+            "-e",
+            "FIXME",
+        ],
+        root,
+    ) else {
+        return Ok(vec![]);
+    };
+    let mut by_file: BTreeMap<&str, Vec<(usize, &str)>> = BTreeMap::new();
+    for entry in output.lines().take(MAX_TODOS) {
+        let mut parts = entry.splitn(3, ':');
+        let (Some(path), Some(Ok(line)), Some(text)) =
+            (parts.next(), parts.next().map(str::parse), parts.next())
+        else {
+            continue;
+        };
+        by_file.entry(path).or_default().push((line, text.trim()));
+    }
+
+    let mut todos = Vec::new();
+    for (path, lines) in by_file {
+        let mut args = vec!["blame".to_string(), "--line-porcelain".to_string()];
+        for (line, _) in &lines {
+            args.push(format!("-L{line},{line}"));
+        }
+        args.extend(["--".to_string(), path.to_string()]);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let times = run_git_command(&args, root)
+            .map(|blame| author_times(&blame))
+            .unwrap_or_default();
+        for (line, text) in lines {
+            let age_days = times
+                .get(&line)
+                .and_then(|secs| DateTime::from_timestamp(*secs, 0))
+                .map_or(0, |written| (now - written).num_days().max(0));
+            todos.push(TodoItem {
+                path: path.to_string(),
+                line,
+                text: text.to_string(),
+                age_days,
+            });
+        }
+    }
+    todos.sort_by(|a, b| b.age_days.cmp(&a.age_days));
+    Ok(todos)
+}
+
+/// `git blame --line-porcelain`の出力から、行番号ごとの作成時刻（UNIX時間）を読む
+fn author_times(blame: &str) -> BTreeMap<usize, i64> {
+    let mut times = BTreeMap::new();
+    let mut current = None;
+    for line in blame.lines() {
+        if let Some(time) = line.strip_prefix("author-time ") {
+            if let (Some(line), Ok(time)) = (current, time.trim().parse()) {
+                times.insert(line, time);
+            }
+        } else if !line.starts_with('\t') {
+            // ヘッダーの最初の行は「コミットID 元の行番号 現在の行番号 [行数]」
+            let mut words = line.split_whitespace();
+            if let (Some(id), Some(_), Some(Ok(number))) =
+                (words.next(), words.next(), words.next().map(str::parse))
+                && id.len() >= 40
+                && id.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                current = Some(number);
+            }
+        }
+    }
+    times
+}
+
+/// `base`から`HEAD`までの依存関係ファイルの追加・削除行
+pub fn dependency_changes(root: &Path, base: &str) -> Result<Vec<DependencyChange>> {
+    let range = format!("{base}..HEAD");
+    let mut args = vec![
+        "-c".to_string(),
+        "core.quotePath=false".to_string(),
+        "diff".to_string(),
+        "--unified=0".to_string(),
+        range,
+        "--".to_string(),
+    ];
+    args.extend(MANIFESTS.iter().map(|name| format!(":(glob)**/{name}")));
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let diff = run_git_command(&args, root)?;
+
+    let mut changes: Vec<DependencyChange> = Vec::new();
+    for line in diff.lines() {
+        if let Some(path) = line.strip_prefix("diff --git ") {
+            let manifest = path.rsplit(" b/").next().unwrap_or(path);
+            changes.push(DependencyChange {
+                manifest: manifest.to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+        if line.starts_with("+++") || line.starts_with("---") {
+            continue;
+        }
+        let Some(change) = changes.last_mut() else {
+            continue;
+        };
+        if let Some(added) = line.strip_prefix('+').map(str::trim)
+            && !added.is_empty()
+        {
+            change.added.push(added.to_string());
+        } else if let Some(removed) = line.strip_prefix('-').map(str::trim)
+            && !removed.is_empty()
+        {
+            change.removed.push(removed.to_string());
+        }
+    }
+    changes.retain(|change| !change.added.is_empty() || !change.removed.is_empty());
+    Ok(changes)
+}
+
+/// 1週間分の集計からダイジェストを作る（モデルは使わない）
+pub fn build_digest(root: &Path, history: &ReviewHistory, now: DateTime<Utc>) -> DigestRecord {
+    let since = now - chrono::Duration::days(PERIOD_DAYS);
+    let trend = finding_trend(history, since);
+    let base = base_commit(root, since);
+    let complexity = base
+        .as_deref()
+        .and_then(|base| complexity_changes(root, base).ok())
+        .unwrap_or_default();
+    let dependencies = base
+        .as_deref()
+        .and_then(|base| dependency_changes(root, base).ok())
+        .unwrap_or_default();
+    let todos = todo_ages(root, now).unwrap_or_default();
+    DigestRecord {
+        ts: now.to_rfc3339_opts(SecondsFormat::Millis, true),
+        since: since.to_rfc3339_opts(SecondsFormat::Millis, true),
+        opened: trend.opened,
+        closed: trend.closed,
+        text: render_digest(&trend, &complexity, &todos, &dependencies, base.is_some()),
+    }
+}
+
+/// ダイジェストの本文（Markdown）
+pub fn render_digest(
+    trend: &FindingTrend,
+    complexity: &[ComplexityChange],
+    todos: &[TodoItem],
+    dependencies: &[DependencyChange],
+    has_base: bool,
+) -> String {
+    let mut out = String::from("## 週次コード健全性ダイジェスト\n\n### レビュー結果\n\n");
+    out.push_str(&format!(
+        "- 新しい指摘: {}件（前週 {}件）\n- 対応済み: {}件（前週 {}件）\n",
+        trend.opened, trend.previous_opened, trend.closed, trend.previous_closed
+    ));
+    let mut reviews: Vec<(&String, &usize)> = trend.by_review.iter().collect();
+    reviews.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    for (review, count) in reviews.into_iter().take(MAX_LISTED) {
+        out.push_str(&format!("  - {review}: {count}件\n"));
+    }
+
+    out.push_str("\n### 複雑さの変化\n\n");
+    if !has_base {
+        out.push_str("比較できる1週間前のコミットがありません\n");
+    } else if complexity.is_empty() {
+        out.push_str("大きな変化はありません\n");
+    } else {
+        let total: i64 = complexity.iter().map(ComplexityChange::delta).sum();
+        out.push_str(&format!(
+            "{}ファイルで分岐の数が変わりました（合計 {total:+}）\n",
+            complexity.len()
+        ));
+        for change in complexity.iter().take(MAX_LISTED) {
+            out.push_str(&format!(
+                "- `{}`: {} → {}（{:+}）\n",
+                change.path,
+                change.before,
+                change.after,
+                change.delta()
+            ));
+        }
+    }
+
+    out.push_str("\n### TODOの経過日数\n\n");
+    if todos.is_empty() {
+        out.push_str("TODO・FIXMEコメントはありません\n");
+    } else {
+        let stale = todos
+            .iter()
+            .filter(|todo| todo.age_days >= STALE_TODO_DAYS)
+            .count();
+        out.push_str(&format!(
+            "{}件のうち{stale}件が{STALE_TODO_DAYS}日以上残っています。古いもの:\n",
+            todos.len()
+        ));
+        for todo in todos.iter().take(MAX_LISTED) {
+            out.push_str(&format!(
+                "- `{}:{}` {}日前: {}\n",
+                todo.path, todo.line, todo.age_days, todo.text
+            ));
+        }
+    }
+
+    out.push_str("\n### 依存関係の変更\n\n");
+    if !has_base {
+        out.push_str("比較できる1週間前のコミットがありません\n");
+    } else if dependencies.is_empty() {
+        out.push_str("変更はありません\n");
+    } else {
+        for change in dependencies {
+            out.push_str(&format!("- `{}`\n", change.manifest));
+            for line in change.added.iter().take(MAX_LISTED) {
+                out.push_str(&format!("  - 追加: `{line}`\n"));
+            }
+            for line in change.removed.iter().take(MAX_LISTED) {
+                out.push_str(&format!("  - 削除: `{line}`\n"));
+            }
+        }
+    }
+    out
+}
+
+/// 前回のダイジェストから1週間たっていれば作成するか
+pub fn digest_due(history: &ReviewHistory, now: DateTime<Utc>) -> bool {
+    history.digests().last().is_none_or(|last| {
+        DateTime::parse_from_rfc3339(&last.ts)
+            .ok()
+            .is_none_or(|ts| now - ts.with_timezone(&Utc) >= chrono::Duration::days(PERIOD_DAYS))
+    })
+}
+
+/// `enabled`の場合だけ、週ごとのダイジェストの作成を始める
+pub fn spawn_weekly_digest(
+    root: PathBuf,
+    history: Arc<ReviewHistory>,
+    config: &DigestConfig,
+    routes: &[NotifyRoute],
+    tx: &broadcast::Sender<EventEnvelope>,
+) {
+    if config.enabled {
+        tokio::spawn(run_weekly_digest(
+            root,
+            history,
+            config.clone(),
+            routes.to_vec(),
+            tx.clone(),
+        ));
+    }
+}
+
+/// 1時間ごとに確認し、前回から1週間たっていればダイジェストを作成して履歴に記録する
+///
+/// `notify`が有効なら、作成したダイジェストを`routes`のすべての通知先にも送る。
+async fn run_weekly_digest(
+    root: PathBuf,
+    history: Arc<ReviewHistory>,
+    config: DigestConfig,
+    routes: Vec<NotifyRoute>,
+    tx: broadcast::Sender<EventEnvelope>,
+) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(3600));
+    loop {
+        ticker.tick().await;
+        if !digest_due(&history, Utc::now()) {
+            continue;
+        }
+        let (root, blocking_history) = (root.clone(), history.clone());
+        let Ok(digest) =
+            tokio::task::spawn_blocking(move || build_digest(&root, &blocking_history, Utc::now()))
+                .await
+        else {
+            continue;
+        };
+        history.record_digest(digest.clone());
+        if config.notify {
+            deliver_digest(&routes, &digest);
+        }
+        let _ = tx.emit(AmbientEvent::System(format!(
+            "週次ダイジェストを作成しました（新しい指摘 {}件、対応済み {}件）。`ambient digest`で表示できます",
+            digest.opened, digest.closed
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_confidence::Severity;
+    use crate::ambient_history::FindingRecord;
+    use crate::ambient_history::Resolution;
+    use crate::ambient_history::ResolutionStatus;
+    use crate::ambient_test_support::TempRepo;
+    use uuid::Uuid;
+
+    fn days_ago(now: DateTime<Utc>, days: i64) -> String {
+        (now - chrono::Duration::days(days)).to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    #[test]
+    fn counts_findings_opened_and_closed_per_week() {
+        let now = Utc::now();
+        let history = ReviewHistory::default();
+        for (days, review) in [(1, "セキュリティ"), (2, "構文"), (3, "構文"), (10, "構文")]
+        {
+            history.record_finding(
+                "src/lib.rs",
+                FindingRecord {
+                    ts: days_ago(now, days),
+                    run_id: None,
+                    job_id: Some(Uuid::new_v4()),
+                    review: review.to_string(),
+                    message: String::new(),
+                    severity: Some(Severity::Medium),
+                    confidence: None,
+                    model: None,
+                    provider: None,
+                    prompt_hash: None,
+                    engine_version: None,
+                    line: None,
+                },
+            );
+        }
+        let mut resolution = Resolution::new(
+            Uuid::new_v4(),
+            "構文".to_string(),
+            ResolutionStatus::Fixed,
+            None,
+            None,
+        );
+        resolution.ts = days_ago(now, 9);
+        history.record_resolution("src/lib.rs", resolution);
+
+        let trend = finding_trend(&history, now - chrono::Duration::days(7));
+        assert_eq!((trend.opened, trend.previous_opened), (3, 1));
+        assert_eq!((trend.closed, trend.previous_closed), (0, 1));
+        assert_eq!(trend.by_review["構文"], 2);
+
+        assert!(digest_due(&history, now));
+        history.record_digest(DigestRecord {
+            ts: days_ago(now, 2),
+            since: days_ago(now, 9),
+            opened: 3,
+            closed: 0,
+            text: String::new(),
+        });
+        assert!(!digest_due(&history, now));
+        assert!(digest_due(&history, now + chrono::Duration::days(5)));
+    }
+
+    #[test]
+    fn measures_branches() {
+        assert_eq!(complexity("fn main() {}"), 1);
+        assert_eq!(
+            complexity("if a && b { for x in y {} } else if c || d {}"),
+            6
+        );
+        // 識別子の一部は数えない
+        assert_eq!(complexity("let iffy = format_for();"), 1);
+    }
+
+    #[test]
+    fn compares_complexity_todos_and_dependencies_with_base() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/lib.rs", "fn a() {}\n").unwrap();
+        repo.write("Cargo.toml", "[dependencies]\nserde = \"1\"\n")
+            .unwrap();
+        repo.add(&["src/lib.rs", "Cargo.toml"]).unwrap();
+        repo.commit("initial").unwrap();
+        let base = run_git_command(&["rev-parse", "HEAD"], repo.path())
+            .unwrap()
+            .trim()
+            .to_string();
+
+        repo.write(
+            "src/lib.rs",
+            "fn a(x: bool) {\n    // TODO: handle errors\n    if x {}\n}\n",
+        )
+        .unwrap();
+        repo.write(
+            "Cargo.toml",
+            "[dependencies]\nserde = \"1\"\ntokio = \"1\"\n",
+        )
+        .unwrap();
+        repo.add(&["src/lib.rs", "Cargo.toml"]).unwrap();
+        repo.commit("grow").unwrap();
+
+        assert_eq!(
+            complexity_changes(repo.path(), &base).unwrap(),
+            vec![ComplexityChange {
+                path: "src/lib.rs".to_string(),
+                before: 1,
+                after: 2,
+            }]
+        );
+        assert_eq!(
+            dependency_changes(repo.path(), &base).unwrap(),
+            vec![DependencyChange {
+                manifest: "Cargo.toml".to_string(),
+                added: vec!["tokio = \"1\"".to_string()],
+                removed: vec![],
+            }]
+        );
+
+        let later = Utc::now() + chrono::Duration::days(30);
+        let todos = todo_ages(repo.path(), later).unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!((todos[0].path.as_str(), todos[0].line), ("src/lib.rs", 2));
+        assert!((29..=30).contains(&todos[0].age_days));
+
+        let text = render_digest(
+            &FindingTrend::default(),
+            &complexity_changes(repo.path(), &base).unwrap(),
+            &todos,
+            &dependency_changes(repo.path(), &base).unwrap(),
+            true,
+        );
+        assert!(text.contains("- `src/lib.rs`: 1 → 2（+1）"));
+        assert!(text.contains("  - 追加: `tokio = \"1\"`"));
+        assert!(text.contains("`src/lib.rs:2` "));
+    }
+}
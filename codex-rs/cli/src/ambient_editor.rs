@@ -0,0 +1,566 @@
+use anyhow::Result;
+use anyhow::bail;
+use axum::Extension;
+use axum::extract::State;
+use axum::extract::ws::Message;
+use axum::extract::ws::WebSocket;
+use axum::extract::ws::WebSocketUpgrade;
+use axum::response::Response;
+use futures::stream::StreamExt;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use url::Url;
+use uuid::Uuid;
+
+use crate::ambient_annotations::Annotation;
+use crate::ambient_annotations::AnnotationsFile;
+use crate::ambient_limits::ConnectionPermit;
+use crate::ambient_limits::send_with_timeout;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::AppState;
+use crate::ambient_server::Finding;
+use crate::ambient_version::VERSION;
+
+/// エディタ拡張向けのWebSocketのエンドポイント
+pub const EDITOR_ENDPOINT: &str = "/api/editor";
+
+/// エディタ向けのプロトコルのバージョン（`hello`で一致しなければ切断する）
+pub const EDITOR_PROTOCOL_VERSION: u32 = 1;
+
+/// 指摘を文書ごとに送る
+pub const CAPABILITY_FINDINGS: &str = "findings";
+/// 確認済みにした指摘を保存時に消す
+pub const CAPABILITY_CLEAR_ON_SAVE: &str = "clear_on_save";
+/// 指摘にモデルが提案した修正を含める
+pub const CAPABILITY_SUGGESTIONS: &str = "suggestions";
+
+/// サーバーが対応している機能
+const SERVER_CAPABILITIES: [&str; 3] = [
+    CAPABILITY_FINDINGS,
+    CAPABILITY_CLEAR_ON_SAVE,
+    CAPABILITY_SUGGESTIONS,
+];
+
+/// エディタ拡張から受け取るメッセージ（`type`で種類を区別する）
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// 接続直後に1度だけ送る
+    Hello {
+        protocol: u32,
+        /// 拡張の名前（ログ用）
+        client: String,
+        /// 拡張が扱える機能
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
+    /// 文書を開いた（消していない指摘を送り直す）
+    Open { uri: String, version: i64 },
+    /// 文書を編集した（以降の指摘にこの版を付ける）
+    Changed { uri: String, version: i64 },
+    /// 文書を閉じた（指摘は保存時まで残す）
+    Close { uri: String },
+    /// 指摘を確認済みにした（次の保存で消す）
+    Ack { uri: String, ids: Vec<Uuid> },
+    /// 文書を保存した
+    Saved { uri: String, version: i64 },
+}
+
+/// エディタ拡張に送るメッセージ
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// `hello`への応答（`capabilities`は双方が対応している機能）
+    Welcome {
+        protocol: u32,
+        server_version: String,
+        capabilities: Vec<String>,
+        root_uri: String,
+    },
+    /// 新しい指摘（送っていない分だけ）
+    Findings {
+        uri: String,
+        /// 指摘が届いたときに開いていた文書の版
+        #[serde(skip_serializing_if = "Option::is_none")]
+        version: Option<i64>,
+        findings: Vec<EditorFinding>,
+    },
+    /// 消した指摘（保存時、または同じファイルを再分析したとき）
+    Cleared {
+        uri: String,
+        ids: Vec<Uuid>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// エディタに表示する指摘
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EditorFinding {
+    /// この接続の中での指摘のID（`ack`で使う）
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub annotation: Annotation,
+}
+
+/// 開いている文書と、送った指摘
+#[derive(Debug, Default)]
+struct DocumentState {
+    version: Option<i64>,
+    /// 送った指摘と、そのチェックサイクルのID
+    findings: Vec<(Option<Uuid>, EditorFinding)>,
+    acked: HashSet<Uuid>,
+}
+
+/// 1つのエディタ接続の状態（WebSocketとは切り離して扱う）
+#[derive(Debug)]
+pub struct EditorSession {
+    root: PathBuf,
+    capabilities: BTreeSet<String>,
+    /// 正規化したURIごとの文書
+    documents: BTreeMap<String, DocumentState>,
+}
+
+impl EditorSession {
+    /// 最初のメッセージが`hello`であることを確かめ、機能を取り決める
+    pub fn handshake(root: &Path, message: ClientMessage) -> Result<(Self, ServerMessage)> {
+        let ClientMessage::Hello {
+            protocol,
+            capabilities,
+            ..
+        } = message
+        else {
+            bail!("最初のメッセージはhelloにしてください");
+        };
+        if protocol != EDITOR_PROTOCOL_VERSION {
+            bail!(
+                "プロトコルのバージョン{protocol}には対応していません（対応: {EDITOR_PROTOCOL_VERSION}）"
+            );
+        }
+        let capabilities: BTreeSet<String> = capabilities
+            .into_iter()
+            .filter(|capability| SERVER_CAPABILITIES.contains(&capability.as_str()))
+            .collect();
+        let welcome = ServerMessage::Welcome {
+            protocol: EDITOR_PROTOCOL_VERSION,
+            server_version: VERSION.to_string(),
+            capabilities: capabilities.iter().cloned().collect(),
+            root_uri: file_uri(root),
+        };
+        let session = Self {
+            root: root.to_path_buf(),
+            capabilities,
+            documents: BTreeMap::new(),
+        };
+        Ok((session, welcome))
+    }
+
+    fn supports(&self, capability: &str) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    /// 接続前に書き出された注釈ファイルの指摘
+    pub fn seed(&mut self, file: AnnotationsFile) -> Vec<ServerMessage> {
+        let run_id = file.run_id.as_deref().and_then(|id| id.parse().ok());
+        file.files
+            .into_iter()
+            .filter_map(|file| {
+                let annotations = file
+                    .hunks
+                    .into_iter()
+                    .flat_map(|hunk| hunk.annotations)
+                    .chain(file.annotations)
+                    .collect();
+                self.deliver(&file.path, run_id, annotations)
+            })
+            .collect()
+    }
+
+    /// 監視ループのイベントのうち、エディタに伝えるもの
+    pub fn on_event(&mut self, event: AmbientEvent) -> Vec<ServerMessage> {
+        match event {
+            AmbientEvent::Finding(finding) => self.deliver_findings(vec![finding]),
+            AmbientEvent::Findings(findings) => self.deliver_findings(findings),
+            AmbientEvent::CycleSummary(summary) => summary
+                .analyzed
+                .iter()
+                .filter_map(|path| self.replace_stale(path, summary.run_id))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// エディタ拡張からのメッセージを処理する
+    pub fn handle(&mut self, message: ClientMessage) -> Vec<ServerMessage> {
+        match message {
+            ClientMessage::Hello { .. } => vec![ServerMessage::Error {
+                message: "helloは接続直後に1度だけ送ってください".to_string(),
+            }],
+            ClientMessage::Open { uri, version } => {
+                let uri = normalize_uri(&uri);
+                let document = self.documents.entry(uri.clone()).or_default();
+                document.version = Some(version);
+                // 開き直した文書には、消していない指摘をまとめて送り直す
+                let findings: Vec<EditorFinding> = document
+                    .findings
+                    .iter()
+                    .map(|(_, finding)| finding.clone())
+                    .collect();
+                if findings.is_empty() {
+                    return Vec::new();
+                }
+                vec![ServerMessage::Findings {
+                    uri,
+                    version: Some(version),
+                    findings,
+                }]
+            }
+            ClientMessage::Changed { uri, version } => {
+                if let Some(document) = self.documents.get_mut(&normalize_uri(&uri)) {
+                    document.version = Some(version);
+                }
+                Vec::new()
+            }
+            ClientMessage::Close { uri } => {
+                if let Some(document) = self.documents.get_mut(&normalize_uri(&uri)) {
+                    document.version = None;
+                }
+                Vec::new()
+            }
+            ClientMessage::Ack { uri, ids } => {
+                if let Some(document) = self.documents.get_mut(&normalize_uri(&uri)) {
+                    let known: HashSet<Uuid> =
+                        document.findings.iter().map(|(_, f)| f.id).collect();
+                    document
+                        .acked
+                        .extend(ids.into_iter().filter(|id| known.contains(id)));
+                }
+                Vec::new()
+            }
+            ClientMessage::Saved { uri, version } => {
+                let uri = normalize_uri(&uri);
+                let clear = self.supports(CAPABILITY_CLEAR_ON_SAVE);
+                let Some(document) = self.documents.get_mut(&uri) else {
+                    return Vec::new();
+                };
+                document.version = Some(version);
+                if !clear || document.acked.is_empty() {
+                    return Vec::new();
+                }
+                let acked = std::mem::take(&mut document.acked);
+                document
+                    .findings
+                    .retain(|(_, finding)| !acked.contains(&finding.id));
+                let mut ids: Vec<Uuid> = acked.into_iter().collect();
+                ids.sort();
+                vec![ServerMessage::Cleared { uri, ids }]
+            }
+        }
+    }
+
+    fn deliver_findings(&mut self, findings: Vec<Finding>) -> Vec<ServerMessage> {
+        let mut by_path: BTreeMap<String, (Option<Uuid>, Vec<Annotation>)> = BTreeMap::new();
+        for finding in &findings {
+            let entry = by_path
+                .entry(finding.file_path.clone())
+                .or_insert((finding.ids.run_id, Vec::new()));
+            entry.1.push(Annotation::from_finding(finding, 1));
+        }
+        by_path
+            .into_iter()
+            .filter_map(|(path, (run_id, annotations))| self.deliver(&path, run_id, annotations))
+            .collect()
+    }
+
+    /// ファイルの指摘にIDを振って記録し、送るメッセージを作る
+    fn deliver(
+        &mut self,
+        path: &str,
+        run_id: Option<Uuid>,
+        annotations: Vec<Annotation>,
+    ) -> Option<ServerMessage> {
+        if annotations.is_empty() || !self.supports(CAPABILITY_FINDINGS) {
+            return None;
+        }
+        let suggestions = self.supports(CAPABILITY_SUGGESTIONS);
+        let uri = file_uri(&self.root.join(path));
+        let document = self.documents.entry(uri.clone()).or_default();
+        let findings: Vec<EditorFinding> = annotations
+            .into_iter()
+            .map(|mut annotation| {
+                if !suggestions {
+                    annotation.suggestion = None;
+                }
+                EditorFinding {
+                    id: Uuid::new_v4(),
+                    annotation,
+                }
+            })
+            .collect();
+        document
+            .findings
+            .extend(findings.iter().map(|finding| (run_id, finding.clone())));
+        Some(ServerMessage::Findings {
+            uri,
+            version: document.version,
+            findings,
+        })
+    }
+
+    /// 再分析したファイルの、以前のサイクルの指摘を消す
+    fn replace_stale(&mut self, path: &str, run_id: Option<Uuid>) -> Option<ServerMessage> {
+        let uri = file_uri(&self.root.join(path));
+        let document = self.documents.get_mut(&uri)?;
+        let mut ids = Vec::new();
+        document.findings.retain(|(finding_run, finding)| {
+            let stale = *finding_run != run_id;
+            if stale {
+                ids.push(finding.id);
+            }
+            !stale
+        });
+        if ids.is_empty() {
+            return None;
+        }
+        for id in &ids {
+            document.acked.remove(id);
+        }
+        Some(ServerMessage::Cleared { uri, ids })
+    }
+}
+
+/// パスの`file://`のURI
+fn file_uri(path: &Path) -> String {
+    Url::from_file_path(path)
+        .map(String::from)
+        .unwrap_or_else(|_| format!("file://{}", path.display()))
+}
+
+/// エディタから届いたURIを、サーバーが作るURIと同じ形にそろえる
+fn normalize_uri(uri: &str) -> String {
+    Url::parse(uri)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .map(|path| file_uri(&path))
+        .unwrap_or_else(|| uri.to_string())
+}
+
+pub(crate) async fn editor_websocket_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+    Extension(permit): Extension<ConnectionPermit>,
+) -> Response {
+    ws.max_frame_size(state.limits.max_ws_frame_bytes)
+        .max_message_size(state.limits.max_ws_message_bytes)
+        .on_upgrade(move |socket| async move {
+            let _permit = permit;
+            editor_websocket(socket, state).await;
+        })
+}
+
+async fn editor_websocket(socket: WebSocket, state: Arc<AppState>) {
+    let (mut sender, mut receiver) = socket.split();
+    // ハンドシェイクの間に届いた指摘も取りこぼさないように、先に購読する
+    let mut rx = state.tx.subscribe();
+    let root = PathBuf::from(&state.project_root);
+    let write_timeout = state.limits.write_timeout();
+
+    let Some(Ok(Message::Text(text))) = receiver.next().await else {
+        return;
+    };
+    let handshake = serde_json::from_str::<ClientMessage>(&text)
+        .map_err(anyhow::Error::from)
+        .and_then(|message| EditorSession::handshake(&root, message));
+    let (mut session, welcome) = match handshake {
+        Ok(handshake) => handshake,
+        Err(e) => {
+            let error = ServerMessage::Error {
+                message: e.to_string(),
+            };
+            send_with_timeout(&mut sender, encode(&error), write_timeout).await;
+            return;
+        }
+    };
+    let mut replies = vec![welcome];
+    if let Some(file) = AnnotationsFile::read(&root) {
+        replies.extend(session.seed(file));
+    }
+
+    loop {
+        for reply in replies.drain(..) {
+            if !send_with_timeout(&mut sender, encode(&reply), write_timeout).await {
+                return; // Client disconnected or too slow.
+            }
+        }
+        tokio::select! {
+            message = receiver.next() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(message) => replies = session.handle(message),
+                    Err(e) => replies.push(ServerMessage::Error {
+                        message: format!("メッセージを解釈できません: {e}"),
+                    }),
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            event = rx.recv() => match event {
+                Ok(envelope) => replies = session.on_event(envelope.event),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+fn encode(message: &ServerMessage) -> Message {
+    Message::Text(serde_json::to_string(message).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_cycle::CycleSummary;
+    use crate::ambient_server::RunIds;
+
+    fn hello(capabilities: &[&str]) -> ClientMessage {
+        ClientMessage::Hello {
+            protocol: EDITOR_PROTOCOL_VERSION,
+            client: "vscode".to_string(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    fn finding(ids: RunIds, line: u32) -> Finding {
+        Finding {
+            ids,
+            file_path: "src/lib.rs".to_string(),
+            review: "構文".to_string(),
+            message: "括弧が閉じていません".to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity: None,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: Some(line),
+            suggestion: Some("}".to_string()),
+            cross_references: vec![],
+        }
+    }
+
+    fn finding_ids(message: &ServerMessage) -> Vec<Uuid> {
+        match message {
+            ServerMessage::Findings { findings, .. } => findings.iter().map(|f| f.id).collect(),
+            other => panic!("findingsではありません: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn negotiates_capabilities_in_the_handshake() {
+        let root = Path::new("/repo");
+        assert!(
+            EditorSession::handshake(
+                root,
+                ClientMessage::Open {
+                    uri: "file:///repo/a.rs".to_string(),
+                    version: 1,
+                },
+            )
+            .is_err()
+        );
+        let old = ClientMessage::Hello {
+            protocol: EDITOR_PROTOCOL_VERSION + 1,
+            client: "vscode".to_string(),
+            capabilities: Vec::new(),
+        };
+        assert!(EditorSession::handshake(root, old).is_err());
+
+        let (mut session, welcome) =
+            EditorSession::handshake(root, hello(&["findings", "hover"])).unwrap();
+        let json = serde_json::to_value(&welcome).unwrap();
+        assert_eq!(json["type"], "welcome");
+        assert_eq!(json["capabilities"], serde_json::json!(["findings"]));
+        assert_eq!(json["root_uri"], "file:///repo");
+
+        // suggestionsを取り決めていなければ修正案を送らない
+        let messages = session.on_event(AmbientEvent::Finding(finding(RunIds::new_run(), 3)));
+        let json = serde_json::to_value(&messages[0]).unwrap();
+        assert_eq!(json["uri"], "file:///repo/src/lib.rs");
+        assert_eq!(json["findings"][0]["line"], 3);
+        assert!(json["findings"][0].get("suggestion").is_none());
+    }
+
+    #[test]
+    fn clears_acked_findings_on_save_and_stale_findings_on_reanalysis() {
+        let root = Path::new("/repo");
+        let uri = "file:///repo/src/lib.rs".to_string();
+        let (mut session, _) =
+            EditorSession::handshake(root, hello(&["findings", "clear_on_save", "suggestions"]))
+                .unwrap();
+        assert!(
+            session
+                .handle(ClientMessage::Open {
+                    uri: uri.clone(),
+                    version: 4,
+                })
+                .is_empty()
+        );
+
+        let first = RunIds::new_run();
+        let messages = session.on_event(AmbientEvent::Findings(vec![
+            finding(first.new_job(), 3),
+            finding(first.new_job(), 8),
+        ]));
+        assert_eq!(messages.len(), 1);
+        let ServerMessage::Findings { version, .. } = &messages[0] else {
+            panic!("findingsではありません");
+        };
+        assert_eq!(*version, Some(4));
+        let ids = finding_ids(&messages[0]);
+
+        // 確認済みにした指摘だけを保存時に消す（未知のIDは無視する）
+        session.handle(ClientMessage::Ack {
+            uri: uri.clone(),
+            ids: vec![ids[0], Uuid::new_v4()],
+        });
+        assert_eq!(
+            session.handle(ClientMessage::Saved {
+                uri: uri.clone(),
+                version: 5,
+            }),
+            vec![ServerMessage::Cleared {
+                uri: uri.clone(),
+                ids: vec![ids[0]],
+            }]
+        );
+        let reopened = session.handle(ClientMessage::Open {
+            uri: uri.clone(),
+            version: 6,
+        });
+        assert_eq!(finding_ids(&reopened[0]), vec![ids[1]]);
+
+        // 次のサイクルで再分析したら、前のサイクルの指摘を消す
+        let second = RunIds::new_run();
+        session.on_event(AmbientEvent::Finding(finding(second.new_job(), 9)));
+        let mut summary = CycleSummary::start(second.run_id);
+        summary.analyzed("src/lib.rs");
+        assert_eq!(
+            session.on_event(AmbientEvent::CycleSummary(summary)),
+            vec![ServerMessage::Cleared {
+                uri,
+                ids: vec![ids[1]],
+            }]
+        );
+    }
+}
@@ -0,0 +1,424 @@
+use std::future::Future;
+
+use anyhow::Context;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+
+/// 埋め込みを作るサービスの種類
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingKind {
+    /// Ollamaの`/api/embeddings`
+    #[default]
+    Ollama,
+    /// OpenAI互換の`/embeddings`
+    Openai,
+    /// モデルを使わない単語のハッシュ（テストやオフライン用）
+    Hash,
+}
+
+impl EmbeddingKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EmbeddingKind::Ollama => "ollama",
+            EmbeddingKind::Openai => "openai",
+            EmbeddingKind::Hash => "hash",
+        }
+    }
+
+    fn default_base_url(self) -> &'static str {
+        match self {
+            EmbeddingKind::Ollama => "http://localhost:11434",
+            EmbeddingKind::Openai => "https://api.openai.com/v1",
+            EmbeddingKind::Hash => "",
+        }
+    }
+
+    fn default_model(self) -> &'static str {
+        match self {
+            EmbeddingKind::Ollama => "nomic-embed-text",
+            EmbeddingKind::Openai => "text-embedding-3-small",
+            EmbeddingKind::Hash => "fnv",
+        }
+    }
+}
+
+/// `.ambient/config.toml`の`[embedding]`（検索用の埋め込み、チャットのモデルとは別に設定する）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct EmbeddingConfig {
+    /// 埋め込みを作るサービス
+    #[serde(default)]
+    pub kind: EmbeddingKind,
+
+    /// APIのベースURL（省略時はサービスごとの既定）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+
+    /// 埋め込みのモデル名（省略時はサービスごとの既定）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// APIキーを読む環境変数（キーが不要なサーバーでは省略）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+
+    /// `hash`の埋め込みの次元数
+    #[serde(default = "default_dimensions")]
+    pub dimensions: usize,
+}
+
+fn default_dimensions() -> usize {
+    256
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            kind: EmbeddingKind::default(),
+            base_url: None,
+            model: None,
+            api_key_env: None,
+            dimensions: default_dimensions(),
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    /// 設定から埋め込みのプロバイダーを作る（APIキーの環境変数がなければエラー）
+    pub fn provider(&self, client: reqwest::Client) -> Result<Embedder> {
+        let base_url = self
+            .base_url
+            .clone()
+            .unwrap_or_else(|| self.kind.default_base_url().to_string())
+            .trim_end_matches('/')
+            .to_string();
+        let model = self
+            .model
+            .clone()
+            .unwrap_or_else(|| self.kind.default_model().to_string());
+        let api_key = match &self.api_key_env {
+            Some(env) => Some(
+                std::env::var(env)
+                    .ok()
+                    .filter(|key| !key.trim().is_empty())
+                    .with_context(|| {
+                        format!("環境変数{env}に埋め込みのAPIキーを設定してください")
+                    })?,
+            ),
+            None => None,
+        };
+        Ok(match self.kind {
+            EmbeddingKind::Ollama => Embedder::Ollama(OllamaEmbeddings {
+                client,
+                base_url,
+                model,
+            }),
+            EmbeddingKind::Openai => Embedder::Openai(OpenAiEmbeddings {
+                client,
+                base_url,
+                model,
+                api_key,
+            }),
+            EmbeddingKind::Hash => Embedder::Hash(HashEmbeddings::new(self.dimensions)),
+        })
+    }
+}
+
+/// テキストを埋め込みのベクトルにする
+pub trait EmbeddingProvider: Send + Sync {
+    /// プロバイダーとモデルの識別子（索引を作り直すかの判断に使う）
+    fn id(&self) -> String;
+
+    /// テキストごとの埋め込みを、入力と同じ順で返す
+    fn embed(&self, texts: &[String]) -> impl Future<Output = Result<Vec<Vec<f32>>>> + Send;
+}
+
+/// 設定で選んだ埋め込みのプロバイダー
+#[derive(Debug, Clone)]
+pub enum Embedder {
+    Ollama(OllamaEmbeddings),
+    Openai(OpenAiEmbeddings),
+    Hash(HashEmbeddings),
+}
+
+impl EmbeddingProvider for Embedder {
+    fn id(&self) -> String {
+        match self {
+            Embedder::Ollama(provider) => provider.id(),
+            Embedder::Openai(provider) => provider.id(),
+            Embedder::Hash(provider) => provider.id(),
+        }
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        match self {
+            Embedder::Ollama(provider) => provider.embed(texts).await,
+            Embedder::Openai(provider) => provider.embed(texts).await,
+            Embedder::Hash(provider) => provider.embed(texts).await,
+        }
+    }
+}
+
+/// Ollamaの`/api/embeddings`（1回に1つのテキスト）
+#[derive(Debug, Clone)]
+pub struct OllamaEmbeddings {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+impl EmbeddingProvider for OllamaEmbeddings {
+    fn id(&self) -> String {
+        format!("ollama:{}", self.model)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/api/embeddings", self.base_url);
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for text in texts {
+            let response: OllamaEmbeddingResponse = self
+                .client
+                .post(&url)
+                .json(&json!({ "model": self.model, "prompt": text }))
+                .send()
+                .await
+                .with_context(|| format!("{url}に接続できません"))?
+                .error_for_status()
+                .with_context(|| format!("{}で埋め込みを作れません", self.model))?
+                .json()
+                .await?;
+            embeddings.push(response.embedding);
+        }
+        Ok(embeddings)
+    }
+}
+
+/// OpenAI互換の`/embeddings`（まとめて1回で送る）
+#[derive(Debug, Clone)]
+pub struct OpenAiEmbeddings {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbeddingResponse {
+    data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl EmbeddingProvider for OpenAiEmbeddings {
+    fn id(&self) -> String {
+        format!("openai:{}", self.model)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(vec![]);
+        }
+        let url = format!("{}/embeddings", self.base_url);
+        let mut request = self
+            .client
+            .post(&url)
+            .json(&json!({ "model": self.model, "input": texts }));
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let mut response: OpenAiEmbeddingResponse = request
+            .send()
+            .await
+            .with_context(|| format!("{url}に接続できません"))?
+            .error_for_status()
+            .with_context(|| format!("{}で埋め込みを作れません", self.model))?
+            .json()
+            .await?;
+        if response.data.len() != texts.len() {
+            anyhow::bail!(
+                "埋め込みの数が入力と一致しません（{}件に対して{}件）",
+                texts.len(),
+                response.data.len()
+            );
+        }
+        response.data.sort_by_key(|embedding| embedding.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect())
+    }
+}
+
+/// 単語のハッシュを次元に振り分ける決定的な埋め込み（モデルを使わない）
+///
+/// 同じ単語を含むテキストほど近くなるため、テストや埋め込みのモデルがない環境の代わりに使える。
+#[derive(Debug, Clone)]
+pub struct HashEmbeddings {
+    dimensions: usize,
+}
+
+impl HashEmbeddings {
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions: dimensions.max(1),
+        }
+    }
+
+    /// 1つのテキストの埋め込み（長さ1に正規化する）
+    pub fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|word| !word.is_empty())
+        {
+            let hash = fnv1a(&word.to_lowercase());
+            let index = (hash % self.dimensions as u64) as usize;
+            // 最上位ビットで符号を変え、衝突した単語が打ち消し合うようにする
+            vector[index] += if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for x in &mut vector {
+                *x /= norm;
+            }
+        }
+        vector
+    }
+}
+
+impl EmbeddingProvider for HashEmbeddings {
+    fn id(&self) -> String {
+        format!("hash:{}", self.dimensions)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.embed_one(text)).collect())
+    }
+}
+
+/// 64ビットのFNV-1a（実行環境やRustのバージョンによらず同じ値になる）
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// 2つの埋め込みのコサイン類似度（長さが違うか、どちらかが0なら0）
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_partial_json;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    fn texts(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn hash_embeddings_are_deterministic_and_similar_for_shared_words() {
+        let provider = HashEmbeddings::new(64);
+        let inputs = texts(&["parse the config file", "parse config", "render html page"]);
+        let first = provider.embed(&inputs).await.unwrap();
+        let second = provider.embed(&inputs).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first[0].len(), 64);
+        assert!(cosine_similarity(&first[0], &first[1]) > cosine_similarity(&first[0], &first[2]));
+        assert!((cosine_similarity(&first[0], &first[0]) - 1.0).abs() < 1e-5);
+    }
+
+    #[tokio::test]
+    async fn ollama_embeds_one_text_per_request() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .and(body_partial_json(json!({ "model": "nomic-embed-text" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "embedding": [0.1, 0.2, 0.3]
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let config = EmbeddingConfig {
+            base_url: Some(server.uri()),
+            ..Default::default()
+        };
+        let provider = config.provider(reqwest::Client::new()).unwrap();
+        assert_eq!(provider.id(), "ollama:nomic-embed-text");
+        let embeddings = provider.embed(&texts(&["a", "b"])).await.unwrap();
+        assert_eq!(embeddings, vec![vec![0.1, 0.2, 0.3]; 2]);
+    }
+
+    #[tokio::test]
+    async fn openai_embeds_in_one_request_and_keeps_input_order() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .and(header("authorization", "Bearer secret"))
+            .and(body_partial_json(json!({ "input": ["a", "b"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": [
+                    { "index": 1, "embedding": [0.0, 1.0] },
+                    { "index": 0, "embedding": [1.0, 0.0] }
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = Embedder::Openai(OpenAiEmbeddings {
+            client: reqwest::Client::new(),
+            base_url: format!("{}/v1", server.uri()),
+            model: "text-embedding-3-small".to_string(),
+            api_key: Some("secret".to_string()),
+        });
+        let embeddings = provider.embed(&texts(&["a", "b"])).await.unwrap();
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    }
+
+    #[test]
+    fn requires_the_api_key_variable_when_configured() {
+        let config = EmbeddingConfig {
+            kind: EmbeddingKind::Openai,
+            api_key_env: Some("AMBIENT_TEST_MISSING_EMBEDDING_KEY".to_string()),
+            ..Default::default()
+        };
+        let err = config.provider(reqwest::Client::new()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("AMBIENT_TEST_MISSING_EMBEDDING_KEY")
+        );
+    }
+}
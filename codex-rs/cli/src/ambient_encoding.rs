@@ -0,0 +1,84 @@
+use encoding_rs::Encoding;
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// バイト列の文字コードを推定する（UTF-8として正しければUTF-8）
+pub fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if std::str::from_utf8(bytes).is_ok() {
+        return encoding_rs::UTF_8;
+    }
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+/// バイト列をUTF-8の文字列にする（Shift_JISやEUC-JPのソースは推定した文字コードから変換する）
+pub fn decode_text(bytes: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_string();
+    }
+    let (text, _, _) = detect_encoding(bytes).decode(bytes);
+    text.into_owned()
+}
+
+/// ファイルを読み、UTF-8の文字列にする（`fs::read_to_string`と違い、UTF-8でないファイルも読める）
+pub fn read_text(path: &Path) -> io::Result<String> {
+    fs::read(path).map(|bytes| decode_text(&bytes))
+}
+
+/// gitが出力したパスのバイト列をそのままパスにする（UTF-8でないファイル名も開ける）
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+/// gitが出力したパスのバイト列をパスにする（Windowsのgitはパスを常にUTF-8で出力する）
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// パスを、gitが出力するのと同じバイト列にする
+#[cfg(unix)]
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+/// パスを、gitが出力するのと同じバイト列にする
+#[cfg(not(unix))]
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcodes_shift_jis_sources() {
+        let source = "// 日本語のコメントです。文字コードを判定します\nfn main() {}\n";
+        let (bytes, _, _) = encoding_rs::SHIFT_JIS.encode(source);
+        assert!(std::str::from_utf8(&bytes).is_err());
+        assert_eq!(detect_encoding(&bytes), encoding_rs::SHIFT_JIS);
+        assert_eq!(decode_text(&bytes), source);
+
+        assert_eq!(detect_encoding(source.as_bytes()), encoding_rs::UTF_8);
+        assert_eq!(decode_text(source.as_bytes()), source);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_non_utf8_paths_intact() {
+        let (name, _, _) = encoding_rs::SHIFT_JIS.encode("設定.rs");
+        let path = path_from_bytes(&name);
+        assert_eq!(path_bytes(&path).as_ref(), name.as_ref());
+    }
+}
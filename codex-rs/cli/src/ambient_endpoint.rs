@@ -0,0 +1,115 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use toml::Value;
+
+/// `[provider]`のエンドポイントをcodexの`model_providers`に登録するときのID
+pub const ENDPOINT_PROVIDER_ID: &str = "ambient-endpoint";
+
+/// `.ambient/config.toml`の`provider`（codexのプロバイダーIDか、OpenAI互換のエンドポイント）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ProviderSetting {
+    /// codexの`model_providers`のキー（`provider = "openai"`）
+    Id(String),
+    /// `[provider]`のテーブル
+    Endpoint(EndpointProvider),
+}
+
+impl ProviderSetting {
+    /// 使用するプロバイダーID（エンドポイントなら`ambient-endpoint`）
+    pub fn id(&self) -> &str {
+        match self {
+            ProviderSetting::Id(id) => id,
+            ProviderSetting::Endpoint(_) => ENDPOINT_PROVIDER_ID,
+        }
+    }
+
+    pub fn endpoint(&self) -> Option<&EndpointProvider> {
+        match self {
+            ProviderSetting::Id(_) => None,
+            ProviderSetting::Endpoint(endpoint) => Some(endpoint),
+        }
+    }
+}
+
+/// codexのプロバイダーの設定を書かずに使う、OpenAI互換のサーバー（LM Studio、vLLM、llama.cppのserverなど）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct EndpointProvider {
+    /// APIのベースURL（`/chat/completions`の手前まで、例: `http://localhost:1234/v1`）
+    pub base_url: String,
+
+    /// APIキーを読む環境変数（キーが不要なサーバーでは省略）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+
+    /// 使用するモデル名（省略時はトップレベルの`model`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl EndpointProvider {
+    /// codexの設定に`model_providers.ambient-endpoint`を追加する上書き
+    ///
+    /// Chat Completions APIで呼び出し、codexのログインは使わない。
+    pub fn config_overrides(&self) -> Vec<(String, Value)> {
+        let key = |field: &str| format!("model_providers.{ENDPOINT_PROVIDER_ID}.{field}");
+        let mut overrides = vec![
+            (key("name"), Value::String(self.base_url.clone())),
+            (key("base_url"), Value::String(self.base_url.clone())),
+            (key("wire_api"), Value::String("chat".to_string())),
+        ];
+        if let Some(env) = &self.api_key_env {
+            overrides.push((key("env_key"), Value::String(env.clone())));
+        }
+        overrides
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        provider: ProviderSetting,
+    }
+
+    #[test]
+    fn accepts_provider_id_or_endpoint_table() {
+        let config: Config = toml::from_str("provider = \"openai\"").unwrap();
+        assert_eq!(config.provider.id(), "openai");
+        assert!(config.provider.endpoint().is_none());
+
+        let config: Config = toml::from_str(
+            "[provider]\nbase_url = \"http://localhost:1234/v1\"\nmodel = \"qwen2.5-coder\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.provider.id(), ENDPOINT_PROVIDER_ID);
+        let endpoint = config.provider.endpoint().unwrap();
+        assert_eq!(endpoint.model.as_deref(), Some("qwen2.5-coder"));
+        assert_eq!(endpoint.api_key_env, None);
+    }
+
+    #[test]
+    fn registers_endpoint_as_chat_provider() {
+        let endpoint = EndpointProvider {
+            base_url: "http://localhost:8000/v1".to_string(),
+            api_key_env: Some("VLLM_API_KEY".to_string()),
+            model: None,
+        };
+        let overrides = endpoint.config_overrides();
+        let value = |key: &str| {
+            overrides
+                .iter()
+                .find(|(k, _)| k == &format!("model_providers.ambient-endpoint.{key}"))
+                .map(|(_, v)| v.as_str().unwrap().to_string())
+        };
+        assert_eq!(
+            value("base_url").as_deref(),
+            Some("http://localhost:8000/v1")
+        );
+        assert_eq!(value("wire_api").as_deref(), Some("chat"));
+        assert_eq!(value("env_key").as_deref(), Some("VLLM_API_KEY"));
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::Utc;
+use std::fs;
+use std::path::Path;
+
+use crate::ambient_time::display_offset;
+
+/// レビューのプロンプトで変更の前に付けるファイルの情報（小さいモデルでもファイルの種類を取り違えにくくする）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub path: String,
+    /// 判定した言語（コードブロックの言語指定にも使う）
+    pub language: String,
+    /// 作業ツリーのファイルの行数（ファイルがなければなし）
+    pub lines: Option<usize>,
+    /// 作業ツリーのファイルの最終更新時刻（表示用タイムゾーン、ファイルがなければなし）
+    pub modified: Option<DateTime<FixedOffset>>,
+}
+
+impl FileMetadata {
+    /// 作業ツリーにないファイル（プルリクエストのdiffだけでレビューする場合など）の情報
+    pub fn new(path: &str, language: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            language: language.to_string(),
+            lines: None,
+            modified: None,
+        }
+    }
+
+    /// `absolute_path`のファイルの行数と最終更新時刻を読む（読めなければなし）
+    pub fn read(path: &str, language: &str, absolute_path: &Path) -> Self {
+        let modified = fs::metadata(absolute_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(|modified| DateTime::<Utc>::from(modified).with_timezone(&display_offset()));
+        Self {
+            lines: fs::read(absolute_path)
+                .ok()
+                .map(|bytes| count_lines(&bytes)),
+            modified,
+            ..Self::new(path, language)
+        }
+    }
+
+    /// `key: value`形式のヘッダー
+    pub fn header(&self) -> String {
+        let mut header = format!("ファイル: {}\n言語: {}\n", self.path, self.language);
+        if let Some(lines) = self.lines {
+            header.push_str(&format!("行数: {lines}\n"));
+        }
+        if let Some(modified) = self.modified {
+            header.push_str(&format!(
+                "最終更新: {}\n",
+                modified.format("%Y-%m-%d %H:%M %:z")
+            ));
+        }
+        header
+    }
+
+    /// ヘッダーと、言語を指定したコードブロックで囲んだ変更
+    pub fn present(&self, content: &str) -> String {
+        format!("{}\n{}", self.header(), fenced(content, &self.language))
+    }
+}
+
+/// 内容を言語を指定したコードブロックで囲む（内容の```より長い囲みを使う）
+pub fn fenced(content: &str, language: &str) -> String {
+    let longest_run = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let newline = if content.ends_with('\n') { "" } else { "\n" };
+    format!("{fence}{language}\n{content}{newline}{fence}")
+}
+
+/// 行数（末尾に改行のない最後の行も数える。Shift_JISなどでも改行のバイトは同じ）
+fn count_lines(bytes: &[u8]) -> usize {
+    let newlines = bytes.iter().filter(|&&b| b == b'\n').count();
+    match bytes.last() {
+        None | Some(b'\n') => newlines,
+        Some(_) => newlines + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn renders_header_and_fenced_diff() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("lib.rs");
+        fs::write(&path, "fn a() {}\nfn b() {}").unwrap();
+        let metadata = FileMetadata::read("src/lib.rs", "rust", &path);
+        assert_eq!(metadata.lines, Some(2));
+        assert!(metadata.modified.is_some());
+
+        let metadata = FileMetadata {
+            modified: None,
+            ..metadata
+        };
+        assert_eq!(
+            metadata.present("+fn b() {}\n"),
+            "ファイル: src/lib.rs\n言語: rust\n行数: 2\n\n```rust\n+fn b() {}\n```"
+        );
+        assert_eq!(
+            FileMetadata::new("README.md", "markdown").header(),
+            "ファイル: README.md\n言語: markdown\n"
+        );
+    }
+
+    #[test]
+    fn uses_a_longer_fence_than_the_content() {
+        assert_eq!(
+            fenced("+```sh\n+ls\n+```", "markdown"),
+            "````markdown\n+```sh\n+ls\n+```\n````"
+        );
+        assert_eq!(count_lines(b""), 0);
+        assert_eq!(count_lines(b"a\n\nb\n"), 3);
+    }
+}
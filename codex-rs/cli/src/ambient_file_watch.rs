@@ -0,0 +1,258 @@
+use anyhow::Context;
+use anyhow::Result;
+use notify::Event;
+use notify::EventKind;
+use notify::RecommendedWatcher;
+use notify::RecursiveMode;
+use notify::Watcher;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::sync::mpsc;
+
+use crate::ambient_project_config::ProjectConfig;
+
+/// 変更を無視するディレクトリ（gitの内部と、ambient自身が書き込む履歴など）
+const IGNORED_DIRS: [&str; 2] = [".git", ".ambient"];
+
+/// 設定ファイルの変更が止まってから読み直すまでの時間
+const CONFIG_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// `.ambient/config.toml`の`[file_watch]`（ファイルの変更を検知して即座にチェックする）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct FileWatchConfig {
+    /// ファイルシステムの変更通知でチェックを始めるか（無効なら`check_interval_secs`ごとの確認だけ）
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// 最後の変更からこの時間（ミリ秒）変更がなければチェックを始める
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_debounce_ms() -> u64 {
+    500
+}
+
+impl Default for FileWatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            debounce_ms: default_debounce_ms(),
+        }
+    }
+}
+
+/// `root`以下の変更を監視し、変更が`debounce_ms`の間止まったら`trigger`に通知する
+///
+/// 戻り値を破棄すると監視が止まる。
+pub fn watch_files(
+    root: &Path,
+    config: &FileWatchConfig,
+    project_config: ProjectConfig,
+    trigger: Arc<Notify>,
+) -> Result<RecommendedWatcher> {
+    // macOSでは正規化したパスで通知されるため、比較する`root`も揃える
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let (tx, rx) = mpsc::unbounded_channel();
+    let watched_root = root.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        if event
+            .paths
+            .iter()
+            .any(|path| is_relevant(&watched_root, path, &project_config))
+        {
+            let _ = tx.send(());
+        }
+    })
+    .context("ファイルの変更を監視できません")?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .with_context(|| format!("{}を監視できません", root.display()))?;
+    tokio::spawn(debounce(
+        rx,
+        Duration::from_millis(config.debounce_ms),
+        trigger,
+    ));
+    Ok(watcher)
+}
+
+/// 設定ファイルの変更を監視し、変更が止まったら`changed`に通知する
+///
+/// 一時ファイルを書いてから置き換えるエディタでも検知できるように、親ディレクトリを監視する。
+/// 起動時に存在しないディレクトリは監視しない。戻り値を破棄すると監視が止まる。
+pub fn watch_config_files(files: &[PathBuf], changed: Arc<Notify>) -> Result<RecommendedWatcher> {
+    let files: BTreeSet<PathBuf> = files.iter().map(|file| canonical_file(file)).collect();
+    let (tx, rx) = mpsc::unbounded_channel();
+    let watched_files = files.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        if event
+            .paths
+            .iter()
+            .any(|path| watched_files.contains(&canonical_file(path)))
+        {
+            let _ = tx.send(());
+        }
+    })
+    .context("設定ファイルの変更を監視できません")?;
+    let dirs: BTreeSet<&Path> = files
+        .iter()
+        .filter_map(|file| file.parent())
+        .filter(|dir| dir.is_dir())
+        .collect();
+    for dir in dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("{}を監視できません", dir.display()))?;
+    }
+    tokio::spawn(debounce(rx, CONFIG_DEBOUNCE, changed));
+    Ok(watcher)
+}
+
+/// 親ディレクトリを正規化したパス（置き換え中のファイルは存在しない場合があるため、ファイル自体は正規化しない）
+fn canonical_file(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => dir
+            .canonicalize()
+            .unwrap_or_else(|_| dir.to_path_buf())
+            .join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// チェックのきっかけにする変更か（`root`の外、無視するディレクトリ、除外パターンは対象外）
+fn is_relevant(root: &Path, path: &Path, project_config: &ProjectConfig) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    let components: Vec<String> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if components.is_empty()
+        || components
+            .iter()
+            .any(|component| IGNORED_DIRS.contains(&component.as_str()))
+    {
+        return false;
+    }
+    !project_config.is_excluded(&components.join("/"))
+}
+
+/// 変更が続いている間は待ち、`quiet`の間止まったら1回だけ通知する
+async fn debounce(mut rx: mpsc::UnboundedReceiver<()>, quiet: Duration, trigger: Arc<Notify>) {
+    while rx.recv().await.is_some() {
+        loop {
+            match tokio::time::timeout(quiet, rx.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+        trigger.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_git_internals_and_excluded_paths() {
+        let root = Path::new("/repo");
+        let config = ProjectConfig {
+            exclude_patterns: vec!["target/**".to_string()],
+            ..Default::default()
+        };
+        assert!(is_relevant(root, Path::new("/repo/src/lib.rs"), &config));
+        assert!(!is_relevant(root, Path::new("/repo/.git/index"), &config));
+        assert!(!is_relevant(
+            root,
+            Path::new("/repo/.ambient/history.jsonl"),
+            &config
+        ));
+        assert!(!is_relevant(
+            root,
+            Path::new("/repo/target/debug/app"),
+            &config
+        ));
+        assert!(!is_relevant(root, Path::new("/other/src/lib.rs"), &config));
+        assert!(!is_relevant(root, root, &config));
+    }
+
+    #[tokio::test]
+    async fn debounces_bursts_of_changes() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let trigger = Arc::new(Notify::new());
+        let task = tokio::spawn(debounce(rx, Duration::from_millis(200), trigger.clone()));
+
+        for _ in 0..5 {
+            tx.send(()).unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        // 変更が止まってすぐには通知しない
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), trigger.notified())
+                .await
+                .is_err()
+        );
+        tokio::time::timeout(Duration::from_secs(2), trigger.notified())
+            .await
+            .unwrap();
+        // 1回の連続した変更では1回だけ通知する
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), trigger.notified())
+                .await
+                .is_err()
+        );
+
+        drop(tx);
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn notifies_when_a_config_file_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.toml");
+        std::fs::write(&config, "check_interval_secs = 60\n").unwrap();
+        let changed = Arc::new(Notify::new());
+        let _watcher = watch_config_files(std::slice::from_ref(&config), changed.clone()).unwrap();
+
+        // 同じディレクトリのほかのファイルの変更では通知しない
+        std::fs::write(dir.path().join("history.jsonl"), "{}\n").unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(800), changed.notified())
+                .await
+                .is_err()
+        );
+
+        let temp = dir.path().join("config.toml.tmp");
+        std::fs::write(&temp, "check_interval_secs = 30\n").unwrap();
+        std::fs::rename(&temp, &config).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), changed.notified())
+            .await
+            .unwrap();
+    }
+}
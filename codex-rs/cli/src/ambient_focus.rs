@@ -0,0 +1,259 @@
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::NaiveTime;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use crate::ambient_locale::Locale;
+
+/// セッションの最初の変更の前にも作業していたとみなす時間
+const LEAD_IN: chrono::Duration = chrono::Duration::minutes(5);
+
+/// 変更が続いていた時間帯（変更の間隔が`gap`を超えたら別のセッション）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// 最初の変更の時刻
+    pub start: DateTime<Utc>,
+    /// 最後の変更の時刻
+    pub end: DateTime<Utc>,
+    /// ファイルごとの変更の回数
+    pub files: BTreeMap<String, usize>,
+}
+
+impl Session {
+    /// 作業時間の推定（最初の変更から最後の変更まで＋`LEAD_IN`）
+    pub fn duration(&self) -> Duration {
+        (self.end - self.start + LEAD_IN)
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    pub fn changes(&self) -> usize {
+        self.files.values().sum()
+    }
+}
+
+/// モジュール（ディレクトリ）ごとの作業時間
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleTime {
+    /// リポジトリルートからのディレクトリ（ルート直下のファイルは`.`）
+    pub module: String,
+    pub time: Duration,
+    pub changes: usize,
+    pub files: usize,
+}
+
+/// `ambient focus`の集計
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FocusReport {
+    pub sessions: Vec<Session>,
+    /// 作業時間の長い順
+    pub modules: Vec<ModuleTime>,
+}
+
+impl FocusReport {
+    pub fn total(&self) -> Duration {
+        self.sessions.iter().map(Session::duration).sum()
+    }
+}
+
+/// 表示用タイムゾーンで今日の0時
+pub fn start_of_today(now: DateTime<Utc>, offset: FixedOffset) -> DateTime<Utc> {
+    let midnight = now
+        .with_timezone(&offset)
+        .date_naive()
+        .and_time(NaiveTime::MIN);
+    midnight
+        .and_local_timezone(offset)
+        .single()
+        .map_or(now, |midnight| midnight.with_timezone(&Utc))
+}
+
+/// 変更の時刻（古い順）をセッションに分ける
+pub fn infer_sessions(activity: &[(String, DateTime<Utc>)], gap: chrono::Duration) -> Vec<Session> {
+    let mut sessions: Vec<Session> = Vec::new();
+    for (path, ts) in activity {
+        match sessions.last_mut() {
+            Some(session) if *ts - session.end <= gap => session.end = session.end.max(*ts),
+            _ => sessions.push(Session {
+                start: *ts,
+                end: *ts,
+                files: BTreeMap::new(),
+            }),
+        }
+        if let Some(session) = sessions.last_mut() {
+            *session.files.entry(path.clone()).or_default() += 1;
+        }
+    }
+    sessions
+}
+
+/// ファイルのパスをモジュールにまとめる（先頭から`depth`階層のディレクトリ）
+pub fn module_of(path: &str, depth: usize) -> String {
+    let dirs: Vec<&str> = path.split('/').collect();
+    let dirs = &dirs[..dirs.len() - 1];
+    if dirs.is_empty() {
+        return ".".to_string();
+    }
+    dirs[..dirs.len().min(depth.max(1))].join("/")
+}
+
+/// セッションの作業時間を、変更の回数の割合でモジュールに配分する
+pub fn build_report(sessions: Vec<Session>, depth: usize) -> FocusReport {
+    let mut modules: BTreeMap<String, (Duration, usize, BTreeSet<&str>)> = BTreeMap::new();
+    for session in &sessions {
+        let duration = session.duration();
+        let changes = session.changes().max(1) as u32;
+        for (path, count) in &session.files {
+            let entry = modules.entry(module_of(path, depth)).or_default();
+            entry.0 += duration * *count as u32 / changes;
+            entry.1 += count;
+            entry.2.insert(path);
+        }
+    }
+    let mut modules: Vec<ModuleTime> = modules
+        .into_iter()
+        .map(|(module, (time, changes, files))| ModuleTime {
+            module,
+            time,
+            changes,
+            files: files.len(),
+        })
+        .collect();
+    modules.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| a.module.cmp(&b.module)));
+    FocusReport { sessions, modules }
+}
+
+/// セッションとモジュール別の作業時間を表示する（日時と時間は`locale`の書式）
+pub fn render_report(report: &FocusReport, locale: Locale, offset: FixedOffset) -> String {
+    if report.sessions.is_empty() {
+        return match locale {
+            Locale::Ja => "この期間に記録された変更はありません\n".to_string(),
+            Locale::En => "No changes were recorded in this period\n".to_string(),
+        };
+    }
+    let mut out = String::from(match locale {
+        Locale::Ja => "作業セッション\n",
+        Locale::En => "Sessions\n",
+    });
+    for session in &report.sessions {
+        let end = session.end.with_timezone(&offset).format("%H:%M");
+        out.push_str(&format!(
+            "  {} - {end}  {:>10}  {}\n",
+            locale.format_datetime(&session.start.to_rfc3339(), offset),
+            locale.format_duration(session.duration()),
+            locale.format_count(session.changes(), "件の変更", "change")
+        ));
+    }
+
+    let total = report.total();
+    out.push_str(match locale {
+        Locale::Ja => "\nモジュール別\n",
+        Locale::En => "\nBy module\n",
+    });
+    for module in &report.modules {
+        let percent = module.time.as_secs() * 100 / total.as_secs().max(1);
+        out.push_str(&format!(
+            "  {:>10}  {percent:>3}%  {}  ({}, {})\n",
+            locale.format_duration(module.time),
+            module.module,
+            locale.format_count(module.changes, "件の変更", "change"),
+            locale.format_count(module.files, "ファイル", "file")
+        ));
+    }
+    out.push_str(&match locale {
+        Locale::Ja => format!("\n合計 {}\n", locale.format_duration(total)),
+        Locale::En => format!("\nTotal {}\n", locale.format_duration(total)),
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("2026-10-16T{time}:00Z"))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn activity() -> Vec<(String, DateTime<Utc>)> {
+        [
+            ("src/ambient/server.rs", "09:00"),
+            ("src/ambient/server.rs", "09:10"),
+            ("src/ambient/ui.rs", "09:10"),
+            ("README.md", "09:25"),
+            ("tests/focus.rs", "13:00"),
+            ("tests/focus.rs", "13:15"),
+        ]
+        .into_iter()
+        .map(|(path, time)| (path.to_string(), at(time)))
+        .collect()
+    }
+
+    #[test]
+    fn splits_sessions_on_idle_gaps() {
+        let sessions = infer_sessions(&activity(), chrono::Duration::minutes(30));
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(
+            (sessions[0].start, sessions[0].end),
+            (at("09:00"), at("09:25"))
+        );
+        assert_eq!(sessions[0].files["src/ambient/server.rs"], 2);
+        assert_eq!(sessions[0].duration(), Duration::from_secs(30 * 60));
+        assert_eq!(sessions[1].changes(), 2);
+
+        // 09:25と13:15はどちらも直前から15分空いている
+        let sessions = infer_sessions(&activity(), chrono::Duration::minutes(10));
+        assert_eq!(sessions.len(), 4);
+    }
+
+    #[test]
+    fn attributes_time_to_modules() {
+        assert_eq!(module_of("src/ambient/server.rs", 2), "src/ambient");
+        assert_eq!(module_of("src/ambient/server.rs", 1), "src");
+        assert_eq!(module_of("src/lib.rs", 2), "src");
+        assert_eq!(module_of("README.md", 2), ".");
+
+        let report = build_report(
+            infer_sessions(&activity(), chrono::Duration::minutes(30)),
+            2,
+        );
+        let modules: Vec<(&str, u64, usize, usize)> = report
+            .modules
+            .iter()
+            .map(|m| (m.module.as_str(), m.time.as_secs() / 60, m.changes, m.files))
+            .collect();
+        assert_eq!(
+            modules,
+            vec![
+                ("src/ambient", 22, 3, 2),
+                ("tests", 20, 2, 1),
+                (".", 7, 1, 1),
+            ]
+        );
+        assert_eq!(report.total(), Duration::from_secs(50 * 60));
+    }
+
+    #[test]
+    fn renders_report_in_local_time() {
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        assert_eq!(start_of_today(at("16:00"), jst), at("15:00"));
+        assert_eq!(
+            start_of_today(at("14:00"), jst),
+            at("15:00") - chrono::Duration::days(1)
+        );
+
+        let report = build_report(
+            infer_sessions(&activity(), chrono::Duration::minutes(30)),
+            2,
+        );
+        let text = render_report(&report, Locale::Ja, jst);
+        assert!(text.contains("2026-10-16 18:00 - 18:25"));
+        assert!(text.ends_with("合計 50分0秒\n"));
+        assert!(render_report(&FocusReport::default(), Locale::En, jst).starts_with("No changes"));
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::Context;
+use anyhow::Result;
+use base64::Engine;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::ambient_integration::ReviewComment;
+use crate::ambient_version::VERSION;
+
+/// HTTPパスワードを使うユーザーを読む環境変数
+pub const USERNAME_ENV_VAR: &str = "GERRIT_USERNAME";
+
+/// GerritのJSON応答の先頭に付くXSSI対策の文字列
+const XSSI_PREFIX: &str = ")]}'";
+
+/// Gerritの変更
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GerritChange {
+    /// サーバーのURL（`/c/`より前）
+    pub base_url: String,
+    pub project: String,
+    pub number: u64,
+}
+
+impl GerritChange {
+    /// `https://<host>/c/<project>/+/<number>`形式のURLを解析する
+    ///
+    /// プロジェクト名には`/`を含められる。番号の後ろのパッチセットやファイルは無視する。
+    pub fn parse(url: &str) -> Result<Self> {
+        let url = url.trim().split(['?', '#']).next().unwrap_or_default();
+        let parsed = url.split_once("/c/").and_then(|(base_url, rest)| {
+            let (project, rest) = rest.split_once("/+/")?;
+            let number = rest.split('/').next()?;
+            Some((base_url, project, number))
+        });
+        let Some((base_url, project, number)) =
+            parsed.filter(|(base_url, project, _)| base_url.contains("://") && !project.is_empty())
+        else {
+            anyhow::bail!(
+                "Gerritの変更ではありません（https://<host>/c/<project>/+/<number>の形式で指定してください）: {url}"
+            );
+        };
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            project: project.to_string(),
+            number: number
+                .parse()
+                .with_context(|| format!("変更の番号が不正です: {number}"))?,
+        })
+    }
+
+    /// 最新のパッチセットのREST APIのURL（認証する場合は`/a/`以下）
+    fn revision_url(&self, authenticated: bool) -> String {
+        let prefix = if authenticated { "/a" } else { "" };
+        format!(
+            "{}{prefix}/changes/{}~{}/revisions/current",
+            self.base_url,
+            self.project.replace('/', "%2F"),
+            self.number
+        )
+    }
+
+    fn html_url(&self) -> String {
+        format!("{}/c/{}/+/{}", self.base_url, self.project, self.number)
+    }
+
+    /// 最新のパッチセットのdiffを取得する（パッチはBase64で返される）
+    pub async fn fetch_diff(
+        &self,
+        client: &reqwest::Client,
+        token: Option<&str>,
+    ) -> Result<String> {
+        let url = format!("{}/patch", self.revision_url(token.is_some()));
+        let response = gerrit_request(client.get(&url), token)?
+            .send()
+            .await
+            .with_context(|| format!("{url}に接続できません"))?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("{self}のdiffを取得できません: {status}");
+        }
+        let encoded = response.text().await?;
+        let patch = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .context("パッチをBase64として解析できません")?;
+        Ok(String::from_utf8_lossy(&patch).into_owned())
+    }
+
+    /// レビュー全体のメッセージと、行ごとのコメントを1回で投稿する（投票はしない）
+    pub async fn publish(
+        &self,
+        client: &reqwest::Client,
+        token: &str,
+        summary: &str,
+        comments: &[&ReviewComment],
+    ) -> Result<String> {
+        let url = format!("{}/review", self.revision_url(true));
+        let response = gerrit_request(client.post(&url), Some(token))?
+            .json(&review_input(summary, comments))
+            .send()
+            .await
+            .with_context(|| format!("{url}に接続できません"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            let text = text.trim_start_matches(XSSI_PREFIX).trim();
+            anyhow::bail!("{self}にレビューを投稿できません: {status} {text}");
+        }
+        Ok(self.html_url())
+    }
+}
+
+impl fmt::Display for GerritChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}~{}", self.project, self.number)
+    }
+}
+
+/// トークンがあれば`GERRIT_USERNAME`のユーザーのHTTPパスワードとしてBasic認証する
+fn gerrit_request(
+    builder: reqwest::RequestBuilder,
+    token: Option<&str>,
+) -> Result<reqwest::RequestBuilder> {
+    let builder = builder.header(reqwest::header::USER_AGENT, format!("ambient/{VERSION}"));
+    let Some(password) = token else {
+        return Ok(builder);
+    };
+    let username = std::env::var(USERNAME_ENV_VAR)
+        .with_context(|| format!("Gerritのユーザー名を{USERNAME_ENV_VAR}に設定してください"))?;
+    Ok(builder.basic_auth(username, Some(password)))
+}
+
+/// `ReviewInput`（ファイルごとのコメントの一覧）
+fn review_input(summary: &str, comments: &[&ReviewComment]) -> serde_json::Value {
+    let mut files: BTreeMap<&str, Vec<serde_json::Value>> = BTreeMap::new();
+    for comment in comments {
+        files
+            .entry(comment.path.as_str())
+            .or_default()
+            .push(serde_json::json!({ "line": comment.line, "message": comment.body }));
+    }
+    serde_json::json!({ "message": summary, "comments": files })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_change_urls() {
+        let change =
+            GerritChange::parse("https://review.example.com/c/platform/build/+/12345/3").unwrap();
+        assert_eq!(
+            change,
+            GerritChange {
+                base_url: "https://review.example.com".to_string(),
+                project: "platform/build".to_string(),
+                number: 12345,
+            }
+        );
+        assert_eq!(change.to_string(), "platform/build~12345");
+        assert_eq!(
+            change.revision_url(true),
+            "https://review.example.com/a/changes/platform%2Fbuild~12345/revisions/current"
+        );
+        assert_eq!(
+            change.html_url(),
+            "https://review.example.com/c/platform/build/+/12345"
+        );
+        assert!(GerritChange::parse("https://github.com/org/repo/pull/1").is_err());
+        assert!(GerritChange::parse("https://review.example.com/c/tools/+/abc").is_err());
+    }
+
+    #[test]
+    fn groups_comments_by_file() {
+        let comment = |path: &str, line: usize| ReviewComment {
+            path: path.to_string(),
+            line: Some(line),
+            body: format!("{path}:{line}"),
+        };
+        let (a1, a2, b) = (comment("a.rs", 1), comment("a.rs", 9), comment("b.rs", 3));
+        assert_eq!(
+            review_input("まとめ", &[&a1, &b, &a2]),
+            serde_json::json!({
+                "message": "まとめ",
+                "comments": {
+                    "a.rs": [
+                        { "line": 1, "message": "a.rs:1" },
+                        { "line": 9, "message": "a.rs:9" },
+                    ],
+                    "b.rs": [{ "line": 3, "message": "b.rs:3" }],
+                },
+            })
+        );
+    }
+}
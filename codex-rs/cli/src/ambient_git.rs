@@ -0,0 +1,606 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::ambient_encoding::decode_text;
+use crate::ambient_encoding::path_bytes;
+use crate::ambient_encoding::path_from_bytes;
+
+// ヘルパー関数: Gitコマンドの実行と結果チェック
+pub fn run_git_command(args: &[&str], cwd: &Path) -> Result<String> {
+    run_git_command_bytes(args, cwd).map(|stdout| decode_git_output(&stdout))
+}
+
+/// Gitコマンドを実行し、標準出力をバイト列のまま返す（UTF-8でないパスと内容を扱う）
+pub fn run_git_command_bytes<S: AsRef<OsStr>>(args: &[S], cwd: &Path) -> Result<Vec<u8>> {
+    let output = Command::new("git").args(args).current_dir(cwd).output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("Git command failed: {stderr}"));
+    }
+
+    Ok(output.stdout)
+}
+
+/// gitの出力をUTF-8の文字列にする（diffはファイルごとに文字コードを推定する）
+fn decode_git_output(output: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(output) {
+        return text.to_string();
+    }
+    let mut text = String::new();
+    let mut section = Vec::new();
+    for line in output.split_inclusive(|&b| b == b'\n') {
+        if line.starts_with(b"diff --git ") && !section.is_empty() {
+            text.push_str(&decode_text(&section));
+            section.clear();
+        }
+        section.extend_from_slice(line);
+    }
+    text.push_str(&decode_text(&section));
+    text
+}
+
+/// 監視対象リポジトリの構成
+#[derive(Debug, Clone)]
+pub struct RepoLayout {
+    /// 作業ツリーのルート（リンクされたworktreeの場合はそのworktreeのルート）
+    pub root: PathBuf,
+
+    /// `git worktree add`で作成されたworktree内かどうか
+    pub is_linked_worktree: bool,
+
+    /// ルートからの相対パスで表したサブモジュール
+    pub submodules: Vec<String>,
+}
+
+/// 変更されたファイル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedFile {
+    /// 監視対象リポジトリのルートからの相対パス
+    pub path: String,
+
+    /// diffを実行するリポジトリのルート（サブモジュール内のファイルならそのサブモジュール）
+    pub repo_dir: PathBuf,
+
+    /// `repo_dir`からの相対パス
+    pub repo_path: String,
+
+    /// `repo_dir`からの相対パス（gitが出力したバイト列のまま。UTF-8でないファイル名も開ける）
+    pub os_path: PathBuf,
+
+    /// 比較の基準にない新規ファイル（未追跡、ステージ済みの追加、基準より後のコミットでの追加）
+    pub is_new: bool,
+
+    /// diffの比較の基準（`HEAD`、または`diff_base`と`HEAD`の分岐点のコミット）
+    pub base: String,
+}
+
+impl ChangedFile {
+    /// 作業ツリーのファイルの絶対パス
+    pub fn absolute_path(&self) -> PathBuf {
+        self.repo_dir.join(&self.os_path)
+    }
+}
+
+/// diffの比較の既定の基準（コミットしていない変更だけを見る）
+pub const DEFAULT_DIFF_BASE: &str = "HEAD";
+
+/// 1回の`git diff`に渡すpathspecの最大数（コマンドライン長の制限対策）
+const DIFF_BATCH_SIZE: usize = 500;
+
+/// 変更ファイルの比較の基準とのdiffを、リポジトリと基準ごとに一括で取得する
+///
+/// 戻り値のキーは`ChangedFile::path`。diffが空のファイルは含まれない。
+pub fn batch_diffs(files: &[ChangedFile]) -> HashMap<String, String> {
+    let mut diffs = HashMap::new();
+    for (repo_dir, base, batch) in diff_batches(files) {
+        let mut args: Vec<&OsStr> = ["-c", "core.quotePath=false", "diff", base, "--"]
+            .into_iter()
+            .map(OsStr::new)
+            .collect();
+        args.extend(batch.iter().map(|f| f.os_path.as_os_str()));
+        let Ok(output) = run_git_command_bytes(&args, repo_dir) else {
+            continue;
+        };
+        // パスはバイト列のまま照合し、内容はファイルごとに文字コードを推定する
+        let mut split = split_diff_bytes_by_file(&output);
+        for file in batch {
+            if let Some(diff) = split.remove(path_bytes(&file.os_path).as_ref()) {
+                diffs.insert(file.path.clone(), decode_text(&diff));
+            }
+        }
+    }
+    diffs
+}
+
+/// 1回の`git diff`で取得するファイルの組（リポジトリと基準ごとに、`DIFF_BATCH_SIZE`件ずつ）
+fn diff_batches(files: &[ChangedFile]) -> Vec<(&Path, &str, Vec<&ChangedFile>)> {
+    let mut by_repo: HashMap<(&Path, &str), Vec<&ChangedFile>> = HashMap::new();
+    for file in files {
+        by_repo
+            .entry((file.repo_dir.as_path(), file.base.as_str()))
+            .or_default()
+            .push(file);
+    }
+    by_repo
+        .into_iter()
+        .flat_map(|((repo_dir, base), repo_files)| {
+            repo_files
+                .chunks(DIFF_BATCH_SIZE)
+                .map(|chunk| (repo_dir, base, chunk.to_vec()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// 複数ファイル分の`git diff`出力をファイルごとに分割する
+pub fn split_diff_by_file(output: &str) -> HashMap<String, String> {
+    split_diff_bytes_by_file(output.as_bytes())
+        .into_iter()
+        .map(|(path, diff)| (decode_text(&path), decode_text(&diff)))
+        .collect()
+}
+
+/// `split_diff_by_file`のバイト列版（キーはヘッダーのパスのバイト列）
+fn split_diff_bytes_by_file(output: &[u8]) -> HashMap<Vec<u8>, Vec<u8>> {
+    let mut diffs = HashMap::new();
+    let mut current: Option<(Vec<u8>, Vec<u8>)> = None;
+    for line in output.split_inclusive(|&b| b == b'\n') {
+        if let Some(header) = line.strip_prefix(b"diff --git ") {
+            if let Some((path, diff)) = current.take() {
+                diffs.insert(path, diff);
+            }
+            current =
+                diff_header_path(header.trim_ascii_end()).map(|path| (path.to_vec(), Vec::new()));
+        }
+        if let Some((_, diff)) = current.as_mut() {
+            diff.extend_from_slice(line);
+        }
+    }
+    if let Some((path, diff)) = current {
+        diffs.insert(path, diff);
+    }
+    diffs
+}
+
+/// `a/<path> b/<path>`形式のヘッダーからパスを取り出す
+fn diff_header_path(header: &[u8]) -> Option<&[u8]> {
+    // 変更前後のパスが同じ場合、ヘッダーは"a/" + path + " b/" + pathになる
+    let same_path = header
+        .len()
+        .checked_sub("a/ b/".len())
+        .map(|len| len / 2)
+        .and_then(|len| header.strip_prefix(b"a/")?.get(..len))
+        .filter(|path| header.ends_with(&[b" b/".as_slice(), *path].concat()));
+    match same_path {
+        Some(path) => Some(path),
+        // リネームの場合は" b/"以降を変更後のパスとして扱う
+        None => header
+            .windows(3)
+            .rposition(|window| window == b" b/")
+            .map(|start| &header[start + 3..]),
+    }
+}
+
+impl RepoLayout {
+    /// `cwd`を含むリポジトリの構成を調べる
+    pub fn discover(cwd: &Path) -> Result<Self> {
+        let root = PathBuf::from(run_git_command(&["rev-parse", "--show-toplevel"], cwd)?.trim());
+
+        // リンクされたworktreeでは`--git-dir`が`<common>/worktrees/<name>`を指す
+        let git_dir = run_git_command(&["rev-parse", "--absolute-git-dir"], &root)?;
+        let common_dir = run_git_command(&["rev-parse", "--git-common-dir"], &root)?;
+        let common_dir = root.join(common_dir.trim());
+        let is_linked_worktree = match (
+            Path::new(git_dir.trim()).canonicalize(),
+            common_dir.canonicalize(),
+        ) {
+            (Ok(git_dir), Ok(common_dir)) => git_dir != common_dir,
+            _ => false,
+        };
+
+        let submodules = list_submodules(&root);
+
+        Ok(Self {
+            root,
+            is_linked_worktree,
+            submodules,
+        })
+    }
+
+    /// 変更されたファイルを列挙する
+    ///
+    /// `recurse_submodules`が`true`の場合、変更のあるサブモジュールの中も
+    /// そのサブモジュール自身のdiffで列挙する。`false`の場合はサブモジュールを
+    /// `skipped_submodules`として返す。
+    ///
+    /// `watch_paths`が空でなければ、`git status`をそのパス以下にpathspecで限定する。
+    ///
+    /// `diff_base`を指定すると、その参照と`HEAD`の分岐点より後にコミットした変更も含める
+    /// （サブモジュールの中は常に`HEAD`と比べる）。
+    pub fn changed_files(
+        &self,
+        recurse_submodules: bool,
+        watch_paths: &[String],
+        diff_base: Option<&str>,
+    ) -> Result<ChangedFiles> {
+        let base = match diff_base {
+            Some(diff_base) => resolve_diff_base(&self.root, diff_base)?,
+            None => DEFAULT_DIFF_BASE.to_string(),
+        };
+        let mut result = ChangedFiles {
+            base: base.clone(),
+            ..Default::default()
+        };
+        collect_changed_files(
+            &self.root,
+            "",
+            &self.submodules,
+            recurse_submodules,
+            watch_paths,
+            &base,
+            &mut result,
+        )?;
+        Ok(result)
+    }
+}
+
+/// `RepoLayout::changed_files`の結果
+#[derive(Debug, Default)]
+pub struct ChangedFiles {
+    pub files: Vec<ChangedFile>,
+    pub skipped_submodules: Vec<String>,
+    /// リポジトリのルートのファイルの比較の基準
+    pub base: String,
+}
+
+/// `diff_base`と`HEAD`の分岐点のコミットを返す
+///
+/// `origin/main`のように先に進んだブランチを指定しても、そちらの新しいコミットを
+/// 取り消す変更としてではなく、分岐してからの自分の変更だけを比べる。
+pub fn resolve_diff_base(repo_dir: &Path, diff_base: &str) -> Result<String> {
+    if diff_base.is_empty() || diff_base.starts_with('-') {
+        anyhow::bail!("比較の基準が不正です: {diff_base}");
+    }
+    let commit = run_git_command(&["merge-base", diff_base, "HEAD"], repo_dir)
+        .with_context(|| format!("比較の基準が見つかりません: {diff_base}"))?;
+    Ok(commit.trim().to_string())
+}
+
+fn collect_changed_files(
+    repo_dir: &Path,
+    prefix: &str,
+    submodules: &[String],
+    recurse_submodules: bool,
+    watch_paths: &[String],
+    base: &str,
+    result: &mut ChangedFiles,
+) -> Result<()> {
+    let Some(mut pathspecs) = scoped_pathspecs(watch_paths, prefix) else {
+        return Ok(());
+    };
+    // サブモジュール内を指すpathspecはgitがエラーにするため、サブモジュール自体に置き換える
+    for pathspec in pathspecs.iter_mut() {
+        if let Some(submodule) = submodules
+            .iter()
+            .find(|sub| pathspec.starts_with(&format!("{sub}/")))
+        {
+            *pathspec = submodule.clone();
+        }
+    }
+    pathspecs.dedup();
+
+    let mut args = vec!["status", "--porcelain=v1", "-z", "--untracked-files=all"];
+    if !pathspecs.is_empty() {
+        args.push("--");
+        args.extend(pathspecs.iter().map(String::as_str));
+    }
+    let status_output = run_git_command_bytes(&args, repo_dir)?;
+
+    for (raw_path, is_new) in parse_porcelain_z(&status_output) {
+        let repo_path = decode_text(raw_path);
+        let path = format!("{prefix}{repo_path}");
+        let repo_path = repo_path.trim_end_matches('/').to_string();
+
+        if submodules.contains(&repo_path) {
+            if recurse_submodules {
+                let sub_dir = repo_dir.join(&repo_path);
+                let nested = list_submodules(&sub_dir);
+                collect_changed_files(
+                    &sub_dir,
+                    &format!("{}/", path.trim_end_matches('/')),
+                    &nested,
+                    recurse_submodules,
+                    watch_paths,
+                    DEFAULT_DIFF_BASE,
+                    result,
+                )?;
+            } else {
+                result
+                    .skipped_submodules
+                    .push(path.trim_end_matches('/').to_string());
+            }
+            continue;
+        }
+
+        result.files.push(ChangedFile {
+            path,
+            repo_dir: repo_dir.to_path_buf(),
+            repo_path,
+            os_path: path_from_bytes(raw_path.strip_suffix(b"/").unwrap_or(raw_path)),
+            is_new,
+            base: base.to_string(),
+        });
+    }
+
+    // 基準より後にコミットした変更（作業ツリーで変えていないファイル）
+    if base != DEFAULT_DIFF_BASE {
+        let mut args = vec!["diff", "--name-status", "--no-renames", "-z", base, "--"];
+        args.extend(pathspecs.iter().map(String::as_str));
+        let diff_output = run_git_command_bytes(&args, repo_dir)?;
+        for (raw_path, is_new) in parse_name_status_z(&diff_output) {
+            let repo_path = decode_text(raw_path);
+            let path = format!("{prefix}{repo_path}");
+            if submodules.contains(&repo_path) || result.files.iter().any(|f| f.path == path) {
+                continue;
+            }
+            result.files.push(ChangedFile {
+                path,
+                repo_dir: repo_dir.to_path_buf(),
+                repo_path,
+                os_path: path_from_bytes(raw_path),
+                is_new,
+                base: base.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// `watch_paths`を`prefix`（サブモジュールのパス）以下のpathspecに変換する
+///
+/// 空のベクタはリポジトリ全体を、`None`はこのリポジトリに監視対象がないことを表す。
+fn scoped_pathspecs(watch_paths: &[String], prefix: &str) -> Option<Vec<String>> {
+    if watch_paths.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let mut pathspecs = Vec::new();
+    for watch_path in watch_paths {
+        let watch_path = watch_path.trim_matches('/');
+        let scope = prefix.trim_end_matches('/');
+        if scope.is_empty() {
+            pathspecs.push(watch_path.to_string());
+        } else if let Some(rest) = watch_path.strip_prefix(scope)
+            && (rest.is_empty() || rest.starts_with('/'))
+        {
+            let rest = rest.trim_start_matches('/');
+            if rest.is_empty() {
+                // サブモジュール全体が監視対象
+                return Some(Vec::new());
+            }
+            pathspecs.push(rest.to_string());
+        } else if scope.starts_with(&format!("{watch_path}/")) {
+            // 監視対象のディレクトリの中にあるサブモジュール
+            return Some(Vec::new());
+        }
+    }
+
+    if pathspecs.is_empty() {
+        None
+    } else {
+        Some(pathspecs)
+    }
+}
+
+/// `git status --porcelain=v1 -z`の出力からパスと新規ファイルかどうかを取り出す
+///
+/// リネーム・コピーのエントリは移動先のパスのみを返す。パスはUTF-8でないファイル名も扱えるよう、
+/// バイト列のまま返す。
+fn parse_porcelain_z(output: &[u8]) -> Vec<(&[u8], bool)> {
+    let mut paths = Vec::new();
+    let mut entries = output.split(|&b| b == 0).filter(|e| !e.is_empty());
+    while let Some(entry) = entries.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        let (status, path) = entry.split_at(3);
+        let is_new = status.starts_with(b"??") || status.starts_with(b"A");
+        paths.push((path, is_new));
+        // リネーム・コピーの場合は次のエントリが移動元のパス
+        if status.starts_with(b"R") || status.starts_with(b"C") {
+            entries.next();
+        }
+    }
+    paths
+}
+
+/// `git diff --name-status --no-renames -z`の出力からパスと追加されたファイルかどうかを取り出す
+fn parse_name_status_z(output: &[u8]) -> Vec<(&[u8], bool)> {
+    let mut paths = Vec::new();
+    let mut entries = output.split(|&b| b == 0).filter(|e| !e.is_empty());
+    while let (Some(status), Some(path)) = (entries.next(), entries.next()) {
+        paths.push((path, status.starts_with(b"A")));
+    }
+    paths
+}
+
+/// `.gitmodules`に登録されたサブモジュールのパスを列挙する
+fn list_submodules(repo_dir: &Path) -> Vec<String> {
+    if !repo_dir.join(".gitmodules").exists() {
+        return Vec::new();
+    }
+    run_git_command(
+        &[
+            "config",
+            "--file",
+            ".gitmodules",
+            "--get-regexp",
+            r"^submodule\..*\.path$",
+        ],
+        repo_dir,
+    )
+    .map(|output| {
+        output
+            .lines()
+            .filter_map(|line| line.split_once(' ').map(|(_, path)| path.to_string()))
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// `.gitattributes`でバイナリとして扱うよう指定されたファイルか（`binary`または`-diff`）
+pub fn marked_binary(repo_dir: &Path, path: &str) -> bool {
+    run_git_command(&["check-attr", "binary", "diff", "--", path], repo_dir).is_ok_and(|output| {
+        output
+            .lines()
+            .any(|line| line.ends_with(": binary: set") || line.ends_with(": diff: unset"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_test_support::TempRepo;
+
+    #[test]
+    fn scopes_watch_paths_to_submodules() {
+        let watch = vec!["services/api".to_string(), "vendor/lib/src".to_string()];
+        assert_eq!(
+            scoped_pathspecs(&watch, ""),
+            Some(vec![
+                "services/api".to_string(),
+                "vendor/lib/src".to_string()
+            ])
+        );
+        assert_eq!(
+            scoped_pathspecs(&watch, "vendor/lib/"),
+            Some(vec!["src".to_string()])
+        );
+        assert_eq!(scoped_pathspecs(&watch, "other/"), None);
+        assert_eq!(
+            scoped_pathspecs(&["services".to_string()], "services/sub/"),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn splits_batched_diff() {
+        let output = "diff --git a/a b.rs b/a b.rs\nindex 1..2\n--- a/a b.rs\n+++ b/a b.rs\n@@ -1 +1 @@\n-x\n+y\ndiff --git a/src/lib.rs b/src/lib.rs\n+z\n";
+        let diffs = split_diff_by_file(output);
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs["a b.rs"].ends_with("+y\n"));
+        assert_eq!(
+            diffs["src/lib.rs"],
+            "diff --git a/src/lib.rs b/src/lib.rs\n+z\n"
+        );
+    }
+
+    #[test]
+    fn reads_binary_attributes() {
+        let repo = TempRepo::new().unwrap();
+        repo.write(".gitattributes", "*.snap -diff\n*.dat binary\n")
+            .unwrap();
+        assert!(marked_binary(repo.path(), "tests/output.snap"));
+        assert!(marked_binary(repo.path(), "assets/model.dat"));
+        assert!(!marked_binary(repo.path(), "src/lib.rs"));
+    }
+
+    #[test]
+    fn includes_commits_since_the_diff_base() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/lib.rs", "pub fn f() {}\n").unwrap();
+        repo.add(&["src/lib.rs"]).unwrap();
+        repo.commit("初期").unwrap();
+        repo.git(&["branch", "main-line"]).unwrap();
+        repo.write("src/feature.rs", "pub fn g() {}\n").unwrap();
+        repo.add(&["src/feature.rs"]).unwrap();
+        repo.commit("機能").unwrap();
+        repo.write("src/lib.rs", "pub fn f() -> u8 { 1 }\n")
+            .unwrap();
+
+        let layout = RepoLayout::discover(repo.path()).unwrap();
+        let changes = layout.changed_files(false, &[], None).unwrap();
+        let paths: Vec<&str> = changes.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["src/lib.rs"]);
+
+        let changes = layout.changed_files(false, &[], Some("main-line")).unwrap();
+        let paths: Vec<&str> = changes.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["src/lib.rs", "src/feature.rs"]);
+        assert!(changes.files[1].is_new);
+        let diffs = batch_diffs(&changes.files);
+        assert!(diffs["src/feature.rs"].contains("+pub fn g() {}"));
+        assert!(diffs["src/lib.rs"].contains("+pub fn f() -> u8 { 1 }"));
+
+        assert!(
+            layout
+                .changed_files(false, &[], Some("no-such-ref"))
+                .is_err()
+        );
+        assert!(
+            layout
+                .changed_files(false, &[], Some("--output=x"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn transcodes_shift_jis_diffs() {
+        let repo = TempRepo::new().unwrap();
+        let (before, _, _) = encoding_rs::SHIFT_JIS.encode("// 設定を読み込みます\n");
+        std::fs::write(repo.path().join("config.rs"), &before).unwrap();
+        repo.add(&["config.rs"]).unwrap();
+        repo.commit("初期").unwrap();
+        let (after, _, _) = encoding_rs::SHIFT_JIS.encode("// 設定ファイルを読み込みます\n");
+        std::fs::write(repo.path().join("config.rs"), &after).unwrap();
+
+        let layout = RepoLayout::discover(repo.path()).unwrap();
+        let changes = layout.changed_files(false, &[], None).unwrap();
+        let diffs = batch_diffs(&changes.files);
+        assert!(diffs["config.rs"].contains("+// 設定ファイルを読み込みます"));
+        assert!(diffs["config.rs"].contains("-// 設定を読み込みます"));
+    }
+
+    #[test]
+    fn parses_renames_and_untracked() {
+        let output = b" M src/lib.rs\0R  new name.rs\0old name.rs\0?? notes/\0AM src/new.rs\0";
+        assert_eq!(
+            parse_porcelain_z(output),
+            vec![
+                (b"src/lib.rs".as_slice(), false),
+                (b"new name.rs".as_slice(), false),
+                (b"notes/".as_slice(), true),
+                (b"src/new.rs".as_slice(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn batches_diffs_by_repository_and_chunk() {
+        let file = |repo_dir: &str, index: usize| ChangedFile {
+            path: format!("src/file{index}.rs"),
+            repo_dir: PathBuf::from(repo_dir),
+            repo_path: format!("src/file{index}.rs"),
+            os_path: PathBuf::from(format!("src/file{index}.rs")),
+            is_new: false,
+            base: DEFAULT_DIFF_BASE.to_string(),
+        };
+        let files: Vec<ChangedFile> = (0..=DIFF_BATCH_SIZE)
+            .map(|index| file("/repo", index))
+            .chain([file("/repo/vendor/lib", 0)])
+            .collect();
+
+        // 501件のリポジトリは500件と1件の2回、サブモジュールは1回で取得する
+        let mut sizes: Vec<usize> = diff_batches(&files)
+            .iter()
+            .map(|(_, _, batch)| batch.len())
+            .collect();
+        sizes.sort_unstable();
+        assert_eq!(sizes, vec![1, 1, DIFF_BATCH_SIZE]);
+    }
+}
@@ -0,0 +1,562 @@
+use futures::Stream;
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::ambient_annotations::AnnotationsUpdate;
+use crate::ambient_auth::AccessTokens;
+use crate::ambient_auth::Role;
+use crate::ambient_auth::Scope;
+use crate::ambient_auth::scope_denied;
+use crate::ambient_cycle::CycleSummary;
+use crate::ambient_housekeeping::Reminder;
+use crate::ambient_housekeeping::ReminderKind;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::ChatMessage;
+use crate::ambient_server::ControlCommand;
+use crate::ambient_server::EmitEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_server::Finding;
+use crate::ambient_server::FindingRepeat;
+use crate::ambient_server::RunIds;
+use crate::ambient_snapshot::CodeExcerpt;
+use crate::ambient_snapshot::Snapshot;
+
+/// `proto/ambient/v1/ambient.proto`から生成した型
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("ambient.v1");
+}
+
+use proto::ambient_service_server::AmbientService;
+use proto::ambient_service_server::AmbientServiceServer;
+
+/// `ListFindings`のために保持するレビュー結果の件数
+const MAX_RECORDED_FINDINGS: usize = 500;
+
+/// `ListFindings`で件数が指定されなかった場合に返す件数
+const DEFAULT_LIST_LIMIT: usize = 100;
+
+/// `Query`の回答を待つ最大時間（REST APIの`POST /api/query`と共通）
+pub(crate) const QUERY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// gRPCサーバーの起動設定
+#[derive(Debug, Clone)]
+pub struct GrpcOptions {
+    pub port: u16,
+    pub tokens: AccessTokens,
+}
+
+/// gRPCサーバーを`127.0.0.1:<port>`で起動する
+///
+/// `trigger`は`TriggerAnalysis`で通知され、監視ループが即座にチェックを実行する。
+pub async fn run_grpc_server(
+    tx: broadcast::Sender<EventEnvelope>,
+    trigger: Arc<Notify>,
+    options: GrpcOptions,
+    shutdown_signal: impl std::future::Future<Output = ()> + Send + 'static,
+) {
+    let findings = Arc::new(Mutex::new(VecDeque::new()));
+    tokio::spawn(record_findings(tx.subscribe(), findings.clone()));
+
+    let service = AmbientGrpc {
+        tx,
+        trigger,
+        findings,
+        tokens: options.tokens,
+    };
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, options.port));
+    println!("gRPC APIが {addr} で動作中です");
+    if let Err(e) = tonic::transport::Server::builder()
+        .add_service(AmbientServiceServer::new(service))
+        .serve_with_shutdown(addr, shutdown_signal)
+        .await
+    {
+        eprintln!("gRPCサーバーの起動に失敗しました: {e}");
+    }
+}
+
+/// 監視ループのイベントから直近のレビュー結果を記録する
+async fn record_findings(
+    mut rx: broadcast::Receiver<EventEnvelope>,
+    findings: Arc<Mutex<VecDeque<Finding>>>,
+) {
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: event @ (AmbientEvent::Finding(_) | AmbientEvent::Findings(_)),
+                project: None,
+                ..
+            }) => {
+                if let Ok(mut findings) = findings.lock() {
+                    for finding in event.into_findings() {
+                        if findings.len() >= MAX_RECORDED_FINDINGS {
+                            findings.pop_front();
+                        }
+                        findings.push_back(finding);
+                    }
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+struct AmbientGrpc {
+    tx: broadcast::Sender<EventEnvelope>,
+    trigger: Arc<Notify>,
+    findings: Arc<Mutex<VecDeque<Finding>>>,
+    tokens: AccessTokens,
+}
+
+impl AmbientGrpc {
+    /// `authorization: Bearer <token>`メタデータから権限を判定する
+    // gRPCのハンドラーはそのまま`Status`を返すため、箱に包まずに返す
+    #[allow(clippy::result_large_err)]
+    fn role<T>(&self, request: &Request<T>) -> Result<Role, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        self.tokens
+            .role_for(token)
+            .ok_or_else(|| Status::unauthenticated("トークンが正しくありません"))
+    }
+
+    /// トークンに`scope`が許可されているか確認する
+    #[allow(clippy::result_large_err)]
+    fn require<T>(&self, request: &Request<T>, scope: Scope) -> Result<(), Status> {
+        if self.role(request)?.allows(scope) {
+            Ok(())
+        } else {
+            Err(Status::permission_denied(scope_denied(scope)))
+        }
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl AmbientService for AmbientGrpc {
+    type StreamEventsStream = EventStream;
+
+    async fn stream_events(
+        &self,
+        request: Request<proto::StreamEventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        self.require(&request, Scope::Read)?;
+        let stream = futures::stream::unfold(self.tx.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => return Some((Ok(proto::Event::from(event)), rx)),
+                    // 遅れたクライアントは取りこぼしたイベントを飛ばして続ける
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn trigger_analysis(
+        &self,
+        request: Request<proto::TriggerAnalysisRequest>,
+    ) -> Result<Response<proto::TriggerAnalysisResponse>, Status> {
+        self.require(&request, Scope::Control)?;
+        self.trigger.notify_one();
+        Ok(Response::new(proto::TriggerAnalysisResponse {}))
+    }
+
+    async fn query(
+        &self,
+        request: Request<proto::QueryRequest>,
+    ) -> Result<Response<proto::QueryResponse>, Status> {
+        self.require(&request, Scope::Chat)?;
+        let proto::QueryRequest { text, user } = request.into_inner();
+        // チャットのコマンドは`Chat`の権限では実行させない（REST APIと同じく受け付けない）
+        let trimmed = text.trim();
+        if trimmed.is_empty() || trimmed.starts_with('/') {
+            return Err(Status::invalid_argument(
+                "質問が空か、チャットのコマンドです",
+            ));
+        }
+
+        // 回答を取りこぼさないよう、質問を送る前に購読する
+        let mut rx = self.tx.subscribe();
+        if !self.tx.emit(AmbientEvent::UserQuery(ChatMessage::new(
+            user.clone(),
+            text,
+        ))) {
+            return Err(Status::unavailable("監視ループが停止しています"));
+        }
+
+        let wait = async {
+            loop {
+                match rx.recv().await {
+                    Ok(EventEnvelope {
+                        event: AmbientEvent::QueryResponse(response),
+                        ..
+                    }) if response.user == user => {
+                        return Ok(response.text);
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => {
+                        return Err(Status::unavailable("監視ループが停止しています"));
+                    }
+                }
+            }
+        };
+        let text = tokio::time::timeout(QUERY_TIMEOUT, wait)
+            .await
+            .map_err(|_| Status::deadline_exceeded("回答がタイムアウトしました"))??;
+        Ok(Response::new(proto::QueryResponse { text }))
+    }
+
+    async fn list_findings(
+        &self,
+        request: Request<proto::ListFindingsRequest>,
+    ) -> Result<Response<proto::ListFindingsResponse>, Status> {
+        self.require(&request, Scope::Read)?;
+        let proto::ListFindingsRequest { file_path, limit } = request.into_inner();
+        let limit = match limit {
+            0 => DEFAULT_LIST_LIMIT,
+            n => n as usize,
+        };
+        let findings = self
+            .findings
+            .lock()
+            .map_err(|_| Status::internal("レビュー結果を読み出せません"))?;
+        let matching: Vec<&Finding> = findings
+            .iter()
+            .filter(|f| file_path.as_ref().is_none_or(|path| &f.file_path == path))
+            .collect();
+        let findings = matching[matching.len().saturating_sub(limit)..]
+            .iter()
+            .map(|f| proto::Finding::from((*f).clone()))
+            .collect();
+        Ok(Response::new(proto::ListFindingsResponse { findings }))
+    }
+}
+
+impl From<EventEnvelope> for proto::Event {
+    fn from(envelope: EventEnvelope) -> Self {
+        use proto::event::Kind;
+        let EventEnvelope {
+            event,
+            ts,
+            utc_offset,
+            project,
+        } = envelope;
+        let ids = match &event {
+            AmbientEvent::Analysis(message) => message.ids,
+            AmbientEvent::AnalysisDelta(delta) => delta.ids,
+            AmbientEvent::AnalysisComplete(complete) => complete.ids,
+            AmbientEvent::Finding(finding) => finding.ids,
+            AmbientEvent::Findings(findings) => findings
+                .first()
+                .map(|finding| RunIds {
+                    job_id: None,
+                    ..finding.ids
+                })
+                .unwrap_or_default(),
+            AmbientEvent::FindingRepeated(repeat) => repeat.ids,
+            AmbientEvent::CycleSummary(summary) => RunIds {
+                run_id: summary.run_id,
+                job_id: None,
+            },
+            AmbientEvent::AnnotationsUpdated(update) => RunIds {
+                run_id: update.run_id,
+                job_id: None,
+            },
+            _ => RunIds::default(),
+        };
+        let kind = match event {
+            AmbientEvent::Analysis(message) => Kind::Analysis(message.text),
+            AmbientEvent::AnalysisDelta(delta) => Kind::AnalysisDelta(proto::AnalysisDelta {
+                id: delta.id.to_string(),
+                text: delta.text,
+            }),
+            AmbientEvent::AnalysisComplete(complete) => {
+                Kind::AnalysisComplete(proto::AnalysisComplete {
+                    id: complete.id.to_string(),
+                })
+            }
+            AmbientEvent::Finding(finding) => Kind::Finding(finding.into()),
+            AmbientEvent::Findings(findings) => Kind::Findings(proto::FindingList {
+                findings: findings.into_iter().map(Into::into).collect(),
+            }),
+            AmbientEvent::UserQuery(message) => Kind::UserQuery(message.into()),
+            AmbientEvent::QueryResponse(message) => Kind::QueryResponse(message.into()),
+            AmbientEvent::System(text) => Kind::System(text),
+            AmbientEvent::ProjectRoot(path) => Kind::ProjectRoot(path),
+            AmbientEvent::CycleSummary(summary) => Kind::CycleSummary(summary.into()),
+            AmbientEvent::FindingRepeated(repeat) => Kind::FindingRepeated(repeat.into()),
+            AmbientEvent::Reminder(reminder) => Kind::Reminder(reminder.into()),
+            AmbientEvent::AnnotationsUpdated(update) => Kind::AnnotationsUpdated(update.into()),
+            AmbientEvent::Control(command) => Kind::Control(command.into()),
+        };
+        Self {
+            kind: Some(kind),
+            run_id: id_string(ids.run_id),
+            job_id: id_string(ids.job_id),
+            ts,
+            utc_offset,
+            project: project.unwrap_or_default(),
+        }
+    }
+}
+
+/// 未設定のIDは空文字列にする（proto3の既定値）
+fn id_string(id: Option<Uuid>) -> String {
+    id.map(|id| id.to_string()).unwrap_or_default()
+}
+
+impl From<ChatMessage> for proto::ChatMessage {
+    fn from(message: ChatMessage) -> Self {
+        Self {
+            user: message.user,
+            text: message.text,
+        }
+    }
+}
+
+impl From<Finding> for proto::Finding {
+    fn from(finding: Finding) -> Self {
+        Self {
+            run_id: id_string(finding.ids.run_id),
+            job_id: id_string(finding.ids.job_id),
+            file_path: finding.file_path,
+            review: finding.review,
+            message: finding.message,
+            snapshots: finding.snapshots.into_iter().map(Into::into).collect(),
+            owners: finding.owners,
+            labels: finding.labels,
+            confidence: finding.confidence.map(u32::from).unwrap_or_default(),
+            needs_human_review: finding.needs_human_review,
+            severity: finding
+                .severity
+                .map(|severity| severity.as_str().to_string())
+                .unwrap_or_default(),
+            line: finding.line.unwrap_or_default(),
+            suggestion: finding.suggestion.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<FindingRepeat> for proto::FindingRepeat {
+    fn from(repeat: FindingRepeat) -> Self {
+        Self {
+            file_path: repeat.file_path,
+            review: repeat.review,
+            count: repeat.count,
+        }
+    }
+}
+
+impl From<Reminder> for proto::Reminder {
+    fn from(reminder: Reminder) -> Self {
+        let kind = match reminder.kind {
+            ReminderKind::Uncommitted => "uncommitted",
+            ReminderKind::Unpushed => "unpushed",
+            ReminderKind::Behind => "behind",
+        };
+        Self {
+            kind: kind.to_string(),
+            text: reminder.text,
+        }
+    }
+}
+
+impl From<ControlCommand> for proto::Control {
+    fn from(command: ControlCommand) -> Self {
+        match command {
+            ControlCommand::Pause { minutes } => Self {
+                command: "pause".to_string(),
+                minutes,
+            },
+            ControlCommand::Resume => Self {
+                command: "resume".to_string(),
+                minutes: 0,
+            },
+        }
+    }
+}
+
+impl From<AnnotationsUpdate> for proto::AnnotationsUpdate {
+    fn from(update: AnnotationsUpdate) -> Self {
+        Self {
+            path: update.path,
+            files: update.files as u32,
+            annotations: update.annotations as u32,
+        }
+    }
+}
+
+impl From<CycleSummary> for proto::CycleSummary {
+    fn from(summary: CycleSummary) -> Self {
+        Self {
+            started_at: summary.started_at,
+            finished_at: summary.finished_at,
+            analyzed: summary.analyzed,
+            skipped: summary
+                .skipped
+                .into_iter()
+                .map(|skipped| proto::SkippedFile {
+                    path: skipped.path,
+                    reason: skipped.reason.as_str().to_string(),
+                })
+                .collect(),
+            text: summary.text,
+            deferred: summary.deferred,
+        }
+    }
+}
+
+impl From<Snapshot> for proto::Snapshot {
+    fn from(snapshot: Snapshot) -> Self {
+        Self {
+            before: snapshot.before.map(Into::into),
+            after: snapshot.after.map(Into::into),
+        }
+    }
+}
+
+impl From<CodeExcerpt> for proto::CodeExcerpt {
+    fn from(excerpt: CodeExcerpt) -> Self {
+        Self {
+            start_line: u32::try_from(excerpt.start_line).unwrap_or(u32::MAX),
+            text: excerpt.text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_confidence::Severity;
+
+    fn service(tokens: AccessTokens) -> AmbientGrpc {
+        let (tx, _) = broadcast::channel(16);
+        AmbientGrpc {
+            tx,
+            trigger: Arc::new(Notify::new()),
+            findings: Arc::new(Mutex::new(VecDeque::new())),
+            tokens,
+        }
+    }
+
+    fn finding(file_path: &str) -> Finding {
+        Finding {
+            ids: RunIds::new_run().new_job(),
+            file_path: file_path.to_string(),
+            review: "セキュリティ".to_string(),
+            message: "問題ありません".to_string(),
+            snapshots: vec![],
+            owners: vec!["@org/security".to_string()],
+            labels: vec!["security".to_string()],
+            confidence: Some(30),
+            severity: Some(Severity::High),
+            needs_human_review: true,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: None,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn converts_events() {
+        let event = proto::Event::from(EventEnvelope::new(AmbientEvent::QueryResponse(
+            ChatMessage::new(Some("alice".to_string()), "answer".to_string()),
+        )));
+        assert_eq!(
+            event.kind,
+            Some(proto::event::Kind::QueryResponse(proto::ChatMessage {
+                user: Some("alice".to_string()),
+                text: "answer".to_string(),
+            }))
+        );
+        assert_eq!(event.run_id, "");
+
+        let ids = RunIds::new_run().new_job();
+        let event = proto::Event::from(EventEnvelope::new(AmbientEvent::analysis(ids, "分析中")));
+        assert_eq!(event.run_id, id_string(ids.run_id));
+        assert_eq!(event.job_id, id_string(ids.job_id));
+    }
+
+    #[tokio::test]
+    async fn viewer_cannot_trigger_analysis() {
+        let grpc = service(AccessTokens {
+            owner: Some("owner-secret".to_string()),
+            viewer: Some("viewer-secret".to_string()),
+            scoped: vec![],
+        });
+        let mut request = Request::new(proto::TriggerAnalysisRequest {});
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer viewer-secret".parse().unwrap());
+        let status = grpc.trigger_analysis(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let status = grpc
+            .trigger_analysis(Request::new(proto::TriggerAnalysisRequest {}))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn rejects_chat_commands_as_queries() {
+        let grpc = service(AccessTokens::default());
+        let status = grpc
+            .query(Request::new(proto::QueryRequest {
+                text: " /pause 1w".to_string(),
+                user: None,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn lists_recent_findings_for_a_file() {
+        let grpc = service(AccessTokens::default());
+        {
+            let mut findings = grpc.findings.lock().unwrap();
+            findings.push_back(finding("a.rs"));
+            findings.push_back(finding("b.rs"));
+            findings.push_back(finding("a.rs"));
+        }
+        let response = grpc
+            .list_findings(Request::new(proto::ListFindingsRequest {
+                file_path: Some("a.rs".to_string()),
+                limit: 1,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.findings.len(), 1);
+        assert_eq!(response.findings[0].file_path, "a.rs");
+        assert_eq!(response.findings[0].owners, vec!["@org/security"]);
+        assert_eq!(response.findings[0].labels, vec!["security"]);
+        assert_eq!(response.findings[0].confidence, 30);
+        assert!(response.findings[0].needs_human_review);
+        assert_eq!(response.findings[0].severity, "high");
+    }
+}
@@ -0,0 +1,765 @@
+use anyhow::Result;
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::ambient_confidence::Feedback;
+use crate::ambient_confidence::Severity;
+use crate::ambient_locale::Locale;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_storage::StorageCipher;
+use crate::ambient_time::display_offset;
+use crate::ambient_time::now_stamp;
+use crate::ambient_version::VERSION;
+
+/// 履歴ファイル（`.ambient/`からの相対パス）
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// ファイルごとに保持する項目数の上限（種類ごと、古いものから捨てる）
+const MAX_ENTRIES_PER_FILE: usize = 100;
+
+/// レビュー結果の対応状況
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolutionStatus {
+    /// 修正済み
+    Fixed,
+    /// 誤検知
+    FalsePositive,
+    /// 対応しない
+    WontFix,
+}
+
+/// 過去のレビュー結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FindingRecord {
+    /// 発生時刻（RFC3339、UTC）
+    pub ts: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = Uuid)]
+    pub run_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = Uuid)]
+    pub job_id: Option<Uuid>,
+    /// レビューの名前
+    pub review: String,
+    /// モデルの回答（Markdown）
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<u8>,
+    /// 回答したモデル
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 回答したモデルのプロバイダーID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// プロジェクト設定のレビューのプロンプトの雛形のハッシュ（組み込みのレビューではなし）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_hash: Option<String>,
+    /// 記録したAmbient Code Watcherのバージョン（組み込みのレビューのプロンプトはバージョンで決まる）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine_version: Option<String>,
+    /// 指摘した行（1始まり、指摘の一覧を求めたレビューのみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+}
+
+/// レビュー結果への対応の記録（`job_id`と`review`でレビュー結果を特定する）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Resolution {
+    /// 記録した時刻（RFC3339、UTC）
+    pub ts: String,
+    #[schema(value_type = String, format = Uuid)]
+    pub job_id: Uuid,
+    pub review: String,
+    pub status: ResolutionStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// 記録したクライアントの表示名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+impl Resolution {
+    /// 現在時刻で対応を記録する
+    pub fn new(
+        job_id: Uuid,
+        review: String,
+        status: ResolutionStatus,
+        note: Option<String>,
+        user: Option<String>,
+    ) -> Self {
+        Self {
+            ts: now_stamp().0,
+            job_id,
+            review,
+            status,
+            note,
+            user,
+        }
+    }
+}
+
+/// ファイルを分析したチェックサイクル
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct AnalysisRecord {
+    /// サイクルの終了時刻（RFC3339、UTC）
+    pub ts: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = Uuid)]
+    pub run_id: Option<Uuid>,
+}
+
+/// 週次ダイジェスト（`ambient digest`で表示する）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DigestRecord {
+    /// 作成した時刻（RFC3339、UTC）
+    pub ts: String,
+    /// 集計を始めた時刻（RFC3339、UTC）
+    pub since: String,
+    /// 期間中に記録されたレビュー結果の数
+    pub opened: usize,
+    /// 期間中に対応を記録したレビュー結果の数
+    pub closed: usize,
+    /// 本文（Markdown）
+    pub text: String,
+}
+
+/// `GET /api/files/{path}/history`の応答（それぞれ古い順）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FileHistory {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    pub findings: Vec<FindingRecord>,
+    pub resolutions: Vec<Resolution>,
+    pub analyses: Vec<AnalysisRecord>,
+}
+
+/// ファイルのパスを付けたレビュー結果（`ambient history`と`GET /api/history`の出力）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FileFinding {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    #[serde(flatten)]
+    pub finding: FindingRecord,
+}
+
+/// レビュー結果の検索条件
+#[derive(Debug, Clone, Default)]
+pub struct FindingQuery {
+    /// ファイルのパス（globパターン可）
+    pub file: Option<String>,
+    /// この重大度以上のみ（重大度のない結果は除く）
+    pub min_severity: Option<Severity>,
+    /// この時刻以降のみ
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl FindingQuery {
+    fn matches(&self, path: &str, finding: &FindingRecord) -> bool {
+        let file_matches = self.file.as_ref().is_none_or(|file| {
+            file == path
+                || glob::Pattern::new(file)
+                    .ok()
+                    .is_some_and(|pattern| pattern.matches(path))
+        });
+        let severity_matches = self
+            .min_severity
+            .is_none_or(|min| finding.severity.is_some_and(|severity| severity >= min));
+        let since_matches = self.since.is_none_or(|since| {
+            DateTime::parse_from_rfc3339(&finding.ts).is_ok_and(|ts| ts >= since)
+        });
+        file_matches && severity_matches && since_matches
+    }
+}
+
+/// 履歴ファイルの1行
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HistoryLine {
+    path: String,
+    #[serde(flatten)]
+    entry: HistoryEntry,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum HistoryEntry {
+    Finding(FindingRecord),
+    Resolution(Resolution),
+    Analysis(AnalysisRecord),
+    /// ファイルに属さない（`path`は空）
+    Digest(DigestRecord),
+}
+
+/// ファイルごとのレビュー履歴（`.ambient/history.jsonl`に追記して再起動後も保持する）
+#[derive(Debug, Default)]
+pub struct ReviewHistory {
+    file: Option<PathBuf>,
+    files: Mutex<HashMap<String, FileHistory>>,
+    digests: Mutex<Vec<DigestRecord>>,
+    /// `[storage]`で暗号化する場合の鍵
+    cipher: StorageCipher,
+}
+
+impl ReviewHistory {
+    /// プロジェクトの履歴ファイル
+    pub fn file_path(project_root: &Path) -> PathBuf {
+        project_root.join(".ambient").join(HISTORY_FILE)
+    }
+
+    /// プロジェクトの履歴ファイルを読み込む（壊れた行と復号できない行は読み飛ばす）
+    pub fn open(project_root: &Path, cipher: StorageCipher) -> Self {
+        let file = Self::file_path(project_root);
+        let mut files = HashMap::new();
+        let mut digests = Vec::new();
+        let mut unreadable = 0;
+        if let Ok(content) = fs::read_to_string(&file) {
+            for line in content.lines() {
+                let Ok(line) = cipher.open(line) else {
+                    unreadable += 1;
+                    continue;
+                };
+                if let Ok(line) = serde_json::from_str::<HistoryLine>(&line) {
+                    apply(&mut files, &mut digests, line);
+                }
+            }
+        }
+        if unreadable > 0 {
+            tracing::warn!(
+                "{}の{unreadable}行を復号できないため読み飛ばしました（`[storage]`の鍵を確認してください）",
+                file.display()
+            );
+        }
+        Self {
+            file: Some(file),
+            files: Mutex::new(files),
+            digests: Mutex::new(digests),
+            cipher,
+        }
+    }
+
+    /// 記録のあるすべてのファイルの履歴（パスの順）
+    pub fn files(&self) -> Vec<FileHistory> {
+        let Ok(files) = self.files.lock() else {
+            return Vec::new();
+        };
+        let mut files: Vec<FileHistory> = files.values().cloned().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files
+    }
+
+    /// ファイルの履歴（記録がなければ`None`）
+    pub fn file(&self, path: &str) -> Option<FileHistory> {
+        self.files.lock().ok()?.get(path).cloned()
+    }
+
+    /// すべてのファイルで、このレビューの結果にユーザーが付けた判断の件数
+    pub fn feedback(&self, review: &str) -> Feedback {
+        let mut feedback = Feedback::default();
+        let Ok(files) = self.files.lock() else {
+            return feedback;
+        };
+        for resolution in files
+            .values()
+            .flat_map(|file| &file.resolutions)
+            .filter(|resolution| resolution.review == review)
+        {
+            match resolution.status {
+                ResolutionStatus::Fixed => feedback.fixed += 1,
+                ResolutionStatus::FalsePositive => feedback.false_positive += 1,
+                ResolutionStatus::WontFix => {}
+            }
+        }
+        feedback
+    }
+
+    /// 条件に一致するレビュー結果（古い順）
+    pub fn findings(&self, query: &FindingQuery) -> Vec<FileFinding> {
+        let Ok(files) = self.files.lock() else {
+            return Vec::new();
+        };
+        let mut findings: Vec<FileFinding> = files
+            .values()
+            .flat_map(|file| {
+                file.findings
+                    .iter()
+                    .filter(|finding| query.matches(&file.path, finding))
+                    .map(|finding| FileFinding {
+                        path: file.path.clone(),
+                        finding: finding.clone(),
+                    })
+            })
+            .collect();
+        findings.sort_by(|a, b| a.finding.ts.cmp(&b.finding.ts));
+        findings
+    }
+
+    /// 条件に一致する新しいレビュー結果を`limit`件まで（古い順）
+    pub fn recent_findings(&self, query: &FindingQuery, limit: usize) -> Vec<FileFinding> {
+        let mut findings = self.findings(query);
+        let older = findings.len().saturating_sub(limit);
+        findings.drain(..older);
+        findings
+    }
+
+    /// この時刻以降にファイルを分析した時刻（古い順、ファイルが変更された時刻の目安）
+    pub fn activity(&self, since: DateTime<Utc>) -> Vec<(String, DateTime<Utc>)> {
+        let Ok(files) = self.files.lock() else {
+            return Vec::new();
+        };
+        let mut activity: Vec<(String, DateTime<Utc>)> = files
+            .values()
+            .flat_map(|file| {
+                file.analyses.iter().filter_map(|analysis| {
+                    let ts = DateTime::parse_from_rfc3339(&analysis.ts).ok()?;
+                    let ts = ts.with_timezone(&Utc);
+                    (ts >= since).then(|| (file.path.clone(), ts))
+                })
+            })
+            .collect();
+        activity.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        activity
+    }
+
+    /// この時刻以降に記録された対応（古い順）
+    pub fn resolutions(&self, since: DateTime<Utc>) -> Vec<Resolution> {
+        let Ok(files) = self.files.lock() else {
+            return Vec::new();
+        };
+        let mut resolutions: Vec<Resolution> = files
+            .values()
+            .flat_map(|file| &file.resolutions)
+            .filter(|resolution| {
+                DateTime::parse_from_rfc3339(&resolution.ts).is_ok_and(|ts| ts >= since)
+            })
+            .cloned()
+            .collect();
+        resolutions.sort_by(|a, b| a.ts.cmp(&b.ts));
+        resolutions
+    }
+
+    /// 作成した週次ダイジェスト（古い順）
+    pub fn digests(&self) -> Vec<DigestRecord> {
+        self.digests
+            .lock()
+            .map(|digests| digests.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn record_finding(&self, path: &str, finding: FindingRecord) {
+        self.record(path, HistoryEntry::Finding(finding));
+    }
+
+    pub fn record_resolution(&self, path: &str, resolution: Resolution) {
+        self.record(path, HistoryEntry::Resolution(resolution));
+    }
+
+    pub fn record_analysis(&self, path: &str, analysis: AnalysisRecord) {
+        self.record(path, HistoryEntry::Analysis(analysis));
+    }
+
+    pub fn record_digest(&self, digest: DigestRecord) {
+        self.record("", HistoryEntry::Digest(digest));
+    }
+
+    fn record(&self, path: &str, entry: HistoryEntry) {
+        let line = HistoryLine {
+            path: path.to_string(),
+            entry,
+        };
+        if let Some(file) = &self.file
+            && let Err(e) = append_line(file, &line, &self.cipher)
+        {
+            tracing::warn!("failed to write review history to {}: {e}", file.display());
+        }
+        if let (Ok(mut files), Ok(mut digests)) = (self.files.lock(), self.digests.lock()) {
+            apply(&mut files, &mut digests, line);
+        }
+    }
+}
+
+fn apply(
+    files: &mut HashMap<String, FileHistory>,
+    digests: &mut Vec<DigestRecord>,
+    line: HistoryLine,
+) {
+    let path = line.path;
+    match line.entry {
+        HistoryEntry::Finding(finding) => {
+            push_capped(&mut file_history(files, path).findings, finding)
+        }
+        HistoryEntry::Resolution(resolution) => {
+            push_capped(&mut file_history(files, path).resolutions, resolution)
+        }
+        HistoryEntry::Analysis(analysis) => {
+            push_capped(&mut file_history(files, path).analyses, analysis)
+        }
+        HistoryEntry::Digest(digest) => push_capped(digests, digest),
+    }
+}
+
+fn file_history(files: &mut HashMap<String, FileHistory>, path: String) -> &mut FileHistory {
+    files.entry(path.clone()).or_insert_with(|| FileHistory {
+        path,
+        ..FileHistory::default()
+    })
+}
+
+fn push_capped<T>(entries: &mut Vec<T>, entry: T) {
+    if entries.len() >= MAX_ENTRIES_PER_FILE {
+        entries.remove(0);
+    }
+    entries.push(entry);
+}
+
+fn append_line(file: &Path, line: &HistoryLine, cipher: &StorageCipher) -> anyhow::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+        // 履歴ファイル自体が変更ファイルとして検出されないようにする
+        let gitignore = dir.join(".gitignore");
+        if !gitignore.exists() {
+            fs::write(&gitignore, format!("{HISTORY_FILE}\n"))?;
+        }
+    }
+    let mut out = OpenOptions::new().create(true).append(true).open(file)?;
+    writeln!(out, "{}", cipher.seal(&serde_json::to_string(line)?)?)?;
+    Ok(())
+}
+
+/// `30m`、`12h`、`7d`、`2w`形式の期間を、現在からさかのぼった時刻にする
+pub fn parse_since(value: &str) -> Result<DateTime<Utc>> {
+    let duration = parse_duration(value)?;
+    Utc::now()
+        .checked_sub_signed(duration)
+        .ok_or_else(|| anyhow::anyhow!("期間が長すぎます: {}", value.trim()))
+}
+
+/// `30m`、`12h`、`7d`、`2w`形式の期間
+pub fn parse_duration(value: &str) -> Result<chrono::Duration> {
+    let value = value.trim();
+    let split = value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| anyhow::anyhow!("期間を解析できません: {value}（例: 30m、12h、7d）"))?;
+    match unit {
+        "s" => chrono::Duration::try_seconds(amount),
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        "w" => chrono::Duration::try_weeks(amount),
+        _ => anyhow::bail!("期間の単位はs、m、h、d、wのいずれかです: {value}"),
+    }
+    .ok_or_else(|| anyhow::anyhow!("期間が長すぎます: {value}"))
+}
+
+/// レビュー結果を1件1行の表にする（回答は1行目のみ、日時と件数は`locale`の書式）
+pub fn render_table(findings: &[FileFinding], locale: Locale) -> String {
+    let mut out = match locale {
+        Locale::Ja => {
+            String::from("日時                      重大度  確信度  ファイル  レビュー  内容\n")
+        }
+        Locale::En => {
+            String::from("Date                      Severity  Confidence  File  Review  Message\n")
+        }
+    };
+    let offset = display_offset();
+    for FileFinding { path, finding } in findings {
+        let severity = finding.severity.map_or("-", Severity::as_str);
+        let confidence = finding
+            .confidence
+            .map_or_else(|| "-".to_string(), |c| c.to_string());
+        let summary = finding
+            .message
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "{:<24}  {severity:<8}  {confidence:>6}  {path}  {}  {summary}\n",
+            locale.format_datetime(&finding.ts, offset),
+            finding.review
+        ));
+    }
+    out.push_str(&locale.format_count(findings.len(), "件", "finding"));
+    out.push('\n');
+    out
+}
+
+/// 監視ループのレビュー結果と分析したファイルを履歴に記録する
+pub async fn record_history(
+    mut rx: broadcast::Receiver<EventEnvelope>,
+    history: Arc<ReviewHistory>,
+) {
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: event @ (AmbientEvent::Finding(_) | AmbientEvent::Findings(_)),
+                project: None,
+                ts,
+                ..
+            }) => {
+                for finding in event.into_findings() {
+                    history.record_finding(
+                        &finding.file_path,
+                        FindingRecord {
+                            ts: ts.clone(),
+                            run_id: finding.ids.run_id,
+                            job_id: finding.ids.job_id,
+                            review: finding.review,
+                            message: finding.message,
+                            severity: finding.severity,
+                            confidence: finding.confidence,
+                            model: finding.model,
+                            provider: finding.provider,
+                            prompt_hash: finding.prompt_hash,
+                            engine_version: Some(VERSION.to_string()),
+                            line: finding.line,
+                        },
+                    );
+                }
+            }
+            Ok(EventEnvelope {
+                event: AmbientEvent::CycleSummary(summary),
+                project: None,
+                ..
+            }) => {
+                for path in &summary.analyzed {
+                    history.record_analysis(
+                        path,
+                        AnalysisRecord {
+                            ts: summary.finished_at.clone(),
+                            run_id: summary.run_id,
+                        },
+                    );
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::EmitEvent;
+    use crate::ambient_server::Finding;
+    use crate::ambient_server::RunIds;
+    use tempfile::tempdir;
+
+    fn finding(review: &str) -> FindingRecord {
+        FindingRecord {
+            ts: "2026-01-01T00:00:00.000Z".to_string(),
+            run_id: None,
+            job_id: Some(Uuid::new_v4()),
+            review: review.to_string(),
+            message: "問題があります".to_string(),
+            severity: Some(Severity::Medium),
+            confidence: None,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            engine_version: None,
+            line: None,
+        }
+    }
+
+    #[test]
+    fn persists_history_across_reopen() {
+        let dir = tempdir().unwrap();
+        let history = ReviewHistory::open(dir.path(), StorageCipher::default());
+        let first = finding("セキュリティ");
+        history.record_finding("src/lib.rs", first.clone());
+        history.record_finding("src/main.rs", finding("構文"));
+        history.record_resolution(
+            "src/lib.rs",
+            Resolution::new(
+                first.job_id.unwrap(),
+                first.review.clone(),
+                ResolutionStatus::FalsePositive,
+                None,
+                Some("alice".to_string()),
+            ),
+        );
+
+        let reopened = ReviewHistory::open(dir.path(), StorageCipher::default());
+        let file = reopened.file("src/lib.rs").unwrap();
+        assert_eq!(file.findings, vec![first]);
+        assert_eq!(file.resolutions.len(), 1);
+        assert_eq!(file.resolutions[0].status, ResolutionStatus::FalsePositive);
+        assert!(reopened.file("src/other.rs").is_none());
+        assert_eq!(
+            reopened.feedback("セキュリティ"),
+            Feedback {
+                fixed: 0,
+                false_positive: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn filters_findings_by_file_severity_and_time() {
+        let history = ReviewHistory::default();
+        let mut old = finding("セキュリティ");
+        old.ts = "2020-01-01T00:00:00.000Z".to_string();
+        old.severity = Some(Severity::High);
+        history.record_finding("src/lib.rs", old);
+        let mut recent = finding("構文");
+        recent.ts = "2026-01-02T00:00:00.000Z".to_string();
+        recent.severity = Some(Severity::High);
+        history.record_finding("src/lib.rs", recent.clone());
+        history.record_finding("src/main.rs", finding("構文"));
+
+        let query = FindingQuery {
+            file: Some("src/*.rs".to_string()),
+            min_severity: Some(Severity::High),
+            since: DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z")
+                .ok()
+                .map(|ts| ts.with_timezone(&Utc)),
+        };
+        assert_eq!(
+            history.findings(&query),
+            vec![FileFinding {
+                path: "src/lib.rs".to_string(),
+                finding: recent,
+            }]
+        );
+        assert_eq!(history.findings(&FindingQuery::default()).len(), 3);
+    }
+
+    #[test]
+    fn keeps_only_the_newest_findings_with_their_model() {
+        let dir = tempdir().unwrap();
+        let history = ReviewHistory::open(dir.path(), StorageCipher::default());
+        for (day, review) in [(3, "構文"), (1, "セキュリティ"), (2, "設計")] {
+            let mut record = finding(review);
+            record.ts = format!("2026-01-0{day}T00:00:00.000Z");
+            record.model = Some("gpt-oss:20b".to_string());
+            history.record_finding("src/lib.rs", record);
+        }
+
+        let reopened = ReviewHistory::open(dir.path(), StorageCipher::default());
+        let recent = reopened.recent_findings(&FindingQuery::default(), 2);
+        let reviews: Vec<&str> = recent.iter().map(|f| f.finding.review.as_str()).collect();
+        assert_eq!(reviews, vec!["設計", "構文"]);
+        assert_eq!(recent[0].finding.model.as_deref(), Some("gpt-oss:20b"));
+        assert_eq!(
+            reopened.recent_findings(&FindingQuery::default(), 10).len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn records_the_model_and_prompt_behind_each_finding() {
+        let history = Arc::new(ReviewHistory::default());
+        let (tx, rx) = broadcast::channel::<EventEnvelope>(16);
+        let recorder = tokio::spawn(record_history(rx, history.clone()));
+        let _ = tx.emit(AmbientEvent::Finding(Finding {
+            ids: RunIds::new_run().new_job(),
+            file_path: "src/lib.rs".to_string(),
+            review: "unwrap".to_string(),
+            message: "unwrapを使っています".to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity: None,
+            needs_human_review: false,
+            model: Some("gpt-oss:20b".to_string()),
+            provider: Some("oss".to_string()),
+            prompt_hash: Some("0123456789abcdef".to_string()),
+            line: Some(3),
+            suggestion: None,
+            cross_references: vec![],
+        }));
+        drop(tx);
+        recorder.await.unwrap();
+
+        let recorded = history.recent_findings(&FindingQuery::default(), 1);
+        let finding = &recorded[0].finding;
+        assert_eq!(finding.model.as_deref(), Some("gpt-oss:20b"));
+        assert_eq!(finding.provider.as_deref(), Some("oss"));
+        assert_eq!(finding.prompt_hash.as_deref(), Some("0123456789abcdef"));
+        assert_eq!(finding.engine_version.as_deref(), Some(VERSION));
+    }
+
+    #[test]
+    fn lists_analysis_times_since() {
+        let history = ReviewHistory::default();
+        for (path, ts) in [
+            ("src/main.rs", "2026-01-02T00:00:00.000Z"),
+            ("src/lib.rs", "2026-01-01T00:00:00.000Z"),
+            ("src/lib.rs", "2025-12-31T00:00:00.000Z"),
+        ] {
+            history.record_analysis(
+                path,
+                AnalysisRecord {
+                    ts: ts.to_string(),
+                    run_id: None,
+                },
+            );
+        }
+        let since = DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let paths: Vec<String> = history
+            .activity(since)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, vec!["src/lib.rs", "src/main.rs"]);
+    }
+
+    #[test]
+    fn parses_since_durations() {
+        let since = parse_since("7d").unwrap();
+        let days = (Utc::now() - since).num_days();
+        assert_eq!(days, 7);
+        assert!(parse_since("7y").is_err());
+        assert!(parse_since("d").is_err());
+    }
+
+    #[test]
+    fn caps_entries_per_file() {
+        let history = ReviewHistory::default();
+        for _ in 0..MAX_ENTRIES_PER_FILE + 5 {
+            history.record_finding("src/lib.rs", finding("構文"));
+        }
+        assert_eq!(
+            history.file("src/lib.rs").unwrap().findings.len(),
+            MAX_ENTRIES_PER_FILE
+        );
+    }
+
+    #[test]
+    fn renders_table_in_configured_locale() {
+        let findings = vec![FileFinding {
+            path: "src/lib.rs".to_string(),
+            finding: finding("セキュリティ"),
+        }];
+        let ja = render_table(&findings, Locale::Ja);
+        assert!(ja.starts_with("日時"));
+        assert!(ja.contains("2026-01-01 ") || ja.contains("2025-12-31 "));
+        assert!(ja.ends_with("1件\n"));
+        let en = render_table(&findings, Locale::En);
+        assert!(en.starts_with("Date"));
+        assert!(en.ends_with("1 finding\n"));
+    }
+}
@@ -0,0 +1,352 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tokio::sync::broadcast;
+
+use crate::ambient_git::run_git_command;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EmitEvent;
+use crate::ambient_server::EventEnvelope;
+
+/// `.ambient/config.toml`の`[housekeeping]`（作業の放置を知らせる低頻度の確認）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct HousekeepingConfig {
+    /// 確認を行うか
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// 確認の間隔（秒）
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+
+    /// 未コミットの変更がこの時間（時間）より古ければ知らせる
+    #[serde(default = "default_dirty_hours")]
+    pub dirty_hours: u64,
+
+    /// pushしていないコミットがこの時間（時間）より古ければ知らせる
+    #[serde(default = "default_unpushed_hours")]
+    pub unpushed_hours: u64,
+
+    /// upstreamからこのコミット数以上遅れていれば知らせる
+    #[serde(default = "default_behind_commits")]
+    pub behind_commits: u64,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_interval() -> u64 {
+    3600
+}
+
+fn default_dirty_hours() -> u64 {
+    4
+}
+
+fn default_unpushed_hours() -> u64 {
+    24
+}
+
+fn default_behind_commits() -> u64 {
+    20
+}
+
+impl Default for HousekeepingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: default_interval(),
+            dirty_hours: default_dirty_hours(),
+            unpushed_hours: default_unpushed_hours(),
+            behind_commits: default_behind_commits(),
+        }
+    }
+}
+
+/// リマインダーの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReminderKind {
+    /// 未コミットの変更が長く残っている
+    Uncommitted,
+    /// pushしていないコミットがある
+    Unpushed,
+    /// upstreamから大きく遅れている
+    Behind,
+}
+
+/// 作業の放置を知らせるメッセージ
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reminder {
+    pub kind: ReminderKind,
+    pub text: String,
+}
+
+/// upstreamとの差
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UpstreamStatus {
+    upstream: String,
+    ahead: u64,
+    behind: u64,
+    /// pushしていないコミットのうち最も古いもののコミット日時（UNIX時間）
+    oldest_unpushed: Option<u64>,
+}
+
+/// リポジトリの状態を確認し、知らせるべきことを返す
+///
+/// upstreamとの比較は最後にfetchした時点のリモート追跡ブランチに対して行う（fetchはしない）。
+pub fn check(root: &Path, config: &HousekeepingConfig, now: SystemTime) -> Vec<Reminder> {
+    let mut reminders = Vec::new();
+
+    if let Ok(Some((age, count))) = uncommitted_age(root, now)
+        && age >= hours(config.dirty_hours)
+    {
+        reminders.push(Reminder {
+            kind: ReminderKind::Uncommitted,
+            text: format!(
+                "{count}件の未コミットの変更が{}時間以上残っています。区切りのよいところでコミットしませんか？",
+                age.as_secs() / 3600
+            ),
+        });
+    }
+
+    let Ok(Some(status)) = upstream_status(root) else {
+        return reminders;
+    };
+    if status.ahead > 0
+        && let Some(oldest) = status.oldest_unpushed
+    {
+        let age = now
+            .duration_since(UNIX_EPOCH + Duration::from_secs(oldest))
+            .unwrap_or_default();
+        if age >= hours(config.unpushed_hours) {
+            reminders.push(Reminder {
+                kind: ReminderKind::Unpushed,
+                text: format!(
+                    "{}にpushしていないコミットが{}件あります（最も古いものは{}時間前）",
+                    status.upstream,
+                    status.ahead,
+                    age.as_secs() / 3600
+                ),
+            });
+        }
+    }
+    if config.behind_commits > 0 && status.behind >= config.behind_commits {
+        reminders.push(Reminder {
+            kind: ReminderKind::Behind,
+            text: format!(
+                "{}から{}コミット遅れています。早めに取り込むと衝突を減らせます",
+                status.upstream, status.behind
+            ),
+        });
+    }
+    reminders
+}
+
+fn hours(hours: u64) -> Duration {
+    Duration::from_secs(hours * 3600)
+}
+
+/// 未コミットの変更のうち最も古い更新からの経過時間と、変更の数（変更がなければ`None`）
+fn uncommitted_age(root: &Path, now: SystemTime) -> Result<Option<(Duration, usize)>> {
+    let output = run_git_command(
+        &["-c", "core.quotePath=false", "status", "--porcelain"],
+        root,
+    )?;
+    let paths: Vec<&str> = output
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .map(|path| path.rsplit(" -> ").next().unwrap_or(path))
+        .collect();
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    // 削除されたファイルは更新日時がわからないので数にだけ含める
+    let oldest = paths
+        .iter()
+        .filter_map(|path| fs::metadata(root.join(path)).ok()?.modified().ok())
+        .min()
+        .unwrap_or(now);
+    Ok(Some((
+        now.duration_since(oldest).unwrap_or_default(),
+        paths.len(),
+    )))
+}
+
+/// upstreamとの差（upstreamが設定されていなければ`None`）
+fn upstream_status(root: &Path) -> Result<Option<UpstreamStatus>> {
+    let Ok(upstream) = run_git_command(
+        &[
+            "rev-parse",
+            "--abbrev-ref",
+            "--symbolic-full-name",
+            "@{upstream}",
+        ],
+        root,
+    ) else {
+        return Ok(None);
+    };
+    let counts = run_git_command(
+        &["rev-list", "--left-right", "--count", "@{upstream}...HEAD"],
+        root,
+    )?;
+    let mut counts = counts.split_whitespace().map(str::parse::<u64>);
+    let (Some(Ok(behind)), Some(Ok(ahead))) = (counts.next(), counts.next()) else {
+        anyhow::bail!("upstreamとの差を読み取れません");
+    };
+    let oldest_unpushed = if ahead > 0 {
+        run_git_command(&["log", "--format=%ct", "@{upstream}..HEAD"], root)?
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .min()
+    } else {
+        None
+    };
+    Ok(Some(UpstreamStatus {
+        upstream: upstream.trim().to_string(),
+        ahead,
+        behind,
+        oldest_unpushed,
+    }))
+}
+
+/// `enabled`の場合だけ、リマインダーの確認を始める
+pub fn spawn_housekeeping(
+    root: PathBuf,
+    config: &HousekeepingConfig,
+    tx: &broadcast::Sender<EventEnvelope>,
+) {
+    if config.enabled {
+        tokio::spawn(run_housekeeping(root, config.clone(), tx.clone()));
+    }
+}
+
+/// 一定間隔でリポジトリを確認し、新しく当てはまったリマインダーを配信する
+///
+/// 同じ種類のリマインダーは、状態が解消されるまで繰り返さない。
+async fn run_housekeeping(
+    root: PathBuf,
+    config: HousekeepingConfig,
+    tx: broadcast::Sender<EventEnvelope>,
+) {
+    let period = Duration::from_secs(config.interval_secs.max(60));
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    let mut active: BTreeSet<ReminderKind> = BTreeSet::new();
+    loop {
+        ticker.tick().await;
+        let (root, config) = (root.clone(), config.clone());
+        let Ok(reminders) =
+            tokio::task::spawn_blocking(move || check(&root, &config, SystemTime::now())).await
+        else {
+            continue;
+        };
+        let current = reminders.iter().map(|reminder| reminder.kind).collect();
+        for reminder in reminders {
+            if !active.contains(&reminder.kind) {
+                let _ = tx.emit(AmbientEvent::Reminder(reminder));
+            }
+        }
+        active = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_test_support::TempRepo;
+
+    fn remote_with_clone() -> (TempRepo, tempfile::TempDir) {
+        let remote = TempRepo::new().unwrap();
+        remote.write("README.md", "hello\n").unwrap();
+        remote.add(&["README.md"]).unwrap();
+        remote.commit("initial").unwrap();
+        let clone = tempfile::tempdir().unwrap();
+        run_git_command(
+            &[
+                "clone",
+                "-q",
+                &remote.path().display().to_string(),
+                &clone.path().display().to_string(),
+            ],
+            remote.path(),
+        )
+        .unwrap();
+        for (key, value) in [
+            ("user.name", "ambient-test"),
+            ("user.email", "ambient-test@example.com"),
+        ] {
+            run_git_command(&["config", key, value], clone.path()).unwrap();
+        }
+        (remote, clone)
+    }
+
+    #[test]
+    fn reminds_about_old_uncommitted_changes() {
+        let repo = TempRepo::new().unwrap();
+        let config = HousekeepingConfig::default();
+        assert!(check(repo.path(), &config, SystemTime::now()).is_empty());
+
+        repo.write("src/lib.rs", "fn main() {}").unwrap();
+        assert!(check(repo.path(), &config, SystemTime::now()).is_empty());
+
+        let later = SystemTime::now() + hours(5);
+        let reminders = check(repo.path(), &config, later);
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0].kind, ReminderKind::Uncommitted);
+        assert!(
+            reminders[0]
+                .text
+                .starts_with("1件の未コミットの変更が5時間以上")
+        );
+    }
+
+    #[test]
+    fn reminds_about_unpushed_and_behind() {
+        let (remote, clone) = remote_with_clone();
+        let config = HousekeepingConfig {
+            behind_commits: 2,
+            ..Default::default()
+        };
+        assert!(check(clone.path(), &config, SystemTime::now()).is_empty());
+
+        fs::write(clone.path().join("local.txt"), "local\n").unwrap();
+        run_git_command(&["add", "local.txt"], clone.path()).unwrap();
+        run_git_command(&["commit", "-q", "-m", "local"], clone.path()).unwrap();
+        for i in 0..2 {
+            remote.write("README.md", &format!("hello {i}\n")).unwrap();
+            remote.add(&["README.md"]).unwrap();
+            remote.commit("upstream").unwrap();
+        }
+        run_git_command(&["fetch", "-q"], clone.path()).unwrap();
+
+        let kinds = |now| {
+            check(clone.path(), &config, now)
+                .into_iter()
+                .map(|reminder| reminder.kind)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds(SystemTime::now()), vec![ReminderKind::Behind]);
+        assert_eq!(
+            kinds(SystemTime::now() + hours(25)),
+            vec![ReminderKind::Unpushed, ReminderKind::Behind]
+        );
+    }
+
+    #[test]
+    fn ignores_branches_without_upstream() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("a.txt", "a").unwrap();
+        repo.add(&["a.txt"]).unwrap();
+        repo.commit("initial").unwrap();
+        assert_eq!(upstream_status(repo.path()).unwrap(), None);
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use crate::ambient_config::HttpClientSettings;
+
+/// 設定に従って接続プールを調整した`reqwest::Client`を作る
+///
+/// 作成したクライアントは実行中ずっと使い回し、プロバイダーへの接続を再利用する。
+pub fn build_http_client(settings: &HttpClientSettings) -> anyhow::Result<reqwest::Client> {
+    let keepalive =
+        (settings.tcp_keepalive_secs > 0).then(|| Duration::from_secs(settings.tcp_keepalive_secs));
+    reqwest::Client::builder()
+        .pool_idle_timeout(Duration::from_secs(settings.pool_idle_timeout_secs))
+        .pool_max_idle_per_host(settings.pool_max_idle_per_host)
+        .tcp_nodelay(settings.tcp_nodelay)
+        .tcp_keepalive(keepalive)
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+        .build()
+        .map_err(|e| anyhow::anyhow!("HTTPクライアントを作成できません: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_partial_settings_with_defaults() {
+        let settings: HttpClientSettings = toml::from_str("connect_timeout_secs = 30").unwrap();
+        assert_eq!(settings.connect_timeout_secs, 30);
+        assert_eq!(
+            settings.pool_idle_timeout_secs,
+            HttpClientSettings::default().pool_idle_timeout_secs
+        );
+        assert!(build_http_client(&settings).is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::ambient_encoding::read_text;
+use crate::ambient_symbols::CrossReference;
+use crate::ambient_symbols::SignatureChange;
+use crate::ambient_symbols::SymbolIndex;
+
+/// 影響範囲の指摘のレビュー名
+pub const IMPACT_REVIEW_NAME: &str = "変更の影響範囲";
+
+/// 影響範囲の指摘に付けるポリシーのラベル
+pub const IMPACT_LABEL: &str = "impact";
+
+/// レビューのプロンプトに付ける呼び出し元の最大数
+const MAX_CALL_SITE_SAMPLES: usize = 10;
+
+/// シグネチャが変わった公開関数と、その呼び出し元
+#[derive(Debug, Clone, Default)]
+pub struct ImpactReport {
+    pub changes: Vec<SignatureChange>,
+}
+
+impl ImpactReport {
+    /// diffでシグネチャが変わった公開関数を索引から探す
+    pub fn collect(index: &SymbolIndex, path: &str, diff: &str) -> Self {
+        Self {
+            changes: index.changed_signatures(path, diff),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// 変わった関数の定義と参照箇所（指摘に添える）
+    pub fn symbols(&self) -> Vec<CrossReference> {
+        self.changes
+            .iter()
+            .map(|change| change.symbol.clone())
+            .collect()
+    }
+
+    /// 更新が必要と思われるファイル（パスの順）
+    pub fn affected_files(&self) -> BTreeSet<&str> {
+        self.changes
+            .iter()
+            .flat_map(|change| change.referencing_files.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// 指摘の本文（関数ごとに、呼び出し元のファイルを列挙する）
+    pub fn describe(&self) -> String {
+        let mut message = format!(
+            "公開関数のシグネチャが変わりました。次の{}個のファイルの呼び出し元も更新が必要な可能性があります。\n",
+            self.affected_files().len()
+        );
+        for change in &self.changes {
+            let symbol = &change.symbol;
+            message.push_str(&format!(
+                "\n`{}`（{}:{}）\n",
+                symbol.name, symbol.defined_in.path, symbol.defined_in.line
+            ));
+            for file in &change.referencing_files {
+                let lines: Vec<String> = symbol
+                    .referenced_from
+                    .iter()
+                    .filter(|location| location.path == *file)
+                    .map(|location| location.line.to_string())
+                    .collect();
+                if lines.is_empty() {
+                    message.push_str(&format!("- {file}\n"));
+                } else {
+                    message.push_str(&format!("- {file}（{}行目）\n", lines.join(", ")));
+                }
+            }
+        }
+        message
+    }
+
+    /// レビューのプロンプトに付ける、呼び出し元のコードの抜粋（最大10件）
+    pub fn render_call_sites(&self, root: &Path) -> String {
+        let mut text = String::from(
+            "\n\n---\n\n以下の公開関数のシグネチャが変わっています。呼び出し元の抜粋と食い違いがないかも確認してください。\n",
+        );
+        let mut samples = 0;
+        for change in &self.changes {
+            let symbol = &change.symbol;
+            text.push_str(&format!(
+                "\n`{}`の呼び出し元（全{}件）:\n",
+                symbol.name, symbol.reference_count
+            ));
+            for location in &symbol.referenced_from {
+                if samples == MAX_CALL_SITE_SAMPLES {
+                    break;
+                }
+                let Some(code) = source_line(root, &location.path, location.line) else {
+                    continue;
+                };
+                text.push_str(&format!(
+                    "- `{}:{}`: `{}`\n",
+                    location.path,
+                    location.line,
+                    code.trim()
+                ));
+                samples += 1;
+            }
+        }
+        text
+    }
+}
+
+/// ファイルの`line`行目（1始まり）
+fn source_line(root: &Path, path: &str, line: u32) -> Option<String> {
+    let content = read_text(&root.join(path)).ok()?;
+    content
+        .lines()
+        .nth(line.checked_sub(1)? as usize)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_test_support::TempRepo;
+
+    #[test]
+    fn lists_callers_of_changed_public_functions() {
+        let repo = TempRepo::new().unwrap();
+        repo.write(
+            "src/lib.rs",
+            "pub fn parse(text: &str) -> usize {\n    text.len()\n}\n",
+        )
+        .unwrap();
+        repo.write(
+            "src/main.rs",
+            "fn main() {\n    let n = lib::parse(\"a\");\n}\n",
+        )
+        .unwrap();
+        repo.write("src/cli.rs", "fn run() {\n    lib::parse(\"b\");\n}\n")
+            .unwrap();
+        repo.add(&["src/lib.rs", "src/main.rs", "src/cli.rs"])
+            .unwrap();
+        let index = SymbolIndex::default();
+        index.sync_repository(repo.path()).unwrap();
+
+        let diff = "@@ -1,1 +1,1 @@\n-pub fn parse(text: String) -> usize {\n+pub fn parse(text: &str) -> usize {\n";
+        let report = ImpactReport::collect(&index, "src/lib.rs", diff);
+        assert_eq!(
+            report.affected_files(),
+            BTreeSet::from(["src/cli.rs", "src/main.rs"])
+        );
+        assert!(report.describe().contains("- src/main.rs（2行目）"));
+        let call_sites = report.render_call_sites(repo.path());
+        assert!(call_sites.contains("- `src/cli.rs:2`: `lib::parse(\"b\");`"));
+        assert!(call_sites.contains("全2件"));
+
+        // 本体だけの変更は影響範囲として扱わない
+        let diff = "@@ -2,1 +2,1 @@\n-    text.chars().count()\n+    text.len()\n";
+        assert!(ImpactReport::collect(&index, "src/lib.rs", diff).is_empty());
+    }
+}
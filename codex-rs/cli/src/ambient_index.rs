@@ -0,0 +1,598 @@
+use anyhow::Context;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::sync::broadcast;
+
+use crate::ambient_embedding::Embedder;
+use crate::ambient_embedding::EmbeddingConfig;
+use crate::ambient_embedding::EmbeddingProvider;
+use crate::ambient_embedding::cosine_similarity;
+use crate::ambient_embedding::fnv1a;
+use crate::ambient_git::run_git_command;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EmitEvent;
+use crate::ambient_server::EventEnvelope;
+
+/// `.ambient`の中の索引ファイル
+const INDEX_FILE: &str = "index.jsonl";
+
+/// これより大きいファイルは索引に入れない（バイト）
+const MAX_FILE_BYTES: u64 = 512 * 1024;
+
+/// 置き換えられた行がこの数以上、かつ有効なファイルの数より多くなったら索引を詰め直す
+const COMPACTION_MIN_STALE: usize = 64;
+
+/// `.ambient/config.toml`の`[index]`（埋め込みの索引をディスクに保持して変更分だけ更新する）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct IndexConfig {
+    /// 監視中に索引を更新するか
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 1つの埋め込みにまとめる行数
+    #[serde(default = "default_chunk_lines")]
+    pub chunk_lines: usize,
+}
+
+fn default_chunk_lines() -> usize {
+    40
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chunk_lines: default_chunk_lines(),
+        }
+    }
+}
+
+/// ファイルの一部分の埋め込み
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedChunk {
+    /// 最初の行（1始まり）
+    pub start_line: usize,
+    /// 最後の行（1始まり、この行を含む）
+    pub end_line: usize,
+    pub vector: Vec<f32>,
+}
+
+/// 索引に入れたファイル（`hash`が変わらなければ埋め込みを作り直さない）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedFile {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    /// 内容のハッシュ（FNV-1a、16進数）
+    pub hash: String,
+    pub chunks: Vec<IndexedChunk>,
+}
+
+/// 索引ファイルの1行（先頭はヘッダー、以降はファイルの追加・更新と削除を追記する）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum IndexLine {
+    Header { provider: String },
+    File(IndexedFile),
+    Removed { path: String },
+}
+
+/// 検索で見つかったファイルの一部分
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// コサイン類似度
+    pub score: f32,
+}
+
+/// `.ambient/index.jsonl`に保持する埋め込みの索引
+///
+/// 更新は追記だけで行い、読み込み時は後の行を優先する。置き換えられた行が増えたら`compact`で詰め直す。
+/// 埋め込みのプロバイダーかモデルが変わった場合は、古い索引を使わずに作り直す。
+#[derive(Debug)]
+pub struct VectorStore {
+    file: PathBuf,
+    provider: String,
+    files: BTreeMap<String, IndexedFile>,
+    /// 置き換えられた行と削除の行の数
+    stale: usize,
+    /// 次の書き込みで索引ファイル全体を書き直すか（ヘッダーがない、またはプロバイダーが違う）
+    rewrite: bool,
+}
+
+impl VectorStore {
+    /// プロジェクトの索引ファイルのパス
+    pub fn file_path(project_root: &Path) -> PathBuf {
+        project_root.join(".ambient").join(INDEX_FILE)
+    }
+
+    /// 索引を読み込む（壊れた行は読み飛ばし、プロバイダーが違えば空の索引にする）
+    pub fn open(project_root: &Path, provider: &str) -> Self {
+        let file = Self::file_path(project_root);
+        let mut store = Self {
+            file,
+            provider: provider.to_string(),
+            files: BTreeMap::new(),
+            stale: 0,
+            rewrite: true,
+        };
+        let Ok(content) = fs::read_to_string(&store.file) else {
+            return store;
+        };
+        let mut lines = content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<IndexLine>(line).ok());
+        match lines.next() {
+            Some(IndexLine::Header { provider: stored }) if stored == provider => {
+                store.rewrite = false;
+            }
+            _ => return store,
+        }
+        for line in lines {
+            store.apply(line);
+        }
+        store
+    }
+
+    /// 索引ファイルを削除して空の索引にする（`ambient index rebuild`用）
+    pub fn clear(&mut self) -> Result<()> {
+        match fs::remove_file(&self.file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("{}を削除できません", self.file.display()));
+            }
+        }
+        self.files.clear();
+        self.stale = 0;
+        self.rewrite = true;
+        Ok(())
+    }
+
+    /// 索引にあるファイルの数
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// 索引にあるファイルのパス
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    /// ファイルがこの内容のハッシュで索引にあるか
+    pub fn is_current(&self, path: &str, hash: &str) -> bool {
+        self.files.get(path).is_some_and(|file| file.hash == hash)
+    }
+
+    /// ファイルを追加または置き換える
+    pub fn upsert(&mut self, file: IndexedFile) -> Result<()> {
+        self.write(IndexLine::File(file))
+    }
+
+    /// ファイルを索引から取り除く（索引になければ何もしない）
+    pub fn remove(&mut self, path: &str) -> Result<()> {
+        if !self.files.contains_key(path) {
+            return Ok(());
+        }
+        self.write(IndexLine::Removed {
+            path: path.to_string(),
+        })
+    }
+
+    /// 置き換えられた行が多く、詰め直したほうがよいか
+    pub fn needs_compaction(&self) -> bool {
+        self.stale >= COMPACTION_MIN_STALE && self.stale > self.files.len()
+    }
+
+    /// 有効なファイルだけを一時ファイルに書き出し、索引ファイルと置き換える
+    pub fn compact(&mut self) -> Result<()> {
+        if let Some(dir) = self.file.parent() {
+            ensure_index_dir(dir)?;
+        }
+        let temp = self.file.with_extension("jsonl.tmp");
+        let mut content = serde_json::to_string(&IndexLine::Header {
+            provider: self.provider.clone(),
+        })?;
+        content.push('\n');
+        for file in self.files.values() {
+            content.push_str(&serde_json::to_string(&IndexLine::File(file.clone()))?);
+            content.push('\n');
+        }
+        fs::write(&temp, content).with_context(|| format!("{}に書き込めません", temp.display()))?;
+        fs::rename(&temp, &self.file)
+            .with_context(|| format!("{}を置き換えられません", self.file.display()))?;
+        self.stale = 0;
+        self.rewrite = false;
+        Ok(())
+    }
+
+    /// 埋め込みに近いファイルの部分を、類似度の高い順に`limit`件まで
+    pub fn search(&self, query: &[f32], limit: usize) -> Vec<SearchHit> {
+        let mut hits: Vec<SearchHit> = self
+            .files
+            .values()
+            .flat_map(|file| {
+                file.chunks.iter().map(|chunk| SearchHit {
+                    path: file.path.clone(),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    score: cosine_similarity(query, &chunk.vector),
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        hits
+    }
+
+    fn write(&mut self, line: IndexLine) -> Result<()> {
+        if self.rewrite {
+            self.apply(line);
+            return self.compact();
+        }
+        if let Some(dir) = self.file.parent() {
+            ensure_index_dir(dir)?;
+        }
+        let mut out = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)
+            .with_context(|| format!("{}を開けません", self.file.display()))?;
+        writeln!(out, "{}", serde_json::to_string(&line)?)?;
+        self.apply(line);
+        Ok(())
+    }
+
+    fn apply(&mut self, line: IndexLine) {
+        match line {
+            IndexLine::Header { .. } => {}
+            IndexLine::File(file) => {
+                if self.files.insert(file.path.clone(), file).is_some() {
+                    self.stale += 1;
+                }
+            }
+            IndexLine::Removed { path } => {
+                self.files.remove(&path);
+                self.stale += 1;
+            }
+        }
+    }
+}
+
+/// `.ambient`を作り、索引ファイルが変更ファイルとして検出されないようにする
+fn ensure_index_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let gitignore = dir.join(".gitignore");
+    let current = fs::read_to_string(&gitignore).unwrap_or_default();
+    if !current.lines().any(|line| line.trim() == INDEX_FILE) {
+        let mut out = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&gitignore)?;
+        if !current.is_empty() && !current.ends_with('\n') {
+            writeln!(out)?;
+        }
+        writeln!(out, "{INDEX_FILE}")?;
+        writeln!(out, "{INDEX_FILE}.tmp")?;
+    }
+    Ok(())
+}
+
+/// 索引の更新の結果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexUpdate {
+    /// 埋め込みを作り直したファイル
+    pub embedded: usize,
+    /// 内容が変わっていなかったファイル
+    pub unchanged: usize,
+    /// 索引から取り除いたファイル
+    pub removed: usize,
+}
+
+impl IndexUpdate {
+    pub fn describe(&self) -> String {
+        format!(
+            "索引を更新しました（埋め込み{}件、変更なし{}件、削除{}件）",
+            self.embedded, self.unchanged, self.removed
+        )
+    }
+}
+
+/// 変更されたファイルの索引を更新する（内容が変わっていなければ埋め込みを作らない）
+///
+/// 削除されたファイル、大きすぎるファイル、UTF-8でないファイルは索引から取り除く。
+pub async fn update_files<P: EmbeddingProvider>(
+    store: &mut VectorStore,
+    provider: &P,
+    root: &Path,
+    paths: &[String],
+    chunk_lines: usize,
+) -> Result<IndexUpdate> {
+    let mut update = IndexUpdate::default();
+    for path in paths {
+        let Some(content) = read_indexable(&root.join(path)) else {
+            if store.files.contains_key(path) {
+                store.remove(path)?;
+                update.removed += 1;
+            }
+            continue;
+        };
+        let hash = format!("{:016x}", fnv1a(&content));
+        if store.is_current(path, &hash) {
+            update.unchanged += 1;
+            continue;
+        }
+        let ranges = chunk_ranges(&content, chunk_lines);
+        let lines: Vec<&str> = content.lines().collect();
+        let texts: Vec<String> = ranges
+            .iter()
+            .map(|&(start, end)| format!("{path}\n{}", lines[start - 1..end].join("\n")))
+            .collect();
+        let vectors = provider.embed(&texts).await?;
+        let chunks = ranges
+            .into_iter()
+            .zip(vectors)
+            .map(|((start_line, end_line), vector)| IndexedChunk {
+                start_line,
+                end_line,
+                vector,
+            })
+            .collect();
+        store.upsert(IndexedFile {
+            path: path.clone(),
+            hash,
+            chunks,
+        })?;
+        update.embedded += 1;
+    }
+    Ok(update)
+}
+
+/// リポジトリのすべてのファイル（追跡中と、無視されていない未追跡のもの、`.ambient`を除く）で索引を揃える
+pub async fn sync_repository<P: EmbeddingProvider>(
+    store: &mut VectorStore,
+    provider: &P,
+    root: &Path,
+    chunk_lines: usize,
+) -> Result<IndexUpdate> {
+    let listed = run_git_command(
+        &["ls-files", "--cached", "--others", "--exclude-standard"],
+        root,
+    )?;
+    let paths: BTreeSet<String> = listed
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with(".ambient/"))
+        .map(str::to_string)
+        .collect();
+    let gone: Vec<String> = store
+        .paths()
+        .filter(|path| !paths.contains(*path))
+        .map(str::to_string)
+        .collect();
+    for path in &gone {
+        store.remove(path)?;
+    }
+    let paths: Vec<String> = paths.into_iter().collect();
+    let mut update = update_files(store, provider, root, &paths, chunk_lines).await?;
+    update.removed += gone.len();
+    Ok(update)
+}
+
+/// 索引に入れられるファイルの内容（大きすぎる、UTF-8でない、読めないファイルは`None`）
+fn read_indexable(path: &Path) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    String::from_utf8(fs::read(path).ok()?).ok()
+}
+
+/// 内容を`chunk_lines`行ずつに分けた行の範囲（1始まり、空白だけの範囲は除く）
+fn chunk_ranges(content: &str, chunk_lines: usize) -> Vec<(usize, usize)> {
+    let lines: Vec<&str> = content.lines().collect();
+    let size = chunk_lines.max(1);
+    (0..lines.len())
+        .step_by(size)
+        .map(|start| (start, (start + size).min(lines.len())))
+        .filter(|(start, end)| {
+            lines[*start..*end]
+                .iter()
+                .any(|line| !line.trim().is_empty())
+        })
+        .map(|(start, end)| (start + 1, end))
+        .collect()
+}
+
+/// `[index]`が有効なら、埋め込みの索引を保持するタスクを始める（埋め込みを作れなければ知らせて始めない）
+pub fn spawn_index(
+    root: PathBuf,
+    config: &IndexConfig,
+    embedding: &EmbeddingConfig,
+    client: reqwest::Client,
+    tx: &broadcast::Sender<EventEnvelope>,
+) {
+    if !config.enabled {
+        return;
+    }
+    match embedding.provider(client) {
+        Ok(embedder) => {
+            println!("埋め込みの索引を{}で更新します", embedder.id());
+            tokio::spawn(run_index(
+                root,
+                config.clone(),
+                embedder,
+                tx.subscribe(),
+                tx.clone(),
+            ));
+        }
+        Err(e) => eprintln!("埋め込みの索引を更新しません: {e:#}"),
+    }
+}
+
+/// 監視中に索引を保持する（起動時に変更分だけ揃え、以降はチェックサイクルで変更されたファイルを更新する）
+///
+/// 更新のたびに、置き換えられた行が多ければ索引ファイルを詰め直す。
+async fn run_index(
+    root: PathBuf,
+    config: IndexConfig,
+    embedder: Embedder,
+    mut rx: broadcast::Receiver<EventEnvelope>,
+    tx: broadcast::Sender<EventEnvelope>,
+) {
+    let mut store = VectorStore::open(&root, &embedder.id());
+    match sync_repository(&mut store, &embedder, &root, config.chunk_lines).await {
+        Ok(update) => {
+            let _ = tx.emit(AmbientEvent::System(update.describe()));
+        }
+        Err(e) => {
+            let _ = tx.emit(AmbientEvent::System(format!("索引を更新できません: {e:#}")));
+        }
+    }
+    compact_if_needed(&mut store);
+    loop {
+        match rx.recv().await {
+            // `projects`で追加したリポジトリのチェックは、そのリポジトリの索引に関係しない
+            Ok(envelope) if envelope.project.is_some() => {}
+            Ok(envelope) => {
+                let AmbientEvent::CycleSummary(summary) = envelope.event else {
+                    continue;
+                };
+                let paths: Vec<String> = summary
+                    .analyzed
+                    .into_iter()
+                    .chain(summary.skipped.into_iter().map(|skipped| skipped.path))
+                    .collect();
+                if paths.is_empty() {
+                    continue;
+                }
+                if let Err(e) =
+                    update_files(&mut store, &embedder, &root, &paths, config.chunk_lines).await
+                {
+                    tracing::warn!("failed to update the embedding index: {e:#}");
+                }
+                compact_if_needed(&mut store);
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+fn compact_if_needed(store: &mut VectorStore) {
+    if store.needs_compaction()
+        && let Err(e) = store.compact()
+    {
+        tracing::warn!("failed to compact the embedding index: {e:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_embedding::HashEmbeddings;
+    use crate::ambient_test_support::TempRepo;
+
+    fn file(path: &str, hash: &str) -> IndexedFile {
+        IndexedFile {
+            path: path.to_string(),
+            hash: hash.to_string(),
+            chunks: vec![IndexedChunk {
+                start_line: 1,
+                end_line: 1,
+                vector: vec![1.0, 0.0],
+            }],
+        }
+    }
+
+    #[test]
+    fn replays_appended_updates_and_compacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::open(dir.path(), "hash:2");
+        for round in 0..COMPACTION_MIN_STALE {
+            store.upsert(file("src/a.rs", &round.to_string())).unwrap();
+        }
+        store.upsert(file("src/b.rs", "b")).unwrap();
+        store.remove("src/b.rs").unwrap();
+
+        let reopened = VectorStore::open(dir.path(), "hash:2");
+        assert_eq!(reopened.len(), 1);
+        let last = (COMPACTION_MIN_STALE - 1).to_string();
+        assert!(reopened.is_current("src/a.rs", &last));
+        assert!(reopened.needs_compaction());
+
+        store.compact().unwrap();
+        let content = fs::read_to_string(VectorStore::file_path(dir.path())).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        let compacted = VectorStore::open(dir.path(), "hash:2");
+        assert!(compacted.is_current("src/a.rs", &last));
+        assert!(!compacted.needs_compaction());
+
+        // プロバイダーが変わったら古い索引は使わない
+        assert!(VectorStore::open(dir.path(), "ollama:nomic-embed-text").is_empty());
+        let gitignore = fs::read_to_string(dir.path().join(".ambient/.gitignore")).unwrap();
+        assert!(gitignore.lines().any(|line| line == INDEX_FILE));
+    }
+
+    #[test]
+    fn splits_content_into_non_blank_chunks() {
+        let content = "a\nb\n\n\nc";
+        assert_eq!(chunk_ranges(content, 2), vec![(1, 2), (5, 5)]);
+        assert!(chunk_ranges("", 40).is_empty());
+    }
+
+    #[tokio::test]
+    async fn embeds_only_changed_files() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/config.rs", "fn parse_config() {}\n")
+            .unwrap();
+        repo.write("src/page.rs", "fn render_page() {}\n").unwrap();
+        repo.add(&["src/config.rs", "src/page.rs"]).unwrap();
+        let provider = HashEmbeddings::new(64);
+
+        let mut store = VectorStore::open(repo.path(), &provider.id());
+        let update = sync_repository(&mut store, &provider, repo.path(), 40)
+            .await
+            .unwrap();
+        assert_eq!((update.embedded, update.unchanged), (2, 0));
+
+        // 再起動しても、変更されたファイルだけ埋め込みを作り直す
+        repo.write("src/page.rs", "fn render_page(title: &str) {}\n")
+            .unwrap();
+        let mut store = VectorStore::open(repo.path(), &provider.id());
+        let update = sync_repository(&mut store, &provider, repo.path(), 40)
+            .await
+            .unwrap();
+        assert_eq!((update.embedded, update.unchanged), (1, 1));
+
+        fs::remove_file(repo.path().join("src/config.rs")).unwrap();
+        let update = update_files(
+            &mut store,
+            &provider,
+            repo.path(),
+            &["src/config.rs".to_string()],
+            40,
+        )
+        .await
+        .unwrap();
+        assert_eq!(update.removed, 1);
+
+        let query = provider.embed_one("src/page.rs render_page title");
+        let hits = store.search(&query, 1);
+        assert_eq!(hits[0].path, "src/page.rs");
+        assert_eq!((hits[0].start_line, hits[0].end_line), (1, 1));
+    }
+}
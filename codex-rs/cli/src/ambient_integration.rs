@@ -0,0 +1,425 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::ambient_azure_devops::AzurePullRequest;
+use crate::ambient_bitbucket::BitbucketPullRequest;
+use crate::ambient_check_run::WEBHOOK_SECRET_ENV;
+use crate::ambient_gerrit::GerritChange;
+use crate::ambient_policy::PolicyReport;
+use crate::ambient_pull_request::GITHUB_API;
+use crate::ambient_pull_request::PullRequest;
+use crate::ambient_secrets::resolve_secret;
+use crate::ambient_server::Finding;
+use crate::ambient_snapshot::in_new_hunk;
+
+/// `.ambient/config.toml`の`[integrations]`（`ambient review-pr`の取得元と投稿先）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct IntegrationsConfig {
+    /// プルリクエストをホストしているサービス
+    #[serde(default)]
+    pub kind: IntegrationKind,
+
+    /// トークンを保存したOSのキーチェーンの項目（`keyring:ambient/github`、見つからなければ環境変数を使う）
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// トークンを読む環境変数（未指定なら`kind`ごとの既定の環境変数）
+    #[serde(default)]
+    pub token_env: Option<String>,
+
+    /// Azure DevOpsの組織（番号だけでプルリクエストを指定するときに使う）
+    #[serde(default)]
+    pub organization: Option<String>,
+
+    /// Azure DevOpsのプロジェクト
+    #[serde(default)]
+    pub project: Option<String>,
+
+    /// Azure Reposのリポジトリ
+    #[serde(default)]
+    pub repository: Option<String>,
+
+    /// チェックサイクルごとにHEADのコミットにGitHubのチェックランを作る（`kind = "github"`のみ）
+    #[serde(default)]
+    pub check_runs: bool,
+
+    /// チェックの再実行を受け付けるWebhookの秘密を読む環境変数（未指定なら`GITHUB_WEBHOOK_SECRET`）
+    #[serde(default)]
+    pub webhook_secret_env: Option<String>,
+}
+
+impl IntegrationsConfig {
+    /// `token`のキーチェーンの項目、なければ環境変数からトークンを読む（空の値は無視する）
+    pub fn token(&self) -> Option<String> {
+        if let Some(reference) = &self.token {
+            match resolve_secret(reference) {
+                Ok(Some(token)) => return Some(token),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("{e:#}"),
+            }
+        }
+        let vars: Vec<&str> = match &self.token_env {
+            Some(var) => vec![var.as_str()],
+            None => self.kind.token_env_vars().to_vec(),
+        };
+        vars.into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .map(|token| token.trim().to_string())
+            .find(|token| !token.is_empty())
+    }
+
+    /// 環境変数からWebhookの秘密を読む（設定されていなければWebhookを受け付けない）
+    pub fn webhook_secret(&self) -> Option<String> {
+        let var = self
+            .webhook_secret_env
+            .as_deref()
+            .unwrap_or(WEBHOOK_SECRET_ENV);
+        std::env::var(var)
+            .ok()
+            .map(|secret| secret.trim().to_string())
+            .filter(|secret| !secret.is_empty())
+    }
+
+    /// トークンを設定する場所の説明（エラーメッセージ用）
+    pub fn token_hint(&self) -> String {
+        let vars = match &self.token_env {
+            Some(var) => var.clone(),
+            None => self.kind.token_env_vars().join("または"),
+        };
+        match &self.token {
+            Some(reference) => format!("{reference}（`ambient secrets set`）または{vars}"),
+            None => vars,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrationKind {
+    #[default]
+    Github,
+    Bitbucket,
+    Gerrit,
+    Azure,
+}
+
+impl IntegrationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IntegrationKind::Github => "github",
+            IntegrationKind::Bitbucket => "bitbucket",
+            IntegrationKind::Gerrit => "gerrit",
+            IntegrationKind::Azure => "azure",
+        }
+    }
+
+    /// 既定でトークンを読む環境変数（先に見つかったものを使う）
+    ///
+    /// Gerritのトークンは`GERRIT_USERNAME`のユーザーのHTTPパスワード。
+    fn token_env_vars(self) -> &'static [&'static str] {
+        match self {
+            IntegrationKind::Github => &["GITHUB_TOKEN", "GH_TOKEN"],
+            IntegrationKind::Bitbucket => &["BITBUCKET_TOKEN"],
+            IntegrationKind::Gerrit => &["GERRIT_HTTP_PASSWORD"],
+            IntegrationKind::Azure => &["AZURE_DEVOPS_PAT", "AZURE_DEVOPS_EXT_PAT"],
+        }
+    }
+}
+
+/// 指摘を投稿先のコメントに対応付けたもの
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReviewComment {
+    pub path: String,
+    /// コメントを付ける変更後の行（diffの範囲外ならなし）
+    pub line: Option<usize>,
+    /// Markdownの本文
+    pub body: String,
+}
+
+/// 指摘をコメントにする
+///
+/// 回答の`path:行番号`がdiffのハンクに含まれていれば、その行へのコメントにする。
+/// それ以外はファイルへのコメントとして、レビュー全体の本文にまとめる。
+pub fn review_comments(
+    findings: &[Finding],
+    diffs: &HashMap<String, String>,
+) -> Vec<ReviewComment> {
+    findings
+        .iter()
+        .map(|finding| {
+            let line = diffs.get(&finding.file_path).and_then(|diff| {
+                finding
+                    .line
+                    .map(|line| line as usize)
+                    .into_iter()
+                    .chain(referenced_lines(&finding.message, &finding.file_path))
+                    .find(|line| in_new_hunk(diff, *line))
+            });
+            let severity = finding
+                .severity
+                .map(|severity| format!("（{}）", severity.as_str()))
+                .unwrap_or_default();
+            let mut body = format!(
+                "**{}**{severity}\n\n{}",
+                finding.review,
+                finding.message.trim()
+            );
+            if let Some(suggestion) = &finding.suggestion {
+                body.push_str(&format!("\n\n修正案: {}", suggestion.trim()));
+            }
+            ReviewComment {
+                path: finding.file_path.clone(),
+                line,
+                body,
+            }
+        })
+        .collect()
+}
+
+/// 回答に出てくる`path:行番号`の行番号（出てきた順）
+pub(crate) fn referenced_lines(message: &str, path: &str) -> Vec<usize> {
+    let prefix = format!("{path}:");
+    message
+        .match_indices(&prefix)
+        .filter_map(|(index, _)| {
+            let rest = &message[index + prefix.len()..];
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            rest[..digits].parse().ok()
+        })
+        .collect()
+}
+
+/// レビュー全体の本文（行に付けられなかったコメントはファイルごとにここに載せる）
+pub fn render_summary(change: &ChangeRef, comments: &[ReviewComment]) -> String {
+    if comments.is_empty() {
+        return format!("ambientで{change}をレビューしました。指摘はありません。\n");
+    }
+    let mut body = format!("ambientで{change}をレビューしました。\n");
+    let inline = comments.iter().filter(|c| c.line.is_some()).count();
+    if inline > 0 {
+        body.push_str(&format!(
+            "\n{inline}件の指摘は該当する行にコメントしました。\n"
+        ));
+    }
+    let mut current = None;
+    for comment in comments.iter().filter(|c| c.line.is_none()) {
+        if current != Some(&comment.path) {
+            body.push_str(&format!("\n### `{}`\n", comment.path));
+            current = Some(&comment.path);
+        }
+        body.push_str(&format!("\n{}\n", comment.body));
+    }
+    body
+}
+
+/// レビューするプルリクエスト（Gerritでは変更）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeRef {
+    Github(PullRequest),
+    Bitbucket(BitbucketPullRequest),
+    Gerrit(GerritChange),
+    Azure(AzurePullRequest),
+}
+
+impl ChangeRef {
+    /// `config.kind`の形式のURLを解析する
+    pub fn parse(config: &IntegrationsConfig, url: &str) -> Result<Self> {
+        Ok(match config.kind {
+            IntegrationKind::Github => ChangeRef::Github(PullRequest::parse(url)?),
+            IntegrationKind::Bitbucket => ChangeRef::Bitbucket(BitbucketPullRequest::parse(url)?),
+            IntegrationKind::Gerrit => ChangeRef::Gerrit(GerritChange::parse(url)?),
+            IntegrationKind::Azure => ChangeRef::Azure(AzurePullRequest::parse(url, config)?),
+        })
+    }
+
+    /// 変更全体のdiffを取得する（Azure DevOpsは`repo_dir`のリポジトリにgitで取得する）
+    pub async fn fetch_diff(
+        &self,
+        client: &reqwest::Client,
+        token: Option<&str>,
+        repo_dir: &Path,
+    ) -> Result<String> {
+        match self {
+            ChangeRef::Github(pr) => {
+                crate::ambient_pull_request::fetch_diff(client, GITHUB_API, pr, token).await
+            }
+            ChangeRef::Bitbucket(pr) => pr.fetch_diff(client, token).await,
+            ChangeRef::Gerrit(change) => change.fetch_diff(client, token).await,
+            ChangeRef::Azure(pr) => pr.fetch_diff(token, repo_dir),
+        }
+    }
+
+    /// レビューを投稿し、確認できるページのURLを返す
+    ///
+    /// ステータスを設定できるサービス（Azure DevOps）には`policy`の判定も送る。
+    pub async fn publish(
+        &self,
+        client: &reqwest::Client,
+        token: &str,
+        summary: &str,
+        comments: &[ReviewComment],
+        policy: &PolicyReport,
+    ) -> Result<String> {
+        let inline: Vec<&ReviewComment> = comments.iter().filter(|c| c.line.is_some()).collect();
+        match self {
+            ChangeRef::Github(pr) => {
+                crate::ambient_pull_request::post_review(
+                    client, GITHUB_API, pr, token, summary, &inline,
+                )
+                .await
+            }
+            ChangeRef::Bitbucket(pr) => pr.publish(client, token, summary, &inline).await,
+            ChangeRef::Gerrit(change) => change.publish(client, token, summary, &inline).await,
+            ChangeRef::Azure(pr) => pr.publish(client, token, summary, &inline, policy).await,
+        }
+    }
+}
+
+impl fmt::Display for ChangeRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeRef::Github(pr) => fmt::Display::fmt(pr, f),
+            ChangeRef::Bitbucket(pr) => fmt::Display::fmt(pr, f),
+            ChangeRef::Gerrit(change) => fmt::Display::fmt(change, f),
+            ChangeRef::Azure(pr) => fmt::Display::fmt(pr, f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_confidence::Severity;
+    use crate::ambient_server::RunIds;
+
+    fn finding(file_path: &str, review: &str, message: &str) -> Finding {
+        Finding {
+            ids: RunIds::new_run(),
+            file_path: file_path.to_string(),
+            review: review.to_string(),
+            message: message.to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity: Some(Severity::High),
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: None,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn anchors_findings_to_changed_lines() {
+        let diffs = HashMap::from([(
+            "src/a.rs".to_string(),
+            "@@ -10,2 +10,3 @@\n x\n+y\n z\n".to_string(),
+        )]);
+        let comments = review_comments(
+            &[
+                finding(
+                    "src/a.rs",
+                    "security",
+                    "`src/a.rs:3`と`src/a.rs:11`に問題があります\n",
+                ),
+                finding("src/a.rs", "syntax", "`src/a.rs:40`で括弧が閉じていません"),
+                finding("src/b.rs", "syntax", "`src/b.rs:1`"),
+            ],
+            &diffs,
+        );
+        let lines: Vec<Option<usize>> = comments.iter().map(|c| c.line).collect();
+        assert_eq!(lines, vec![Some(11), None, None]);
+        assert_eq!(
+            comments[0].body,
+            "**security**（high）\n\n`src/a.rs:3`と`src/a.rs:11`に問題があります"
+        );
+    }
+
+    #[test]
+    fn prefers_structured_line_and_appends_suggestion() {
+        let diffs = HashMap::from([(
+            "src/a.rs".to_string(),
+            "@@ -10,2 +10,3 @@\n x\n+y\n z\n".to_string(),
+        )]);
+        let structured = Finding {
+            line: Some(12),
+            suggestion: Some("`unwrap`を`?`にする".to_string()),
+            ..finding("src/a.rs", "syntax", "`src/a.rs:11`でpanicします")
+        };
+        let comments = review_comments(&[structured], &diffs);
+        assert_eq!(comments[0].line, Some(12));
+        assert!(
+            comments[0]
+                .body
+                .ends_with("\n\n修正案: `unwrap`を`?`にする")
+        );
+    }
+
+    #[test]
+    fn summary_lists_comments_without_lines_by_file() {
+        let change = ChangeRef::parse(
+            &IntegrationsConfig::default(),
+            "https://github.com/org/repo/pull/7",
+        )
+        .unwrap();
+        assert!(render_summary(&change, &[]).contains("指摘はありません"));
+
+        let comment = |path: &str, line: Option<usize>| ReviewComment {
+            path: path.to_string(),
+            line,
+            body: format!("{path} {line:?}"),
+        };
+        let body = render_summary(
+            &change,
+            &[
+                comment("src/a.rs", Some(3)),
+                comment("src/a.rs", None),
+                comment("src/a.rs", None),
+                comment("src/b.rs", None),
+            ],
+        );
+        assert!(body.starts_with("ambientでorg/repo#7をレビューしました。\n"));
+        assert!(body.contains("1件の指摘は該当する行にコメントしました。"));
+        assert_eq!(body.matches("### `src/a.rs`").count(), 1);
+        assert_eq!(body.matches("### ").count(), 2);
+        assert!(!body.contains("Some(3)"));
+    }
+
+    #[test]
+    fn token_comes_from_kind_or_configured_env() {
+        let config = IntegrationsConfig {
+            kind: IntegrationKind::Gerrit,
+            ..Default::default()
+        };
+        assert_eq!(config.token_hint(), "GERRIT_HTTP_PASSWORD");
+        assert_eq!(
+            IntegrationsConfig::default().token_hint(),
+            "GITHUB_TOKENまたはGH_TOKEN"
+        );
+        let config = IntegrationsConfig {
+            token_env: Some("AMBIENT_TEST_UNSET_TOKEN".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.token(), None);
+        let config = IntegrationsConfig {
+            token: Some("keyring:ambient/github".to_string()),
+            token_env: Some("REVIEW_PAT".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.token_hint(),
+            "keyring:ambient/github（`ambient secrets set`）またはREVIEW_PAT"
+        );
+    }
+}
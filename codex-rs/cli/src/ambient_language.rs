@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// 言語を判定できなかった場合の名前
+pub const UNKNOWN_LANGUAGE: &str = "text";
+
+/// 拡張子から言語名への組み込み対応表
+const EXTENSION_LANGUAGES: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("toml", "toml"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("cjs", "javascript"),
+    ("jsx", "javascript"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("py", "python"),
+    ("go", "go"),
+    ("java", "java"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cpp", "cpp"),
+    ("cc", "cpp"),
+    ("hpp", "cpp"),
+    ("cs", "csharp"),
+    ("rb", "ruby"),
+    ("php", "php"),
+    ("swift", "swift"),
+    ("kt", "kotlin"),
+    ("scala", "scala"),
+    ("sh", "shell"),
+    ("bash", "shell"),
+    ("zsh", "shell"),
+    ("fish", "fish"),
+    ("yml", "yaml"),
+    ("yaml", "yaml"),
+    ("json", "json"),
+    ("xml", "xml"),
+    ("html", "html"),
+    ("css", "css"),
+    ("scss", "scss"),
+    ("sass", "sass"),
+    ("less", "less"),
+    ("sql", "sql"),
+    ("md", "markdown"),
+    ("mdx", "markdown"),
+];
+
+/// 拡張子を持たないファイル名から言語名への組み込み対応表
+const FILE_NAME_LANGUAGES: &[(&str, &str)] = &[
+    ("Makefile", "make"),
+    ("Dockerfile", "dockerfile"),
+    ("Rakefile", "ruby"),
+    ("Gemfile", "ruby"),
+];
+
+/// ファイルパスから言語を判定する
+///
+/// `overrides`（globパターン → 言語名）が組み込みの判定より優先される。
+/// 複数のパターンにマッチした場合はより長い（具体的な）パターンを採用する。
+pub fn detect_language(file_path: &str, overrides: &BTreeMap<String, String>) -> String {
+    let file_name = Path::new(file_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(file_path);
+
+    let overridden = overrides
+        .iter()
+        .filter(|(pattern, _)| {
+            glob::Pattern::new(pattern)
+                .ok()
+                .is_some_and(|p| p.matches(file_path) || p.matches(file_name))
+        })
+        .max_by_key(|(pattern, _)| pattern.len());
+    if let Some((_, language)) = overridden {
+        return language.clone();
+    }
+
+    if let Some((_, language)) = FILE_NAME_LANGUAGES
+        .iter()
+        .find(|(name, _)| *name == file_name)
+    {
+        return (*language).to_string();
+    }
+
+    Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(|ext| {
+            EXTENSION_LANGUAGES
+                .iter()
+                .find(|(known, _)| known.eq_ignore_ascii_case(ext))
+        })
+        .map(|(_, language)| (*language).to_string())
+        .unwrap_or_else(|| UNKNOWN_LANGUAGE.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_detection() {
+        let overrides = BTreeMap::new();
+        assert_eq!(detect_language("src/main.rs", &overrides), "rust");
+        assert_eq!(
+            detect_language("docker/Dockerfile", &overrides),
+            "dockerfile"
+        );
+        assert_eq!(
+            detect_language("notes.unknown", &overrides),
+            UNKNOWN_LANGUAGE
+        );
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let overrides = BTreeMap::from([
+            ("*.tpl".to_string(), "go-template".to_string()),
+            ("Justfile".to_string(), "make".to_string()),
+            ("*.rs".to_string(), "rust-legacy".to_string()),
+            ("legacy/*.rs".to_string(), "rust-2015".to_string()),
+        ]);
+        assert_eq!(detect_language("web/index.tpl", &overrides), "go-template");
+        assert_eq!(detect_language("tools/Justfile", &overrides), "make");
+        assert_eq!(detect_language("legacy/lib.rs", &overrides), "rust-2015");
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::ambient_git::run_git_command;
+
+/// 大きなファイルの確認のレビュー名
+pub const LARGE_FILE_REVIEW_NAME: &str = "大きなファイルのコミット確認";
+
+/// 大きなファイルの指摘に付けるラベル（`[policy]`の`blocking`に加えるとチェックを失敗させる）
+pub const LARGE_FILE_LABEL: &str = "large-file";
+
+/// `.ambient/config.toml`の`[large_files]`（ステージされた大きなファイル・バイナリの警告）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct LargeFileConfig {
+    /// 確認を行うか
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// ステージされたファイルがこのサイズ（バイト）を超えたら警告する
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+
+    /// サイズに関係なく警告する拡張子（ドットなし、大文字小文字は区別しない）
+    #[serde(default = "default_binary_extensions")]
+    pub binary_extensions: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_bytes() -> u64 {
+    5 * 1024 * 1024
+}
+
+fn default_binary_extensions() -> Vec<String> {
+    [
+        "zip", "tar", "gz", "tgz", "7z", "rar", "jar", "exe", "dll", "so", "dylib", "bin", "iso",
+        "dmg", "mp4", "mov", "psd", "sqlite",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect()
+}
+
+impl Default for LargeFileConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_bytes: default_max_bytes(),
+            binary_extensions: default_binary_extensions(),
+        }
+    }
+}
+
+/// 警告の理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LargeFileReason {
+    /// `max_bytes`を超えている
+    TooLarge,
+    /// `binary_extensions`の拡張子
+    Binary,
+}
+
+/// コミットされようとしている大きなファイル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LargeFile {
+    /// リポジトリのルートからの相対パス
+    pub path: String,
+    /// ステージされた内容のサイズ（バイト）
+    pub size: u64,
+    pub reason: LargeFileReason,
+    /// リポジトリでgit-lfsが設定されているか（`.gitattributes`に`filter=lfs`がある）
+    pub lfs_configured: bool,
+}
+
+impl LargeFile {
+    /// 指摘として表示する文面
+    pub fn message(&self) -> String {
+        let path = &self.path;
+        let size = format_size(self.size);
+        let mut text = match self.reason {
+            LargeFileReason::TooLarge => format!(
+                "`{path}`（{size}）がステージされています。コミットするとリポジトリの履歴に残り続け、あとから削除しても容量は減りません。"
+            ),
+            LargeFileReason::Binary => format!(
+                "`{path}`はバイナリファイル（{size}）です。コミットすると差分が追えず、履歴に残り続けます。"
+            ),
+        };
+        match extension(path).filter(|_| self.lfs_configured) {
+            Some(ext) => text.push_str(&format!(
+                "\n\nこのリポジトリではgit-lfsが設定されています。`git lfs track \"*.{ext}\"`を実行してから`git add`し直してください。"
+            )),
+            None => text.push_str(&format!(
+                "\n\n意図したものでなければ`git restore --staged {path}`でステージを取り消し、`.gitignore`に追加してください。"
+            )),
+        }
+        text
+    }
+}
+
+/// ステージされた（追加・変更された）ファイルのうち、警告すべきもの
+///
+/// モデルは使わず、gitのインデックスの内容だけで判断する。git-lfsで管理されるファイルは除く。
+pub fn staged_large_files(root: &Path, config: &LargeFileConfig) -> Result<Vec<LargeFile>> {
+    if !config.enabled {
+        return Ok(vec![]);
+    }
+    let output = run_git_command(
+        &["diff", "--cached", "--name-only", "--diff-filter=AM", "-z"],
+        root,
+    )?;
+    let lfs_configured = fs::read_to_string(root.join(".gitattributes"))
+        .is_ok_and(|attributes| attributes.contains("filter=lfs"));
+    let mut files = Vec::new();
+    for path in output.split('\0').filter(|path| !path.is_empty()) {
+        let size: u64 = run_git_command(&["cat-file", "-s", &format!(":{path}")], root)?
+            .trim()
+            .parse()?;
+        let reason = if size > config.max_bytes {
+            LargeFileReason::TooLarge
+        } else if extension(path).is_some_and(|ext| {
+            config
+                .binary_extensions
+                .iter()
+                .any(|binary| binary.eq_ignore_ascii_case(ext))
+        }) {
+            LargeFileReason::Binary
+        } else {
+            continue;
+        };
+        if lfs_configured && tracked_by_lfs(root, path) {
+            continue;
+        }
+        files.push(LargeFile {
+            path: path.to_string(),
+            size,
+            reason,
+            lfs_configured,
+        });
+    }
+    Ok(files)
+}
+
+/// git-lfsで管理されるパスか
+fn tracked_by_lfs(root: &Path, path: &str) -> bool {
+    run_git_command(&["check-attr", "filter", "--", path], root)
+        .is_ok_and(|output| output.trim_end().ends_with(": lfs"))
+}
+
+fn extension(path: &str) -> Option<&str> {
+    let name = path.rsplit('/').next()?;
+    name.rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .filter(|ext| !ext.is_empty())
+}
+
+fn format_size(bytes: u64) -> String {
+    const KIB: f64 = 1024.0;
+    let bytes_f = bytes as f64;
+    if bytes_f >= KIB * KIB {
+        format!("{:.1} MiB", bytes_f / (KIB * KIB))
+    } else if bytes_f >= KIB {
+        format!("{:.1} KiB", bytes_f / KIB)
+    } else {
+        format!("{bytes} B")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_test_support::TempRepo;
+
+    #[test]
+    fn warns_about_large_and_binary_staged_files() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/lib.rs", "fn main() {}").unwrap();
+        repo.write("data/dump.json", &"x".repeat(2048)).unwrap();
+        repo.write("release.ZIP", "PK").unwrap();
+        repo.write("unstaged.zip", "PK").unwrap();
+        repo.add(&["src/lib.rs", "data/dump.json", "release.ZIP"])
+            .unwrap();
+        let config = LargeFileConfig {
+            max_bytes: 1024,
+            ..Default::default()
+        };
+
+        let files = staged_large_files(repo.path(), &config).unwrap();
+        assert_eq!(
+            files
+                .iter()
+                .map(|file| (file.path.as_str(), file.reason))
+                .collect::<Vec<_>>(),
+            vec![
+                ("data/dump.json", LargeFileReason::TooLarge),
+                ("release.ZIP", LargeFileReason::Binary),
+            ]
+        );
+        assert_eq!(files[0].size, 2048);
+        let message = files[0].message();
+        assert!(message.starts_with("`data/dump.json`（2.0 KiB）がステージされています。"));
+        assert!(message.contains("git restore --staged data/dump.json"));
+    }
+
+    #[test]
+    fn suggests_lfs_and_skips_lfs_tracked_files() {
+        let repo = TempRepo::new().unwrap();
+        repo.write(
+            ".gitattributes",
+            "*.psd filter=lfs diff=lfs merge=lfs -text\n",
+        )
+        .unwrap();
+        repo.write("design.psd", "8BPS").unwrap();
+        repo.write("video.mp4", "ftyp").unwrap();
+        repo.add(&["design.psd", "video.mp4"]).unwrap();
+
+        let files = staged_large_files(repo.path(), &LargeFileConfig::default()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "video.mp4");
+        assert!(files[0].message().contains("`git lfs track \"*.mp4\"`"));
+    }
+
+    #[test]
+    fn can_be_disabled() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("app.exe", "MZ").unwrap();
+        repo.add(&["app.exe"]).unwrap();
+        let config = LargeFileConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        assert!(staged_large_files(repo.path(), &config).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use axum::extract::Request;
+use axum::extract::State;
+use axum::extract::ws::Message;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use futures::sink::Sink;
+use futures::sink::SinkExt;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+
+use crate::ambient_server::AppState;
+
+/// `.ambient/config.toml`の`[server_limits]`（ローカルの他のプロセスからの過大な入力を拒否する）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ServerLimits {
+    /// リクエストボディの上限（バイト、超えたら`413`）
+    pub max_body_bytes: usize,
+    /// WebSocketの1フレームの上限（バイト、超えたら切断する）
+    pub max_ws_frame_bytes: usize,
+    /// WebSocketの1メッセージの上限（バイト、超えたら切断する）
+    pub max_ws_message_bytes: usize,
+    /// 同時に処理するリクエストと開いているWebSocketの上限（超えたら`503`、0は無制限）
+    pub max_connections: usize,
+    /// クライアントへの1回の書き込みを待つ秒数（超えたら遅いクライアントとして切断する）
+    pub write_timeout_secs: u64,
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: 1024 * 1024,
+            max_ws_frame_bytes: 64 * 1024,
+            max_ws_message_bytes: 256 * 1024,
+            max_connections: 64,
+            write_timeout_secs: 10,
+        }
+    }
+}
+
+impl ServerLimits {
+    pub fn write_timeout(&self) -> Duration {
+        Duration::from_secs(self.write_timeout_secs)
+    }
+}
+
+/// 処理中のリクエストと開いているWebSocketを数える
+pub(crate) struct ConnectionLimiter {
+    semaphore: Arc<Semaphore>,
+}
+
+/// 接続が終わるまで持つ枠（WebSocketはソケットを閉じるまで持つ）
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionPermit {
+    _permit: Arc<OwnedSemaphorePermit>,
+}
+
+impl ConnectionLimiter {
+    pub(crate) fn new(max_connections: usize) -> Self {
+        let permits = match max_connections {
+            0 => Semaphore::MAX_PERMITS,
+            n => n,
+        };
+        Self {
+            semaphore: Arc::new(Semaphore::new(permits)),
+        }
+    }
+
+    /// 空いている枠を取る（上限に達していれば`None`）
+    pub(crate) fn try_acquire(&self) -> Option<ConnectionPermit> {
+        self.semaphore
+            .clone()
+            .try_acquire_owned()
+            .ok()
+            .map(|permit| ConnectionPermit {
+                _permit: Arc::new(permit),
+            })
+    }
+}
+
+/// 同時接続数の上限を超えたリクエストを`503`で拒否し、枠をリクエストに付与する
+pub(crate) async fn limit_connections(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some(permit) = state.connections.try_acquire() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "同時接続数の上限に達しました。しばらく待ってから再度お試しください",
+        )
+            .into_response();
+    };
+    req.extensions_mut().insert(permit);
+    next.run(req).await
+}
+
+/// WebSocketに書き込む（`timeout`までに書き込めなければ、遅いクライアントとして`false`）
+pub(crate) async fn send_with_timeout<S>(
+    sender: &mut S,
+    message: Message,
+    timeout: Duration,
+) -> bool
+where
+    S: Sink<Message> + Unpin,
+{
+    matches!(
+        tokio::time::timeout(timeout, sender.send(message)).await,
+        Ok(Ok(()))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::pin::pin;
+
+    #[test]
+    fn limits_concurrent_connections() {
+        let limiter = ConnectionLimiter::new(2);
+        let first = limiter.try_acquire().unwrap();
+        let second = limiter.try_acquire().unwrap();
+        assert!(limiter.try_acquire().is_none());
+        drop(first);
+        assert!(limiter.try_acquire().is_some());
+        drop(second);
+
+        let unlimited = ConnectionLimiter::new(0);
+        let permits: Vec<_> = (0..1000).map(|_| unlimited.try_acquire()).collect();
+        assert!(permits.iter().all(Option::is_some));
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_slow_clients() {
+        let mut fast = futures::sink::drain();
+        assert!(
+            send_with_timeout(&mut fast, Message::Text("a".into()), Duration::from_secs(1)).await
+        );
+
+        let mut stalled = pin!(futures::sink::unfold((), |(), _: Message| {
+            futures::future::pending::<Result<(), Infallible>>()
+        }));
+        assert!(
+            !send_with_timeout(
+                &mut stalled,
+                Message::Text("a".into()),
+                Duration::from_millis(50)
+            )
+            .await
+        );
+    }
+
+    #[test]
+    fn parses_partial_limits() {
+        let limits: ServerLimits = toml::from_str("max_connections = 8").unwrap();
+        assert_eq!(
+            limits,
+            ServerLimits {
+                max_connections: 8,
+                ..ServerLimits::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use chrono::DateTime;
+use chrono::FixedOffset;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::Duration;
+
+/// レポートや要約の表示言語（`.ambient/config.toml`の`language`）
+///
+/// 日付・件数・所要時間の書式もこれに合わせる。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// 日本語（`2026-10-16 09:30`、`1,234件`、`1時間5分`）
+    #[default]
+    Ja,
+    /// 英語（`Oct 16, 2026 9:30 AM`、`1,234 files`、`1h 5m`）
+    En,
+}
+
+impl Locale {
+    /// 日時（RFC3339）を表示用タイムゾーンに変換して表示する（解析できなければそのまま返す）
+    pub fn format_datetime(self, ts: &str, offset: FixedOffset) -> String {
+        let Ok(datetime) = DateTime::parse_from_rfc3339(ts) else {
+            return ts.to_string();
+        };
+        let datetime = datetime.with_timezone(&offset);
+        match self {
+            Locale::Ja => datetime.format("%Y-%m-%d %H:%M").to_string(),
+            Locale::En => datetime.format("%b %-d, %Y %-I:%M %p").to_string(),
+        }
+    }
+
+    /// 件数（3桁ごとに区切り、`unit`は日本語の助数詞、`noun`は英語の単数形）
+    pub fn format_count(self, count: usize, unit: &str, noun: &str) -> String {
+        let number = group_digits(count);
+        match self {
+            Locale::Ja => format!("{number}{unit}"),
+            Locale::En if count == 1 => format!("{number} {noun}"),
+            Locale::En => format!("{number} {noun}s"),
+        }
+    }
+
+    /// 所要時間（1分未満は秒、1時間未満は分と秒、それ以上は時間と分）
+    pub fn format_duration(self, duration: Duration) -> String {
+        let secs = duration.as_secs();
+        let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+        match (self, hours, minutes) {
+            (Locale::Ja, 0, 0) => format!("{seconds}秒"),
+            (Locale::Ja, 0, _) => format!("{minutes}分{seconds}秒"),
+            (Locale::Ja, _, _) => format!("{hours}時間{minutes}分"),
+            (Locale::En, 0, 0) => format!("{seconds}s"),
+            (Locale::En, 0, _) => format!("{minutes}m {seconds}s"),
+            (Locale::En, _, _) => format!("{hours}h {minutes}m"),
+        }
+    }
+}
+
+/// 3桁ごとに`,`で区切る
+fn group_digits(value: usize) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dates_per_locale() {
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        let ts = "2026-10-16T00:30:00.000Z";
+        assert_eq!(Locale::Ja.format_datetime(ts, jst), "2026-10-16 09:30");
+        assert_eq!(Locale::En.format_datetime(ts, jst), "Oct 16, 2026 9:30 AM");
+        assert_eq!(Locale::En.format_datetime("不明", jst), "不明");
+    }
+
+    #[test]
+    fn formats_counts_and_durations_per_locale() {
+        assert_eq!(Locale::Ja.format_count(1234, "件", "file"), "1,234件");
+        assert_eq!(Locale::En.format_count(1, "件", "file"), "1 file");
+        assert_eq!(
+            Locale::En.format_count(1234567, "件", "file"),
+            "1,234,567 files"
+        );
+        assert_eq!(Locale::Ja.format_duration(Duration::from_secs(42)), "42秒");
+        assert_eq!(
+            Locale::Ja.format_duration(Duration::from_secs(3900)),
+            "1時間5分"
+        );
+        assert_eq!(
+            Locale::En.format_duration(Duration::from_secs(125)),
+            "2m 5s"
+        );
+    }
+}
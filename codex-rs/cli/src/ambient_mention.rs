@@ -0,0 +1,226 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::ambient_config::RedactionLevel;
+use crate::ambient_encoding::read_text;
+use crate::ambient_git::run_git_command;
+use crate::ambient_redact::redact;
+use crate::ambient_safe_path::SafePath;
+use crate::ambient_safe_path::is_forbidden_path;
+
+/// `GET /api/paths`が返す候補の既定の数
+pub const DEFAULT_PATH_SUGGESTIONS: usize = 20;
+
+/// 質問に添えるファイルの内容の上限（文字数、超えた分は切り詰める）
+pub const MENTION_MAX_CHARS: usize = 8_000;
+
+/// 1つの質問に添えるファイルの数の上限
+const MAX_MENTIONS: usize = 5;
+
+/// 補完の候補にするリポジトリ内のファイル（追跡中と、無視されていない未追跡のファイル）
+pub fn list_repo_paths(root: &Path) -> Result<Vec<String>> {
+    let output = run_git_command(
+        &[
+            "-c",
+            "core.quotePath=false",
+            "ls-files",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+        ],
+        root,
+    )?;
+    let mut paths: Vec<String> = output
+        .lines()
+        .filter(|path| !path.is_empty() && !is_forbidden_path(path))
+        .map(str::to_string)
+        .collect();
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// `query`の文字を順に含むパスを、一致の良い順に最大`limit`件返す
+pub fn match_paths(paths: &[String], query: &str, limit: usize) -> Vec<String> {
+    let mut scored: Vec<(u32, &String)> = paths
+        .iter()
+        .filter_map(|path| fuzzy_score(query, path).map(|score| (score, path)))
+        .collect();
+    scored.sort_by(|(a_score, a), (b_score, b)| {
+        b_score
+            .cmp(a_score)
+            .then(a.len().cmp(&b.len()))
+            .then(a.cmp(b))
+    });
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, path)| path.clone())
+        .collect()
+}
+
+/// あいまい一致の点数（大文字小文字は区別せず、`query`の文字を順に含まなければ`None`）
+///
+/// 連続した一致、区切り文字の直後での一致、ファイル名への一致を高く評価する。
+fn fuzzy_score(query: &str, path: &str) -> Option<u32> {
+    let query = query.trim().to_lowercase();
+    let lower = path.to_lowercase();
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut chars = lower.char_indices();
+    for wanted in query.chars() {
+        let (index, _) = chars.by_ref().find(|(_, c)| *c == wanted)?;
+        score += 1;
+        if previous.is_some_and(|previous| lower[previous..index].chars().count() == 1) {
+            score += 5;
+        }
+        let at_boundary = lower[..index]
+            .chars()
+            .next_back()
+            .is_none_or(|c| matches!(c, '/' | '_' | '-' | '.'));
+        if at_boundary {
+            score += 3;
+        }
+        previous = Some(index);
+    }
+    let file_name = lower.rsplit('/').next().unwrap_or_default();
+    if !query.is_empty() && file_name.contains(&query) {
+        score += 10;
+    }
+    Some(score)
+}
+
+/// 質問中の`@path`（空白の直後または先頭から始まる語）を出現順に返す
+pub fn mentioned_paths(text: &str) -> Vec<&str> {
+    let mut paths = Vec::new();
+    for word in text.split_whitespace() {
+        let Some(path) = word.strip_prefix('@') else {
+            continue;
+        };
+        let path = path.trim_end_matches([',', '.', ':', ';', '?', '!', '、', '。', '？']);
+        if !path.is_empty() && !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// 質問で`@path`と書かれたファイルの内容を、切り詰めて質問の後ろに添える
+///
+/// リポジトリの外や読めないファイルは無視し、質問はそのまま送る。
+pub fn with_mentioned_files(text: &str, root: &Path, redaction: RedactionLevel) -> String {
+    let files = mentioned_files(text, root, redaction);
+    format!("{text}{}", render_mentions(&files, usize::MAX))
+}
+
+/// 質問で`@path`と書かれたファイルの相対パスと、伏せ字にした内容（リポジトリの外や読めないファイルは除く）
+pub fn mentioned_files(
+    text: &str,
+    root: &Path,
+    redaction: RedactionLevel,
+) -> Vec<(String, String)> {
+    let mut files = Vec::new();
+    for path in mentioned_paths(text).into_iter().take(MAX_MENTIONS) {
+        let Ok(path) = SafePath::resolve(root, path) else {
+            continue;
+        };
+        let Ok(content) = read_text(path.absolute()) else {
+            continue;
+        };
+        files.push((path.relative().to_string(), redact(&content, redaction)));
+    }
+    files
+}
+
+/// 質問の後ろに添えるファイルの内容（1ファイルは`MENTION_MAX_CHARS`文字、合わせて`max_chars`文字まで）
+pub fn render_mentions(files: &[(String, String)], max_chars: usize) -> String {
+    let mut text = String::new();
+    let mut budget = max_chars;
+    for (path, content) in files {
+        let limit = MENTION_MAX_CHARS.min(budget);
+        if limit == 0 {
+            break;
+        }
+        let truncated: String = content.chars().take(limit).collect();
+        budget -= truncated.chars().count();
+        let note = if truncated.len() < content.len() {
+            format!("（先頭{limit}文字のみ）")
+        } else {
+            String::new()
+        };
+        text.push_str(&format!(
+            "\n\n---\n\n`{path}`の内容{note}:\n\n```\n{truncated}\n```"
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_test_support::TempRepo;
+
+    #[test]
+    fn ranks_fuzzy_matches() {
+        let paths = vec![
+            "cli/src/ambient_parser.rs".to_string(),
+            "docs/parity.md".to_string(),
+            "src/par.rs".to_string(),
+            "README.md".to_string(),
+        ];
+        let matches = match_paths(&paths, "par", 10);
+        assert_eq!(matches[0], "src/par.rs");
+        assert!(!matches.contains(&"README.md".to_string()));
+        assert_eq!(match_paths(&paths, "par", 1).len(), 1);
+        assert_eq!(match_paths(&paths, "", 10).len(), 4);
+    }
+
+    #[test]
+    fn lists_repo_paths_without_private_directories() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/lib.rs", "").unwrap();
+        repo.write(".ambient/config.toml", "").unwrap();
+        repo.write(".gitignore", "target/\n").unwrap();
+        repo.write("target/debug.log", "").unwrap();
+
+        let paths = list_repo_paths(repo.path()).unwrap();
+        assert!(paths.contains(&"src/lib.rs".to_string()));
+        assert!(!paths.iter().any(|path| path.starts_with(".ambient/")));
+        assert!(!paths.iter().any(|path| path.starts_with("target/")));
+    }
+
+    #[test]
+    fn injects_mentioned_files() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/foo.rs", "fn foo() {}").unwrap();
+        repo.write("big.txt", &"a".repeat(MENTION_MAX_CHARS + 10))
+            .unwrap();
+
+        assert_eq!(
+            mentioned_paths("@src/foo.rs と @big.txt、 @src/foo.rs?"),
+            vec!["src/foo.rs", "big.txt"]
+        );
+        let prompt = with_mentioned_files(
+            "@src/foo.rs は何をしますか？ @../secret @missing.rs",
+            repo.path(),
+            RedactionLevel::default(),
+        );
+        assert!(prompt.starts_with("@src/foo.rs は何をしますか？"));
+        assert!(prompt.contains("`src/foo.rs`の内容:\n\n```\nfn foo() {}\n```"));
+        assert!(!prompt.contains("secret`"));
+
+        let prompt = with_mentioned_files("@big.txt", repo.path(), RedactionLevel::default());
+        assert!(prompt.contains("（先頭8000文字のみ）"));
+
+        // 配分された文字数を超える分は、ファイルをまたいで切り詰める
+        let files = mentioned_files(
+            "@src/foo.rs @big.txt",
+            repo.path(),
+            RedactionLevel::default(),
+        );
+        let text = render_mentions(&files, 15);
+        assert!(text.contains("```\nfn foo() {}\n```"));
+        assert!(text.contains("`big.txt`の内容（先頭4文字のみ）:\n\n```\naaaa\n```"));
+        assert!(!render_mentions(&files, 11).contains("big.txt"));
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use crate::ambient_output_format::FINDINGS_INSTRUCTION;
+
+/// モデルを呼び出さずに決まった回答を返すプロバイダーのID（プロファイルの`model_provider`に指定する）
+pub const MOCK_PROVIDER_ID: &str = "mock";
+
+/// 回答として使うフィクスチャの拡張子
+const FIXTURE_EXTENSIONS: &[&str] = &["md", "txt"];
+
+/// どのフィクスチャにも一致しないときに使うファイル名（拡張子を除く）
+const DEFAULT_FIXTURE: &str = "default";
+
+/// OllamaやGPUのないマシンでUIや通知を開発・デモするためのプロバイダー
+///
+/// フィクスチャのディレクトリにある`<キーワード>.md`は、プロンプトにキーワードが含まれるときの回答になる
+/// （複数一致した場合は長いキーワードを優先する）。どれにも一致しなければ`default.md`、
+/// それもなければプロンプトから決まる回答を返す。
+#[derive(Debug, Clone, Default)]
+pub struct MockProvider {
+    /// キーワードの長い順に並べたフィクスチャ
+    fixtures: Vec<(String, String)>,
+    fallback: Option<String>,
+}
+
+impl MockProvider {
+    /// フィクスチャのディレクトリを読み込む（`None`の場合は組み込みの回答だけを使う）
+    pub fn load(dir: Option<&Path>) -> Result<Self> {
+        let Some(dir) = dir else {
+            return Ok(Self::default());
+        };
+        let mut provider = Self::default();
+        let entries =
+            fs::read_dir(dir).with_context(|| format!("{}を読み込めません", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let is_fixture = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| FIXTURE_EXTENSIONS.contains(&ext));
+            let Some(keyword) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if !is_fixture || !path.is_file() {
+                continue;
+            }
+            let response = fs::read_to_string(&path)
+                .with_context(|| format!("{}を読み込めません", path.display()))?;
+            if keyword == DEFAULT_FIXTURE {
+                provider.fallback = Some(response);
+            } else {
+                provider.fixtures.push((keyword.to_string(), response));
+            }
+        }
+        provider
+            .fixtures
+            .sort_by(|(a, _), (b, _)| b.chars().count().cmp(&a.chars().count()).then(a.cmp(b)));
+        Ok(provider)
+    }
+
+    /// プロンプトに対する回答（同じプロンプトには常に同じ回答を返す）
+    pub fn respond(&self, prompt: &str) -> String {
+        if let Some((_, response)) = self
+            .fixtures
+            .iter()
+            .find(|(keyword, _)| prompt.contains(keyword.as_str()))
+        {
+            return response.clone();
+        }
+        if let Some(response) = &self.fallback {
+            return response.clone();
+        }
+        derived_response(prompt)
+    }
+}
+
+/// フィクスチャがないときの回答（重大度と確信度はプロンプトのハッシュで決める）
+fn derived_response(prompt: &str) -> String {
+    let hash = fnv1a(prompt);
+    let target = mentioned_path(prompt)
+        .map(|path| format!("`{path}`"))
+        .unwrap_or_else(|| "このコード".to_string());
+    // 指摘の一覧を求めるレビューには、1件の指摘のJSONを返す
+    if prompt.contains(FINDINGS_INSTRUCTION) {
+        let severity = ["low", "medium", "high"][(hash % 3) as usize];
+        return serde_json::json!({
+            "findings": [{
+                "line": 1 + hash % 20,
+                "severity": severity,
+                "message": format!("（モック）{target}の指摘です。これはモックプロバイダーの回答です。"),
+                "suggestion": null,
+            }]
+        })
+        .to_string();
+    }
+    let mut response =
+        format!("（モック）{target}を確認しました。これはモックプロバイダーの回答です。");
+    // 自己評価を求めるレビューのプロンプトには、重大度と確信度の行を付ける
+    if prompt.contains("確信度") {
+        let severity = ["low", "medium", "high"][(hash % 3) as usize];
+        let confidence = 40 + hash % 61;
+        response.push_str(&format!("\n重大度: {severity}\n確信度: {confidence}"));
+    }
+    response
+}
+
+/// プロンプト中で最初に出てくるファイルパスらしい語（`/`と拡張子を含む語）
+fn mentioned_path(prompt: &str) -> Option<&str> {
+    prompt
+        .split(|c: char| c.is_whitespace() || "`'\"()（）「」".contains(c))
+        .map(|word| word.trim_end_matches([':', ',', '.', '、', '。']))
+        .find(|word| {
+            word.contains('/')
+                && !word.contains("://")
+                && word.rsplit_once('.').is_some_and(|(_, ext)| {
+                    !ext.is_empty() && ext.chars().all(char::is_alphanumeric)
+                })
+        })
+}
+
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_confidence::CONFIDENCE_INSTRUCTION;
+    use crate::ambient_confidence::extract_assessment;
+    use crate::ambient_output_format::parse_findings_output;
+    use tempfile::tempdir;
+
+    #[test]
+    fn prefers_longest_matching_fixture() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("セキュリティ.md"),
+            "秘密情報が含まれています",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("セキュリティ監査.md"),
+            "監査ログがありません",
+        )
+        .unwrap();
+        fs::write(dir.path().join("default.txt"), "問題ありません").unwrap();
+        fs::write(dir.path().join("notes.json"), "{}").unwrap();
+
+        let provider = MockProvider::load(Some(dir.path())).unwrap();
+        assert_eq!(
+            provider.respond("セキュリティ監査の観点で"),
+            "監査ログがありません"
+        );
+        assert_eq!(
+            provider.respond("セキュリティの観点で"),
+            "秘密情報が含まれています"
+        );
+        assert_eq!(provider.respond("パフォーマンス"), "問題ありません");
+    }
+
+    #[test]
+    fn derives_deterministic_assessment_without_fixtures() {
+        let provider = MockProvider::load(None).unwrap();
+        let prompt = format!("src/lib.rs をレビューしてください。{CONFIDENCE_INSTRUCTION}");
+        let response = provider.respond(&prompt);
+        assert_eq!(response, provider.respond(&prompt));
+
+        let (message, severity, confidence) = extract_assessment(&response);
+        assert!(message.contains("`src/lib.rs`"));
+        assert!(severity.is_some());
+        assert!(confidence.is_some_and(|confidence| (40..=100).contains(&confidence)));
+
+        assert!(!provider.respond("こんにちは").contains("確信度"));
+    }
+
+    #[test]
+    fn answers_findings_prompts_with_json() {
+        let provider = MockProvider::load(None).unwrap();
+        let prompt = format!("src/lib.rs をレビューしてください。{FINDINGS_INSTRUCTION}");
+        let items = parse_findings_output(&provider.respond(&prompt)).unwrap();
+        assert_eq!(items.len(), 1);
+        assert!(items[0].message.contains("`src/lib.rs`"));
+        assert!(items[0].line.is_some());
+    }
+}
@@ -0,0 +1,153 @@
+use std::process::Command;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::ambient_config::NotifyRoute;
+use crate::ambient_history::DigestRecord;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_server::Finding;
+
+/// 通知先が設定されている場合だけ、レビュー結果の配送を始める
+pub fn spawn_routes(routes: &[NotifyRoute], tx: &broadcast::Sender<EventEnvelope>) {
+    if !routes.is_empty() {
+        tokio::spawn(route_findings(tx.subscribe(), routes.to_vec()));
+    }
+}
+
+/// 監視ループのレビュー結果を、条件に一致する通知先に配送する
+async fn route_findings(mut rx: broadcast::Receiver<EventEnvelope>, routes: Vec<NotifyRoute>) {
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: event @ (AmbientEvent::Finding(_) | AmbientEvent::Findings(_)),
+                ts,
+                ..
+            }) => {
+                // 確信度の低い指摘は人が確認するまで通知しない
+                for finding in event.findings().iter().filter(|f| !f.needs_human_review) {
+                    for route in routes.iter().filter(|route| route_matches(route, finding)) {
+                        let payload = serde_json::json!({
+                            "type": "ambient-finding",
+                            "ts": ts,
+                            "file_path": finding.file_path,
+                            "review": finding.review,
+                            "owners": finding.owners,
+                            "message": finding.message,
+                            "line": finding.line,
+                        });
+                        spawn_notifier(&route.command, &payload.to_string());
+                    }
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// レビュー結果が通知先の条件にすべて一致するか
+fn route_matches(route: &NotifyRoute, finding: &Finding) -> bool {
+    let path_matches = route.paths.is_empty()
+        || route.paths.iter().any(|pattern| {
+            glob::Pattern::new(pattern)
+                .ok()
+                .is_some_and(|p| p.matches(&finding.file_path))
+        });
+    let owner_matches =
+        route.owners.is_empty() || route.owners.iter().any(|o| finding.owners.contains(o));
+    let review_matches = route.reviews.is_empty()
+        || route
+            .reviews
+            .iter()
+            .any(|review| finding.review.contains(review.as_str()));
+    path_matches && owner_matches && review_matches
+}
+
+/// 週次ダイジェストをすべての通知先に送る（通知先の条件はレビュー結果にだけ使う）
+pub fn deliver_digest(routes: &[NotifyRoute], digest: &DigestRecord) {
+    let payload = serde_json::json!({
+        "type": "ambient-digest",
+        "ts": digest.ts,
+        "since": digest.since,
+        "opened": digest.opened,
+        "closed": digest.closed,
+        "message": digest.text,
+    });
+    for route in routes {
+        spawn_notifier(&route.command, &payload.to_string());
+    }
+}
+
+fn spawn_notifier(command: &[String], payload: &str) {
+    let Some((program, args)) = command.split_first() else {
+        return;
+    };
+    // Fire-and-forget – we do not wait for completion.
+    if let Err(e) = Command::new(program).args(args).arg(payload).spawn() {
+        tracing::warn!("failed to spawn notifier '{program}': {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::RunIds;
+
+    fn finding(file_path: &str, review: &str, owners: &[&str]) -> Finding {
+        Finding {
+            ids: RunIds::default(),
+            file_path: file_path.to_string(),
+            review: review.to_string(),
+            message: String::new(),
+            snapshots: vec![],
+            owners: owners.iter().map(|o| o.to_string()).collect(),
+            labels: vec![],
+            confidence: None,
+            severity: None,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: None,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn routes_by_path_review_and_owner() {
+        let security = NotifyRoute {
+            command: vec!["notify-security".to_string()],
+            paths: vec!["auth/**".to_string()],
+            reviews: vec!["セキュリティ".to_string()],
+            ..Default::default()
+        };
+        assert!(route_matches(
+            &security,
+            &finding("auth/login.rs", "セキュリティリスクの検出", &[])
+        ));
+        assert!(!route_matches(
+            &security,
+            &finding("auth/login.rs", "パフォーマンス最適化", &[])
+        ));
+        assert!(!route_matches(
+            &security,
+            &finding("web/app.js", "セキュリティリスクの検出", &[])
+        ));
+
+        let frontend = NotifyRoute {
+            command: vec!["notify-frontend".to_string()],
+            owners: vec!["@org/frontend".to_string()],
+            ..Default::default()
+        };
+        assert!(route_matches(
+            &frontend,
+            &finding("web/app.js", "構文エラー", &["@org/frontend"])
+        ));
+        assert!(!route_matches(
+            &frontend,
+            &finding("src/lib.rs", "構文エラー", &["@org/backend"])
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+/// レビューが求める回答の形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// 自由なテキスト（Markdown）
+    #[default]
+    Text,
+    /// JSON（解析できなければ1回だけ修正を依頼し、それでも駄目ならテキストとして扱う）
+    Json,
+    /// 指摘の一覧のJSON（1件ずつ行・重大度・修正案を持つ`Findings`イベントとして送る）
+    Findings,
+}
+
+/// 指摘の一覧を求めるレビューのプロンプトの末尾に付ける、回答の形式の指示
+pub const FINDINGS_INSTRUCTION: &str = "\n\n---\n\n説明や```の囲みを付けず、次の形式のJSONだけで回答してください。指摘がなければ`findings`を空の配列にしてください。\n{\"findings\": [{\"line\": 行番号, \"severity\": \"critical|high|medium|low\", \"message\": \"問題の説明\", \"suggestion\": \"修正案（なければnull）\"}]}";
+
+/// モデルが返した1件の指摘
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FindingItem {
+    /// 指摘した行（1始まり）
+    #[serde(default)]
+    pub line: Option<u32>,
+    /// モデルが書いた重大度（`Severity::normalize`で揃える）
+    #[serde(default)]
+    pub severity: Option<String>,
+    pub message: String,
+    #[serde(default)]
+    pub suggestion: Option<String>,
+}
+
+/// 指摘の一覧の回答を解析する（`{"findings": [...]}`か、指摘の配列）
+pub fn parse_findings_output(output: &str) -> Result<Vec<FindingItem>, serde_json::Error> {
+    let items = match parse_json_output(output)? {
+        serde_json::Value::Object(mut object) => {
+            object.remove("findings").unwrap_or(serde_json::Value::Null)
+        }
+        value => value,
+    };
+    serde_json::from_value(items)
+}
+
+/// 回答が求めた形式として解析できるか（テキストは常に解析できる）
+pub fn check_output(format: OutputFormat, output: &str) -> Result<(), serde_json::Error> {
+    match format {
+        OutputFormat::Text => Ok(()),
+        OutputFormat::Json => parse_json_output(output).map(|_| ()),
+        OutputFormat::Findings => parse_findings_output(output).map(|_| ()),
+    }
+}
+
+/// モデルに渡すレビューのプロンプトと、期待する回答の形式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReviewPrompt {
+    pub text: String,
+    pub format: OutputFormat,
+    /// 前に付ける固定した文書の文字数の上限（`[context_budget]`の配分、なしは切り詰めない）
+    pub pinned_chars: Option<usize>,
+}
+
+impl From<String> for ReviewPrompt {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            format: OutputFormat::Text,
+            pinned_chars: None,
+        }
+    }
+}
+
+/// モデルの回答をJSONとして解析する（前後の空白と```` ```json ````の囲みは取り除く）
+pub fn parse_json_output(output: &str) -> Result<serde_json::Value, serde_json::Error> {
+    let trimmed = output.trim();
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(body.trim())
+}
+
+/// 不正なJSONを返したモデルに、同じ内容をJSONで出し直すよう依頼するプロンプト
+pub fn repair_prompt(prompt: &str, output: &str, error: &str) -> String {
+    format!(
+        "{prompt}\n\n---\n\nあなたの前回の出力は不正なJSONでした: {error}\n\n前回の出力:\n\n{output}\n\n説明や```の囲みを付けず、同じ内容を正しいJSONだけで出力し直してください。"
+    )
+}
+
+/// JSON形式のレビューの回答の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatOutcome {
+    /// 最初の回答が正しいJSONだった
+    Valid,
+    /// 修正の依頼で正しいJSONになった
+    Repaired,
+    /// 修正しても解析できず、テキストとして扱った
+    Failed,
+}
+
+/// モデルごとのJSON形式の回答の集計
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ModelFormatStats {
+    pub model: String,
+    /// JSON形式を求めたレビューの回答数
+    pub responses: u64,
+    /// 修正の依頼で正しいJSONになった回答数
+    pub repaired: u64,
+    /// 修正しても解析できなかった回答数
+    pub failed: u64,
+    /// 修正が必要だった割合（`(repaired + failed) / responses`）
+    pub repair_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    responses: u64,
+    repaired: u64,
+    failed: u64,
+}
+
+/// JSON形式の回答の修正状況をモデルごとに集計する
+#[derive(Debug, Default)]
+pub struct OutputFormatStats {
+    models: Mutex<BTreeMap<String, Counts>>,
+}
+
+impl OutputFormatStats {
+    pub fn record(&self, model: &str, outcome: FormatOutcome) {
+        let Ok(mut models) = self.models.lock() else {
+            return;
+        };
+        let counts = models.entry(model.to_string()).or_default();
+        counts.responses += 1;
+        match outcome {
+            FormatOutcome::Valid => {}
+            FormatOutcome::Repaired => counts.repaired += 1,
+            FormatOutcome::Failed => counts.failed += 1,
+        }
+    }
+
+    /// モデル名の順に並べた集計
+    pub fn snapshot(&self) -> Vec<ModelFormatStats> {
+        let Ok(models) = self.models.lock() else {
+            return Vec::new();
+        };
+        models
+            .iter()
+            .map(|(model, counts)| ModelFormatStats {
+                model: model.clone(),
+                responses: counts.responses,
+                repaired: counts.repaired,
+                failed: counts.failed,
+                repair_rate: if counts.responses == 0 {
+                    0.0
+                } else {
+                    (counts.repaired + counts.failed) as f64 / counts.responses as f64
+                },
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fenced_json() {
+        assert_eq!(
+            parse_json_output("```json\n{\"issues\": []}\n```\n").unwrap(),
+            serde_json::json!({"issues": []})
+        );
+        assert!(parse_json_output("問題はありません").is_err());
+    }
+
+    #[test]
+    fn parses_findings_object_or_array() {
+        let items = parse_findings_output(
+            "{\"findings\": [{\"line\": 12, \"severity\": \"high\", \"message\": \"unwrapでpanicする\", \"suggestion\": \"?で返す\"}]}",
+        )
+        .unwrap();
+        assert_eq!(items[0].line, Some(12));
+        assert_eq!(items[0].suggestion.as_deref(), Some("?で返す"));
+
+        let items = parse_findings_output("[{\"message\": \"命名が不統一\"}]").unwrap();
+        assert_eq!(items[0].line, None);
+        assert!(
+            parse_findings_output("{\"findings\": []}")
+                .unwrap()
+                .is_empty()
+        );
+
+        assert!(check_output(OutputFormat::Findings, "{\"issues\": []}").is_err());
+        assert!(check_output(OutputFormat::Json, "{\"issues\": []}").is_ok());
+    }
+
+    #[test]
+    fn computes_repair_rate_per_model() {
+        let stats = OutputFormatStats::default();
+        stats.record("qwen", FormatOutcome::Valid);
+        stats.record("qwen", FormatOutcome::Repaired);
+        stats.record("qwen", FormatOutcome::Failed);
+        stats.record("qwen", FormatOutcome::Valid);
+        stats.record("gpt-oss", FormatOutcome::Valid);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot[0].model, "gpt-oss");
+        assert_eq!(snapshot[0].repair_rate, 0.0);
+        assert_eq!(snapshot[1].responses, 4);
+        assert_eq!(snapshot[1].repaired, 1);
+        assert_eq!(snapshot[1].failed, 1);
+        assert_eq!(snapshot[1].repair_rate, 0.5);
+    }
+}
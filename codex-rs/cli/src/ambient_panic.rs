@@ -0,0 +1,110 @@
+use futures::FutureExt;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Once;
+
+/// エラーイベントに含めるバックトレースの最大行数
+const MAX_BACKTRACE_LINES: usize = 40;
+
+thread_local! {
+    /// このスレッドで最後に起きたパニックの発生箇所のバックトレース（パニックフックが記録する）
+    static LAST_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// 捕まえたパニック
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicReport {
+    /// `panic!`のメッセージ
+    pub message: String,
+    /// 発生箇所のバックトレース（先頭から`MAX_BACKTRACE_LINES`行まで）
+    pub backtrace: Option<String>,
+}
+
+impl PanicReport {
+    /// エラーイベントの本文（メッセージとバックトレース）
+    pub fn describe(&self) -> String {
+        match &self.backtrace {
+            Some(backtrace) => format!("{}\n\nバックトレース:\n{backtrace}", self.message),
+            None => self.message.clone(),
+        }
+    }
+}
+
+/// `future`の中のパニックを捕まえる（そのジョブだけを中止し、監視ループやほかのジョブは続ける）
+///
+/// 非同期のタスクはポーリングのたびにスレッドが変わりうるが、パニックはそれを捕まえる
+/// `catch_unwind`と同じポーリングの中で起きるため、フックが記録したバックトレースを同じスレッドで取り出せる。
+pub async fn catch_panic<F: Future>(future: F) -> Result<F::Output, PanicReport> {
+    install_backtrace_hook();
+    AssertUnwindSafe(future)
+        .catch_unwind()
+        .await
+        .map_err(|payload| PanicReport {
+            message: panic_message(payload.as_ref()),
+            backtrace: LAST_BACKTRACE
+                .with(|last| last.borrow_mut().take())
+                .map(|backtrace| truncate_lines(&backtrace.to_string(), MAX_BACKTRACE_LINES)),
+        })
+}
+
+/// パニックの発生箇所のバックトレースを記録するフックを登録する（元のフックも呼ぶ）
+fn install_backtrace_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            LAST_BACKTRACE.with(|last| *last.borrow_mut() = Some(Backtrace::force_capture()));
+            previous(info);
+        }));
+    });
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "不明なパニック".to_string())
+}
+
+fn truncate_lines(text: &str, max_lines: usize) -> String {
+    let mut lines: Vec<&str> = text.lines().take(max_lines + 1).collect();
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        lines.push("…");
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_panics_with_a_backtrace() {
+        assert_eq!(catch_panic(async { 42 }).await, Ok(42));
+
+        let report = catch_panic(async {
+            tokio::task::yield_now().await;
+            panic!("{}行目を解析できません", 12);
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(report.message, "12行目を解析できません");
+        assert!(report.backtrace.is_some());
+        assert!(
+            report
+                .describe()
+                .starts_with("12行目を解析できません\n\nバックトレース:\n")
+        );
+
+        let report = catch_panic(async { panic!("固定のメッセージ") })
+            .await
+            .unwrap_err();
+        assert_eq!(report.message, "固定のメッセージ");
+    }
+}
@@ -0,0 +1,235 @@
+use std::path::Path;
+
+use crate::ambient_cycle::SkipReason;
+use crate::ambient_file_metadata::FileMetadata;
+use crate::ambient_project_config::ProjectConfig;
+use crate::ambient_project_config::PromptVariables;
+
+/// トークン数を見積もるときの1トークンあたりの文字数（実際の数はモデルのトークナイザーで変わる）
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// 組み込みのレビューのプロンプトのおおよその文字数（変更の内容を除く）
+const BUILTIN_PROMPT_CHARS: usize = 200;
+
+/// `ambient explain-plan`で、1つのファイルに実行するレビュー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedReview {
+    pub name: String,
+    /// レビューを適用した`file_patterns`のパターン（組み込みのレビューではなし）
+    pub pattern: Option<String>,
+    /// 優先度（組み込みのレビューではなし）
+    pub priority: Option<u32>,
+    /// 送るプロンプトのおおよそのトークン数
+    pub estimated_tokens: usize,
+    /// プロンプトを用意できない理由（プロンプトファイルがないなど）
+    pub error: Option<String>,
+}
+
+impl PlannedReview {
+    /// 組み込みのレビュー（プロンプトの長さは概算）
+    pub fn builtin(name: &str, content_chars: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            pattern: None,
+            priority: None,
+            estimated_tokens: estimate_tokens(BUILTIN_PROMPT_CHARS + content_chars),
+            error: None,
+        }
+    }
+}
+
+/// 1つのファイルの分析の予定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePlan {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    /// 分析しない理由（分析する場合はなし）
+    pub skip: Option<SkipReason>,
+    /// 実行するレビュー（優先度の高い順）
+    pub reviews: Vec<PlannedReview>,
+}
+
+/// 現在の設定と変更で、次のチェックが分析するファイルとレビュー
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReviewPlan {
+    pub files: Vec<FilePlan>,
+}
+
+/// 文字数からトークン数を見積もる
+pub fn estimate_tokens(chars: usize) -> usize {
+    chars.div_ceil(CHARS_PER_TOKEN)
+}
+
+/// ファイルに実行するレビュー（合う設定のレビューがなければ`builtin`の組み込みのレビュー）
+///
+/// 設定のレビューは、分析のときと同じようにプロンプトを組み立てて長さを数える。
+/// `pinned_chars`は、各プロンプトの前に付ける固定した文書の文字数。
+pub fn plan_reviews(
+    project_config: &ProjectConfig,
+    project_path: &Path,
+    file_path: &str,
+    content: &str,
+    branch: Option<&str>,
+    pinned_chars: usize,
+    builtin: &[&str],
+) -> Vec<PlannedReview> {
+    let reviews = project_config.get_reviews_for_file(file_path);
+    let content_chars = content.chars().count();
+    if reviews.is_empty() {
+        return builtin
+            .iter()
+            .map(|name| PlannedReview::builtin(name, pinned_chars + content_chars))
+            .collect();
+    }
+    let language = project_config.language_for(file_path);
+    let metadata = FileMetadata::new(file_path, &language);
+    let variables = PromptVariables {
+        file_path,
+        language: &language,
+        diff: content,
+        branch,
+        metadata: Some(&metadata),
+    };
+    reviews
+        .into_iter()
+        .map(|review| {
+            let prompt = project_config.review_prompt(project_path, review, &variables);
+            PlannedReview {
+                name: review.name.clone(),
+                pattern: project_config
+                    .matching_pattern(file_path, &review.file_patterns)
+                    .map(str::to_string),
+                priority: Some(review.priority),
+                estimated_tokens: prompt.as_ref().map_or(0, |prompt| {
+                    estimate_tokens(pinned_chars + prompt.chars().count())
+                }),
+                error: prompt.err().map(|e| format!("{e:#}")),
+            }
+        })
+        .collect()
+}
+
+impl ReviewPlan {
+    /// 見積もったトークン数の合計
+    pub fn total_tokens(&self) -> usize {
+        self.files
+            .iter()
+            .flat_map(|file| &file.reviews)
+            .map(|review| review.estimated_tokens)
+            .sum()
+    }
+
+    /// 表示用のテキスト（分析するファイル、スキップするファイル、合計の順）
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let (analyzed, skipped): (Vec<&FilePlan>, Vec<&FilePlan>) =
+            self.files.iter().partition(|file| file.skip.is_none());
+        for file in &analyzed {
+            text.push_str(&format!("{}\n", file.path));
+            if file.reviews.is_empty() {
+                text.push_str("  （diffがなく、合うレビューもないため実行しない）\n");
+            }
+            for review in &file.reviews {
+                let mut details = Vec::new();
+                match &review.pattern {
+                    Some(pattern) => details.push(format!("パターン: {pattern}")),
+                    None => details.push("組み込み".to_string()),
+                }
+                if let Some(priority) = review.priority {
+                    details.push(format!("優先度: {priority}"));
+                }
+                match &review.error {
+                    Some(error) => details.push(format!("エラー: {error}")),
+                    None => details.push(format!("約{}トークン", review.estimated_tokens)),
+                }
+                text.push_str(&format!("  - {}（{}）\n", review.name, details.join("、")));
+            }
+        }
+        if !skipped.is_empty() {
+            text.push_str("\nスキップ:\n");
+            for file in &skipped {
+                let reason = file.skip.map_or("", SkipReason::describe);
+                text.push_str(&format!("  {}: {reason}\n", file.path));
+            }
+        }
+        let review_count: usize = analyzed.iter().map(|file| file.reviews.len()).sum();
+        text.push_str(&format!(
+            "\n{}個のファイルに{review_count}件のレビュー、約{}トークン（スキップ{}個）\n",
+            analyzed.len(),
+            self.total_tokens(),
+            skipped.len()
+        ));
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_matching_reviews_with_patterns_and_token_estimates() {
+        let config: ProjectConfig = toml::from_str(
+            r#"
+[[reviews]]
+name = "unwrap"
+file_patterns = ["*.py", "src/**"]
+prompt = "{file_path}のunwrapを報告してください"
+priority = 300
+
+[[reviews]]
+name = "docs"
+file_patterns = ["docs/**"]
+prompt = "文書を確認してください"
+
+[[reviews]]
+name = "外部"
+file_patterns = ["*.rs"]
+prompt_file = "prompts/missing.md"
+"#,
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let reviews = plan_reviews(
+            &config,
+            dir.path(),
+            "src/lib.rs",
+            "+let x = foo().unwrap();",
+            None,
+            0,
+            &["構文"],
+        );
+        assert_eq!(reviews.len(), 2);
+        assert_eq!(reviews[0].name, "unwrap");
+        assert_eq!(reviews[0].pattern.as_deref(), Some("src/**"));
+        assert_eq!(reviews[0].priority, Some(300));
+        let prompt = "src/lib.rsのunwrapを報告してください\n\n---\n\nファイル: src/lib.rs\n言語: rust\n\n```rust\n+let x = foo().unwrap();\n```";
+        assert_eq!(
+            reviews[0].estimated_tokens,
+            estimate_tokens(prompt.chars().count())
+        );
+        assert!(reviews[1].error.is_some());
+
+        let reviews = plan_reviews(&config, dir.path(), "README.md", "本文", None, 8, &["構文"]);
+        assert_eq!(reviews, vec![PlannedReview::builtin("構文", 10)]);
+
+        let plan = ReviewPlan {
+            files: vec![
+                FilePlan {
+                    path: "README.md".to_string(),
+                    skip: None,
+                    reviews,
+                },
+                FilePlan {
+                    path: "logo.png".to_string(),
+                    skip: Some(SkipReason::Binary),
+                    reviews: vec![],
+                },
+            ],
+        };
+        assert_eq!(
+            plan.render(),
+            "README.md\n  - 構文（組み込み、約53トークン）\n\nスキップ:\n  logo.png: バイナリファイル\n\n1個のファイルに1件のレビュー、約53トークン（スキップ1個）\n"
+        );
+    }
+}
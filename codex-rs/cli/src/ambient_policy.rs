@@ -0,0 +1,159 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::ambient_server::Finding;
+
+/// 組み込みのセキュリティレビューが付けるラベル
+pub const SECURITY_LABEL: &str = "security";
+
+/// ブロックするラベルの指摘があったときの終了コード
+pub const POLICY_BLOCKED_EXIT_CODE: i32 = 3;
+
+/// `.ambient/config.toml`の`[policy]`（どのラベルの指摘でチェックを失敗させるか）
+///
+/// 重大度とは別に、レビューが付けたラベル（`security`、`compliance`、`perf`など）で判断する。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PolicyConfig {
+    /// このラベルが付いた指摘があれば失敗させる（大文字小文字は区別しない）
+    #[serde(default)]
+    pub blocking: Vec<String>,
+}
+
+impl PolicyConfig {
+    /// 指摘のラベルのうち、ブロックするもの
+    pub fn blocking_labels<'a>(&self, finding: &'a Finding) -> Vec<&'a str> {
+        finding
+            .labels
+            .iter()
+            .filter(|label| {
+                self.blocking
+                    .iter()
+                    .any(|blocking| blocking.eq_ignore_ascii_case(label))
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// 指摘をポリシーに照らして判定する
+    pub fn evaluate<'a>(&self, findings: impl IntoIterator<Item = &'a Finding>) -> PolicyReport {
+        let violations = findings
+            .into_iter()
+            .filter_map(|finding| {
+                let labels = self.blocking_labels(finding);
+                (!labels.is_empty()).then(|| PolicyViolation {
+                    file_path: finding.file_path.clone(),
+                    review: finding.review.clone(),
+                    labels: labels.into_iter().map(str::to_string).collect(),
+                })
+            })
+            .collect();
+        PolicyReport { violations }
+    }
+}
+
+/// ブロックするラベルが付いた指摘
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyViolation {
+    pub file_path: String,
+    pub review: String,
+    /// ブロックの理由になったラベル
+    pub labels: Vec<String>,
+}
+
+/// ポリシーの判定結果
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyReport {
+    pub violations: Vec<PolicyViolation>,
+}
+
+impl PolicyReport {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// プロセスの終了コード（通過は0）
+    pub fn exit_code(&self) -> i32 {
+        if self.passed() {
+            0
+        } else {
+            POLICY_BLOCKED_EXIT_CODE
+        }
+    }
+
+    /// 判定結果の表示用の文面
+    pub fn describe(&self) -> String {
+        if self.passed() {
+            return "ポリシー: ブロックする指摘はありません".to_string();
+        }
+        let mut text = format!(
+            "ポリシー: ブロックする指摘が{}件あります",
+            self.violations.len()
+        );
+        for violation in &self.violations {
+            text.push_str(&format!(
+                "\n  {} {}（{}）",
+                violation.file_path,
+                violation.review,
+                violation.labels.join(", ")
+            ));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::RunIds;
+
+    fn finding(review: &str, labels: &[&str]) -> Finding {
+        Finding {
+            ids: RunIds::new_run().new_job(),
+            file_path: "src/auth.rs".to_string(),
+            review: review.to_string(),
+            message: "指摘".to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            confidence: None,
+            severity: None,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: None,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn blocks_only_configured_labels() {
+        let policy: PolicyConfig =
+            toml::from_str("blocking = [\"Security\", \"compliance\"]").unwrap();
+        let findings = [
+            finding("セキュリティリスク検出", &["security"]),
+            finding("パフォーマンス最適化", &["perf"]),
+            finding("ライセンス確認", &["compliance", "perf"]),
+        ];
+
+        let report = policy.evaluate(&findings);
+        assert_eq!(report.violations.len(), 2);
+        assert_eq!(report.violations[1].labels, vec!["compliance".to_string()]);
+        assert_eq!(report.exit_code(), POLICY_BLOCKED_EXIT_CODE);
+        assert!(
+            report
+                .describe()
+                .contains("src/auth.rs ライセンス確認（compliance）")
+        );
+    }
+
+    #[test]
+    fn passes_without_blocking_labels() {
+        let report =
+            PolicyConfig::default().evaluate(&[finding("セキュリティリスク検出", &["security"])]);
+        assert!(report.passed());
+        assert_eq!(report.exit_code(), 0);
+    }
+}
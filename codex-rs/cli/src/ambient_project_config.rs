@@ -1,16 +1,58 @@
+use anyhow::Context;
 use anyhow::Result;
+use anyhow::bail;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::fs;
+use std::path::Component;
 use std::path::Path;
+use std::path::PathBuf;
+
+use crate::ambient_auth::AccessTokens;
+use crate::ambient_auth::ApiToken;
+use crate::ambient_budget::ContextBudget;
+use crate::ambient_commit_lint::CommitLintConfig;
+use crate::ambient_cycle::BinaryDetection;
+use crate::ambient_digest::DigestConfig;
+use crate::ambient_embedding::EmbeddingConfig;
+use crate::ambient_endpoint::ProviderSetting;
+use crate::ambient_file_metadata::FileMetadata;
+use crate::ambient_file_watch::FileWatchConfig;
+use crate::ambient_housekeeping::HousekeepingConfig;
+use crate::ambient_index::IndexConfig;
+use crate::ambient_integration::IntegrationsConfig;
+use crate::ambient_language::detect_language;
+use crate::ambient_large_file::LargeFileConfig;
+use crate::ambient_limits::ServerLimits;
+use crate::ambient_locale::Locale;
+use crate::ambient_output_format::OutputFormat;
+use crate::ambient_policy::PolicyConfig;
+use crate::ambient_policy::SECURITY_LABEL;
+use crate::ambient_rebase_plan::RebasePlanConfig;
+use crate::ambient_review_cache::content_hash;
+use crate::ambient_storage::StorageConfig;
+use crate::ambient_webhook::NotificationsConfig;
+use crate::ambient_webhook::WebhookFormat;
 
 /// プロジェクトごとのAmbient Code Watcher設定
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct ProjectConfig {
     /// Ollama設定
     #[serde(default)]
     pub ollama: OllamaConfig,
 
-    /// ファイル変更の検出間隔（秒）
+    /// レビューと質問に使うモデルプロバイダー（codexの`model_providers`のキーか、
+    /// OpenAI互換のサーバーの`[provider]`テーブル、未指定なら`oss`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<ProviderSetting>,
+
+    /// レビューと質問に使うモデル名（未指定なら`gpt-oss:20b`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// ファイル変更の検出間隔（秒）（`[file_watch]`が有効なら、変更通知を取りこぼしたときの確認に使う）
     #[serde(default = "default_check_interval")]
     pub check_interval_secs: u64,
 
@@ -18,10 +60,76 @@ pub struct ProjectConfig {
     #[serde(default = "default_port")]
     pub port: u16,
 
+    /// 待ち受けアドレス（`unix:/path/ambient.sock`でTCPの代わりにUnixドメインソケットを使う）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+
+    /// gRPC APIのポート（指定した場合のみ`127.0.0.1`で起動）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grpc_port: Option<u16>,
+
+    /// イベントの時刻を表示するタイムゾーン（`local`、`utc`、`+09:00`形式、未指定はlocal）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+
+    /// レポートや要約の表示言語（`ja`または`en`、日付・件数・所要時間の書式も合わせる）
+    #[serde(default)]
+    pub language: Locale,
+
     /// レビューを有効にするかどうか
     #[serde(default = "default_enabled")]
     pub enabled: bool,
 
+    /// 監視対象のディレクトリ（リポジトリルートからの相対パス、空の場合はリポジトリ全体）
+    #[serde(default)]
+    pub watch_paths: Vec<String>,
+
+    /// サブモジュール内の変更もそれぞれのdiffで分析するか
+    #[serde(default)]
+    pub recurse_submodules: bool,
+
+    /// diffの比較の基準にするgitの参照（例: `origin/main`、`HEAD~3`、タグ）
+    ///
+    /// 指定すると、この参照から分岐した後にコミットした変更もレビューする（未指定ならコミットしていない変更のみ）。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff_base: Option<String>,
+
+    /// 全権限のアクセストークン（未設定の場合、トークンなしで接続できる）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+
+    /// 閲覧専用のアクセストークン（共有用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub viewer_token: Option<String>,
+
+    /// スコープを指定したアクセストークン（`[[api_tokens]]`、設定するとトークンなしでは接続できない）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_tokens: Vec<ApiToken>,
+
+    /// 1ユーザーあたり1分間に受け付ける質問数（0は無制限）
+    #[serde(default = "default_query_rate_limit")]
+    pub query_rate_limit_per_minute: u32,
+
+    /// リクエストの大きさ、WebSocketのメッセージ、同時接続数、書き込みの待ち時間の上限
+    #[serde(default)]
+    pub server_limits: ServerLimits,
+
+    /// すべてのレビューと質問に使うシステムプロンプト（レビュアーの役割や回答の言語など）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_instructions: Option<String>,
+
+    /// codexのプロジェクト文書（`AGENTS.md`）をすべてのレビューと質問の前に付けるか
+    #[serde(default = "default_enabled")]
+    pub project_doc: bool,
+
+    /// すべてのレビューと質問の前に付ける`.ambient/context/`の文書（ファイル名、globパターン可）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_context: Vec<String>,
+
+    /// 固定した文書に使うトークン数の上限（超えた分は切り詰める）
+    #[serde(default = "default_pinned_context_tokens")]
+    pub pinned_context_tokens: usize,
+
     /// 除外パターン
     #[serde(default)]
     pub exclude_patterns: Vec<String>,
@@ -30,17 +138,114 @@ pub struct ProjectConfig {
     #[serde(default)]
     pub custom_prompts: Vec<CustomPrompt>,
 
-    /// 分析を有効にする拡張子のリスト
-    #[serde(default = "default_file_extensions")]
-    pub file_extensions: Vec<String>,
+    /// 分析を有効にする拡張子のリスト（未指定なら`~/.codex/ambient.toml`の`file_extensions`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_extensions: Option<Vec<String>>,
+
+    /// バイナリのファイルの判定方法（`git`では`.gitattributes`の指定も使う）
+    #[serde(default)]
+    pub binary_detection: BinaryDetection,
+
+    /// 分析するファイルの最大サイズ（バイト）
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+
+    /// 1回のチェックで分析するファイル数の上限（0は無制限）
+    #[serde(default)]
+    pub max_files_per_cycle: usize,
+
+    /// 1回のチェックでファイルの分析を始める時間の上限（秒、0は無制限）
+    ///
+    /// 過ぎたら残りのファイルは次のチェックに同じ順で持ち越す（分析中のファイルは最後まで分析する）。
+    #[serde(default)]
+    pub max_cycle_duration_secs: u64,
+
+    /// 1回のレビューと質問で送るプロンプトのトークン数の上限（0は無制限）
+    ///
+    /// 超える場合は`context_budget`で区分ごとに配分し、diffはハンクの境目で分けて別々にレビューして、
+    /// 回答を1つの指摘にまとめる。
+    #[serde(default = "default_max_prompt_tokens")]
+    pub max_prompt_tokens: usize,
+
+    /// `max_prompt_tokens`を超えるときの、固定した文書・変更・関連ファイル・質問への配分
+    #[serde(default)]
+    pub context_budget: ContextBudget,
+
+    /// 言語判定の上書き（globパターン → 言語名）
+    #[serde(default)]
+    pub language_overrides: BTreeMap<String, String>,
 
     /// レビュー設定
     #[serde(default)]
     pub reviews: Vec<ReviewConfig>,
+
+    /// どのラベルの指摘でチェックを失敗させるか
+    #[serde(default)]
+    pub policy: PolicyConfig,
+
+    /// 長く残った未コミットの変更、未pushのコミット、upstreamからの遅れを知らせる確認
+    #[serde(default)]
+    pub housekeeping: HousekeepingConfig,
+
+    /// コミットされようとしている大きなファイル・バイナリの警告
+    #[serde(default)]
+    pub large_files: LargeFileConfig,
+
+    /// コミットメッセージとブランチ名の規約
+    #[serde(default)]
+    pub commit_lint: CommitLintConfig,
+
+    /// push前の細かいコミットをまとめる`git rebase -i`の提案
+    #[serde(default)]
+    pub rebase_plan: RebasePlanConfig,
+
+    /// 週に1回のコード健全性のダイジェスト
+    #[serde(default)]
+    pub digest: DigestConfig,
+
+    /// 検索用の埋め込みを作るサービス（チャットのモデルとは別）
+    #[serde(default)]
+    pub embedding: EmbeddingConfig,
+
+    /// 埋め込みの索引（`.ambient/index.jsonl`に保持し、変更されたファイルだけ更新する）
+    #[serde(default)]
+    pub index: IndexConfig,
+
+    /// `ambient review-pr`でプルリクエストを取得し、レビューを投稿するサービス
+    #[serde(default)]
+    pub integrations: IntegrationsConfig,
+
+    /// 重大な指摘をチームのチャンネルなどに送るWebhook
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+
+    /// ファイルの変更を検知して、定期チェックを待たずにチェックする
+    #[serde(default)]
+    pub file_watch: FileWatchConfig,
+
+    /// 履歴とレビューのキャッシュを暗号化して保存する
+    #[serde(default)]
+    pub storage: StorageConfig,
+
+    /// 新規ファイルの配置・命名・登録・テストを確認するレビューを行うか
+    #[serde(default = "default_enabled")]
+    pub scaffold_review: bool,
+
+    /// 公開関数のシグネチャの変更で、呼び出し元を影響範囲として指摘するか
+    #[serde(default = "default_enabled")]
+    pub impact_review: bool,
+
+    /// この確信度（0〜100）未満の指摘を「要確認」として折りたたみ、通知しない（0で無効）
+    #[serde(default = "default_min_confidence")]
+    pub min_confidence: u8,
+
+    /// 同じファイル・同じレビューの似た指摘を再配信しない時間（秒、0で無効）
+    #[serde(default = "default_repeat_cooldown")]
+    pub repeat_cooldown_secs: u64,
 }
 
 /// Ollama設定
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct OllamaConfig {
     /// OllamaのベースURL
     #[serde(default = "default_ollama_base_url")]
@@ -52,7 +257,7 @@ pub struct OllamaConfig {
 }
 
 /// 個別のレビュー設定
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct ReviewConfig {
     /// レビューの名前
     pub name: String,
@@ -64,20 +269,153 @@ pub struct ReviewConfig {
     /// このレビューを適用するファイルパターン
     pub file_patterns: Vec<String>,
 
-    /// レビューのプロンプト
+    /// このレビューを適用する言語（空の場合はすべての言語）
+    #[serde(default)]
+    pub languages: Vec<String>,
+
+    /// レビューのプロンプト（`prompt_file`か`prompt_id`を指定した場合は使わない）
+    #[serde(default)]
     pub prompt: String,
 
-    /// 優先度（高い順に実行）
+    /// プロンプトのファイル（`.ambient/`からの相対パス、例: `prompts/security.md`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_file: Option<String>,
+
+    /// プロンプトに使う`custom_prompts`のID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_id: Option<String>,
+
+    /// 優先度（高い順に実行し、同じ優先度では設定の先にあるレビューから）
     #[serde(default = "default_priority")]
     pub priority: u32,
 
+    /// レビューのグループ（例: `syntax`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// 同じ`group`の`exclusive`なレビューのうち、1つのファイルには最も優先度の高い1つだけを実行する
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exclusive: bool,
+
     /// このレビューを有効にするか
     #[serde(default = "default_enabled")]
     pub enabled: bool,
+
+    /// 回答の形式を示す例（プロンプトの後に入力と期待する回答の組として渡す）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<ReviewExample>,
+
+    /// 回答の形式（`json`の場合、不正なJSONには1回だけ修正を依頼する）
+    #[serde(default)]
+    pub output_format: OutputFormat,
+
+    /// 指摘に付けるポリシーのラベル（`[policy]`の`blocking`に含まれるとチェックを失敗させる）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+}
+
+/// レビューの入力と期待する回答の例
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ReviewExample {
+    /// 例として渡すコードまたはdiff
+    pub input: String,
+
+    /// そのコードに対して期待する回答
+    pub expected_output: String,
+}
+
+/// プロンプトの雛形に埋め込む値（`{file_path}`、`{language}`、`{diff}`、`{branch}`）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PromptVariables<'a> {
+    pub file_path: &'a str,
+    pub language: &'a str,
+    /// レビューする変更（diffまたはファイル全体）
+    pub diff: &'a str,
+    /// 現在のブランチ名（detached HEADでは空文字列に置換する）
+    pub branch: Option<&'a str>,
+    /// `{diff}`を使わない雛形で、末尾の変更の前に付けるファイルの情報（変更は言語を指定したコードブロックで囲む）
+    pub metadata: Option<&'a FileMetadata>,
+}
+
+impl PromptVariables<'_> {
+    /// 雛形の変数を1回の走査で置換する（埋め込んだdiffの中の`{…}`は置換しない）
+    fn interpolate(&self, template: &str) -> String {
+        let variables = [
+            ("{file_path}", self.file_path),
+            ("{language}", self.language),
+            ("{diff}", self.diff),
+            ("{branch}", self.branch.unwrap_or_default()),
+        ];
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match variables.iter().find(|(name, _)| rest.starts_with(name)) {
+                Some((name, value)) => {
+                    rendered.push_str(value);
+                    rest = &rest[name.len()..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+impl ReviewConfig {
+    /// プロンプトの雛形（`prompt_file`、`prompt_id`、`prompt`の順に使う）
+    ///
+    /// ファイルは分析のたびに読むため、編集はすぐに次のチェックに反映される。
+    pub fn prompt_template(
+        &self,
+        project_path: &Path,
+        custom_prompts: &[CustomPrompt],
+    ) -> Result<String> {
+        if let Some(file) = &self.prompt_file {
+            let relative = Path::new(file);
+            if relative
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+            {
+                bail!("プロンプトファイルは`.ambient/`の中のパスで指定してください: {file}");
+            }
+            let path = project_path.join(".ambient").join(relative);
+            return fs::read_to_string(&path).with_context(|| {
+                format!("プロンプトファイルを読み込めません: {}", path.display())
+            });
+        }
+        if let Some(id) = &self.prompt_id {
+            return custom_prompts
+                .iter()
+                .find(|prompt| &prompt.id == id)
+                .map(|prompt| prompt.content.clone())
+                .with_context(|| format!("`{id}`というカスタムプロンプトはありません"));
+        }
+        Ok(self.prompt.clone())
+    }
+
+    /// 雛形の変数を置換し、例があれば続けて並べる
+    pub fn render_prompt(&self, template: &str, variables: &PromptVariables) -> String {
+        let mut prompt = variables.interpolate(template);
+        for (i, example) in self.examples.iter().enumerate() {
+            prompt.push_str(&format!(
+                "\n\n## 例{}\n\n### 入力\n\n{}\n\n### 回答\n\n{}",
+                i + 1,
+                example.input.trim_end(),
+                example.expected_output.trim_end()
+            ));
+        }
+        prompt
+    }
 }
 
 /// カスタムプロンプト
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct CustomPrompt {
     /// プロンプトID
     pub id: String,
@@ -138,6 +476,30 @@ fn default_file_extensions() -> Vec<String> {
     ]
 }
 
+fn default_max_file_bytes() -> u64 {
+    512 * 1024
+}
+
+fn default_max_prompt_tokens() -> usize {
+    6_000 // gpt-oss:20bをOllamaの既定のコンテキスト長で使っても、回答の分が残る
+}
+
+fn default_min_confidence() -> u8 {
+    50
+}
+
+fn default_repeat_cooldown() -> u64 {
+    3600
+}
+
+fn default_pinned_context_tokens() -> usize {
+    2_000
+}
+
+fn default_query_rate_limit() -> u32 {
+    10
+}
+
 fn default_priority() -> u32 {
     100
 }
@@ -163,9 +525,27 @@ impl Default for ProjectConfig {
     fn default() -> Self {
         Self {
             ollama: OllamaConfig::default(),
+            provider: None,
+            model: None,
             check_interval_secs: default_check_interval(),
             port: default_port(),
+            listen: None,
+            grpc_port: None,
+            timezone: None,
+            language: Locale::default(),
             enabled: true,
+            watch_paths: vec![],
+            recurse_submodules: false,
+            diff_base: None,
+            access_token: None,
+            viewer_token: None,
+            api_tokens: vec![],
+            query_rate_limit_per_minute: default_query_rate_limit(),
+            server_limits: ServerLimits::default(),
+            base_instructions: None,
+            project_doc: true,
+            pinned_context: vec![],
+            pinned_context_tokens: default_pinned_context_tokens(),
             exclude_patterns: vec![
                 "target/**".to_string(),
                 "node_modules/**".to_string(),
@@ -173,31 +553,78 @@ impl Default for ProjectConfig {
                 "*.min.js".to_string(),
             ],
             custom_prompts: vec![],
-            file_extensions: default_file_extensions(),
+            file_extensions: None,
+            binary_detection: BinaryDetection::default(),
+            max_file_bytes: default_max_file_bytes(),
+            max_files_per_cycle: 0,
+            max_cycle_duration_secs: 0,
+            max_prompt_tokens: default_max_prompt_tokens(),
+            context_budget: ContextBudget::default(),
+            scaffold_review: true,
+            impact_review: true,
+            min_confidence: default_min_confidence(),
+            repeat_cooldown_secs: default_repeat_cooldown(),
+            language_overrides: BTreeMap::new(),
+            policy: PolicyConfig::default(),
+            housekeeping: HousekeepingConfig::default(),
+            large_files: LargeFileConfig::default(),
+            commit_lint: CommitLintConfig::default(),
+            rebase_plan: RebasePlanConfig::default(),
+            digest: DigestConfig::default(),
+            embedding: EmbeddingConfig::default(),
+            index: IndexConfig::default(),
+            integrations: IntegrationsConfig::default(),
+            notifications: NotificationsConfig::default(),
+            file_watch: FileWatchConfig::default(),
+            storage: StorageConfig::default(),
             reviews: vec![
                 ReviewConfig {
                     name: "構文エラー・型エラーチェック".to_string(),
                     description: "コードの構文エラーと型の不一致を検出".to_string(),
                     file_patterns: vec!["*.rs".to_string(), "*.ts".to_string(), "*.js".to_string()],
+                    languages: vec![],
                     prompt: "以下のコードを分析して、構文エラーや型エラーの可能性を日本語で報告してください：\n1. 未定義変数、括弧の不一致、セミコロン忘れ\n2. 型の不一致\n3. エラー箇所は`{file_path}:行番号`形式で".to_string(),
+                    prompt_file: None,
+                    prompt_id: None,
                     priority: 200,
+                    group: None,
+                    exclusive: false,
                     enabled: true,
+                    examples: vec![],
+                    output_format: OutputFormat::Text,
+                    labels: vec![],
                 },
                 ReviewConfig {
                     name: "セキュリティリスク検出".to_string(),
                     description: "セキュリティ脆弱性とハードコードされた秘密情報を検出".to_string(),
                     file_patterns: vec!["*".to_string()],
+                    languages: vec![],
                     prompt: "以下のコードのセキュリティリスクを日本語で報告してください：\n1. ハードコードされたAPIキー、パスワード、トークン\n2. SQLインジェクション、XSSの脆弱性\n3. 安全でない入力検証".to_string(),
+                    prompt_file: None,
+                    prompt_id: None,
                     priority: 150,
+                    group: None,
+                    exclusive: false,
                     enabled: true,
+                    examples: vec![],
+                    output_format: OutputFormat::Text,
+                    labels: vec![SECURITY_LABEL.to_string()],
                 },
                 ReviewConfig {
                     name: "パフォーマンス最適化".to_string(),
                     description: "パフォーマンス問題と最適化の機会を検出".to_string(),
                     file_patterns: vec!["*.rs".to_string(), "*.go".to_string(), "*.cpp".to_string()],
+                    languages: vec![],
                     prompt: "以下のコードのパフォーマンス問題を日本語で分析してください：\n1. O(n²)以上の計算量\n2. 不要なループやメモリリーク\n3. より効率的な実装方法の提案".to_string(),
+                    prompt_file: None,
+                    prompt_id: None,
                     priority: 100,
+                    group: None,
+                    exclusive: false,
                     enabled: true,
+                    examples: vec![],
+                    output_format: OutputFormat::Text,
+                    labels: vec!["perf".to_string()],
                 },
             ],
         }
@@ -205,6 +632,40 @@ impl Default for ProjectConfig {
 }
 
 impl ProjectConfig {
+    /// サーバーのアクセストークン
+    pub fn access_tokens(&self) -> AccessTokens {
+        AccessTokens {
+            owner: self.access_token.clone(),
+            viewer: self.viewer_token.clone(),
+            scoped: self.api_tokens.clone(),
+        }
+    }
+
+    /// 監視中に変わっても、再起動するまで反映されない設定（待ち受けとトークン）が違うか
+    pub fn needs_restart(&self, other: &Self) -> bool {
+        self.port != other.port
+            || self.listen != other.listen
+            || self.grpc_port != other.grpc_port
+            || self.access_token != other.access_token
+            || self.viewer_token != other.viewer_token
+            || self.api_tokens != other.api_tokens
+            || self.server_limits != other.server_limits
+    }
+
+    /// 使用するプロバイダーID（`[provider]`のエンドポイントなら`ambient-endpoint`）
+    pub fn provider_id(&self) -> Option<&str> {
+        self.provider.as_ref().map(ProviderSetting::id)
+    }
+
+    /// 使用するモデル名（`[provider]`の`model`を優先する）
+    pub fn model_id(&self) -> Option<&str> {
+        self.provider
+            .as_ref()
+            .and_then(ProviderSetting::endpoint)
+            .and_then(|endpoint| endpoint.model.as_deref())
+            .or(self.model.as_deref())
+    }
+
     /// プロジェクト設定を読み込む
     pub fn load_from_project(project_path: &Path) -> Result<Self> {
         let config_dir = project_path.join(".ambient");
@@ -230,12 +691,21 @@ impl ProjectConfig {
         // TOMLの順序を制御するために手動でフォーマット
         let mut content = String::new();
 
-        // Ollama設定を最初に配置
-        content.push_str("# Ollama設定\n");
-        content.push_str("[ollama]\n");
-        content.push_str(&format!("base_url = \"{}\"\n", self.ollama.base_url));
-        content.push_str(&format!("model = \"{}\"\n", self.ollama.model));
-        content.push('\n');
+        // テーブルより前に置く必要があるため、モデルの指定を最初に配置
+        let provider_id = match &self.provider {
+            Some(ProviderSetting::Id(id)) => Some(id),
+            _ => None,
+        };
+        if provider_id.is_some() || self.model.is_some() {
+            content.push_str("# モデル設定\n");
+            if let Some(provider) = provider_id {
+                content.push_str(&format!("provider = \"{provider}\"\n"));
+            }
+            if let Some(model) = &self.model {
+                content.push_str(&format!("model = \"{model}\"\n"));
+            }
+            content.push('\n');
+        }
 
         // 基本設定
         content.push_str("# 基本設定\n");
@@ -244,7 +714,62 @@ impl ProjectConfig {
             self.check_interval_secs
         ));
         content.push_str(&format!("port = {}\n", self.port));
+        if let Some(listen) = &self.listen {
+            content.push_str(&format!("listen = \"{listen}\"\n"));
+        }
+        if let Some(grpc_port) = self.grpc_port {
+            content.push_str(&format!("grpc_port = {grpc_port}\n"));
+        }
+        if let Some(timezone) = &self.timezone {
+            content.push_str(&format!("timezone = \"{timezone}\"\n"));
+        }
+        if self.language == Locale::En {
+            content.push_str("language = \"en\"\n");
+        }
         content.push_str(&format!("enabled = {}\n", self.enabled));
+        if !self.watch_paths.is_empty() {
+            content.push_str("watch_paths = [\n");
+            for path in &self.watch_paths {
+                content.push_str(&format!("    \"{path}\",\n"));
+            }
+            content.push_str("]\n");
+        }
+        content.push_str(&format!(
+            "recurse_submodules = {}\n",
+            self.recurse_submodules
+        ));
+        if let Some(diff_base) = &self.diff_base {
+            content.push_str(&format!("diff_base = {}\n", toml_string(diff_base)));
+        }
+        content.push_str(&format!(
+            "query_rate_limit_per_minute = {}\n",
+            self.query_rate_limit_per_minute
+        ));
+        if let Some(token) = &self.access_token {
+            content.push_str(&format!("access_token = \"{token}\"\n"));
+        }
+        if let Some(token) = &self.viewer_token {
+            content.push_str(&format!("viewer_token = \"{token}\"\n"));
+        }
+        if let Some(instructions) = &self.base_instructions {
+            content.push_str(&format!(
+                "base_instructions = \"\"\"\n{instructions}\"\"\"\n"
+            ));
+        }
+        if !self.project_doc {
+            content.push_str("project_doc = false\n");
+        }
+        if !self.pinned_context.is_empty() {
+            content.push_str("pinned_context = [\n");
+            for document in &self.pinned_context {
+                content.push_str(&format!("    \"{document}\",\n"));
+            }
+            content.push_str("]\n");
+            content.push_str(&format!(
+                "pinned_context_tokens = {}\n",
+                self.pinned_context_tokens
+            ));
+        }
         content.push('\n');
 
         // 除外パターン
@@ -254,16 +779,312 @@ impl ProjectConfig {
             content.push_str(&format!("    \"{pattern}\",\n"));
         }
         content.push_str("]\n");
-        content.push_str("custom_prompts = []\n");
+        if self.custom_prompts.is_empty() {
+            content.push_str("custom_prompts = []\n");
+        }
 
-        // ファイル拡張子
-        content.push_str("file_extensions = [\n");
-        for ext in &self.file_extensions {
-            content.push_str(&format!("    \"{ext}\",\n"));
+        // ファイル拡張子（未指定ならグローバル設定のものを使う）
+        if let Some(extensions) = &self.file_extensions {
+            content.push_str("file_extensions = [\n");
+            for ext in extensions {
+                content.push_str(&format!("    \"{ext}\",\n"));
+            }
+            content.push_str("]\n");
         }
-        content.push_str("]\n");
+        if self.binary_detection == BinaryDetection::Git {
+            content.push_str("binary_detection = \"git\"\n");
+        }
+        content.push_str(&format!("max_file_bytes = {}\n", self.max_file_bytes));
+        content.push_str(&format!(
+            "max_files_per_cycle = {}\n",
+            self.max_files_per_cycle
+        ));
+        if self.max_cycle_duration_secs > 0 {
+            content.push_str(&format!(
+                "max_cycle_duration_secs = {}\n",
+                self.max_cycle_duration_secs
+            ));
+        }
+        content.push_str(&format!("max_prompt_tokens = {}\n", self.max_prompt_tokens));
+        content.push_str(&format!("scaffold_review = {}\n", self.scaffold_review));
+        content.push_str(&format!("impact_review = {}\n", self.impact_review));
+        content.push_str(&format!("min_confidence = {}\n", self.min_confidence));
+        content.push_str(&format!(
+            "repeat_cooldown_secs = {}\n",
+            self.repeat_cooldown_secs
+        ));
         content.push('\n');
 
+        // Ollama設定（トップレベルのキーがこのテーブルに入らないよう、基本設定の後に配置）
+        content.push_str("# Ollama設定\n");
+        content.push_str("[ollama]\n");
+        content.push_str(&format!("base_url = \"{}\"\n", self.ollama.base_url));
+        content.push_str(&format!("model = \"{}\"\n", self.ollama.model));
+        content.push('\n');
+
+        // 言語判定の上書き
+        if !self.language_overrides.is_empty() {
+            content.push_str("[language_overrides]\n");
+            for (pattern, language) in &self.language_overrides {
+                content.push_str(&format!("\"{pattern}\" = \"{language}\"\n"));
+            }
+            content.push('\n');
+        }
+
+        // ポリシー
+        if !self.policy.blocking.is_empty() {
+            content.push_str("[policy]\n");
+            content.push_str("blocking = [\n");
+            for label in &self.policy.blocking {
+                content.push_str(&format!("    \"{label}\",\n"));
+            }
+            content.push_str("]\n\n");
+        }
+
+        // 作業の放置の確認
+        if self.housekeeping != HousekeepingConfig::default() {
+            let housekeeping = &self.housekeeping;
+            content.push_str("[housekeeping]\n");
+            content.push_str(&format!("enabled = {}\n", housekeeping.enabled));
+            content.push_str(&format!("interval_secs = {}\n", housekeeping.interval_secs));
+            content.push_str(&format!("dirty_hours = {}\n", housekeeping.dirty_hours));
+            content.push_str(&format!(
+                "unpushed_hours = {}\n",
+                housekeeping.unpushed_hours
+            ));
+            content.push_str(&format!(
+                "behind_commits = {}\n\n",
+                housekeeping.behind_commits
+            ));
+        }
+
+        // 大きなファイルの警告
+        if self.large_files != LargeFileConfig::default() {
+            let large_files = &self.large_files;
+            content.push_str("[large_files]\n");
+            content.push_str(&format!("enabled = {}\n", large_files.enabled));
+            content.push_str(&format!("max_bytes = {}\n", large_files.max_bytes));
+            content.push_str("binary_extensions = [");
+            let extensions: Vec<String> = large_files
+                .binary_extensions
+                .iter()
+                .map(|ext| format!("\"{ext}\""))
+                .collect();
+            content.push_str(&extensions.join(", "));
+            content.push_str("]\n\n");
+        }
+
+        // コミットとブランチ名の規約
+        if self.commit_lint.is_enabled() {
+            let commit_lint = &self.commit_lint;
+            content.push_str("[commit_lint]\n");
+            if let Some(pattern) = &commit_lint.message_pattern {
+                content.push_str(&format!("message_pattern = {}\n", toml_string(pattern)));
+            }
+            if !commit_lint.types.is_empty() {
+                let types: Vec<String> = commit_lint
+                    .types
+                    .iter()
+                    .map(|kind| format!("\"{kind}\""))
+                    .collect();
+                content.push_str(&format!("types = [{}]\n", types.join(", ")));
+            }
+            if let Some(pattern) = &commit_lint.branch_pattern {
+                content.push_str(&format!("branch_pattern = {}\n", toml_string(pattern)));
+            }
+            content.push('\n');
+        }
+
+        // コミット整理の提案
+        if self.rebase_plan != RebasePlanConfig::default() {
+            content.push_str("[rebase_plan]\n");
+            content.push_str(&format!("enabled = {}\n", self.rebase_plan.enabled));
+            content.push_str(&format!(
+                "min_commits = {}\n\n",
+                self.rebase_plan.min_commits
+            ));
+        }
+
+        // 週次ダイジェスト
+        if self.digest != DigestConfig::default() {
+            content.push_str("[digest]\n");
+            content.push_str(&format!("enabled = {}\n", self.digest.enabled));
+            content.push_str(&format!("notify = {}\n\n", self.digest.notify));
+        }
+
+        // 検索用の埋め込み
+        if self.embedding != EmbeddingConfig::default() {
+            let embedding = &self.embedding;
+            content.push_str("[embedding]\n");
+            content.push_str(&format!("kind = \"{}\"\n", embedding.kind.as_str()));
+            let optional = [
+                ("base_url", &embedding.base_url),
+                ("model", &embedding.model),
+                ("api_key_env", &embedding.api_key_env),
+            ];
+            for (key, value) in optional {
+                if let Some(value) = value {
+                    content.push_str(&format!("{key} = {}\n", toml_string(value)));
+                }
+            }
+            content.push_str(&format!("dimensions = {}\n\n", embedding.dimensions));
+        }
+
+        // 埋め込みの索引
+        if self.index != IndexConfig::default() {
+            content.push_str("[index]\n");
+            content.push_str(&format!("enabled = {}\n", self.index.enabled));
+            content.push_str(&format!("chunk_lines = {}\n\n", self.index.chunk_lines));
+        }
+
+        // ファイルの変更の検知
+        if self.file_watch != FileWatchConfig::default() {
+            content.push_str("[file_watch]\n");
+            content.push_str(&format!("enabled = {}\n", self.file_watch.enabled));
+            content.push_str(&format!(
+                "debounce_ms = {}\n\n",
+                self.file_watch.debounce_ms
+            ));
+        }
+
+        // プロンプトの区分ごとの配分
+        if self.context_budget != ContextBudget::default() {
+            content.push_str("[context_budget]\n");
+            for (name, share) in [
+                ("pinned", self.context_budget.pinned),
+                ("diff", self.context_budget.diff),
+                ("related", self.context_budget.related),
+                ("conversation", self.context_budget.conversation),
+            ] {
+                content.push_str(&format!(
+                    "{name} = {{ percent = {}, min_tokens = {} }}\n",
+                    share.percent, share.min_tokens
+                ));
+            }
+            content.push('\n');
+        }
+
+        // サーバーが受け付けるリクエストと接続の上限
+        if self.server_limits != ServerLimits::default() {
+            let limits = &self.server_limits;
+            content.push_str("[server_limits]\n");
+            content.push_str(&format!("max_body_bytes = {}\n", limits.max_body_bytes));
+            content.push_str(&format!(
+                "max_ws_frame_bytes = {}\n",
+                limits.max_ws_frame_bytes
+            ));
+            content.push_str(&format!(
+                "max_ws_message_bytes = {}\n",
+                limits.max_ws_message_bytes
+            ));
+            content.push_str(&format!("max_connections = {}\n", limits.max_connections));
+            content.push_str(&format!(
+                "write_timeout_secs = {}\n\n",
+                limits.write_timeout_secs
+            ));
+        }
+
+        // 履歴とキャッシュの暗号化
+        if self.storage != StorageConfig::default() {
+            content.push_str("[storage]\n");
+            content.push_str(&format!("encrypt = {}\n", self.storage.encrypt));
+            content.push_str(&format!("key = \"{}\"\n", self.storage.key.as_str()));
+            if let Some(key_file) = &self.storage.key_file {
+                content.push_str(&format!(
+                    "key_file = {}\n",
+                    toml_string(&key_file.display().to_string())
+                ));
+            }
+            content.push('\n');
+        }
+
+        // OpenAI互換のエンドポイント
+        if let Some(ProviderSetting::Endpoint(endpoint)) = &self.provider {
+            content.push_str("[provider]\n");
+            content.push_str(&format!("base_url = {}\n", toml_string(&endpoint.base_url)));
+            if let Some(env) = &endpoint.api_key_env {
+                content.push_str(&format!("api_key_env = {}\n", toml_string(env)));
+            }
+            if let Some(model) = &endpoint.model {
+                content.push_str(&format!("model = {}\n", toml_string(model)));
+            }
+            content.push('\n');
+        }
+
+        // プルリクエストのレビューの連携先
+        if self.integrations != IntegrationsConfig::default() {
+            content.push_str("[integrations]\n");
+            content.push_str(&format!("kind = \"{}\"\n", self.integrations.kind.as_str()));
+            let optional = [
+                ("token", &self.integrations.token),
+                ("token_env", &self.integrations.token_env),
+                ("organization", &self.integrations.organization),
+                ("project", &self.integrations.project),
+                ("repository", &self.integrations.repository),
+                ("webhook_secret_env", &self.integrations.webhook_secret_env),
+            ];
+            for (key, value) in optional {
+                if let Some(value) = value {
+                    content.push_str(&format!("{key} = {}\n", toml_string(value)));
+                }
+            }
+            if self.integrations.check_runs {
+                content.push_str("check_runs = true\n");
+            }
+            content.push('\n');
+        }
+
+        // スコープを指定したアクセストークン
+        for api_token in &self.api_tokens {
+            content.push_str("[[api_tokens]]\n");
+            if let Some(name) = &api_token.name {
+                content.push_str(&format!("name = {}\n", toml_string(name)));
+            }
+            content.push_str(&format!("token = {}\n", toml_string(&api_token.token)));
+            let scopes: Vec<String> = api_token
+                .scopes
+                .iter()
+                .map(|scope| format!("\"{}\"", scope.as_str()))
+                .collect();
+            content.push_str(&format!("scopes = [{}]\n", scopes.join(", ")));
+            content.push('\n');
+        }
+
+        // 重大な指摘を送るWebhook
+        for webhook in &self.notifications.webhooks {
+            content.push_str("[[notifications.webhooks]]\n");
+            let optional = [("url", &webhook.url), ("url_env", &webhook.url_env)];
+            for (key, value) in optional {
+                if let Some(value) = value {
+                    content.push_str(&format!("{key} = {}\n", toml_string(value)));
+                }
+            }
+            if webhook.format != WebhookFormat::default() {
+                content.push_str(&format!("format = \"{}\"\n", webhook.format.as_str()));
+            }
+            content.push_str(&format!(
+                "min_severity = \"{}\"\n",
+                webhook.min_severity.as_str()
+            ));
+            let lists = [("reviews", &webhook.reviews), ("labels", &webhook.labels)];
+            for (key, values) in lists {
+                if !values.is_empty() {
+                    let values: Vec<String> =
+                        values.iter().map(|value| toml_string(value)).collect();
+                    content.push_str(&format!("{key} = [{}]\n", values.join(", ")));
+                }
+            }
+            content.push('\n');
+        }
+
+        // レビューから`prompt_id`で参照するプロンプト
+        for prompt in &self.custom_prompts {
+            content.push_str("[[custom_prompts]]\n");
+            content.push_str(&format!("id = {}\n", toml_string(&prompt.id)));
+            content.push_str(&format!("content = {}\n", toml_string(&prompt.content)));
+            content.push('\n');
+        }
+
         // レビュー設定
         for review in &self.reviews {
             content.push_str("[[reviews]]\n");
@@ -274,10 +1095,53 @@ impl ProjectConfig {
                 content.push_str(&format!("    \"{pattern}\",\n"));
             }
             content.push_str("]\n");
+            if !review.languages.is_empty() {
+                content.push_str("languages = [\n");
+                for language in &review.languages {
+                    content.push_str(&format!("    \"{language}\",\n"));
+                }
+                content.push_str("]\n");
+            }
             content.push_str(&format!("prompt = \"\"\"\n{}\"\"\"\n", review.prompt));
+            let optional = [
+                ("prompt_file", &review.prompt_file),
+                ("prompt_id", &review.prompt_id),
+            ];
+            for (key, value) in optional {
+                if let Some(value) = value {
+                    content.push_str(&format!("{key} = {}\n", toml_string(value)));
+                }
+            }
             content.push_str(&format!("priority = {}\n", review.priority));
+            if let Some(group) = &review.group {
+                content.push_str(&format!("group = {}\n", toml_string(group)));
+            }
+            if review.exclusive {
+                content.push_str("exclusive = true\n");
+            }
             content.push_str(&format!("enabled = {}\n", review.enabled));
+            match review.output_format {
+                OutputFormat::Text => {}
+                OutputFormat::Json => content.push_str("output_format = \"json\"\n"),
+                OutputFormat::Findings => content.push_str("output_format = \"findings\"\n"),
+            }
+            if !review.labels.is_empty() {
+                content.push_str("labels = [\n");
+                for label in &review.labels {
+                    content.push_str(&format!("    \"{label}\",\n"));
+                }
+                content.push_str("]\n");
+            }
             content.push('\n');
+            for example in &review.examples {
+                content.push_str("[[reviews.examples]]\n");
+                content.push_str(&format!("input = \"\"\"\n{}\"\"\"\n", example.input));
+                content.push_str(&format!(
+                    "expected_output = \"\"\"\n{}\"\"\"\n",
+                    example.expected_output
+                ));
+                content.push('\n');
+            }
         }
 
         fs::write(&config_file, content)?;
@@ -285,13 +1149,21 @@ impl ProjectConfig {
         Ok(())
     }
 
-    /// サンプル設定ファイルを生成
-    pub fn create_sample(project_path: &Path) -> Result<()> {
+    /// サンプル設定ファイルを生成し、作成したパスを返す（`minimal`では`config.toml`のみ）
+    pub fn create_sample(project_path: &Path, minimal: bool) -> Result<Vec<PathBuf>> {
         let config = Self::default();
         config.save_to_project(project_path)?;
+        let config_dir = project_path.join(".ambient");
+        let mut created = vec![config_dir.join("config.toml")];
+        if minimal {
+            return Ok(created);
+        }
+
+        let prompts_dir = config_dir.join("prompts");
+        fs::create_dir_all(&prompts_dir)?;
+        created.push(prompts_dir);
 
         // READMEも作成
-        let config_dir = project_path.join(".ambient");
         let readme_path = config_dir.join("README.md");
         let readme_content = r#"# Ambient Code Watcher プロジェクト設定
 
@@ -347,8 +1219,8 @@ exclude_patterns = [
 
 長いプロンプトは別ファイルに保存して参照することもできます：
 
-1. `prompts/`ディレクトリにファイルを作成
-2. `custom_prompts`で参照
+1. `prompts/`ディレクトリにファイルを作成し、レビューの`prompt_file`で参照
+2. `custom_prompts`に定義し、レビューの`prompt_id`で参照
 
 ```toml
 [[custom_prompts]]
@@ -359,60 +1231,657 @@ content = """
 2. 依存関係の適切性
 3. モジュール間の結合度
 """
+
+[[reviews]]
+name = "セキュリティ"
+file_patterns = ["*"]
+prompt_file = "prompts/security.md"
+
+[[reviews]]
+name = "アーキテクチャ"
+file_patterns = ["src/**/*.rs"]
+prompt_id = "architecture_review"
 ```
+
+プロンプトの中の`{file_path}`、`{language}`、`{branch}`、`{diff}`は分析のときに置き換えられます。
+`{diff}`を使わないプロンプトには、末尾にファイルの情報（言語・行数・最終更新）と、言語を指定した
+コードブロックで囲んだ変更の内容が付きます。
 "#;
         fs::write(&readme_path, readme_content)?;
+        created.push(readme_path);
 
-        Ok(())
+        Ok(created)
+    }
+
+    /// ファイルの言語を判定（`language_overrides`を優先）
+    pub fn language_for(&self, file_path: &str) -> String {
+        detect_language(file_path, &self.language_overrides)
+    }
+
+    /// レビューのプロンプトの雛形（例を含む）のハッシュ（雛形を読めなければなし）
+    ///
+    /// 指摘の履歴に残し、雛形を変える前と後の指摘を見分けられるようにする。
+    pub fn prompt_hash(&self, project_path: &Path, review: &ReviewConfig) -> Option<String> {
+        let template = review
+            .prompt_template(project_path, &self.custom_prompts)
+            .ok()?;
+        let rendered = review.render_prompt(&template, &PromptVariables::default());
+        Some(content_hash(&rendered).chars().take(16).collect())
+    }
+
+    /// レビューに送るプロンプト（`{diff}`を使わない雛形では、末尾に変更を付ける）
+    pub fn review_prompt(
+        &self,
+        project_path: &Path,
+        review: &ReviewConfig,
+        variables: &PromptVariables,
+    ) -> Result<String> {
+        let template = review.prompt_template(project_path, &self.custom_prompts)?;
+        let prompt = review.render_prompt(&template, variables);
+        if template.contains("{diff}") {
+            return Ok(prompt);
+        }
+        match variables.metadata {
+            Some(metadata) => Ok(format!(
+                "{prompt}\n\n---\n\n{}",
+                metadata.present(variables.diff)
+            )),
+            None => Ok(format!("{prompt}\n\n---\n\n{}", variables.diff)),
+        }
     }
 
     /// ファイルパスに適用するレビューを取得
     pub fn get_reviews_for_file(&self, file_path: &str) -> Vec<&ReviewConfig> {
+        let language = self.language_for(file_path);
         let mut reviews: Vec<&ReviewConfig> = self
             .reviews
             .iter()
-            .filter(|r| r.enabled && self.matches_patterns(file_path, &r.file_patterns))
+            .filter(|r| {
+                r.enabled
+                    && self.matches_patterns(file_path, &r.file_patterns)
+                    && (r.languages.is_empty()
+                        || r.languages
+                            .iter()
+                            .any(|l| l.eq_ignore_ascii_case(&language)))
+            })
             .collect();
 
-        // 優先度順にソート（高い順）
+        // 優先度順にソート（高い順、安定ソートのため同じ優先度では設定の順）
         reviews.sort_by(|a, b| b.priority.cmp(&a.priority));
+
+        // 排他のグループでは、並べた順で最初のレビューだけを残す
+        let mut claimed_groups = HashSet::new();
+        reviews.retain(|review| match (&review.group, review.exclusive) {
+            (Some(group), true) => claimed_groups.insert(group.as_str()),
+            _ => true,
+        });
         reviews
     }
 
     /// ファイルパスがパターンにマッチするか
     fn matches_patterns(&self, file_path: &str, patterns: &[String]) -> bool {
-        for pattern in patterns {
-            if pattern == "*" {
-                return true;
-            }
-
-            // 簡単なglob実装
-            if pattern.starts_with("*.") {
-                let ext = pattern.trim_start_matches("*.");
-                if file_path.ends_with(&format!(".{ext}")) {
-                    return true;
-                }
-            }
-
-            if pattern.ends_with("/**") {
-                let prefix = pattern.trim_end_matches("/**");
-                if file_path.starts_with(prefix) {
-                    return true;
-                }
-            }
+        self.matching_pattern(file_path, patterns).is_some()
+    }
 
-            if glob::Pattern::new(pattern)
-                .ok()
-                .is_some_and(|p| p.matches(file_path))
-            {
-                return true;
-            }
-        }
-        false
+    /// ファイルパスに最初にマッチしたパターン
+    pub fn matching_pattern<'a>(&self, file_path: &str, patterns: &'a [String]) -> Option<&'a str> {
+        patterns
+            .iter()
+            .find(|pattern| pattern_matches(pattern, file_path))
+            .map(String::as_str)
     }
 
     /// ファイルが除外パターンにマッチするか
     pub fn is_excluded(&self, file_path: &str) -> bool {
         self.matches_patterns(file_path, &self.exclude_patterns)
     }
+
+    /// `file_extensions`を指定していなければ`extensions`（グローバル設定の拡張子）を使う
+    pub fn with_default_extensions(mut self, extensions: &[String]) -> Self {
+        if self.file_extensions.is_none() {
+            self.file_extensions = Some(extensions.to_vec());
+        }
+        self
+    }
+
+    /// 拡張子が分析対象か（拡張子のないファイルと、リストが空の場合は常に対象）
+    ///
+    /// `file_extensions`を指定せず、グローバル設定も渡されていなければ既定の拡張子で判定する。
+    pub fn has_analyzed_extension(&self, file_path: &str) -> bool {
+        match &self.file_extensions {
+            Some(extensions) => extension_allowed(extensions, file_path),
+            None => extension_allowed(&default_file_extensions(), file_path),
+        }
+    }
+}
+
+fn extension_allowed(extensions: &[String], file_path: &str) -> bool {
+    if extensions.is_empty() {
+        return true;
+    }
+    match Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+    {
+        Some(ext) => extensions
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(ext)),
+        None => true,
+    }
+}
+
+/// 正規表現などの文字列をTOMLの文字列として書く（バックスラッシュや引用符をエスケープする）
+/// パターンがファイルパスにマッチするか（`*`、`*.拡張子`、`ディレクトリ/**`とglob）
+fn pattern_matches(pattern: &str, file_path: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+
+    // 簡単なglob実装
+    if pattern.starts_with("*.") {
+        let ext = pattern.trim_start_matches("*.");
+        if file_path.ends_with(&format!(".{ext}")) {
+            return true;
+        }
+    }
+
+    if pattern.ends_with("/**") {
+        let prefix = pattern.trim_end_matches("/**");
+        if file_path.starts_with(prefix) {
+            return true;
+        }
+    }
+
+    glob::Pattern::new(pattern)
+        .ok()
+        .is_some_and(|p| p.matches(file_path))
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_auth::Scope;
+    use crate::ambient_confidence::Severity;
+    use crate::ambient_embedding::EmbeddingKind;
+    use crate::ambient_endpoint::EndpointProvider;
+    use crate::ambient_integration::IntegrationKind;
+    use crate::ambient_webhook::WebhookConfig;
+
+    #[test]
+    fn renders_examples_after_prompt() {
+        let config: ProjectConfig = toml::from_str(
+            r#"
+[[reviews]]
+name = "unwrap"
+file_patterns = ["*.rs"]
+prompt = "{file_path}のunwrapを報告してください"
+
+[[reviews.examples]]
+input = "let x = foo().unwrap();"
+expected_output = "- 1行目: unwrap"
+"#,
+        )
+        .unwrap();
+        let review = &config.reviews[0];
+        let variables = PromptVariables {
+            file_path: "src/lib.rs",
+            language: "rust",
+            ..Default::default()
+        };
+        assert_eq!(
+            review.render_prompt(&review.prompt, &variables),
+            "src/lib.rsのunwrapを報告してください\n\n## 例1\n\n### 入力\n\nlet x = foo().unwrap();\n\n### 回答\n\n- 1行目: unwrap"
+        );
+    }
+    #[test]
+    fn resolves_prompt_files_and_custom_prompts() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".ambient/prompts")).unwrap();
+        fs::write(
+            dir.path().join(".ambient/prompts/security.md"),
+            "{branch}の{file_path}を確認してください\n{diff}\n{unknown}",
+        )
+        .unwrap();
+        let config: ProjectConfig = toml::from_str(
+            r#"
+[[custom_prompts]]
+id = "perf"
+content = "{language}の性能を確認してください"
+
+[[reviews]]
+name = "セキュリティ"
+file_patterns = ["*"]
+prompt_file = "prompts/security.md"
+
+[[reviews]]
+name = "性能"
+file_patterns = ["*"]
+prompt_id = "perf"
+
+[[reviews]]
+name = "外部"
+file_patterns = ["*"]
+prompt_file = "../secret.md"
+
+[[reviews]]
+name = "未定義"
+file_patterns = ["*"]
+prompt_id = "missing"
+"#,
+        )
+        .unwrap();
+        let variables = PromptVariables {
+            file_path: "src/db.rs",
+            language: "rust",
+            diff: "+let s = \"{branch}\";",
+            branch: Some("feature/login"),
+            metadata: None,
+        };
+        let prompt =
+            |index: usize| config.review_prompt(dir.path(), &config.reviews[index], &variables);
+        assert_eq!(
+            prompt(0).unwrap(),
+            "feature/loginのsrc/db.rsを確認してください\n+let s = \"{branch}\";\n{unknown}"
+        );
+        assert_eq!(
+            prompt(1).unwrap(),
+            "rustの性能を確認してください\n\n---\n\n+let s = \"{branch}\";"
+        );
+        assert!(prompt(2).is_err());
+        assert!(prompt(3).is_err());
+        // ファイルの情報があれば、末尾の変更の前に付けてコードブロックで囲む
+        let metadata = FileMetadata::new("src/db.rs", "rust");
+        let with_metadata = PromptVariables {
+            metadata: Some(&metadata),
+            ..variables
+        };
+        assert_eq!(
+            config
+                .review_prompt(dir.path(), &config.reviews[1], &with_metadata)
+                .unwrap(),
+            "rustの性能を確認してください\n\n---\n\nファイル: src/db.rs\n言語: rust\n\n```rust\n+let s = \"{branch}\";\n```"
+        );
+        assert_eq!(
+            config
+                .review_prompt(dir.path(), &config.reviews[0], &with_metadata)
+                .unwrap(),
+            prompt(0).unwrap()
+        );
+
+        let hash = |index: usize| config.prompt_hash(dir.path(), &config.reviews[index]);
+        assert_eq!(hash(0).map(|hash| hash.len()), Some(16));
+        assert_ne!(hash(0), hash(1));
+        assert_eq!(hash(3), None);
+
+        config.save_to_project(dir.path()).unwrap();
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.custom_prompts.len(), 1);
+        assert_eq!(
+            loaded.reviews[0].prompt_file.as_deref(),
+            Some("prompts/security.md")
+        );
+        assert_eq!(loaded.reviews[1].prompt_id.as_deref(), Some("perf"));
+    }
+
+    #[test]
+    fn uses_global_extensions_unless_set() {
+        let global = vec!["rs".to_string(), "vue".to_string()];
+        let config = ProjectConfig::default().with_default_extensions(&global);
+        assert!(config.has_analyzed_extension("src/App.vue"));
+        assert!(!config.has_analyzed_extension("logo.png"));
+
+        let config: ProjectConfig = toml::from_str("file_extensions = [\"py\"]").unwrap();
+        let config = config.with_default_extensions(&global);
+        assert!(config.has_analyzed_extension("main.py"));
+        assert!(!config.has_analyzed_extension("src/App.vue"));
+        assert!(config.has_analyzed_extension("Makefile"));
+    }
+
+    #[test]
+    fn creates_sample_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let created = ProjectConfig::create_sample(dir.path(), true).unwrap();
+        assert_eq!(created, vec![dir.path().join(".ambient/config.toml")]);
+        assert!(!dir.path().join(".ambient/README.md").exists());
+
+        let created = ProjectConfig::create_sample(dir.path(), false).unwrap();
+        assert_eq!(created.len(), 3);
+        assert!(dir.path().join(".ambient/prompts").is_dir());
+        assert!(dir.path().join(".ambient/README.md").exists());
+        let config = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(config.reviews.len(), ProjectConfig::default().reviews.len());
+    }
+
+    #[test]
+    fn only_listener_and_token_changes_need_a_restart() {
+        let config = ProjectConfig::default();
+        let changed = ProjectConfig {
+            check_interval_secs: 5,
+            exclude_patterns: vec!["dist/**".to_string()],
+            ..ProjectConfig::default()
+        };
+        assert!(!config.needs_restart(&changed));
+        let changed = ProjectConfig {
+            port: 38081,
+            ..ProjectConfig::default()
+        };
+        assert!(config.needs_restart(&changed));
+        let changed = ProjectConfig {
+            viewer_token: Some("share".to_string()),
+            ..ProjectConfig::default()
+        };
+        assert!(config.needs_restart(&changed));
+        let changed = ProjectConfig {
+            api_tokens: vec![ApiToken {
+                name: Some("ci".to_string()),
+                token: "ci-secret".to_string(),
+                scopes: vec![Scope::Read],
+            }],
+            ..ProjectConfig::default()
+        };
+        assert!(config.needs_restart(&changed));
+    }
+
+    #[test]
+    fn saves_context_budget_when_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.save_to_project(dir.path()).unwrap();
+        let saved = fs::read_to_string(dir.path().join(".ambient/config.toml")).unwrap();
+        assert!(!saved.contains("[context_budget]"));
+
+        config.context_budget.diff.percent = 70;
+        config.context_budget.related.min_tokens = 300;
+        config.save_to_project(dir.path()).unwrap();
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.context_budget, config.context_budget);
+    }
+
+    #[test]
+    fn saves_server_limits_when_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        ProjectConfig::default()
+            .save_to_project(dir.path())
+            .unwrap();
+        let saved = fs::read_to_string(dir.path().join(".ambient/config.toml")).unwrap();
+        assert!(!saved.contains("[server_limits]"));
+
+        let config = ProjectConfig {
+            server_limits: ServerLimits {
+                max_connections: 8,
+                write_timeout_secs: 3,
+                ..ServerLimits::default()
+            },
+            ..ProjectConfig::default()
+        };
+        config.save_to_project(dir.path()).unwrap();
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.server_limits, config.server_limits);
+        assert!(config.needs_restart(&ProjectConfig::default()));
+    }
+
+    #[test]
+    fn saves_api_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ProjectConfig {
+            api_tokens: vec![
+                ApiToken {
+                    name: Some("ci".to_string()),
+                    token: "ci-secret".to_string(),
+                    scopes: vec![Scope::Read],
+                },
+                ApiToken {
+                    name: None,
+                    token: "bot-secret".to_string(),
+                    scopes: vec![Scope::Read, Scope::Chat],
+                },
+            ],
+            ..ProjectConfig::default()
+        };
+        config.save_to_project(dir.path()).unwrap();
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.api_tokens, config.api_tokens);
+    }
+
+    #[test]
+    fn saves_policy_and_review_labels() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.policy.blocking = vec!["security".to_string(), "compliance".to_string()];
+        config.save_to_project(dir.path()).unwrap();
+
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.policy, config.policy);
+        let labels: Vec<_> = loaded
+            .reviews
+            .iter()
+            .map(|review| review.labels.clone())
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                vec![],
+                vec!["security".to_string()],
+                vec!["perf".to_string()]
+            ]
+        );
+    }
+
+    #[test]
+    fn saves_housekeeping_when_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.save_to_project(dir.path()).unwrap();
+        let saved = fs::read_to_string(dir.path().join(".ambient").join("config.toml")).unwrap();
+        assert!(!saved.contains("[housekeeping]"));
+
+        config.housekeeping.enabled = false;
+        config.housekeeping.dirty_hours = 8;
+        config.save_to_project(dir.path()).unwrap();
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.housekeeping, config.housekeeping);
+    }
+
+    #[test]
+    fn saves_storage_settings_when_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.storage.encrypt = true;
+        config.storage.key = crate::ambient_storage::KeySource::Keychain;
+        config.save_to_project(dir.path()).unwrap();
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.storage, config.storage);
+    }
+
+    #[test]
+    fn saves_large_file_settings_when_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.large_files.max_bytes = 1024;
+        config.large_files.binary_extensions = vec!["onnx".to_string()];
+        config.save_to_project(dir.path()).unwrap();
+
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.large_files, config.large_files);
+    }
+
+    #[test]
+    fn saves_commit_lint_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.commit_lint.message_pattern = Some(r"^\S.{0,49}$".to_string());
+        config.commit_lint.types = vec!["feat".to_string(), "fix".to_string()];
+        config.commit_lint.branch_pattern = Some(r#"^(feat|fix)/[a-z"]+$"#.to_string());
+        config.save_to_project(dir.path()).unwrap();
+
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.commit_lint, config.commit_lint);
+    }
+
+    #[test]
+    fn saves_integrations_when_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.integrations.kind = IntegrationKind::Azure;
+        config.integrations.token_env = Some("REVIEW_PAT".to_string());
+        config.integrations.organization = Some("contoso".to_string());
+        config.integrations.repository = Some("web".to_string());
+        config.integrations.check_runs = true;
+        config.save_to_project(dir.path()).unwrap();
+
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.integrations, config.integrations);
+    }
+
+    #[test]
+    fn runs_one_exclusive_review_per_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let config: ProjectConfig = toml::from_str(
+            r#"
+[[reviews]]
+name = "quick-syntax"
+file_patterns = ["*.rs"]
+prompt = "構文だけを確認してください"
+group = "syntax"
+exclusive = true
+
+[[reviews]]
+name = "deep-syntax"
+file_patterns = ["src/**"]
+prompt = "構文と型を詳しく確認してください"
+priority = 200
+group = "syntax"
+exclusive = true
+
+[[reviews]]
+name = "lint"
+file_patterns = ["*.rs"]
+prompt = "lintを確認してください"
+group = "syntax"
+
+[[reviews]]
+name = "security"
+file_patterns = ["*.rs"]
+prompt = "セキュリティを確認してください"
+"#,
+        )
+        .unwrap();
+        let names = |config: &ProjectConfig, file_path: &str| {
+            config
+                .get_reviews_for_file(file_path)
+                .iter()
+                .map(|review| review.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&config, "src/lib.rs"),
+            vec!["deep-syntax", "lint", "security"]
+        );
+        assert_eq!(
+            names(&config, "build.rs"),
+            vec!["quick-syntax", "lint", "security"]
+        );
+
+        // 同じ優先度では設定の先にあるレビューを実行する
+        let mut tied = config.clone();
+        tied.reviews[1].priority = 100;
+        assert_eq!(
+            names(&tied, "src/lib.rs"),
+            vec!["quick-syntax", "lint", "security"]
+        );
+        tied.reviews[0].enabled = false;
+        assert_eq!(
+            names(&tied, "src/lib.rs"),
+            vec!["deep-syntax", "lint", "security"]
+        );
+
+        config.save_to_project(dir.path()).unwrap();
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.reviews[1].group.as_deref(), Some("syntax"));
+        assert!(loaded.reviews[1].exclusive);
+        assert!(!loaded.reviews[2].exclusive);
+    }
+
+    #[test]
+    fn saves_notification_webhooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.notifications.webhooks = vec![WebhookConfig {
+            url: None,
+            url_env: Some("SECURITY_SLACK_WEBHOOK".to_string()),
+            format: WebhookFormat::Slack,
+            min_severity: Severity::Critical,
+            reviews: vec!["セキュリティ".to_string()],
+            labels: vec!["security".to_string()],
+        }];
+        config.save_to_project(dir.path()).unwrap();
+
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.notifications, config.notifications);
+    }
+
+    #[test]
+    fn saves_provider_and_model_as_top_level_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ProjectConfig {
+            provider: Some(ProviderSetting::Id("openai".to_string())),
+            model: Some("gpt-4o".to_string()),
+            diff_base: Some("origin/main".to_string()),
+            ..Default::default()
+        };
+        config.save_to_project(dir.path()).unwrap();
+
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.provider, config.provider);
+        assert_eq!(loaded.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(loaded.diff_base.as_deref(), Some("origin/main"));
+        assert_eq!(loaded.ollama.model, "gpt-oss:20b");
+    }
+
+    #[test]
+    fn saves_embedding_when_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.embedding.kind = EmbeddingKind::Openai;
+        config.embedding.base_url = Some("http://localhost:8000/v1".to_string());
+        config.embedding.model = Some("bge-m3".to_string());
+        config.save_to_project(dir.path()).unwrap();
+
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.embedding, config.embedding);
+    }
+
+    #[test]
+    fn saves_index_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ProjectConfig::default();
+        config.index.enabled = true;
+        config.index.chunk_lines = 60;
+        config.save_to_project(dir.path()).unwrap();
+
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.index, config.index);
+    }
+
+    #[test]
+    fn saves_endpoint_provider_as_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ProjectConfig {
+            provider: Some(ProviderSetting::Endpoint(EndpointProvider {
+                base_url: "http://localhost:1234/v1".to_string(),
+                api_key_env: Some("LM_STUDIO_KEY".to_string()),
+                model: Some("qwen2.5-coder".to_string()),
+            })),
+            ..Default::default()
+        };
+        config.save_to_project(dir.path()).unwrap();
+
+        let loaded = ProjectConfig::load_from_project(dir.path()).unwrap();
+        assert_eq!(loaded.provider, config.provider);
+        assert_eq!(loaded.model_id(), Some("qwen2.5-coder"));
+    }
 }
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::ambient_server::EventEnvelope;
+
+/// `~/.codex/ambient.toml`の`projects`で追加した、起動したディレクトリ以外に監視するリポジトリ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchedProject {
+    /// イベントに付ける名前（ディレクトリ名、同じ名前が複数あれば親ディレクトリ名を付ける）
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// `projects`のうち、起動したディレクトリと重複せず、ディレクトリとして存在するもの
+pub fn watched_projects(projects: &[String], current_dir: &Path) -> Vec<WatchedProject> {
+    let current_dir = canonical(current_dir);
+    let mut seen = HashSet::from([current_dir]);
+    let mut paths = Vec::new();
+    for project in projects {
+        let path = canonical(&expand_home(project));
+        if !path.is_dir() {
+            tracing::warn!("監視するプロジェクトが見つかりません: {project}");
+            continue;
+        }
+        if seen.insert(path.clone()) {
+            paths.push(path);
+        }
+    }
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for path in &paths {
+        *counts.entry(dir_name(path)).or_default() += 1;
+    }
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = dir_name(&path);
+            let name = match path.parent() {
+                Some(parent) if counts[&name] > 1 => format!("{}/{name}", dir_name(parent)),
+                _ => name,
+            };
+            WatchedProject { name, path }
+        })
+        .collect()
+}
+
+/// `~/`で始まるパスをホームディレクトリからのパスにする
+fn expand_home(path: &str) -> PathBuf {
+    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+    match (path.strip_prefix("~/"), home) {
+        (Some(rest), Ok(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn dir_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// 追加したリポジトリのイベントにその名前を付けて、起動したプロジェクトのチャンネルに送る
+pub async fn forward_project_events(
+    mut rx: broadcast::Receiver<EventEnvelope>,
+    tx: broadcast::Sender<EventEnvelope>,
+    project: String,
+) {
+    loop {
+        match rx.recv().await {
+            Ok(envelope) => {
+                let _ = tx.send(EventEnvelope {
+                    project: Some(project.clone()),
+                    ..envelope
+                });
+            }
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::AmbientEvent;
+    use crate::ambient_server::EmitEvent;
+    use tempfile::tempdir;
+
+    #[test]
+    fn names_projects_by_directory_and_skips_duplicates() {
+        let dir = tempdir().unwrap();
+        let root = canonical(dir.path());
+        for path in ["current", "api", "work/web", "personal/web"] {
+            fs::create_dir_all(root.join(path)).unwrap();
+        }
+        let projects: Vec<String> = [
+            "api",
+            "api/",
+            "current",
+            "missing",
+            "work/web",
+            "personal/web",
+        ]
+        .iter()
+        .map(|path| root.join(path).display().to_string())
+        .collect();
+
+        let watched = watched_projects(&projects, &root.join("current"));
+        assert_eq!(
+            watched,
+            vec![
+                WatchedProject {
+                    name: "api".to_string(),
+                    path: root.join("api"),
+                },
+                WatchedProject {
+                    name: "work/web".to_string(),
+                    path: root.join("work/web"),
+                },
+                WatchedProject {
+                    name: "personal/web".to_string(),
+                    path: root.join("personal/web"),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn tags_forwarded_events_with_the_project() {
+        let (project_tx, _) = broadcast::channel::<EventEnvelope>(16);
+        let (tx, mut rx) = broadcast::channel::<EventEnvelope>(16);
+        let forward = tokio::spawn(forward_project_events(
+            project_tx.subscribe(),
+            tx,
+            "api".to_string(),
+        ));
+        assert!(project_tx.emit(AmbientEvent::System("チェック".to_string())));
+        drop(project_tx);
+        forward.await.unwrap();
+
+        let envelope = rx.recv().await.unwrap();
+        assert_eq!(envelope.project.as_deref(), Some("api"));
+        assert!(envelope.to_json().contains("\"project\":\"api\""));
+    }
+}
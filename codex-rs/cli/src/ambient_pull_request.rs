@@ -0,0 +1,195 @@
+use anyhow::Context;
+use anyhow::Result;
+use std::fmt;
+
+use crate::ambient_git::split_diff_by_file;
+use crate::ambient_integration::ReviewComment;
+use crate::ambient_version::VERSION;
+
+/// GitHubのAPI
+pub const GITHUB_API: &str = "https://api.github.com";
+
+/// レビューするプルリクエスト
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRequest {
+    pub owner: String,
+    pub repo: String,
+    pub number: u64,
+}
+
+impl PullRequest {
+    /// `https://github.com/<owner>/<repo>/pull/<number>`形式のURLを解析する
+    ///
+    /// 末尾の`/files`やクエリ、`#`以降は無視する。
+    pub fn parse(url: &str) -> Result<Self> {
+        let path = url
+            .trim()
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("www.");
+        let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        match parts.as_slice() {
+            ["github.com", owner, repo, "pull", number, ..] => Ok(Self {
+                owner: owner.to_string(),
+                repo: repo.to_string(),
+                number: number
+                    .parse()
+                    .with_context(|| format!("プルリクエストの番号が不正です: {number}"))?,
+            }),
+            _ => anyhow::bail!(
+                "プルリクエストのURLではありません（https://github.com/<owner>/<repo>/pull/<number>の形式で指定してください）: {url}"
+            ),
+        }
+    }
+
+    fn api_url(&self, api_base: &str) -> String {
+        format!(
+            "{api_base}/repos/{}/{}/pulls/{}",
+            self.owner, self.repo, self.number
+        )
+    }
+}
+
+impl fmt::Display for PullRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}#{}", self.owner, self.repo, self.number)
+    }
+}
+
+pub(crate) fn github_request(
+    builder: reqwest::RequestBuilder,
+    accept: &str,
+    token: Option<&str>,
+) -> reqwest::RequestBuilder {
+    let builder = builder
+        .header(reqwest::header::USER_AGENT, format!("ambient/{VERSION}"))
+        .header(reqwest::header::ACCEPT, accept)
+        .header("X-GitHub-Api-Version", "2022-11-28");
+    match token {
+        Some(token) => builder.bearer_auth(token),
+        None => builder,
+    }
+}
+
+/// プルリクエスト全体のdiffを取得する（公開リポジトリならトークンなしでも取得できる）
+pub async fn fetch_diff(
+    client: &reqwest::Client,
+    api_base: &str,
+    pr: &PullRequest,
+    token: Option<&str>,
+) -> Result<String> {
+    let url = pr.api_url(api_base);
+    let response = github_request(client.get(&url), "application/vnd.github.diff", token)
+        .send()
+        .await
+        .with_context(|| format!("{url}に接続できません"))?;
+    let status = response.status();
+    if !status.is_success() {
+        let hint = if token.is_none() {
+            "（非公開のリポジトリはGITHUB_TOKENにトークンを設定してください）"
+        } else {
+            ""
+        };
+        anyhow::bail!("{pr}のdiffを取得できません: {status}{hint}");
+    }
+    Ok(response.text().await?)
+}
+
+/// diffをファイルごとに分け、パスの順に並べる
+pub fn file_diffs(diff: &str) -> Vec<(String, String)> {
+    let mut files: Vec<(String, String)> = split_diff_by_file(diff).into_iter().collect();
+    files.sort();
+    files
+}
+
+/// 削除されたファイルとバイナリの変更はレビューしない
+pub fn is_reviewable(diff: &str) -> bool {
+    !diff.lines().any(|line| {
+        line.starts_with("deleted file mode")
+            || line.starts_with("Binary files ")
+            || line == "GIT binary patch"
+    })
+}
+
+/// レビューを投稿する（承認や変更要求はしないコメントだけのレビュー）
+///
+/// `comments`は変更後の行に付ける。投稿したレビューのURLを返す。
+pub async fn post_review(
+    client: &reqwest::Client,
+    api_base: &str,
+    pr: &PullRequest,
+    token: &str,
+    body: &str,
+    comments: &[&ReviewComment],
+) -> Result<String> {
+    let url = format!("{}/reviews", pr.api_url(api_base));
+    let comments: Vec<serde_json::Value> = comments
+        .iter()
+        .map(|comment| {
+            serde_json::json!({
+                "path": comment.path,
+                "line": comment.line,
+                "side": "RIGHT",
+                "body": comment.body,
+            })
+        })
+        .collect();
+    let response = github_request(
+        client.post(&url),
+        "application/vnd.github+json",
+        Some(token),
+    )
+    .json(&serde_json::json!({ "body": body, "event": "COMMENT", "comments": comments }))
+    .send()
+    .await
+    .with_context(|| format!("{url}に接続できません"))?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("{pr}にレビューを投稿できません: {status}");
+    }
+    let review: serde_json::Value = response.json().await?;
+    Ok(review["html_url"].as_str().unwrap_or_default().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pull_request_urls() {
+        let expected = PullRequest {
+            owner: "org".to_string(),
+            repo: "repo".to_string(),
+            number: 123,
+        };
+        for url in [
+            "https://github.com/org/repo/pull/123",
+            "github.com/org/repo/pull/123/files?diff=split",
+            "https://www.github.com/org/repo/pull/123#discussion_r1",
+        ] {
+            assert_eq!(PullRequest::parse(url).unwrap(), expected, "{url}");
+        }
+        assert_eq!(expected.to_string(), "org/repo#123");
+        assert_eq!(
+            expected.api_url("https://api.github.com"),
+            "https://api.github.com/repos/org/repo/pulls/123"
+        );
+        assert!(PullRequest::parse("https://github.com/org/repo/issues/1").is_err());
+        assert!(PullRequest::parse("https://github.com/org/repo/pull/abc").is_err());
+    }
+
+    #[test]
+    fn splits_diff_into_reviewable_files() {
+        let diff = "diff --git a/src/b.rs b/src/b.rs\n--- a/src/b.rs\n+++ b/src/b.rs\n@@ -1 +1 @@\n-x\n+y\n\
+diff --git a/a.png b/a.png\nBinary files a/a.png and b/a.png differ\n\
+diff --git a/old.rs b/old.rs\ndeleted file mode 100644\n--- a/old.rs\n+++ /dev/null\n";
+        let files = file_diffs(diff);
+        let paths: Vec<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["a.png", "old.rs", "src/b.rs"]);
+        let reviewable: Vec<bool> = files.iter().map(|(_, diff)| is_reviewable(diff)).collect();
+        assert_eq!(reviewable, vec![false, false, true]);
+    }
+}
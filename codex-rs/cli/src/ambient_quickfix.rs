@@ -0,0 +1,90 @@
+use crate::ambient_annotations::AnnotationLevel;
+use crate::ambient_annotations::finding_line;
+use crate::ambient_confidence::Severity;
+use crate::ambient_history::FileFinding;
+use crate::ambient_server::Finding;
+
+/// quickfixの1行（列は分からないので1、本文は1行にまとめる）
+fn quickfix_line(
+    path: &str,
+    line: usize,
+    severity: Option<Severity>,
+    review: &str,
+    message: &str,
+) -> String {
+    let message = message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "{path}:{line}:1: {}: [{review}] {message}",
+        AnnotationLevel::from_severity(severity).as_str()
+    )
+}
+
+/// レビュー結果のquickfixの行（行が分からない指摘は1行目）
+pub fn render_finding(finding: &Finding) -> String {
+    quickfix_line(
+        &finding.file_path,
+        finding_line(finding).unwrap_or(1),
+        finding.severity,
+        &finding.review,
+        &finding.message,
+    )
+}
+
+/// 履歴のレビュー結果のquickfixの行（末尾に改行を付ける）
+pub fn render_history(findings: &[FileFinding]) -> String {
+    findings
+        .iter()
+        .map(|FileFinding { path, finding }| {
+            let line = quickfix_line(
+                path,
+                finding.line.map_or(1, |line| line.max(1) as usize),
+                finding.severity,
+                &finding.review,
+                &finding.message,
+            );
+            format!("{line}\n")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_history::FindingRecord;
+
+    #[test]
+    fn renders_one_errorformat_line_per_finding() {
+        let record = |line, severity| FileFinding {
+            path: "src/lib.rs".to_string(),
+            finding: FindingRecord {
+                ts: "2026-10-16T03:12:45Z".to_string(),
+                run_id: None,
+                job_id: None,
+                review: "構文".to_string(),
+                message: "括弧が\n\n  閉じていません\n".to_string(),
+                severity,
+                confidence: None,
+                model: None,
+                provider: None,
+                prompt_hash: None,
+                engine_version: None,
+                line,
+            },
+        };
+        assert_eq!(
+            render_history(&[
+                record(Some(12), Some(Severity::High)),
+                record(None, Some(Severity::Medium)),
+                record(Some(3), None),
+            ]),
+            "src/lib.rs:12:1: error: [構文] 括弧が 閉じていません\n\
+             src/lib.rs:1:1: warning: [構文] 括弧が 閉じていません\n\
+             src/lib.rs:3:1: info: [構文] 括弧が 閉じていません\n"
+        );
+    }
+}
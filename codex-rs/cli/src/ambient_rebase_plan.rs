@@ -0,0 +1,293 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::ambient_git::run_git_command;
+
+/// コミット整理の提案のレビュー名
+pub const REBASE_PLAN_REVIEW_NAME: &str = "コミット整理の提案";
+
+/// 提案の対象にするpush前のコミットの上限
+const MAX_LOCAL_COMMITS: usize = 30;
+
+/// `git rebase -i`のtodoリストで使えるコマンド（短縮形を含む）
+const TODO_COMMANDS: &[&str] = &[
+    "pick", "p", "reword", "r", "squash", "s", "fixup", "f", "exec", "x",
+];
+
+/// `.ambient/config.toml`の`[rebase_plan]`（push前の細かいコミットをまとめる提案）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RebasePlanConfig {
+    /// 提案を行うか
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// push前のコミットがこの数以上あり、同じファイルを変更しているときに提案する
+    #[serde(default = "default_min_commits")]
+    pub min_commits: usize,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_min_commits() -> usize {
+    3
+}
+
+impl Default for RebasePlanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_commits: default_min_commits(),
+        }
+    }
+}
+
+/// push前のコミット
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalCommit {
+    /// 短縮したコミットID
+    pub id: String,
+    pub subject: String,
+    /// 変更したファイル
+    pub files: Vec<String>,
+}
+
+/// upstreamにないコミットを古い順に返す（upstreamが設定されていなければ空）
+pub fn local_commits(root: &Path) -> Result<Vec<LocalCommit>> {
+    if run_git_command(&["rev-parse", "--verify", "-q", "@{upstream}"], root).is_err() {
+        return Ok(vec![]);
+    }
+    let max_count = format!("--max-count={MAX_LOCAL_COMMITS}");
+    let log = run_git_command(
+        &[
+            "-c",
+            "core.quotePath=false",
+            "log",
+            &max_count,
+            "--format=%x1e%h%x00%s",
+            "--name-only",
+            "@{upstream}..HEAD",
+        ],
+        root,
+    )?;
+    let mut commits: Vec<LocalCommit> = log
+        .split('\x1e')
+        .filter_map(|entry| {
+            let (id, rest) = entry.split_once('\0')?;
+            let mut lines = rest.lines();
+            let subject = lines.next().unwrap_or_default().to_string();
+            let files = lines
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect();
+            Some(LocalCommit {
+                id: id.to_string(),
+                subject,
+                files,
+            })
+        })
+        .collect();
+    commits.reverse();
+    Ok(commits)
+}
+
+/// 整理を提案すべき履歴か（コミットが多く、同じファイルを複数のコミットで変更している）
+pub fn needs_cleanup(commits: &[LocalCommit], config: &RebasePlanConfig) -> bool {
+    if !config.enabled || commits.len() < config.min_commits.max(2) {
+        return false;
+    }
+    let mut touched: BTreeMap<&str, usize> = BTreeMap::new();
+    for commit in commits {
+        for file in &commit.files {
+            *touched.entry(file.as_str()).or_default() += 1;
+        }
+    }
+    touched.values().any(|count| *count > 1)
+}
+
+/// 整理の計画を`git rebase -i`のtodoリストとして答えてもらうプロンプト
+pub fn render_prompt(commits: &[LocalCommit]) -> String {
+    let mut prompt = String::from(
+        "以下はまだpushしていないコミットです（古い順）。同じ変更を細かく分けたコミットや、直前のコミットの修正だけのコミットをまとめる`git rebase -i`のtodoリストを作ってください。\n\n\
+         - すべてのコミットをちょうど1回ずつ、順序を保って含めてください（dropは使わない）\n\
+         - 使えるコマンドは`pick`、`squash`、`fixup`、`exec`です\n\
+         - まとめたコミットのメッセージを変える場合は、そのまとまりの直後に`exec git commit --amend --only -m \"新しいメッセージ\"`を置いてください\n\
+         - todoリストだけを```で囲んで答えてください\n\nコミット:\n",
+    );
+    for commit in commits {
+        prompt.push_str(&format!(
+            "- {} {}（{}）\n",
+            commit.id,
+            commit.subject,
+            commit.files.join(", ")
+        ));
+    }
+    prompt
+}
+
+/// 回答からtodoリストを取り出し、すべてのコミットを1回ずつ含むことを確かめる
+///
+/// コミットが抜けていたり知らない行があったりする場合は`None`（そのまま実行すると変更を失うため）。
+pub fn extract_todo(response: &str, commits: &[LocalCommit]) -> Option<String> {
+    let body = match response.split_once("```") {
+        Some((_, rest)) => {
+            let rest = rest.split_once('\n').map_or("", |(_, rest)| rest);
+            rest.split_once("```").map_or(rest, |(body, _)| body)
+        }
+        None => response,
+    };
+    let mut lines = Vec::new();
+    let mut seen = Vec::new();
+    for line in body.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let command = words.next()?;
+        if !TODO_COMMANDS.contains(&command) {
+            return None;
+        }
+        if !matches!(command, "exec" | "x") {
+            // 最初のコミットはまとめる先がない
+            if seen.is_empty() && matches!(command, "squash" | "s" | "fixup" | "f") {
+                return None;
+            }
+            let id = words.next()?;
+            let commit = commits
+                .iter()
+                .find(|commit| commit.id.starts_with(id) || id.starts_with(&commit.id))?;
+            seen.push(commit.id.as_str());
+        }
+        lines.push(line);
+    }
+    let expected: Vec<&str> = commits.iter().map(|commit| commit.id.as_str()).collect();
+    (seen == expected).then(|| lines.join("\n"))
+}
+
+/// 指摘として表示する文面
+pub fn render_plan(todo: &str) -> String {
+    format!(
+        "push前のコミットをまとめると履歴が読みやすくなります。`git rebase -i @{{upstream}}`を実行し、todoリストを次の内容に置き換えてください。\n\n```\n{todo}\n```"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_test_support::TempRepo;
+
+    fn commit(id: &str, subject: &str, files: &[&str]) -> LocalCommit {
+        LocalCommit {
+            id: id.to_string(),
+            subject: subject.to_string(),
+            files: files.iter().map(|file| file.to_string()).collect(),
+        }
+    }
+
+    fn messy_history() -> Vec<LocalCommit> {
+        vec![
+            commit("a1b2c3d", "add parser", &["src/parser.rs"]),
+            commit("b2c3d4e", "fix typo", &["src/parser.rs"]),
+            commit("c3d4e5f", "wip", &["src/parser.rs", "tests/parser.rs"]),
+        ]
+    }
+
+    #[test]
+    fn suggests_cleanup_only_for_overlapping_commits() {
+        let config = RebasePlanConfig::default();
+        assert!(needs_cleanup(&messy_history(), &config));
+        assert!(!needs_cleanup(&messy_history()[..2], &config));
+
+        let separate = vec![
+            commit("a", "a", &["a.rs"]),
+            commit("b", "b", &["b.rs"]),
+            commit("c", "c", &["c.rs"]),
+        ];
+        assert!(!needs_cleanup(&separate, &config));
+
+        let disabled = RebasePlanConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        assert!(!needs_cleanup(&messy_history(), &disabled));
+    }
+
+    #[test]
+    fn extracts_valid_todo_lists() {
+        let commits = messy_history();
+        let response = "こうまとめられます。\n\n```text\npick a1b2c3d add parser\nfixup b2c3d4e fix typo\nsquash c3d4e5f wip\nexec git commit --amend --only -m \"feat: add parser\"\n```\n";
+        let todo = extract_todo(response, &commits).unwrap();
+        assert!(todo.starts_with("pick a1b2c3d add parser\nfixup b2c3d4e"));
+        assert!(todo.ends_with("-m \"feat: add parser\""));
+        assert!(render_plan(&todo).contains("`git rebase -i @{upstream}`"));
+
+        // コミットが抜けている、順序が違う、知らないコマンドがある、最初のコミットをまとめている
+        assert_eq!(extract_todo("pick a1b2c3d\nfixup c3d4e5f", &commits), None);
+        assert_eq!(
+            extract_todo("fixup a1b2c3d\npick b2c3d4e\npick c3d4e5f", &commits),
+            None
+        );
+        assert_eq!(
+            extract_todo("pick b2c3d4e\npick a1b2c3d\npick c3d4e5f", &commits),
+            None
+        );
+        assert_eq!(
+            extract_todo("pick a1b2c3d\ndrop b2c3d4e\npick c3d4e5f", &commits),
+            None
+        );
+    }
+
+    #[test]
+    fn lists_unpushed_commits_with_files() {
+        let remote = TempRepo::new().unwrap();
+        remote.write("README.md", "hello\n").unwrap();
+        remote.add(&["README.md"]).unwrap();
+        remote.commit("initial").unwrap();
+        let clone = tempfile::tempdir().unwrap();
+        let clone_path = clone.path().display().to_string();
+        run_git_command(
+            &[
+                "clone",
+                "-q",
+                &remote.path().display().to_string(),
+                &clone_path,
+            ],
+            remote.path(),
+        )
+        .unwrap();
+        let git = |args: &[&str]| {
+            let mut all = vec![
+                "-c",
+                "user.name=ambient-test",
+                "-c",
+                "user.email=ambient-test@example.com",
+            ];
+            all.extend_from_slice(args);
+            run_git_command(&all, clone.path()).unwrap();
+        };
+        for (content, message) in [("a", "add notes"), ("b", "update notes")] {
+            std::fs::write(clone.path().join("notes.txt"), content).unwrap();
+            git(&["add", "notes.txt"]);
+            git(&["commit", "-q", "-m", message]);
+        }
+
+        let commits = local_commits(clone.path()).unwrap();
+        assert_eq!(
+            commits
+                .iter()
+                .map(|commit| (commit.subject.as_str(), commit.files.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("add notes", vec!["notes.txt".to_string()]),
+                ("update notes", vec!["notes.txt".to_string()]),
+            ]
+        );
+        assert!(local_commits(remote.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::ambient_config::RedactionLevel;
+
+/// マスキング後に置き換える文字列
+const REDACTED: &str = "***";
+
+/// 代入値をマスキングするキー名（小文字で比較）
+const SECRET_KEYS: &[&str] = &[
+    "api_key",
+    "apikey",
+    "api-key",
+    "secret",
+    "password",
+    "passwd",
+    "token",
+    "private_key",
+    "access_key",
+];
+
+/// `Strict`でマスキングするトークン風文字列の最小長
+const MIN_TOKEN_LEN: usize = 32;
+
+/// 引用符のない代入値をマスキングする最小長（これより短い値や英字だけの値は型名や識別子とみなす）
+const MIN_BARE_VALUE_LEN: usize = 8;
+
+/// モデルへ送信する前にテキストから秘密情報をマスキングする
+pub fn redact(text: &str, level: RedactionLevel) -> String {
+    if level == RedactionLevel::Off {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let (body, newline) = match line.strip_suffix('\n') {
+            Some(body) => (body, "\n"),
+            None => (line, ""),
+        };
+        let mut redacted = redact_assignment(body);
+        if level == RedactionLevel::Strict {
+            redacted = redact_long_tokens(&redacted);
+        }
+        out.push_str(&redacted);
+        out.push_str(newline);
+    }
+    out
+}
+
+/// `api_key = "..."` や `password: ...` のリテラルの値をマスキングする
+///
+/// `pub token: String,`や`let password = read_input();`のようなコードは変更しない。
+fn redact_assignment(line: &str) -> String {
+    let lower = line.to_ascii_lowercase();
+    let Some(key_end) = SECRET_KEYS
+        .iter()
+        .filter_map(|key| lower.find(key).map(|pos| pos + key.len()))
+        .min()
+    else {
+        return line.to_string();
+    };
+
+    let rest = &line[key_end..];
+    let Some(sep) = rest.find(['=', ':']) else {
+        return line.to_string();
+    };
+    // キー名と区切り文字の間に識別子以外が挟まっている場合は代入とみなさない
+    if !rest[..sep]
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '"' || c == '\'' || c == ' ')
+    {
+        return line.to_string();
+    }
+
+    let value = &line[key_end + sep + 1..];
+    let value_start = line.len() - value.trim_start().len();
+    match secret_value_range(&line[value_start..]) {
+        Some((start, end)) => format!(
+            "{}{REDACTED}{}",
+            &line[..value_start + start],
+            &line[value_start + end..]
+        ),
+        None => line.to_string(),
+    }
+}
+
+/// 値が文字列リテラルか秘密らしい値なら、マスキングする範囲（値の先頭からのバイト位置）
+fn secret_value_range(value: &str) -> Option<(usize, usize)> {
+    let first = value.chars().next()?;
+    if matches!(first, '"' | '\'' | '`') {
+        // 閉じていない文字列は行末までを値とみなす
+        let end = value[1..].find(first).map_or(value.len(), |pos| pos + 1);
+        return (end > 1).then_some((1, end));
+    }
+    let end = value
+        .find(|c: char| {
+            !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '/' | '=' | '.'))
+        })
+        .unwrap_or(value.len());
+    let token = &value[..end];
+    // 英字と数字が混ざった長い値だけを秘密とみなし、型名や関数呼び出しは残す
+    let looks_secret = token.len() >= MIN_BARE_VALUE_LEN
+        && token.chars().any(|c| c.is_ascii_digit())
+        && token.chars().any(|c| c.is_ascii_alphabetic());
+    let rest = value[end..].trim_start();
+    (looks_secret && (rest.is_empty() || rest.starts_with([',', ';', '#']))).then_some((0, end))
+}
+
+/// 英数字などが長く連続するトークン風文字列をマスキングする
+fn redact_long_tokens(line: &str) -> String {
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-');
+
+    let mut out = String::with_capacity(line.len());
+    let mut token = String::new();
+    for c in line.chars() {
+        if is_token_char(c) {
+            token.push(c);
+            continue;
+        }
+        flush_token(&mut out, &mut token);
+        out.push(c);
+    }
+    flush_token(&mut out, &mut token);
+    out
+}
+
+fn flush_token(out: &mut String, token: &mut String) {
+    if token.len() >= MIN_TOKEN_LEN && token.chars().any(|c| c.is_ascii_digit()) {
+        out.push_str(REDACTED);
+    } else {
+        out.push_str(token);
+    }
+    token.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_masks_assignments_only() {
+        let diff = "+let api_key = \"sk-123\";\n+let name = \"value\";\n";
+        assert_eq!(
+            redact(diff, RedactionLevel::Standard),
+            "+let api_key = \"***\";\n+let name = \"value\";\n"
+        );
+    }
+
+    #[test]
+    fn masks_literal_values_but_not_code() {
+        assert_eq!(
+            redact("API_KEY=abc123def456\n", RedactionLevel::Standard),
+            "API_KEY=***\n"
+        );
+        assert_eq!(
+            redact("  \"password\": 'hunter2',", RedactionLevel::Standard),
+            "  \"password\": '***',"
+        );
+        for code in [
+            "+    pub token: String,",
+            "+    let password = read_input();",
+            "+    let secret = config.secret.clone();",
+            "+fn check(token: &str) -> bool {",
+        ] {
+            assert_eq!(redact(code, RedactionLevel::Standard), code);
+        }
+    }
+
+    #[test]
+    fn strict_masks_long_tokens() {
+        let line = "+url = \"https://example.com/abcdef0123456789abcdef0123456789\"";
+        assert_eq!(
+            redact(line, RedactionLevel::Strict),
+            "+url = \"https://example.com/***\""
+        );
+        assert_eq!(redact(line, RedactionLevel::Off), line);
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::ambient_server::Finding;
+use crate::ambient_server::FindingRepeat;
+use crate::ambient_server::RunIds;
+
+/// この類似度（0〜1）以上の回答は同じ指摘とみなす
+const SIMILARITY_THRESHOLD: f64 = 0.85;
+
+/// ファイルとレビューごとに最後に配信した指摘
+#[derive(Debug, Clone)]
+struct Original {
+    ids: RunIds,
+    message: String,
+    sent_at: Instant,
+    count: u32,
+}
+
+/// 同じファイル・同じレビューで似た指摘が続くとき、クールダウンの間は配信せずに回数だけ数える
+#[derive(Debug)]
+pub struct RepeatFilter {
+    cooldown: Duration,
+    originals: Mutex<HashMap<(String, String), Original>>,
+}
+
+impl RepeatFilter {
+    /// `cooldown`が0の場合は何も抑制しない
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            originals: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 指摘を配信してよければ`None`、前回と同じ指摘なら元の指摘の回数を増やして返す
+    pub fn check(&self, finding: &Finding, now: Instant) -> Option<FindingRepeat> {
+        if self.cooldown.is_zero() {
+            return None;
+        }
+        let mut originals = self.originals.lock().ok()?;
+        let key = (finding.file_path.clone(), finding.review.clone());
+        if let Some(original) = originals.get_mut(&key)
+            && now.duration_since(original.sent_at) < self.cooldown
+            && similarity(&original.message, &finding.message) >= SIMILARITY_THRESHOLD
+        {
+            original.count += 1;
+            return Some(FindingRepeat {
+                ids: original.ids,
+                file_path: finding.file_path.clone(),
+                review: finding.review.clone(),
+                count: original.count,
+            });
+        }
+        originals.insert(
+            key,
+            Original {
+                ids: finding.ids,
+                message: finding.message.clone(),
+                sent_at: now,
+                count: 1,
+            },
+        );
+        None
+    }
+}
+
+/// 2つの回答の類似度（文字のbigramのDice係数、0〜1）
+///
+/// 行番号だけが変わった回答を同じとみなすため、数字と空白は比較しない。
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let common = a.intersection(&b).count();
+    (2 * common) as f64 / (a.len() + b.len()) as f64
+}
+
+fn bigrams(text: &str) -> HashSet<(char, char)> {
+    let chars: Vec<char> = text
+        .chars()
+        .filter(|c| !c.is_ascii_digit() && !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(message: &str) -> Finding {
+        Finding {
+            ids: RunIds::new_run().new_job(),
+            file_path: "src/lib.rs".to_string(),
+            review: "セキュリティ".to_string(),
+            message: message.to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity: None,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: None,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn suppresses_near_duplicates_during_cooldown() {
+        let filter = RepeatFilter::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let original = finding("`src/lib.rs:12`でAPIキーがハードコードされています");
+        assert!(filter.check(&original, start).is_none());
+
+        let repeat = filter
+            .check(
+                &finding("`src/lib.rs:14` で APIキーがハードコードされています。"),
+                start + Duration::from_secs(10),
+            )
+            .unwrap();
+        assert_eq!(repeat.ids, original.ids);
+        assert_eq!(repeat.count, 2);
+
+        // 内容の違う指摘と、クールダウン後の指摘は配信する
+        assert!(
+            filter
+                .check(
+                    &finding("SQLインジェクションの可能性があります"),
+                    start + Duration::from_secs(20)
+                )
+                .is_none()
+        );
+        assert!(
+            filter
+                .check(
+                    &finding("SQLインジェクションの可能性があります"),
+                    start + Duration::from_secs(90)
+                )
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn zero_cooldown_disables_filter() {
+        let filter = RepeatFilter::new(Duration::ZERO);
+        let now = Instant::now();
+        assert!(filter.check(&finding("同じ"), now).is_none());
+        assert!(filter.check(&finding("同じ"), now).is_none());
+    }
+}
@@ -0,0 +1,585 @@
+use anyhow::Result;
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::NaiveDate;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+
+use crate::ambient_history::FindingRecord;
+use crate::ambient_history::ResolutionStatus;
+use crate::ambient_history::ReviewHistory;
+use crate::ambient_locale::Locale;
+
+/// 推移グラフに載せる日数の上限（新しい日から数える）
+const TREND_DAYS: usize = 90;
+
+/// `ambient report`の出力形式（`json`は標準出力、`site`は`--out`のディレクトリ、`sarif`は`--out`のファイルか標準出力）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Site,
+    Sarif,
+}
+
+/// 履歴から集計したレビュー結果（`ambient report`のすべての形式の元になる）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReportData {
+    /// 集計した時刻（RFC3339、UTC）
+    pub generated_at: String,
+    /// 集計の対象にした最も古い時刻（RFC3339、UTC、指定がなければすべて）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    pub total: usize,
+    /// 対応が記録されていないレビュー結果の数
+    pub open: usize,
+    /// 対応していないレビュー結果の重大度ごとの数（重大度のないものは`none`）
+    pub open_by_severity: BTreeMap<String, usize>,
+    /// 対応していない結果の多い順
+    pub files: Vec<FileReport>,
+    /// 日ごとの記録と対応の数（表示用タイムゾーンの日付、古い順）
+    pub trend: Vec<TrendPoint>,
+}
+
+/// ファイルごとのレビュー結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub open: usize,
+    /// 新しい順
+    pub findings: Vec<ReportFinding>,
+}
+
+/// 対応状況を付けたレビュー結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReportFinding {
+    #[serde(flatten)]
+    pub finding: FindingRecord,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<ResolutionStatus>,
+}
+
+/// 1日の記録と対応の数
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TrendPoint {
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub opened: usize,
+    pub closed: usize,
+}
+
+/// 履歴のレビュー結果を、ファイルごとの一覧と日ごとの推移にまとめる
+pub fn aggregate(
+    history: &ReviewHistory,
+    since: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    offset: FixedOffset,
+) -> ReportData {
+    let in_range = |ts: &str| {
+        since.is_none_or(|since| DateTime::parse_from_rfc3339(ts).is_ok_and(|ts| ts >= since))
+    };
+    let day = |ts: &str| {
+        DateTime::parse_from_rfc3339(ts)
+            .ok()
+            .map(|ts| ts.with_timezone(&offset).date_naive())
+    };
+    let mut daily: BTreeMap<NaiveDate, (usize, usize)> = BTreeMap::new();
+    let mut open_by_severity: BTreeMap<String, usize> = BTreeMap::new();
+    let mut files = Vec::new();
+    let mut total = 0;
+    for file in history.files() {
+        let mut findings: Vec<ReportFinding> = file
+            .findings
+            .iter()
+            .filter(|finding| in_range(&finding.ts))
+            .map(|finding| ReportFinding {
+                finding: finding.clone(),
+                // 同じ結果に複数の対応があれば最後のもの
+                status: file
+                    .resolutions
+                    .iter()
+                    .rev()
+                    .find(|resolution| {
+                        Some(resolution.job_id) == finding.job_id
+                            && resolution.review == finding.review
+                    })
+                    .map(|resolution| resolution.status),
+            })
+            .collect();
+        for resolution in file.resolutions.iter().filter(|r| in_range(&r.ts)) {
+            if let Some(date) = day(&resolution.ts) {
+                daily.entry(date).or_default().1 += 1;
+            }
+        }
+        if findings.is_empty() {
+            continue;
+        }
+        for finding in &findings {
+            if let Some(date) = day(&finding.finding.ts) {
+                daily.entry(date).or_default().0 += 1;
+            }
+            if finding.status.is_none() {
+                let severity = finding.finding.severity.map_or("none", |s| s.as_str());
+                *open_by_severity.entry(severity.to_string()).or_default() += 1;
+            }
+        }
+        total += findings.len();
+        findings.reverse();
+        files.push(FileReport {
+            path: file.path,
+            open: findings.iter().filter(|f| f.status.is_none()).count(),
+            findings,
+        });
+    }
+    files.sort_by(|a, b| b.open.cmp(&a.open).then_with(|| a.path.cmp(&b.path)));
+
+    // 記録のない日も0件として並べる
+    let today = now.with_timezone(&offset).date_naive();
+    let first = daily.keys().next().copied().unwrap_or(today);
+    let trend = first
+        .iter_days()
+        .take_while(|date| *date <= today)
+        .map(|date| {
+            let (opened, closed) = daily.get(&date).copied().unwrap_or_default();
+            TrendPoint {
+                date: date.format("%Y-%m-%d").to_string(),
+                opened,
+                closed,
+            }
+        })
+        .collect::<Vec<_>>();
+    let trend = trend[trend.len().saturating_sub(TREND_DAYS)..].to_vec();
+
+    ReportData {
+        generated_at: now.to_rfc3339_opts(SecondsFormat::Millis, true),
+        since: since.map(|since| since.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        total,
+        open: files.iter().map(|file| file.open).sum(),
+        open_by_severity,
+        files,
+        trend,
+    }
+}
+
+/// サイトの見出しと表の列名
+struct Labels {
+    title: &'static str,
+    generated: &'static str,
+    open: &'static str,
+    total: &'static str,
+    trend: &'static str,
+    opened: &'static str,
+    closed: &'static str,
+    files: &'static str,
+    file: &'static str,
+    severity: &'static str,
+    review: &'static str,
+    status: &'static str,
+    back: &'static str,
+    empty: &'static str,
+}
+
+impl Labels {
+    fn for_locale(locale: Locale) -> Self {
+        match locale {
+            Locale::Ja => Self {
+                title: "Ambient Code Watcher レポート",
+                generated: "作成日時",
+                open: "未対応",
+                total: "レビュー結果",
+                trend: "日ごとの推移",
+                opened: "記録",
+                closed: "対応",
+                files: "ファイル別",
+                file: "ファイル",
+                severity: "重大度",
+                review: "レビュー",
+                status: "対応",
+                back: "一覧に戻る",
+                empty: "レビュー結果はありません",
+            },
+            Locale::En => Self {
+                title: "Ambient Code Watcher Report",
+                generated: "Generated",
+                open: "Open",
+                total: "Findings",
+                trend: "Daily trend",
+                opened: "Opened",
+                closed: "Resolved",
+                files: "Files",
+                file: "File",
+                severity: "Severity",
+                review: "Review",
+                status: "Status",
+                back: "Back to index",
+                empty: "No findings",
+            },
+        }
+    }
+}
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2rem auto;max-width:960px;padding:0 1rem;color:#1f2328}\
+table{border-collapse:collapse;width:100%}th,td{border-bottom:1px solid #d0d7de;padding:.4rem;text-align:left;vertical-align:top}\
+pre{white-space:pre-wrap;background:#f6f8fa;padding:.6rem;margin:0}.num{text-align:right}\
+.sev-critical,.sev-high{color:#cf222e;font-weight:600}.sev-medium{color:#9a6700}.muted{color:#656d76}";
+
+/// 集計を静的なHTMLサイトとして書き出し、書いたページの数を返す
+///
+/// `index.html`、ファイルごとの`files/*.html`、推移グラフのデータ`trends.json`を作る。
+/// 外部のCSSやスクリプトは読み込まないので、CIの成果物やGitHub Pagesにそのまま置ける。
+pub fn write_site(
+    report: &ReportData,
+    out_dir: &Path,
+    locale: Locale,
+    offset: FixedOffset,
+) -> Result<usize> {
+    let labels = Labels::for_locale(locale);
+    let format = Format { locale, offset };
+    fs::create_dir_all(out_dir.join("files"))?;
+    fs::write(
+        out_dir.join("trends.json"),
+        serde_json::to_string_pretty(&report.trend)? + "\n",
+    )?;
+    fs::write(
+        out_dir.join("index.html"),
+        render_index(report, &labels, format),
+    )?;
+    for file in &report.files {
+        fs::write(
+            out_dir.join("files").join(page_name(&file.path)),
+            render_file_page(file, &labels, format),
+        )?;
+    }
+    Ok(report.files.len() + 1)
+}
+
+/// 日時と件数の書式
+#[derive(Clone, Copy)]
+struct Format {
+    locale: Locale,
+    offset: FixedOffset,
+}
+
+impl Format {
+    fn datetime(self, ts: &str) -> String {
+        escape(&self.locale.format_datetime(ts, self.offset))
+    }
+
+    fn count(self, count: usize) -> String {
+        self.locale.format_count(count, "件", "finding")
+    }
+}
+
+/// ファイルのページ名（パスの区切りを置き換え、衝突しないようにハッシュを付ける）
+fn page_name(path: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let readable: String = path
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{readable}-{:08x}.html", hasher.finish() as u32)
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape(title)
+    )
+}
+
+fn render_index(report: &ReportData, labels: &Labels, format: Format) -> String {
+    let mut body = format!(
+        "<h1>{}</h1>\n<p class=\"muted\">{}: {}</p>\n",
+        escape(labels.title),
+        labels.generated,
+        format.datetime(&report.generated_at)
+    );
+    body.push_str(&format!(
+        "<p>{}: <strong>{}</strong> / {}: {}</p>\n",
+        labels.open,
+        format.count(report.open),
+        labels.total,
+        format.count(report.total)
+    ));
+    if !report.open_by_severity.is_empty() {
+        body.push_str("<ul>\n");
+        for (severity, count) in report.open_by_severity.iter().rev() {
+            body.push_str(&format!(
+                "<li class=\"sev-{severity}\">{severity}: {count}</li>\n"
+            ));
+        }
+        body.push_str("</ul>\n");
+    }
+
+    body.push_str(&format!("<h2>{}</h2>\n", labels.trend));
+    body.push_str(&trend_chart(&report.trend, labels));
+
+    body.push_str(&format!("<h2>{}</h2>\n", labels.files));
+    if report.files.is_empty() {
+        body.push_str(&format!("<p>{}</p>\n", labels.empty));
+    } else {
+        body.push_str(&format!(
+            "<table>\n<tr><th>{}</th><th class=\"num\">{}</th><th class=\"num\">{}</th></tr>\n",
+            labels.file, labels.open, labels.total
+        ));
+        for file in &report.files {
+            body.push_str(&format!(
+                "<tr><td><a href=\"files/{}\">{}</a></td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+                page_name(&file.path),
+                escape(&file.path),
+                file.open,
+                file.findings.len()
+            ));
+        }
+        body.push_str("</table>\n");
+    }
+    page(labels.title, &body)
+}
+
+/// 日ごとの記録（赤）と対応（緑）の棒グラフ（SVG）
+fn trend_chart(trend: &[TrendPoint], labels: &Labels) -> String {
+    const BAR: usize = 4;
+    const HEIGHT: usize = 100;
+    let max = trend
+        .iter()
+        .map(|point| point.opened.max(point.closed))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let width = trend.len().max(1) * (BAR * 2 + 2);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{HEIGHT}\" role=\"img\" aria-label=\"{}\">\n",
+        escape(labels.trend)
+    );
+    for (i, point) in trend.iter().enumerate() {
+        let x = i * (BAR * 2 + 2);
+        for (offset, count, color, label) in [
+            (0, point.opened, "#cf222e", labels.opened),
+            (BAR, point.closed, "#1a7f37", labels.closed),
+        ] {
+            let height = count * HEIGHT / max;
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{BAR}\" height=\"{height}\" fill=\"{color}\"><title>{} {label}: {count}</title></rect>\n",
+                x + offset,
+                HEIGHT - height,
+                point.date
+            ));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg.push_str(&format!(
+        "<p class=\"muted\"><span style=\"color:#cf222e\">■</span> {} <span style=\"color:#1a7f37\">■</span> {} (<a href=\"trends.json\">trends.json</a>)</p>\n",
+        labels.opened, labels.closed
+    ));
+    svg
+}
+
+fn render_file_page(file: &FileReport, labels: &Labels, format: Format) -> String {
+    let mut body = format!(
+        "<p><a href=\"../index.html\">{}</a></p>\n<h1>{}</h1>\n<p>{}: {}</p>\n<table>\n<tr><th>{}</th><th>{}</th><th>{}</th><th></th></tr>\n",
+        labels.back,
+        escape(&file.path),
+        labels.open,
+        format.count(file.open),
+        labels.severity,
+        labels.review,
+        labels.status
+    );
+    for ReportFinding { finding, status } in &file.findings {
+        let severity = finding.severity.map_or("-", |s| s.as_str());
+        let status = status.map_or("-", |status| match status {
+            ResolutionStatus::Fixed => "fixed",
+            ResolutionStatus::FalsePositive => "false_positive",
+            ResolutionStatus::WontFix => "wont_fix",
+        });
+        let mut recorded = format.datetime(&finding.ts);
+        if let Some(provenance) = provenance(finding) {
+            recorded.push_str(&format!("<br>{}", escape(&provenance)));
+        }
+        body.push_str(&format!(
+            "<tr><td class=\"sev-{severity}\">{severity}</td><td>{}<br><span class=\"muted\">{recorded}</span></td><td>{status}</td><td><pre>{}</pre></td></tr>\n",
+            escape(&finding.review),
+            escape(&finding.message)
+        ));
+    }
+    body.push_str("</table>\n");
+    page(&file.path, &body)
+}
+
+/// `gpt-oss:20b（oss） · prompt 0123456789abcdef · v0.3.0`（記録されていない項目は省く）
+fn provenance(finding: &FindingRecord) -> Option<String> {
+    let model = match (&finding.model, &finding.provider) {
+        (Some(model), Some(provider)) => Some(format!("{model}（{provider}）")),
+        (model, provider) => model.clone().or_else(|| provider.clone()),
+    };
+    let parts: Vec<String> = model
+        .into_iter()
+        .chain(
+            finding
+                .prompt_hash
+                .as_ref()
+                .map(|hash| format!("prompt {hash}")),
+        )
+        .chain(
+            finding
+                .engine_version
+                .as_ref()
+                .map(|version| format!("v{version}")),
+        )
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_confidence::Severity;
+    use crate::ambient_history::Resolution;
+    use uuid::Uuid;
+
+    fn finding(ts: &str, review: &str, severity: Severity) -> FindingRecord {
+        FindingRecord {
+            ts: ts.to_string(),
+            run_id: None,
+            job_id: Some(Uuid::new_v4()),
+            review: review.to_string(),
+            message: "`a < b`の比較が逆です".to_string(),
+            severity: Some(severity),
+            confidence: None,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            engine_version: None,
+            line: None,
+        }
+    }
+
+    fn history() -> ReviewHistory {
+        let history = ReviewHistory::default();
+        let fixed = finding("2026-10-14T01:00:00.000Z", "構文", Severity::High);
+        history.record_finding("src/lib.rs", fixed.clone());
+        history.record_finding(
+            "src/lib.rs",
+            FindingRecord {
+                model: Some("gpt-oss:20b".to_string()),
+                provider: Some("oss".to_string()),
+                prompt_hash: Some("0123456789abcdef".to_string()),
+                engine_version: Some("0.3.0".to_string()),
+                ..finding(
+                    "2026-10-16T01:00:00.000Z",
+                    "セキュリティ",
+                    Severity::Critical,
+                )
+            },
+        );
+        history.record_finding(
+            "web/app.js",
+            finding("2026-10-16T02:00:00.000Z", "構文", Severity::Low),
+        );
+        let mut resolution = Resolution::new(
+            fixed.job_id.unwrap(),
+            fixed.review,
+            ResolutionStatus::Fixed,
+            None,
+            None,
+        );
+        resolution.ts = "2026-10-15T01:00:00.000Z".to_string();
+        history.record_resolution("src/lib.rs", resolution);
+        history
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-10-16T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn aggregates_open_findings_and_daily_trend() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let report = aggregate(&history(), None, now(), utc);
+        assert_eq!((report.total, report.open), (3, 2));
+        assert_eq!(
+            report.open_by_severity,
+            BTreeMap::from([("critical".to_string(), 1), ("low".to_string(), 1)])
+        );
+        assert_eq!(report.files[0].path, "src/lib.rs");
+        assert_eq!(report.files[0].findings[0].finding.review, "セキュリティ");
+        assert_eq!(
+            report.files[0].findings[1].status,
+            Some(ResolutionStatus::Fixed)
+        );
+        assert_eq!(
+            report
+                .trend
+                .iter()
+                .map(|point| (point.date.as_str(), point.opened, point.closed))
+                .collect::<Vec<_>>(),
+            vec![
+                ("2026-10-14", 1, 0),
+                ("2026-10-15", 0, 1),
+                ("2026-10-16", 2, 0),
+            ]
+        );
+
+        let since = DateTime::parse_from_rfc3339("2026-10-16T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let recent = aggregate(&history(), Some(since), now(), utc);
+        assert_eq!((recent.total, recent.open), (2, 2));
+    }
+
+    #[test]
+    fn writes_self_contained_site() {
+        let dir = tempfile::tempdir().unwrap();
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        let report = aggregate(&history(), None, now(), jst);
+        assert_eq!(write_site(&report, dir.path(), Locale::Ja, jst).unwrap(), 3);
+
+        let index = fs::read_to_string(dir.path().join("index.html")).unwrap();
+        assert!(index.contains("作成日時: 2026-10-16 21:00"));
+        assert!(index.contains(&format!("href=\"files/{}\"", page_name("src/lib.rs"))));
+        assert!(index.contains("<svg"));
+        assert!(!index.contains("<script"));
+
+        let page =
+            fs::read_to_string(dir.path().join("files").join(page_name("src/lib.rs"))).unwrap();
+        assert!(page.contains("`a &lt; b`の比較が逆です"));
+        assert!(page.contains("href=\"../index.html\""));
+        assert!(page.contains("gpt-oss:20b（oss） · prompt 0123456789abcdef · v0.3.0"));
+
+        let trends: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("trends.json")).unwrap())
+                .unwrap();
+        assert_eq!(trends.as_array().unwrap().len(), 3);
+        assert_ne!(page_name("src/lib.rs"), page_name("src_lib.rs"));
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::ambient_storage::StorageCipher;
+use crate::ambient_time::now_stamp;
+
+/// `.ambient`の下のキャッシュのディレクトリ
+const CACHE_DIR: &str = "cache";
+
+/// 保存したレビューの回答
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedReview {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    pub review: String,
+    /// diffを含むプロンプトのSHA-256
+    pub diff_hash: String,
+    /// 回答したモデル
+    pub model: String,
+    /// モデルの回答（JSON形式のレビューは検証済みのもの）
+    pub response: String,
+    /// 保存した日時
+    pub ts: String,
+}
+
+/// レビューの回答のキャッシュ（`.ambient/cache/`に、ファイルとレビューの組ごとに最新の回答を保存する）
+///
+/// 再起動後や、変更を戻してから再び同じ変更をしたときに、同じdiffをモデルに送り直さない。
+/// diffが変わると同じ組の回答を上書きするので、編集を続けてもエントリは増えない。
+/// diffのハッシュやモデルが保存したものと違う場合は使わない。
+#[derive(Debug, Clone)]
+pub struct ReviewCache {
+    dir: PathBuf,
+    /// 保存した回答を使わずに、新しい回答で上書きする（`--no-cache`）
+    bypass: bool,
+    /// `[storage]`で暗号化する場合の鍵
+    cipher: StorageCipher,
+}
+
+impl ReviewCache {
+    /// プロジェクトのキャッシュのディレクトリ
+    pub fn dir_path(project_root: &Path) -> PathBuf {
+        project_root.join(".ambient").join(CACHE_DIR)
+    }
+
+    pub fn open(project_root: &Path, bypass: bool, cipher: StorageCipher) -> Self {
+        Self {
+            dir: Self::dir_path(project_root),
+            bypass,
+            cipher,
+        }
+    }
+
+    /// 同じファイル・diff・レビューに対してこのモデルが以前に返した回答
+    pub fn get(&self, path: &str, review: &str, model: &str, prompt: &str) -> Option<String> {
+        if self.bypass {
+            return None;
+        }
+        let diff_hash = content_hash(prompt);
+        let content = fs::read_to_string(self.entry_path(path, review)).ok()?;
+        let content = self.cipher.open(&content).ok()?;
+        let cached: CachedReview = serde_json::from_str(&content).ok()?;
+        (cached.path == path
+            && cached.review == review
+            && cached.diff_hash == diff_hash
+            && cached.model == model)
+            .then_some(cached.response)
+    }
+
+    /// 回答を保存する（同じファイルとレビューの古い回答は、diffが違っても上書きする）
+    pub fn put(
+        &self,
+        path: &str,
+        review: &str,
+        model: &str,
+        prompt: &str,
+        response: &str,
+    ) -> Result<()> {
+        ensure_cache_dir(&self.dir)?;
+        let entry = CachedReview {
+            path: path.to_string(),
+            review: review.to_string(),
+            diff_hash: content_hash(prompt),
+            model: model.to_string(),
+            response: response.to_string(),
+            ts: now_stamp().0,
+        };
+        fs::write(
+            self.entry_path(path, review),
+            self.cipher.seal(&serde_json::to_string(&entry)?)?,
+        )?;
+        Ok(())
+    }
+
+    fn entry_path(&self, path: &str, review: &str) -> PathBuf {
+        let key = content_hash(&format!("{path}\0{review}"));
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+/// 内容のSHA-256（16進数）
+pub fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// キャッシュのディレクトリを作り、その中身が変更ファイルとして検出されないようにする
+fn ensure_cache_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(&gitignore, "*\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_responses_for_the_same_diff_and_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ReviewCache::open(dir.path(), false, StorageCipher::default());
+        assert_eq!(cache.get("src/lib.rs", "構文", "gpt-5", "diff"), None);
+
+        cache
+            .put("src/lib.rs", "構文", "gpt-5", "diff", "問題ありません")
+            .unwrap();
+        assert_eq!(
+            cache.get("src/lib.rs", "構文", "gpt-5", "diff").as_deref(),
+            Some("問題ありません")
+        );
+        // diff・レビュー・モデルのどれかが違えば使わない
+        assert_eq!(cache.get("src/lib.rs", "構文", "gpt-5", "diff 2"), None);
+        assert_eq!(
+            cache.get("src/lib.rs", "セキュリティ", "gpt-5", "diff"),
+            None
+        );
+        assert_eq!(cache.get("src/lib.rs", "構文", "o3", "diff"), None);
+        assert!(
+            ReviewCache::dir_path(dir.path())
+                .join(".gitignore")
+                .exists()
+        );
+
+        // `--no-cache`では読まずに上書きする
+        let bypass = ReviewCache::open(dir.path(), true, StorageCipher::default());
+        assert_eq!(bypass.get("src/lib.rs", "構文", "gpt-5", "diff"), None);
+        bypass
+            .put(
+                "src/lib.rs",
+                "構文",
+                "gpt-5",
+                "diff",
+                "括弧が閉じていません",
+            )
+            .unwrap();
+        assert_eq!(
+            cache.get("src/lib.rs", "構文", "gpt-5", "diff").as_deref(),
+            Some("括弧が閉じていません")
+        );
+    }
+
+    #[test]
+    fn keeps_one_entry_per_file_and_review() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ReviewCache::open(dir.path(), false, StorageCipher::default());
+        for (index, diff) in ["diff 1", "diff 2", "diff 3"].iter().enumerate() {
+            cache
+                .put("src/lib.rs", "構文", "gpt-5", diff, &format!("回答{index}"))
+                .unwrap();
+        }
+        cache
+            .put(
+                "src/lib.rs",
+                "セキュリティ",
+                "gpt-5",
+                "diff 3",
+                "問題ありません",
+            )
+            .unwrap();
+
+        let entries = fs::read_dir(ReviewCache::dir_path(dir.path()))
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .count();
+        assert_eq!(entries, 2);
+        // 上書きされた古いdiffの回答は使わない
+        assert_eq!(cache.get("src/lib.rs", "構文", "gpt-5", "diff 1"), None);
+        assert_eq!(
+            cache
+                .get("src/lib.rs", "構文", "gpt-5", "diff 3")
+                .as_deref(),
+            Some("回答2")
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+use std::io;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// サーバーから読み出しを許可しないディレクトリ（秘密情報を含み得る）
+const FORBIDDEN_DIRS: &[&str] = &[".git", ".ambient"];
+
+/// リポジトリルートに閉じ込められたパス
+///
+/// サーバーがクライアントから受け取ったパスでファイルを読む場合は、
+/// 必ずこの型を経由する。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafePath {
+    relative: String,
+    absolute: PathBuf,
+}
+
+/// パスの解決に失敗した理由
+#[derive(Debug)]
+pub enum SafePathError {
+    /// 空のパス
+    Empty,
+    /// 絶対パスが指定された
+    Absolute,
+    /// `..`を含む
+    Traversal,
+    /// シンボリックリンクなどでルートの外に解決された
+    OutsideRoot,
+    /// `.git/`や`.ambient/`の中を指している
+    Forbidden,
+    /// ルートの正規化に失敗した
+    Root(io::Error),
+}
+
+impl fmt::Display for SafePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SafePathError::Empty => write!(f, "パスが空です"),
+            SafePathError::Absolute => write!(f, "絶対パスは指定できません"),
+            SafePathError::Traversal => write!(f, "`..`を含むパスは指定できません"),
+            SafePathError::OutsideRoot => write!(f, "リポジトリの外を指すパスです"),
+            SafePathError::Forbidden => write!(f, "このディレクトリは参照できません"),
+            SafePathError::Root(e) => write!(f, "リポジトリルートを解決できません: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SafePathError {}
+
+impl SafePath {
+    /// `requested`をリポジトリルート`root`からの相対パスとして解決する
+    ///
+    /// 作業ツリーに存在しないパス（削除されたファイルなど）も、存在する最も
+    /// 深い親ディレクトリを正規化してルート内にあることを確認する。
+    pub fn resolve(root: &Path, requested: &str) -> Result<Self, SafePathError> {
+        let requested_path = Path::new(requested);
+        if requested.trim().is_empty() {
+            return Err(SafePathError::Empty);
+        }
+        if requested_path.is_absolute() || requested.starts_with('/') {
+            return Err(SafePathError::Absolute);
+        }
+
+        let mut normalized = Vec::new();
+        for component in requested_path.components() {
+            match component {
+                Component::Normal(part) => normalized.push(part.to_string_lossy().to_string()),
+                Component::CurDir => {}
+                Component::ParentDir => return Err(SafePathError::Traversal),
+                Component::RootDir | Component::Prefix(_) => {
+                    return Err(SafePathError::Absolute);
+                }
+            }
+        }
+        if normalized.is_empty() {
+            return Err(SafePathError::Empty);
+        }
+        if is_forbidden(normalized.iter().map(String::as_str)) {
+            return Err(SafePathError::Forbidden);
+        }
+
+        let root = canonical_root(root)?;
+        let absolute = root.join(normalized.join("/"));
+
+        // シンボリックリンクを解決した実体もルート内かつ禁止ディレクトリ外であること
+        let resolved = canonicalize_existing(&absolute);
+        let inside = resolved
+            .strip_prefix(&root)
+            .map_err(|_| SafePathError::OutsideRoot)?;
+        if is_forbidden(inside.components().filter_map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })) {
+            return Err(SafePathError::Forbidden);
+        }
+
+        Ok(Self {
+            relative: normalized.join("/"),
+            absolute,
+        })
+    }
+
+    /// ルートからの相対パス（区切り文字は`/`）
+    pub fn relative(&self) -> &str {
+        &self.relative
+    }
+
+    /// 絶対パス
+    pub fn absolute(&self) -> &Path {
+        &self.absolute
+    }
+}
+
+/// リポジトリルートを正規化する（クライアントに公開するパスもこれを使う）
+pub fn canonical_root(root: &Path) -> Result<PathBuf, SafePathError> {
+    root.canonicalize().map_err(SafePathError::Root)
+}
+
+/// リポジトリルートからの相対パス（区切り文字は`/`）が参照できないディレクトリの中か
+pub fn is_forbidden_path(relative: &str) -> bool {
+    is_forbidden(relative.split('/'))
+}
+
+fn is_forbidden<'a>(mut components: impl Iterator<Item = &'a str>) -> bool {
+    components.any(|part| FORBIDDEN_DIRS.contains(&part))
+}
+
+/// 存在する最も深い祖先を正規化し、残りの要素をつなげる
+fn canonicalize_existing(path: &Path) -> PathBuf {
+    let mut existing = path.to_path_buf();
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return rest
+                .iter()
+                .rev()
+                .fold(canonical, |acc: PathBuf, part| acc.join(part));
+        }
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                rest.push(name.to_os_string());
+                existing = parent.to_path_buf();
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn resolves_paths_inside_root() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+
+        let path = SafePath::resolve(dir.path(), "./src/lib.rs").unwrap();
+        assert_eq!(path.relative(), "src/lib.rs");
+        assert!(path.absolute().ends_with("src/lib.rs"));
+
+        // 削除済みのファイルも解決できる
+        let deleted = SafePath::resolve(dir.path(), "src/removed.rs").unwrap();
+        assert_eq!(deleted.relative(), "src/removed.rs");
+    }
+
+    #[test]
+    fn rejects_traversal_and_absolute_paths() {
+        let dir = tempdir().unwrap();
+        assert!(matches!(
+            SafePath::resolve(dir.path(), "../etc/passwd"),
+            Err(SafePathError::Traversal)
+        ));
+        assert!(matches!(
+            SafePath::resolve(dir.path(), "src/../../secret"),
+            Err(SafePathError::Traversal)
+        ));
+        assert!(matches!(
+            SafePath::resolve(dir.path(), "/etc/passwd"),
+            Err(SafePathError::Absolute)
+        ));
+        assert!(matches!(
+            SafePath::resolve(dir.path(), ""),
+            Err(SafePathError::Empty)
+        ));
+    }
+
+    #[test]
+    fn rejects_secret_directories() {
+        let dir = tempdir().unwrap();
+        assert!(matches!(
+            SafePath::resolve(dir.path(), ".git/config"),
+            Err(SafePathError::Forbidden)
+        ));
+        assert!(matches!(
+            SafePath::resolve(dir.path(), ".ambient/config.toml"),
+            Err(SafePathError::Forbidden)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlink_escapes() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join(".git"), dir.path().join("git-link")).unwrap();
+
+        assert!(matches!(
+            SafePath::resolve(dir.path(), "link/secret.txt"),
+            Err(SafePathError::OutsideRoot)
+        ));
+        assert!(matches!(
+            SafePath::resolve(dir.path(), "git-link/config"),
+            Err(SafePathError::Forbidden)
+        ));
+    }
+}
@@ -0,0 +1,289 @@
+use percent_encoding::AsciiSet;
+use percent_encoding::CONTROLS;
+use percent_encoding::utf8_percent_encode;
+use serde_json::Value;
+use serde_json::json;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+use crate::ambient_confidence::Severity;
+use crate::ambient_integration::referenced_lines;
+use crate::ambient_report::ReportData;
+use crate::ambient_version::VERSION;
+
+/// 出力するSARIFのバージョン
+pub const SARIF_VERSION: &str = "2.1.0";
+
+/// SARIF 2.1.0のJSONスキーマ
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// `artifactLocation.uri`でエスケープする文字（区切りの`/`は残し、ASCII以外もエスケープされる）
+const URI_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// 集計したレビュー結果のうち、対応していないものをSARIFのログにする（GitHubのコードスキャン用）
+///
+/// ルールはレビューごとに1つ作る。行番号は指摘の一覧の行、なければ回答中の`path:行番号`を使い、
+/// どちらもなければファイルの先頭を指す（コードスキャンは行のない結果を受け付けないため）。
+/// 結果の`guid`はジョブIDとレビュー名から作るので、1回のジョブの複数のレビューでも重ならない。
+pub fn render_sarif(report: &ReportData) -> Value {
+    let open = report.files.iter().flat_map(|file| {
+        file.findings
+            .iter()
+            .filter(|finding| finding.status.is_none())
+            .map(move |finding| (file.path.as_str(), &finding.finding))
+    });
+    let reviews: BTreeSet<&str> = open
+        .clone()
+        .map(|(_, finding)| finding.review.as_str())
+        .collect();
+    let rules: Vec<Value> = reviews
+        .iter()
+        .map(|review| {
+            json!({
+                "id": review,
+                "name": review,
+                "shortDescription": { "text": review },
+            })
+        })
+        .collect();
+    let results: Vec<Value> = open
+        .map(|(path, finding)| {
+            let line = finding
+                .line
+                .map(|line| line as usize)
+                .or_else(|| referenced_lines(&finding.message, path).into_iter().next())
+                .unwrap_or(1)
+                .max(1);
+            let mut result = json!({
+                "ruleId": finding.review,
+                "ruleIndex": reviews.iter().position(|review| *review == finding.review),
+                "level": level(finding.severity),
+                "message": { "text": finding.message.trim() },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {
+                            "uri": utf8_percent_encode(path, URI_PATH).to_string(),
+                            "uriBaseId": "%SRCROOT%",
+                        },
+                        "region": { "startLine": line },
+                    }
+                }],
+                "properties": {
+                    "severity": finding.severity.map(Severity::as_str),
+                    "confidence": finding.confidence,
+                    "model": finding.model,
+                    "provider": finding.provider,
+                    "promptHash": finding.prompt_hash,
+                    "engineVersion": finding.engine_version,
+                    "detectedAt": finding.ts,
+                },
+            });
+            if let Some(job_id) = finding.job_id {
+                result["guid"] = json!(Uuid::new_v5(&job_id, finding.review.as_bytes()));
+            }
+            result
+        })
+        .collect();
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "ambient",
+                    "version": VERSION,
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// 重大度を結果のレベルにする（high以上は`error`、mediumは`warning`、それ以外は`note`）
+fn level(severity: Option<Severity>) -> &'static str {
+    match severity {
+        Some(Severity::Critical | Severity::High) => "error",
+        Some(Severity::Medium) => "warning",
+        Some(Severity::Low) | None => "note",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_history::FindingRecord;
+    use crate::ambient_history::Resolution;
+    use crate::ambient_history::ResolutionStatus;
+    use crate::ambient_history::ReviewHistory;
+    use crate::ambient_report::aggregate;
+    use chrono::FixedOffset;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn finding(
+        review: &str,
+        message: &str,
+        line: Option<u32>,
+        severity: Severity,
+    ) -> FindingRecord {
+        FindingRecord {
+            ts: "2026-10-16T01:00:00.000Z".to_string(),
+            run_id: None,
+            job_id: Some(Uuid::new_v4()),
+            review: review.to_string(),
+            message: message.to_string(),
+            severity: Some(severity),
+            confidence: Some(80),
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            engine_version: None,
+            line,
+        }
+    }
+
+    #[test]
+    fn exports_open_findings_with_rules_and_lines() {
+        let history = ReviewHistory::default();
+        history.record_finding(
+            "src/lib.rs",
+            FindingRecord {
+                model: Some("gpt-oss:20b".to_string()),
+                provider: Some("oss".to_string()),
+                prompt_hash: Some("0123456789abcdef".to_string()),
+                engine_version: Some("0.3.0".to_string()),
+                ..finding(
+                    "セキュリティ",
+                    "鍵が埋め込まれています",
+                    Some(7),
+                    Severity::High,
+                )
+            },
+        );
+        history.record_finding(
+            "src/lib.rs",
+            finding(
+                "構文",
+                "`src/lib.rs:12`で括弧が閉じていません",
+                None,
+                Severity::Medium,
+            ),
+        );
+        let fixed = finding("構文", "修正済み", None, Severity::Low);
+        history.record_finding("src/main.rs", fixed.clone());
+        history.record_resolution(
+            "src/main.rs",
+            Resolution::new(
+                fixed.job_id.unwrap(),
+                fixed.review,
+                ResolutionStatus::Fixed,
+                None,
+                None,
+            ),
+        );
+        let report = aggregate(
+            &history,
+            None,
+            Utc::now(),
+            FixedOffset::east_opt(0).unwrap(),
+        );
+
+        let sarif = render_sarif(&report);
+        assert_eq!(sarif["version"], SARIF_VERSION);
+        let run = &sarif["runs"][0];
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), 2);
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        let lines: BTreeSet<(String, u64, String)> = results
+            .iter()
+            .map(|result| {
+                let location = &result["locations"][0]["physicalLocation"];
+                (
+                    result["ruleId"].as_str().unwrap().to_string(),
+                    location["region"]["startLine"].as_u64().unwrap(),
+                    result["level"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            BTreeSet::from([
+                ("セキュリティ".to_string(), 7, "error".to_string()),
+                ("構文".to_string(), 12, "warning".to_string()),
+            ])
+        );
+        for result in results {
+            let index = result["ruleIndex"].as_u64().unwrap() as usize;
+            assert_eq!(rules[index]["id"], result["ruleId"]);
+        }
+        // 記録したときのモデルとプロンプトを結果ごとに残す
+        let security = results
+            .iter()
+            .find(|result| result["ruleId"] == "セキュリティ")
+            .unwrap();
+        assert_eq!(
+            security["properties"],
+            json!({
+                "severity": "high",
+                "confidence": 80,
+                "model": "gpt-oss:20b",
+                "provider": "oss",
+                "promptHash": "0123456789abcdef",
+                "engineVersion": "0.3.0",
+                "detectedAt": "2026-10-16T01:00:00.000Z",
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_results_valid_for_code_scanning() {
+        let history = ReviewHistory::default();
+        let job_id = Uuid::new_v4();
+        for review in ["構文", "セキュリティ"] {
+            history.record_finding(
+                "src/my dir/日本.rs",
+                FindingRecord {
+                    job_id: Some(job_id),
+                    ..finding(review, "問題があります", Some(0), Severity::Low)
+                },
+            );
+        }
+        let report = aggregate(
+            &history,
+            None,
+            Utc::now(),
+            FixedOffset::east_opt(0).unwrap(),
+        );
+
+        let sarif = render_sarif(&report);
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        // 同じジョブでもレビューごとにguidが分かれる
+        assert_ne!(results[0]["guid"], results[1]["guid"]);
+        for result in results {
+            let location = &result["locations"][0]["physicalLocation"];
+            assert_eq!(location["region"]["startLine"], 1);
+            assert_eq!(
+                location["artifactLocation"]["uri"],
+                "src/my%20dir/%E6%97%A5%E6%9C%AC.rs"
+            );
+        }
+    }
+}
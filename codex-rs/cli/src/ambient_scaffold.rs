@@ -0,0 +1,236 @@
+use std::fs;
+use std::path::Path;
+
+/// 新規ファイルの構成チェックのレビュー名
+pub const SCAFFOLD_REVIEW_NAME: &str = "新規ファイルの構成チェック";
+
+/// 比較用に列挙する同じディレクトリのファイル数の上限
+const MAX_SIBLINGS: usize = 30;
+
+/// 新規ファイルの配置・命名・登録・テストを確認するための情報
+///
+/// diffのレビューでは分からない、周囲のファイルとの関係を事前に集めてモデルに渡す。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScaffoldContext {
+    /// 同じディレクトリにある既存のファイル名
+    pub siblings: Vec<String>,
+    /// モジュールを登録するファイルと、登録されているか（確認できる言語のみ）
+    pub registration: Option<(String, bool)>,
+    /// 対応するテストとして探したパス
+    pub test_candidates: Vec<String>,
+    /// テストが見つかったか（ファイル内のテストを含む）
+    pub has_test: bool,
+}
+
+impl ScaffoldContext {
+    /// `root`からの相対パス`path`の新規ファイルについて情報を集める
+    pub fn collect(root: &Path, path: &str, content: &str) -> Self {
+        let path = Path::new(path);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+        let mut siblings: Vec<String> = fs::read_dir(root.join(dir))
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+                    .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+                    .filter(|name| name != file_name)
+                    .collect()
+            })
+            .unwrap_or_default();
+        siblings.sort();
+        siblings.truncate(MAX_SIBLINGS);
+
+        let registration = match ext {
+            "rs" => rust_registration(root, dir, file_name, stem),
+            "js" | "jsx" | "ts" | "tsx" => index_registration(root, dir, stem),
+            "py" => python_registration(root, dir),
+            _ => None,
+        };
+
+        let test_candidates = test_candidates(dir, stem, ext);
+        let has_test =
+            inline_tests(ext, content) || test_candidates.iter().any(|c| root.join(c).exists());
+
+        Self {
+            siblings,
+            registration,
+            test_candidates,
+            has_test,
+        }
+    }
+
+    /// モデルに渡すプロンプトを作成する
+    pub fn render_prompt(&self, file_path: &str, language: &str, content: &str) -> String {
+        let siblings = if self.siblings.is_empty() {
+            "（なし）".to_string()
+        } else {
+            self.siblings.join(", ")
+        };
+        let registration = match &self.registration {
+            Some((file, true)) => format!("{file} に登録済み"),
+            Some((file, false)) => format!("{file} に登録されていない"),
+            None => "確認対象外".to_string(),
+        };
+        let tests = if self.has_test {
+            "あり".to_string()
+        } else if self.test_candidates.is_empty() {
+            "見つからない".to_string()
+        } else {
+            format!(
+                "見つからない（確認したパス: {}）",
+                self.test_candidates.join(", ")
+            )
+        };
+        format!(
+            "あなたはコードレビューアシスタントです。`{file_path}`（{language}）は新しく追加されたファイルです。diffではなく、プロジェクトの構成との整合性を以下の観点で日本語で報告してください：\n\n1. モジュールの配置（このディレクトリに置くのが適切か）\n2. 同じディレクトリのファイルと比べた命名規則の一貫性\n3. mod宣言・export・`__init__.py`などへの登録漏れ\n4. 対応するテストファイルの有無\n\n問題がない場合は『構成上の問題は見つかりませんでした』と答えてください。\n\n## 調査結果\n\n- 同じディレクトリのファイル: {siblings}\n- モジュールの登録: {registration}\n- テスト: {tests}\n\n---\n\n{content}"
+        )
+    }
+}
+
+/// Rustのファイルが親モジュールに`mod`で宣言されているか
+fn rust_registration(
+    root: &Path,
+    dir: &Path,
+    file_name: &str,
+    stem: &str,
+) -> Option<(String, bool)> {
+    // クレートのルートや、個別にコンパイルされるディレクトリは対象外
+    if matches!(file_name, "lib.rs" | "main.rs" | "build.rs")
+        || dir.components().any(|c| {
+            matches!(
+                c.as_os_str().to_str(),
+                Some("tests" | "benches" | "examples" | "bin")
+            )
+        })
+    {
+        return None;
+    }
+    // `foo/mod.rs`は`foo`モジュールとして1つ上のディレクトリで宣言される
+    let (module, dir) = if file_name == "mod.rs" {
+        let module = dir.file_name()?.to_str()?;
+        (module, dir.parent()?)
+    } else {
+        (stem, dir)
+    };
+    let mut parents = vec![dir.join("lib.rs"), dir.join("main.rs"), dir.join("mod.rs")];
+    if let (Some(parent), Some(name)) = (dir.parent(), dir.file_name()) {
+        parents.push(parent.join(name).with_extension("rs"));
+    }
+    let parent = parents.into_iter().find(|p| root.join(p).is_file())?;
+    let source = fs::read_to_string(root.join(&parent)).ok()?;
+    let declared = source.lines().any(|line| {
+        let line = line.trim_start();
+        let line = line
+            .strip_prefix("pub(crate) ")
+            .or_else(|| line.strip_prefix("pub "))
+            .unwrap_or(line);
+        line.strip_prefix("mod ")
+            .map(|rest| rest.trim_end_matches([';', ' ', '{']))
+            == Some(module)
+    });
+    Some((parent.display().to_string(), declared))
+}
+
+/// JavaScript/TypeScriptのファイルが同じディレクトリの`index`から公開されているか
+fn index_registration(root: &Path, dir: &Path, stem: &str) -> Option<(String, bool)> {
+    if stem == "index" {
+        return None;
+    }
+    let index = ["index.ts", "index.tsx", "index.js", "index.jsx"]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|p| root.join(p).is_file())?;
+    let source = fs::read_to_string(root.join(&index)).ok()?;
+    let exported =
+        source.contains(&format!("./{stem}'")) || source.contains(&format!("./{stem}\""));
+    Some((index.display().to_string(), exported))
+}
+
+/// Pythonのファイルのディレクトリがパッケージ（`__init__.py`あり）か
+fn python_registration(root: &Path, dir: &Path) -> Option<(String, bool)> {
+    if dir.as_os_str().is_empty() {
+        return None;
+    }
+    let init = dir.join("__init__.py");
+    Some((init.display().to_string(), root.join(&init).is_file()))
+}
+
+/// 言語の慣習に従ったテストファイルの候補
+fn test_candidates(dir: &Path, stem: &str, ext: &str) -> Vec<String> {
+    let candidates = match ext {
+        "rs" => vec![
+            dir.join(format!("{stem}_tests.rs")),
+            dir.join(stem).join("tests.rs"),
+            Path::new("tests").join(format!("{stem}.rs")),
+        ],
+        "go" => vec![dir.join(format!("{stem}_test.go"))],
+        "py" => vec![
+            dir.join(format!("test_{stem}.py")),
+            dir.join(format!("{stem}_test.py")),
+            Path::new("tests").join(format!("test_{stem}.py")),
+        ],
+        "js" | "jsx" | "ts" | "tsx" => vec![
+            dir.join(format!("{stem}.test.{ext}")),
+            dir.join(format!("{stem}.spec.{ext}")),
+            dir.join("__tests__").join(format!("{stem}.test.{ext}")),
+        ],
+        _ => vec![],
+    };
+    candidates
+        .into_iter()
+        .map(|p| p.display().to_string())
+        .collect()
+}
+
+/// ファイル内にテストが書かれているか
+fn inline_tests(ext: &str, content: &str) -> bool {
+    match ext {
+        "rs" => content.contains("#[cfg(test)]") || content.contains("#[test]"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn detects_missing_rust_mod_declaration() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "pub mod config;\n").unwrap();
+        fs::write(dir.path().join("src/config.rs"), "").unwrap();
+
+        let context = ScaffoldContext::collect(dir.path(), "src/parser.rs", "fn parse() {}");
+        assert_eq!(context.siblings, vec!["config.rs", "lib.rs"]);
+        assert_eq!(
+            context.registration,
+            Some(("src/lib.rs".to_string(), false))
+        );
+        assert!(!context.has_test);
+
+        let context = ScaffoldContext::collect(dir.path(), "src/config.rs", "#[cfg(test)]");
+        assert_eq!(context.registration, Some(("src/lib.rs".to_string(), true)));
+        assert!(context.has_test);
+    }
+
+    #[test]
+    fn finds_sibling_test_files() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("web")).unwrap();
+        fs::write(dir.path().join("web/index.ts"), "export * from './api';\n").unwrap();
+        fs::write(dir.path().join("web/api.test.ts"), "").unwrap();
+
+        let context = ScaffoldContext::collect(dir.path(), "web/api.ts", "");
+        assert_eq!(
+            context.registration,
+            Some(("web/index.ts".to_string(), true))
+        );
+        assert!(context.has_test);
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+use crate::ambient_config::SchedulerSettings;
+
+/// モデル呼び出しの優先度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Web UIやgRPCからの質問（対話的）
+    Chat,
+    /// 定期チェックのレビュー（バックグラウンド）
+    Review,
+}
+
+/// 共有のモデルへの呼び出しを順番待ちさせるスケジューラー
+///
+/// 同時に実行できる呼び出し数を制限し、空きが出たら待っている質問を
+/// レビューより優先して実行する。両方が待っている場合は`chat_weight`対
+/// `review_weight`の比率で割り当てる（`review_weight = 0`なら質問を常に優先）。
+#[derive(Debug)]
+pub struct ModelScheduler {
+    state: Mutex<SchedulerState>,
+    chat_weight: u64,
+    review_weight: u64,
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    available: usize,
+    chat: VecDeque<oneshot::Sender<()>>,
+    review: VecDeque<oneshot::Sender<()>>,
+    chat_served: u64,
+    review_served: u64,
+}
+
+/// 呼び出しの実行権（破棄すると次の待ちに引き渡す）
+#[derive(Debug)]
+pub struct SchedulerPermit<'a> {
+    scheduler: &'a ModelScheduler,
+}
+
+impl Drop for SchedulerPermit<'_> {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+/// 順番待ちの途中で取り消された場合に、受け取り済みの実行権を返す
+struct Waiter<'a> {
+    rx: Option<oneshot::Receiver<()>>,
+    scheduler: &'a ModelScheduler,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if let Some(mut rx) = self.rx.take() {
+            rx.close();
+            if rx.try_recv().is_ok() {
+                self.scheduler.release();
+            }
+        }
+    }
+}
+
+impl ModelScheduler {
+    pub fn new(settings: &SchedulerSettings) -> Self {
+        Self {
+            state: Mutex::new(SchedulerState {
+                available: settings.max_concurrent_requests.max(1),
+                chat: VecDeque::new(),
+                review: VecDeque::new(),
+                chat_served: 0,
+                review_served: 0,
+            }),
+            chat_weight: u64::from(settings.chat_weight),
+            review_weight: u64::from(settings.review_weight),
+        }
+    }
+
+    /// 実行権を取得する（空きがなければ優先度に従って待つ）
+    pub async fn acquire(&self, priority: Priority) -> SchedulerPermit<'_> {
+        let rx = {
+            let Ok(mut state) = self.state.lock() else {
+                return SchedulerPermit { scheduler: self };
+            };
+            if state.available > 0 {
+                state.available -= 1;
+                return SchedulerPermit { scheduler: self };
+            }
+            let (tx, rx) = oneshot::channel();
+            match priority {
+                Priority::Chat => state.chat.push_back(tx),
+                Priority::Review => state.review.push_back(tx),
+            }
+            rx
+        };
+
+        let mut waiter = Waiter {
+            rx: Some(rx),
+            scheduler: self,
+        };
+        if let Some(rx) = waiter.rx.as_mut() {
+            let _ = rx.await;
+        }
+        waiter.rx = None;
+        SchedulerPermit { scheduler: self }
+    }
+
+    /// 実行権を次の待ちに引き渡す（待ちがなければ空きに戻す）
+    fn release(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        loop {
+            let next = match (state.chat.is_empty(), state.review.is_empty()) {
+                (true, true) => {
+                    state.available += 1;
+                    state.chat_served = 0;
+                    state.review_served = 0;
+                    return;
+                }
+                (false, true) => Priority::Chat,
+                (true, false) => Priority::Review,
+                (false, false) => {
+                    if state.chat_served * self.review_weight
+                        <= state.review_served * self.chat_weight
+                    {
+                        Priority::Chat
+                    } else {
+                        Priority::Review
+                    }
+                }
+            };
+            let waiter = match next {
+                Priority::Chat => state.chat.pop_front(),
+                Priority::Review => state.review.pop_front(),
+            };
+            // 待ちが取り消されていれば次に進む
+            if let Some(waiter) = waiter
+                && waiter.send(()).is_ok()
+            {
+                match next {
+                    Priority::Chat => state.chat_served += 1,
+                    Priority::Review => state.review_served += 1,
+                }
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::task::yield_now;
+
+    fn settings(chat_weight: u32, review_weight: u32) -> SchedulerSettings {
+        SchedulerSettings {
+            max_concurrent_requests: 1,
+            chat_weight,
+            review_weight,
+        }
+    }
+
+    /// 1件実行中の状態で`queued`の順に待たせ、実行された順序を返す
+    async fn run_order(scheduler: &ModelScheduler, queued: &[Priority]) -> Vec<Priority> {
+        let order = Mutex::new(Vec::new());
+        let first = scheduler.acquire(Priority::Review).await;
+        let waiters = queued.iter().enumerate().map(|(i, &priority)| {
+            let order = &order;
+            async move {
+                // 並び順どおりに待ちに入るよう、順番にずらす
+                for _ in 0..i {
+                    yield_now().await;
+                }
+                let _permit = scheduler.acquire(priority).await;
+                order.lock().unwrap().push(priority);
+                yield_now().await;
+            }
+        });
+        let release = async move {
+            for _ in 0..=queued.len() {
+                yield_now().await;
+            }
+            drop(first);
+        };
+        futures::join!(futures::future::join_all(waiters), release);
+        order.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn chat_preempts_queued_reviews() {
+        let scheduler = ModelScheduler::new(&settings(1, 0));
+        let order = run_order(
+            &scheduler,
+            &[Priority::Review, Priority::Review, Priority::Chat],
+        )
+        .await;
+        assert_eq!(
+            order,
+            vec![Priority::Chat, Priority::Review, Priority::Review]
+        );
+    }
+
+    #[tokio::test]
+    async fn weights_let_reviews_through() {
+        let scheduler = ModelScheduler::new(&settings(1, 1));
+        let order = run_order(
+            &scheduler,
+            &[
+                Priority::Chat,
+                Priority::Chat,
+                Priority::Review,
+                Priority::Review,
+            ],
+        )
+        .await;
+        assert_eq!(
+            order,
+            vec![
+                Priority::Chat,
+                Priority::Review,
+                Priority::Chat,
+                Priority::Review
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiters_do_not_leak_permits() {
+        let scheduler = ModelScheduler::new(&settings(1, 0));
+        let first = scheduler.acquire(Priority::Review).await;
+        {
+            let cancelled = scheduler.acquire(Priority::Chat);
+            futures::pin_mut!(cancelled);
+            assert!(futures::poll!(cancelled.as_mut()).is_pending());
+        }
+        drop(first);
+        let _permit = scheduler.acquire(Priority::Review).await;
+    }
+}
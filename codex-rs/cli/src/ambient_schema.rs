@@ -0,0 +1,133 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+use std::fs;
+use std::path::Path;
+
+use crate::ambient_annotations::AnnotationsFile;
+use crate::ambient_config::AmbientConfig;
+use crate::ambient_project_config::ProjectConfig;
+
+/// スキーマを出力するファイルの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaTarget {
+    /// ~/.codex/ambient.toml
+    Global,
+    /// .ambient/config.toml
+    Project,
+    /// .ambient/annotations.json（エディタのプラグイン向け）
+    Annotations,
+}
+
+impl SchemaTarget {
+    /// `--out`で書き出す際のファイル名
+    pub fn file_name(self) -> &'static str {
+        match self {
+            SchemaTarget::Global => "ambient.schema.json",
+            SchemaTarget::Project => "config.schema.json",
+            SchemaTarget::Annotations => "annotations.schema.json",
+        }
+    }
+
+    /// serdeの型から生成したJSON Schema
+    pub fn schema(self) -> RootSchema {
+        match self {
+            SchemaTarget::Global => schema_for!(AmbientConfig),
+            SchemaTarget::Project => schema_for!(ProjectConfig),
+            SchemaTarget::Annotations => schema_for!(AnnotationsFile),
+        }
+    }
+}
+
+/// JSON Schemaを整形済みの文字列として生成する
+pub fn schema_json(target: SchemaTarget) -> Result<String> {
+    let mut json = serde_json::to_string_pretty(&target.schema())?;
+    json.push('\n');
+    Ok(json)
+}
+
+/// すべてのスキーマを指定ディレクトリに書き出す
+pub fn write_schemas(out_dir: &Path) -> Result<()> {
+    fs::create_dir_all(out_dir)?;
+    for target in [
+        SchemaTarget::Global,
+        SchemaTarget::Project,
+        SchemaTarget::Annotations,
+    ] {
+        let path = out_dir.join(target.file_name());
+        fs::write(&path, schema_json(target)?)?;
+        println!("✓ スキーマを書き出しました: {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_auth::ApiToken;
+    use crate::ambient_auth::Scope;
+    use crate::ambient_endpoint::ProviderSetting;
+    use std::collections::BTreeSet;
+
+    /// スキーマの`properties`と、すべての項目を埋めた値をシリアライズしたキーが一致すること
+    ///
+    /// 省略可能な項目を埋め忘れた場合もスキーマ側のキーが余るため失敗する。
+    fn assert_schema_matches_fields<T: serde::Serialize>(value: &T, target: SchemaTarget) {
+        let schema = serde_json::to_value(target.schema()).unwrap();
+        let properties: BTreeSet<&String> =
+            schema["properties"].as_object().unwrap().keys().collect();
+        let serialized = serde_json::to_value(value).unwrap();
+        let fields: BTreeSet<&String> = serialized.as_object().unwrap().keys().collect();
+        assert_eq!(
+            properties, fields,
+            "the {target:?} schema and the populated fixture have different keys"
+        );
+    }
+
+    #[test]
+    fn global_schema_covers_config() {
+        let config = AmbientConfig {
+            provider: Some("openai".to_string()),
+            model: Some("gpt-4o".to_string()),
+            profile: Some("fast".to_string()),
+            profiles: [("fast".to_string(), Default::default())].into(),
+            projects: vec!["~/src/api".to_string()],
+            ..AmbientConfig::default()
+        };
+        assert_schema_matches_fields(&config, SchemaTarget::Global);
+    }
+
+    #[test]
+    fn project_schema_covers_config() {
+        let config = ProjectConfig {
+            provider: Some(ProviderSetting::Id("openai".to_string())),
+            model: Some("gpt-4o".to_string()),
+            listen: Some("unix:/tmp/ambient.sock".to_string()),
+            grpc_port: Some(50051),
+            timezone: Some("Asia/Tokyo".to_string()),
+            diff_base: Some("origin/main".to_string()),
+            access_token: Some("owner-secret".to_string()),
+            viewer_token: Some("viewer-secret".to_string()),
+            api_tokens: vec![ApiToken {
+                name: Some("ci".to_string()),
+                token: "ci-secret".to_string(),
+                scopes: vec![Scope::Read],
+            }],
+            base_instructions: Some("簡潔に".to_string()),
+            pinned_context: vec!["docs/ARCHITECTURE.md".to_string()],
+            file_extensions: Some(vec!["rs".to_string()]),
+            ..ProjectConfig::default()
+        };
+        assert_schema_matches_fields(&config, SchemaTarget::Project);
+    }
+
+    #[test]
+    fn annotations_schema_covers_file() {
+        let annotations = AnnotationsFile {
+            run_id: Some("run".to_string()),
+            ..AnnotationsFile::default()
+        };
+        assert_schema_matches_fields(&annotations, SchemaTarget::Annotations);
+    }
+}
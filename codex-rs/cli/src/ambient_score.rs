@@ -0,0 +1,345 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::ambient_confidence::Severity;
+use crate::ambient_git::run_git_command;
+use crate::ambient_history::ReviewHistory;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EventEnvelope;
+
+/// 指摘の重みがこの値（変更100行あたり）のときにスコアが50になる
+const HALF_SCORE_DENSITY: u64 = 10;
+
+/// 変更が少ないときに1件の指摘でスコアが大きく下がらないよう、変更行数をこの値以上とみなす
+const MIN_CHANGED_LINES: u64 = 50;
+
+/// チェックサイクルごとの健全性スコア（`GET /api/quality-score`で取得できる）
+///
+/// 未コミットの変更に残っている未対応の指摘を重大度で重み付けし、変更行数で割って0〜100にする。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct QualityScore {
+    /// 計算したチェックサイクルのID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = Uuid)]
+    pub run_id: Option<Uuid>,
+    /// 計算した時刻（RFC3339、UTC）
+    pub ts: String,
+    /// 0〜100（100は未対応の指摘なし）
+    pub score: u8,
+    /// 未対応の指摘の数
+    pub open_findings: usize,
+    /// 重大度で重み付けした未対応の指摘の合計
+    pub weighted_findings: u64,
+    /// `HEAD`からの変更行数（追加と削除、追跡していないファイルを含む）
+    pub changed_lines: u64,
+}
+
+/// 重大度の重み（重大度のない指摘は`low`と同じ）
+pub fn severity_weight(severity: Option<Severity>) -> u64 {
+    match severity {
+        Some(Severity::Critical) => 10,
+        Some(Severity::High) => 5,
+        Some(Severity::Medium) => 2,
+        Some(Severity::Low) | None => 1,
+    }
+}
+
+/// 重み付けした指摘の数と変更行数からスコアを計算する
+///
+/// 変更100行あたりの重みを`d`として`100 * 10 / (10 + d)`（`d = 0`なら100）。
+pub fn compute_score(weighted_findings: u64, changed_lines: u64) -> u8 {
+    let density = weighted_findings * 100 / changed_lines.max(MIN_CHANGED_LINES);
+    (100 * HALF_SCORE_DENSITY / (HALF_SCORE_DENSITY + density)) as u8
+}
+
+/// `HEAD`からのファイルごとの変更行数（追跡していないファイルは行数）
+pub fn changed_lines(root: &Path) -> HashMap<String, u64> {
+    let mut lines = HashMap::new();
+    if let Ok(numstat) = run_git_command(
+        &["-c", "core.quotePath=false", "diff", "HEAD", "--numstat"],
+        root,
+    ) {
+        for line in numstat.lines() {
+            let mut columns = line.splitn(3, '\t');
+            let (Some(added), Some(deleted), Some(path)) =
+                (columns.next(), columns.next(), columns.next())
+            else {
+                continue;
+            };
+            // バイナリファイルは`-`
+            let count = added.parse::<u64>().unwrap_or(0) + deleted.parse::<u64>().unwrap_or(0);
+            lines.insert(path.to_string(), count);
+        }
+    }
+    if let Ok(untracked) = run_git_command(
+        &[
+            "-c",
+            "core.quotePath=false",
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+        ],
+        root,
+    ) {
+        for path in untracked.lines().filter(|path| !path.is_empty()) {
+            let count =
+                fs::read_to_string(root.join(path)).map_or(0, |content| content.lines().count());
+            lines.insert(path.to_string(), count as u64);
+        }
+    }
+    lines
+}
+
+/// ファイルの最新のチェックサイクルで出た指摘
+#[derive(Debug, Clone, Default)]
+struct FileFindings {
+    run_id: Option<Uuid>,
+    /// ジョブID、レビュー名、重大度
+    findings: Vec<(Option<Uuid>, String, Option<Severity>)>,
+}
+
+/// チェックサイクルのイベントから、ファイルごとの最新の指摘を追跡する
+#[derive(Debug, Default)]
+pub struct ScoreTracker {
+    files: HashMap<String, FileFindings>,
+}
+
+impl ScoreTracker {
+    /// 指摘を記録する（同じファイルで新しいサイクルの指摘が来たら前のサイクルの指摘は捨てる）
+    pub fn record_finding(
+        &mut self,
+        path: &str,
+        run_id: Option<Uuid>,
+        job_id: Option<Uuid>,
+        review: &str,
+        severity: Option<Severity>,
+    ) {
+        let file = self.files.entry(path.to_string()).or_default();
+        if file.run_id != run_id {
+            *file = FileFindings {
+                run_id,
+                findings: Vec::new(),
+            };
+        }
+        file.findings.push((job_id, review.to_string(), severity));
+    }
+
+    /// サイクルで分析したのに指摘がなかったファイルの指摘を消す
+    pub fn finish_cycle(&mut self, run_id: Option<Uuid>, analyzed: &[String]) {
+        for path in analyzed {
+            if self
+                .files
+                .get(path)
+                .is_some_and(|file| file.run_id != run_id)
+            {
+                self.files.remove(path);
+            }
+        }
+    }
+
+    /// 変更中のファイルに残っている未対応の指摘からスコアを計算する
+    pub fn score(
+        &self,
+        changed: &HashMap<String, u64>,
+        history: &ReviewHistory,
+        run_id: Option<Uuid>,
+        ts: String,
+    ) -> QualityScore {
+        let mut open_findings = 0;
+        let mut weighted_findings = 0;
+        for (path, file) in &self.files {
+            if !changed.contains_key(path) {
+                continue;
+            }
+            let resolved: HashSet<(Uuid, String)> = history
+                .file(path)
+                .map(|history| {
+                    history
+                        .resolutions
+                        .into_iter()
+                        .map(|resolution| (resolution.job_id, resolution.review))
+                        .collect()
+                })
+                .unwrap_or_default();
+            for (job_id, review, severity) in &file.findings {
+                if job_id.is_some_and(|job_id| resolved.contains(&(job_id, review.clone()))) {
+                    continue;
+                }
+                open_findings += 1;
+                weighted_findings += severity_weight(*severity);
+            }
+        }
+        let changed_lines = changed.values().sum();
+        QualityScore {
+            run_id,
+            ts,
+            score: compute_score(weighted_findings, changed_lines),
+            open_findings,
+            weighted_findings,
+            changed_lines,
+        }
+    }
+}
+
+/// チェックサイクルが終わるたびにスコアを計算し、`latest`を更新する
+pub async fn track_quality_score(
+    mut rx: broadcast::Receiver<EventEnvelope>,
+    root: PathBuf,
+    history: Arc<ReviewHistory>,
+    latest: Arc<Mutex<Option<QualityScore>>>,
+) {
+    let mut tracker = ScoreTracker::default();
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: event @ (AmbientEvent::Finding(_) | AmbientEvent::Findings(_)),
+                project: None,
+                ..
+            }) => {
+                for finding in event.findings() {
+                    tracker.record_finding(
+                        &finding.file_path,
+                        finding.ids.run_id,
+                        finding.ids.job_id,
+                        &finding.review,
+                        finding.severity,
+                    );
+                }
+            }
+            Ok(EventEnvelope {
+                event: AmbientEvent::CycleSummary(summary),
+                project: None,
+                ts,
+                ..
+            }) => {
+                tracker.finish_cycle(summary.run_id, &summary.analyzed);
+                let git_root = root.clone();
+                let Ok(changed) =
+                    tokio::task::spawn_blocking(move || changed_lines(&git_root)).await
+                else {
+                    continue;
+                };
+                let score = tracker.score(&changed, &history, summary.run_id, ts);
+                if let Ok(mut latest) = latest.lock() {
+                    *latest = Some(score);
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// READMEに貼るバッジ（スコアがまだなければ`unknown`）
+pub fn render_badge(score: Option<u8>) -> String {
+    let (value, color) = match score {
+        Some(score) if score >= 90 => (score.to_string(), "#4c1"),
+        Some(score) if score >= 75 => (score.to_string(), "#97ca00"),
+        Some(score) if score >= 50 => (score.to_string(), "#dfb317"),
+        Some(score) => (score.to_string(), "#e05d44"),
+        None => ("unknown".to_string(), "#9f9f9f"),
+    };
+    let label = "ambient health";
+    let label_width = label.len() * 7 + 10;
+    let value_width = value.len() * 7 + 10;
+    let width = label_width + value_width;
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"20\" role=\"img\" aria-label=\"{label}: {value}\">\
+<title>{label}: {value}</title>\
+<rect width=\"{label_width}\" height=\"20\" fill=\"#555\"/>\
+<rect x=\"{label_width}\" width=\"{value_width}\" height=\"20\" fill=\"{color}\"/>\
+<g fill=\"#fff\" text-anchor=\"middle\" font-family=\"Verdana,DejaVu Sans,sans-serif\" font-size=\"11\">\
+<text x=\"{}\" y=\"14\">{label}</text><text x=\"{}\" y=\"14\">{value}</text></g></svg>\n",
+        label_width / 2,
+        label_width + value_width / 2
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_history::Resolution;
+    use crate::ambient_history::ResolutionStatus;
+    use crate::ambient_test_support::TempRepo;
+
+    #[test]
+    fn scores_weighted_findings_per_changed_lines() {
+        assert_eq!(compute_score(0, 0), 100);
+        assert_eq!(compute_score(10, 100), 50);
+        assert_eq!(compute_score(1, 100), 90);
+        // 変更が少なくても50行とみなす
+        assert_eq!(compute_score(5, 1), 50);
+        assert_eq!(compute_score(1000, 100), 0);
+    }
+
+    #[test]
+    fn keeps_only_latest_unresolved_findings_of_changed_files() {
+        let (first, second) = (Some(Uuid::new_v4()), Some(Uuid::new_v4()));
+        let resolved_job = Uuid::new_v4();
+        let mut tracker = ScoreTracker::default();
+        tracker.record_finding("a.rs", first, None, "構文", Some(Severity::Critical));
+        tracker.record_finding("b.rs", first, None, "構文", Some(Severity::High));
+        tracker.record_finding("c.rs", first, Some(resolved_job), "構文", None);
+        tracker.finish_cycle(first, &["a.rs".into(), "b.rs".into(), "c.rs".into()]);
+        // 次のサイクルでa.rsの指摘は軽くなり、b.rsは直った
+        tracker.record_finding("a.rs", second, None, "構文", Some(Severity::Medium));
+        tracker.finish_cycle(second, &["a.rs".into(), "b.rs".into()]);
+
+        let history = ReviewHistory::default();
+        history.record_resolution(
+            "c.rs",
+            Resolution::new(
+                resolved_job,
+                "構文".to_string(),
+                ResolutionStatus::FalsePositive,
+                None,
+                None,
+            ),
+        );
+        let changed = HashMap::from([
+            ("a.rs".to_string(), 40),
+            ("b.rs".to_string(), 60),
+            ("c.rs".to_string(), 0),
+        ]);
+        let score = tracker.score(&changed, &history, second, String::new());
+        assert_eq!((score.open_findings, score.weighted_findings), (1, 2));
+        assert_eq!(score.changed_lines, 100);
+        assert_eq!(score.score, 83);
+
+        // 変更中でないファイルの指摘は数えない
+        let score = tracker.score(&HashMap::new(), &history, second, String::new());
+        assert_eq!(score.open_findings, 0);
+    }
+
+    #[test]
+    fn counts_changed_and_untracked_lines() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("a.txt", "one\ntwo\n").unwrap();
+        repo.add(&["a.txt"]).unwrap();
+        repo.commit("initial").unwrap();
+        repo.write("a.txt", "one\nthree\n").unwrap();
+        repo.write("new.txt", "x\ny\nz\n").unwrap();
+
+        let lines = changed_lines(repo.path());
+        assert_eq!(lines["a.txt"], 2);
+        assert_eq!(lines["new.txt"], 3);
+    }
+
+    #[test]
+    fn renders_badge_colors() {
+        assert!(render_badge(Some(95)).contains("fill=\"#4c1\""));
+        assert!(render_badge(Some(40)).contains(">40</text>"));
+        assert!(render_badge(None).contains("unknown"));
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+
+/// 設定の値をOSのキーチェーンから読むときの接頭辞（`token = "keyring:ambient/github"`）
+pub const KEYRING_PREFIX: &str = "keyring:";
+
+/// サービス名を省略したときのキーチェーンのサービス名
+const DEFAULT_SERVICE: &str = "ambient";
+
+/// キーチェーンの項目の名前（`ambient/github`のようにサービス名とアカウント名を`/`でつなぐ）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretName {
+    pub service: String,
+    pub account: String,
+}
+
+impl SecretName {
+    /// `ambient/github`または`github`（サービス名は`ambient`）
+    pub fn parse(name: &str) -> Result<Self> {
+        let name = name.trim();
+        let (service, account) = name.split_once('/').unwrap_or((DEFAULT_SERVICE, name));
+        if service.is_empty() || account.is_empty() {
+            bail!("秘密の名前は`サービス/アカウント`の形式で指定してください: {name}");
+        }
+        Ok(Self {
+            service: service.to_string(),
+            account: account.to_string(),
+        })
+    }
+
+    /// 設定ファイルに書く参照（`keyring:ambient/github`）
+    pub fn reference(&self) -> String {
+        format!("{KEYRING_PREFIX}{}/{}", self.service, self.account)
+    }
+
+    fn entry(&self) -> Result<keyring::Entry> {
+        keyring::Entry::new(&self.service, &self.account).context("キーチェーンを開けません")
+    }
+}
+
+/// `keyring:`で始まる値の、キーチェーンの項目の名前（それ以外の値はなし）
+pub fn keyring_reference(value: &str) -> Option<Result<SecretName>> {
+    value
+        .trim()
+        .strip_prefix(KEYRING_PREFIX)
+        .map(SecretName::parse)
+}
+
+/// 設定の値を解決する（`keyring:`で始まる値はキーチェーンから読み、それ以外はそのまま使う）
+///
+/// キーチェーンに項目がない場合と、空の値はなし。
+pub fn resolve_secret(value: &str) -> Result<Option<String>> {
+    let secret = match keyring_reference(value) {
+        Some(name) => get_secret(&name?)?,
+        None => Some(value.to_string()),
+    };
+    Ok(secret
+        .map(|secret| secret.trim().to_string())
+        .filter(|secret| !secret.is_empty()))
+}
+
+/// キーチェーンの項目を読む（項目がなければなし）
+pub fn get_secret(name: &SecretName) -> Result<Option<String>> {
+    match name.entry()?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => {
+            Err(e).with_context(|| format!("キーチェーンから{}を読めません", name.reference()))
+        }
+    }
+}
+
+/// キーチェーンに保存する（同じ名前の項目は上書きする）
+pub fn set_secret(name: &SecretName, secret: &str) -> Result<()> {
+    name.entry()?
+        .set_password(secret)
+        .with_context(|| format!("キーチェーンに{}を保存できません", name.reference()))
+}
+
+/// キーチェーンから削除する（項目がなければ`false`）
+pub fn delete_secret(name: &SecretName) -> Result<bool> {
+    match name.entry()?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => {
+            Err(e).with_context(|| format!("キーチェーンから{}を削除できません", name.reference()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names_with_the_default_service() {
+        let name = SecretName::parse("ambient/github").unwrap();
+        assert_eq!(name.service, "ambient");
+        assert_eq!(name.account, "github");
+        assert_eq!(
+            SecretName::parse("slack").unwrap(),
+            SecretName {
+                service: "ambient".to_string(),
+                account: "slack".to_string(),
+            }
+        );
+        assert_eq!(
+            SecretName::parse("work/jira").unwrap().reference(),
+            "keyring:work/jira"
+        );
+        assert!(SecretName::parse("ambient/").is_err());
+        assert!(SecretName::parse("").is_err());
+
+        assert!(keyring_reference("https://hooks.slack.com/services/x").is_none());
+        assert_eq!(
+            keyring_reference("keyring:ambient/github")
+                .unwrap()
+                .unwrap()
+                .account,
+            "github"
+        );
+        // キーチェーンの参照でない値はそのまま使う
+        assert_eq!(
+            resolve_secret(" https://example.com/hook ")
+                .unwrap()
+                .as_deref(),
+            Some("https://example.com/hook")
+        );
+        assert_eq!(resolve_secret("").unwrap(), None);
+    }
+}
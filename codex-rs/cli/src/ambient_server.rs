@@ -1,48 +1,525 @@
 use axum::{
-    Router,
+    Extension, Router,
     extract::{
-        State,
+        DefaultBodyLimit, Query, State,
         ws::{Message, WebSocket, WebSocketUpgrade},
     },
-    response::IntoResponse,
-    routing::get,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
 };
-use futures::{sink::SinkExt, stream::StreamExt};
+use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::broadcast;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, broadcast, mpsc};
+use uuid::Uuid;
+
+use crate::ambient_annotations::AnnotationsUpdate;
+use crate::ambient_api::BADGE_ENDPOINT;
+use crate::ambient_api::CHECK_ENDPOINT;
+use crate::ambient_api::CONFIG_ENDPOINT;
+use crate::ambient_api::DIFF_ENDPOINT;
+use crate::ambient_api::FILE_ENDPOINT;
+use crate::ambient_api::FILES_ROUTE;
+use crate::ambient_api::GITHUB_WEBHOOK_ENDPOINT;
+use crate::ambient_api::HISTORY_ENDPOINT;
+use crate::ambient_api::LAST_CYCLE_ENDPOINT;
+use crate::ambient_api::OPENAPI_ENDPOINT;
+use crate::ambient_api::OUTPUT_FORMAT_STATS_ENDPOINT;
+use crate::ambient_api::PATHS_ENDPOINT;
+use crate::ambient_api::PAUSE_ENDPOINT;
+use crate::ambient_api::QUALITY_SCORE_ENDPOINT;
+use crate::ambient_api::QUERY_ENDPOINT;
+use crate::ambient_api::RESUME_ENDPOINT;
+use crate::ambient_api::STATUS_ENDPOINT;
+use crate::ambient_api::SYMBOLS_ENDPOINT;
+use crate::ambient_api::VERSION_ENDPOINT;
+use crate::ambient_api::badge_handler;
+use crate::ambient_api::check_handler;
+use crate::ambient_api::config_handler;
+use crate::ambient_api::diff_handler;
+use crate::ambient_api::file_handler;
+use crate::ambient_api::file_history_handler;
+use crate::ambient_api::github_webhook_handler;
+use crate::ambient_api::history_handler;
+use crate::ambient_api::last_cycle_handler;
+use crate::ambient_api::openapi_handler;
+use crate::ambient_api::output_format_stats_handler;
+use crate::ambient_api::paths_handler;
+use crate::ambient_api::pause_handler;
+use crate::ambient_api::quality_score_handler;
+use crate::ambient_api::query_handler;
+use crate::ambient_api::resolution_handler;
+use crate::ambient_api::resume_handler;
+use crate::ambient_api::status_handler;
+use crate::ambient_api::symbols_handler;
+use crate::ambient_api::version_handler;
+use crate::ambient_auth::AccessTokens;
+use crate::ambient_auth::ClientKey;
+use crate::ambient_auth::Role;
+use crate::ambient_auth::Scope;
+use crate::ambient_auth::require_token;
+use crate::ambient_auth::scope_denied;
+use crate::ambient_budget::BudgetAllocation;
+use crate::ambient_check_run::CheckRunWebhook;
+use crate::ambient_confidence::Severity;
+use crate::ambient_cycle::CycleSummary;
+use crate::ambient_editor::EDITOR_ENDPOINT;
+use crate::ambient_editor::editor_websocket_handler;
+use crate::ambient_history::ReviewHistory;
+use crate::ambient_history::record_history;
+use crate::ambient_housekeeping::Reminder;
+use crate::ambient_limits::ConnectionLimiter;
+use crate::ambient_limits::ConnectionPermit;
+use crate::ambient_limits::ServerLimits;
+use crate::ambient_limits::limit_connections;
+use crate::ambient_limits::send_with_timeout;
+use crate::ambient_output_format::OutputFormatStats;
+use crate::ambient_safe_path::canonical_root;
+use crate::ambient_score::QualityScore;
+use crate::ambient_score::track_quality_score;
+use crate::ambient_snapshot::Snapshot;
+use crate::ambient_symbols::CrossReference;
+use crate::ambient_symbols::SymbolIndex;
+use crate::ambient_time::now_stamp;
+use crate::ambient_ui_assets::embedded_ui_handler;
+use crate::ambient_version::VERSION;
+use crate::ambient_wire::WireEncoding;
+
+/// `listen = "unix:<path>"`の接頭辞
+pub const UNIX_LISTEN_PREFIX: &str = "unix:";
+
+/// 表示名の最大文字数
+const MAX_DISPLAY_NAME_LEN: usize = 32;
+
+/// トークンなしで`POST /api/query`を使うクライアントのレート制限キー（すべてのリクエストで共有する）
+pub(crate) const ANONYMOUS_CLIENT: &str = "anonymous";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum AmbientEvent {
-    Analysis(String),
-    UserQuery(String),
-    QueryResponse(String), // 質問への回答を区別
+    Analysis(AnalysisMessage),
+    // ストリーミング中のモデルの回答の断片
+    AnalysisDelta(AnalysisDelta),
+    // 回答のストリーミングの終わり
+    AnalysisComplete(AnalysisComplete),
+    Finding(Finding),       // レビュー結果と変更前後のコード抜粋
+    Findings(Vec<Finding>), // 指摘の一覧を求めたレビューの結果（空なら指摘なし）
+    UserQuery(ChatMessage),
+    QueryResponse(ChatMessage), // 質問への回答を区別
     System(String),
-    ProjectRoot(String), // プロジェクトルートパス
+    ProjectRoot(String),            // プロジェクトルートパス
+    CycleSummary(CycleSummary),     // チェックサイクルごとの分析・スキップの集計
+    FindingRepeated(FindingRepeat), // クールダウン中に繰り返された指摘（配信済みの指摘の回数）
+    Reminder(Reminder),             // 未コミット・未pushの作業やupstreamからの遅れの知らせ
+    // `.ambient/annotations.json`を書き直した
+    AnnotationsUpdated(AnnotationsUpdate),
+    // 監視ループへの一時停止・再開の指示（Web UIやREST APIから）
+    Control(ControlCommand),
+}
+
+/// 発生時刻を付けたイベント（クライアントに配信する単位）
+///
+/// JSONではイベント本体と同じ階層に`ts`と`utc_offset`（と`project`）が入る。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    #[serde(flatten)]
+    pub event: AmbientEvent,
+    /// 発生時刻（RFC3339、UTC）
+    pub ts: String,
+    /// 表示用タイムゾーンのUTCからのオフセット（例: `+09:00`）
+    pub utc_offset: String,
+    /// `~/.codex/ambient.toml`の`projects`で追加したリポジトリの名前（起動したディレクトリのプロジェクトはなし）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+}
+
+impl EventEnvelope {
+    /// 現在時刻でイベントを包む
+    pub fn new(event: AmbientEvent) -> Self {
+        let (ts, utc_offset) = now_stamp();
+        Self {
+            event,
+            ts,
+            utc_offset,
+            project: None,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// イベントに発生時刻を付けて配信する
+pub trait EmitEvent {
+    /// 受信者が1つもいなければ`false`
+    fn emit(&self, event: AmbientEvent) -> bool;
+}
+
+impl EmitEvent for broadcast::Sender<EventEnvelope> {
+    fn emit(&self, event: AmbientEvent) -> bool {
+        self.send(EventEnvelope::new(event)).is_ok()
+    }
+}
+
+/// チェックサイクルとファイル分析ジョブの識別子
+///
+/// サイクルが並行して実行されても、クライアントがイベントを正しくまとめられるようにする。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunIds {
+    /// チェックサイクルのID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<Uuid>,
+    /// サイクル内の1ファイルの分析ジョブのID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<Uuid>,
+}
+
+impl RunIds {
+    /// 新しいチェックサイクルのIDを発行する
+    pub fn new_run() -> Self {
+        Self {
+            run_id: Some(Uuid::new_v4()),
+            job_id: None,
+        }
+    }
+
+    /// このサイクル内の新しいジョブのIDを発行する
+    pub fn new_job(self) -> Self {
+        Self {
+            job_id: Some(Uuid::new_v4()),
+            ..self
+        }
+    }
+}
+
+/// 分析の進捗メッセージ
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnalysisMessage {
+    #[serde(flatten)]
+    pub ids: RunIds,
+    pub text: String,
+    /// プロンプトが`max_prompt_tokens`を超えたときの、区分ごとのトークン数の配分
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<BudgetAllocation>,
+}
+
+/// ストリーミング中のモデルの回答の断片（`id`は1回の回答ごとに発行する）
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnalysisDelta {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub ids: RunIds,
+    /// 前回の断片に続く回答の文字列
+    pub text: String,
+}
+
+/// 回答のストリーミングの終わり（成功・失敗にかかわらず送る。結果は`Finding`などで別に届く）
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnalysisComplete {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub ids: RunIds,
+}
+
+/// 監視ループへの指示
+///
+/// JSONでは`{"Control":{"Pause":{"minutes":30}}}`と`{"Control":"Resume"}`。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlCommand {
+    /// 定期チェックとファイルの変更によるチェックを`minutes`分止める
+    Pause { minutes: u64 },
+    /// 一時停止を解除する
+    Resume,
+}
+
+/// 質問とその回答（誰の質問かを表示名で区別する）
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// 質問したクライアントの表示名（未指定の場合は`None`）
+    #[serde(default)]
+    pub user: Option<String>,
+    pub text: String,
+}
+
+impl ChatMessage {
+    pub fn new(user: Option<String>, text: String) -> Self {
+        Self { user, text }
+    }
+}
+
+/// 1つのレビューの結果
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Finding {
+    /// このレビューを実行したサイクルとジョブ
+    #[serde(flatten)]
+    pub ids: RunIds,
+    /// リポジトリルートからの相対パス
+    pub file_path: String,
+    /// レビューの名前
+    pub review: String,
+    /// モデルの回答（Markdown）
+    pub message: String,
+    /// 変更箇所ごとの変更前後のコード抜粋
+    #[serde(default)]
+    pub snapshots: Vec<Snapshot>,
+    /// `CODEOWNERS`によるファイルの所有者
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub owners: Vec<String>,
+    /// レビューが付けたポリシーのラベル（`security`、`compliance`、`perf`など）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// モデルの自己評価を過去の判断で補正した確信度（0〜100、回答に含まれなければなし）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<u8>,
+    /// モデルが自己評価した重大度（回答に含まれなければなし）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    /// 確信度が低いため人の確認が必要（UIでは折りたたみ、通知しない）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub needs_human_review: bool,
+    /// 回答したモデル
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 回答したモデルのプロバイダーID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// プロジェクト設定のレビューのプロンプトの雛形のハッシュ（組み込みのレビューではなし）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_hash: Option<String>,
+    /// 指摘した行（1始まり、指摘の一覧を求めたレビューのみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// モデルが提案した修正（指摘の一覧を求めたレビューのみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+    /// 変更された定義と、その名前を参照している箇所
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cross_references: Vec<CrossReference>,
+}
+
+/// 配信済みの指摘と同じ内容が繰り返されたこと
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FindingRepeat {
+    /// 最初に配信した指摘のサイクルとジョブ
+    #[serde(flatten)]
+    pub ids: RunIds,
+    pub file_path: String,
+    pub review: String,
+    /// 最初の指摘を含めた回数
+    pub count: u32,
 }
 
 impl AmbientEvent {
+    /// 分析の進捗メッセージのイベントを作る
+    pub fn analysis(ids: RunIds, text: impl Into<String>) -> Self {
+        AmbientEvent::Analysis(AnalysisMessage {
+            ids,
+            text: text.into(),
+            budget: None,
+        })
+    }
+
+    /// 区分ごとのトークン数の配分を知らせる分析の進捗メッセージのイベントを作る
+    pub fn budget(ids: RunIds, allocation: BudgetAllocation) -> Self {
+        AmbientEvent::Analysis(AnalysisMessage {
+            ids,
+            text: allocation.describe(),
+            budget: Some(allocation),
+        })
+    }
+
+    /// イベントに含まれるレビュー結果（`Finding`と`Findings`以外は空）
+    pub fn findings(&self) -> &[Finding] {
+        match self {
+            AmbientEvent::Finding(finding) => std::slice::from_ref(finding),
+            AmbientEvent::Findings(findings) => findings,
+            _ => &[],
+        }
+    }
+
+    /// イベントに含まれるレビュー結果を取り出す
+    pub fn into_findings(self) -> Vec<Finding> {
+        match self {
+            AmbientEvent::Finding(finding) => vec![finding],
+            AmbientEvent::Findings(findings) => findings,
+            _ => vec![],
+        }
+    }
+
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap_or_default()
     }
 }
 
-#[derive(Clone)]
-struct AppState {
-    tx: broadcast::Sender<AmbientEvent>,
-    project_root: String,
+/// クライアントごとの質問数を一定時間の窓で制限する
+pub(crate) struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    history: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            history: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// `key`の質問を1件記録し、上限内であれば`true`を返す
+    pub(crate) fn check(&self, key: &str) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+        let Ok(mut history) = self.history.lock() else {
+            return true;
+        };
+        let now = Instant::now();
+        // 窓の中に質問のないクライアントは忘れる（接続ごとのキーが溜まり続けないように）
+        history.retain(|_, entries| {
+            while entries
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= self.window)
+            {
+                entries.pop_front();
+            }
+            !entries.is_empty()
+        });
+        let entries = history.entry(key.to_string()).or_default();
+        if entries.len() >= self.limit as usize {
+            return false;
+        }
+        entries.push_back(now);
+        true
+    }
+}
+
+pub(crate) struct AppState {
+    pub(crate) tx: broadcast::Sender<EventEnvelope>,
+    pub(crate) project_root: String,
+    pub(crate) tokens: AccessTokens,
+    pub(crate) rate_limiter: RateLimiter,
+    /// 直近のチェックサイクルの集計
+    pub(crate) last_cycle: Mutex<Option<CycleSummary>>,
+    /// ファイルごとのレビュー履歴
+    pub(crate) history: Arc<ReviewHistory>,
+    /// JSON形式のレビューの回答の集計（監視ループと共有）
+    pub(crate) output_format_stats: Arc<OutputFormatStats>,
+    /// 直近のチェックサイクルの健全性スコア
+    pub(crate) quality_score: Arc<Mutex<Option<QualityScore>>>,
+    /// GitHubからのチェックの再実行の要求（秘密が設定されている場合のみ）
+    pub(crate) check_run_webhook: Option<CheckRunWebhook>,
+    /// シンボルの定義と参照の索引（監視ループと共有）
+    pub(crate) symbols: Arc<SymbolIndex>,
+    /// 即座にチェックを実行させる通知（監視ループが待つ）
+    pub(crate) trigger: Arc<Notify>,
+    /// リクエストと接続の上限
+    pub(crate) limits: ServerLimits,
+    /// 処理中のリクエストと開いているWebSocketの数
+    pub(crate) connections: ConnectionLimiter,
+}
+
+/// サーバーの起動設定
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    pub port: u16,
+    pub tokens: AccessTokens,
+    /// 1ユーザーあたり1分間に受け付ける質問数（0は無制限）
+    pub query_rate_limit_per_minute: u32,
+    /// 待ち受けアドレス（`unix:<path>`でUnixドメインソケット、未指定ならTCPの`port`）
+    pub listen: Option<String>,
+    /// JSON形式のレビューの回答の集計（監視ループが記録する）
+    pub output_format_stats: Arc<OutputFormatStats>,
+    /// ファイルごとのレビュー履歴（監視ループが確信度の補正に使う）
+    pub history: Arc<ReviewHistory>,
+    /// GitHubのチェックの再実行を受け付けるWebhook
+    pub check_run_webhook: Option<CheckRunWebhook>,
+    /// シンボルの定義と参照の索引（監視ループが更新する）
+    pub symbols: Arc<SymbolIndex>,
+    /// `POST /api/check`で通知し、監視ループに即座にチェックを実行させる
+    pub trigger: Arc<Notify>,
+    /// リクエストと接続の上限（`[server_limits]`）
+    pub limits: ServerLimits,
+}
+
+/// 監視ループのイベントから直近のチェックサイクルの集計を記録する
+async fn record_last_cycle(mut rx: broadcast::Receiver<EventEnvelope>, state: Arc<AppState>) {
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: AmbientEvent::CycleSummary(summary),
+                project: None,
+                ..
+            }) => {
+                if let Ok(mut last_cycle) = state.last_cycle.lock() {
+                    *last_cycle = Some(summary);
+                }
+            }
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
 }
 
 pub async fn run_server(
-    tx: broadcast::Sender<AmbientEvent>,
-    port: u16,
+    tx: broadcast::Sender<EventEnvelope>,
+    options: ServerOptions,
     shutdown_signal: impl std::future::Future<Output = ()> + Send + 'static,
 ) {
+    let ServerOptions {
+        port,
+        tokens,
+        query_rate_limit_per_minute,
+        listen,
+        output_format_stats,
+        history,
+        check_run_webhook,
+        symbols,
+        trigger,
+        limits,
+    } = options;
+
+    // クライアントに公開するルートは正規化したパスのみ
     let project_root = std::env::current_dir()
+        .ok()
+        .and_then(|p| canonical_root(&p).ok())
         .map(|p| p.display().to_string())
-        .unwrap_or_else(|_| ".".to_string());
+        .unwrap_or_else(|| ".".to_string());
 
-    let app_state = Arc::new(AppState { tx, project_root });
+    let has_viewer_token = tokens.viewer.is_some();
+    tokio::spawn(record_history(tx.subscribe(), history.clone()));
+    let quality_score = Arc::new(Mutex::new(None));
+    tokio::spawn(track_quality_score(
+        tx.subscribe(),
+        PathBuf::from(&project_root),
+        history.clone(),
+        quality_score.clone(),
+    ));
+    let app_state = Arc::new(AppState {
+        tx,
+        project_root,
+        tokens,
+        rate_limiter: RateLimiter::new(query_rate_limit_per_minute, Duration::from_secs(60)),
+        last_cycle: Mutex::new(None),
+        history,
+        output_format_stats,
+        quality_score,
+        check_run_webhook,
+        symbols,
+        trigger,
+        connections: ConnectionLimiter::new(limits.max_connections),
+        limits: limits.clone(),
+    });
+    tokio::spawn(record_last_cycle(
+        app_state.tx.subscribe(),
+        app_state.clone(),
+    ));
 
     // Serve static files from the `ambient_ui` directory.
     // Try multiple possible locations for the UI files
@@ -52,7 +529,7 @@ pub async fn run_server(
         "cli/src/ambient_ui".to_string(),
         // When running from cargo target directory
         "../../../cli/src/ambient_ui".to_string(),
-        // When installed via install.sh
+        // When installed via install.sh or `ambient install-ui`
         format!("{}/.config/ambient/ui", home_dir),
     ];
 
@@ -64,18 +541,72 @@ pub async fn run_server(
         }
     }
 
-    let serve_dir_path = serve_dir_path.unwrap_or_else(|| {
-        eprintln!("警告: UIファイルが見つかりません。デフォルトパスを使用します。");
-        "cli/src/ambient_ui".to_string()
-    });
-
-    let serve_dir =
-        tower_http::services::ServeDir::new(serve_dir_path).append_index_html_on_directories(true);
-
     let app = Router::new()
         .route("/ws", get(websocket_handler))
-        .nest_service("/", serve_dir)
-        .with_state(app_state);
+        .route(EDITOR_ENDPOINT, get(editor_websocket_handler))
+        .route(FILE_ENDPOINT, get(file_handler))
+        .route(DIFF_ENDPOINT, get(diff_handler))
+        .route(LAST_CYCLE_ENDPOINT, get(last_cycle_handler))
+        .route(
+            OUTPUT_FORMAT_STATS_ENDPOINT,
+            get(output_format_stats_handler),
+        )
+        .route(VERSION_ENDPOINT, get(version_handler))
+        .route(PATHS_ENDPOINT, get(paths_handler))
+        .route(QUALITY_SCORE_ENDPOINT, get(quality_score_handler))
+        .route(HISTORY_ENDPOINT, get(history_handler))
+        .route(SYMBOLS_ENDPOINT, get(symbols_handler))
+        .route(STATUS_ENDPOINT, get(status_handler))
+        .route(CONFIG_ENDPOINT, get(config_handler))
+        .route(QUERY_ENDPOINT, post(query_handler))
+        .route(CHECK_ENDPOINT, post(check_handler))
+        .route(PAUSE_ENDPOINT, post(pause_handler))
+        .route(RESUME_ENDPOINT, post(resume_handler))
+        .route(
+            FILES_ROUTE,
+            get(file_history_handler).post(resolution_handler),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            require_token,
+        ))
+        // API定義は秘密情報を含まないため認証なしで公開する
+        .route(OPENAPI_ENDPOINT, get(openapi_handler))
+        .route(BADGE_ENDPOINT, get(badge_handler))
+        // GitHubはトークンを送れないため、Webhookの署名で確認する
+        .route(GITHUB_WEBHOOK_ENDPOINT, post(github_webhook_handler));
+    // UIファイルが見つからなければ、実行ファイルに埋め込んだUIを配信する
+    let app = match serve_dir_path {
+        Some(path) => app.nest_service(
+            "/",
+            tower_http::services::ServeDir::new(path).append_index_html_on_directories(true),
+        ),
+        None => app.fallback(embedded_ui_handler),
+    }
+    // 静的ファイルを含むすべてのリクエストに、同時接続数とボディの大きさの上限を適用する
+    .layer(middleware::from_fn_with_state(
+        app_state.clone(),
+        limit_connections,
+    ))
+    .layer(DefaultBodyLimit::max(limits.max_body_bytes))
+    .with_state(app_state);
+
+    // Unixドメインソケットが指定されていればTCPポートは開かない
+    if let Some(socket_path) = listen
+        .as_deref()
+        .and_then(|l| l.strip_prefix(UNIX_LISTEN_PREFIX))
+    {
+        #[cfg(unix)]
+        crate::ambient_unix_socket::serve_unix(
+            std::path::Path::new(socket_path),
+            app,
+            shutdown_signal,
+        )
+        .await;
+        #[cfg(not(unix))]
+        eprintln!("Unixドメインソケットはこのプラットフォームでは使用できません: {socket_path}");
+        return;
+    }
 
     // 指定されたポートを試し、失敗したら次のポートを試す
     let mut try_port = port;
@@ -102,6 +633,12 @@ pub async fn run_server(
             "Ambient Code Watcherが http://127.0.0.1:{actual_port} で動作中です (設定ポート{port}は使用中)"
         );
     }
+    // トークンはログや画面共有に残らないよう表示せず、設定ファイルの値を使うよう案内する
+    if has_viewer_token {
+        println!(
+            "閲覧専用URL: http://127.0.0.1:{actual_port}/?token=<viewer_token> (<viewer_token>は設定ファイルの値)"
+        );
+    }
 
     if let Err(e) = axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal)
@@ -111,55 +648,148 @@ pub async fn run_server(
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct WebSocketParams {
+    /// 質問に付与する表示名
+    name: Option<String>,
+    /// イベントの符号化方式（`json`、`zlib-json`、`msgpack`）
+    #[serde(default)]
+    encoding: WireEncoding,
+}
+
 async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|socket| websocket(socket, state))
+    Extension(role): Extension<Role>,
+    Extension(client): Extension<ClientKey>,
+    Extension(permit): Extension<ConnectionPermit>,
+    Query(params): Query<WebSocketParams>,
+) -> Response {
+    let user = match params.name.as_deref().map(validate_display_name) {
+        Some(Ok(name)) => Some(name),
+        Some(Err(e)) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        None => None,
+    };
+    let encoding = params.encoding;
+    ws.max_frame_size(state.limits.max_ws_frame_bytes)
+        .max_message_size(state.limits.max_ws_message_bytes)
+        .on_upgrade(move |socket| async move {
+            // ソケットを閉じるまで同時接続数の枠を持つ
+            let _permit = permit;
+            // トークンなしの接続は、接続ごとに送信頻度を数える
+            let rate_key = client
+                .0
+                .unwrap_or_else(|| format!("connection:{}", Uuid::new_v4()));
+            websocket(socket, state, role, user, rate_key, encoding).await;
+        })
+}
+
+/// 表示名を検証する（前後の空白を除去し、長さと使用できる文字を制限）
+pub(crate) fn validate_display_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let len = name.chars().count();
+    if len == 0 || len > MAX_DISPLAY_NAME_LEN {
+        return Err(format!(
+            "表示名は1〜{MAX_DISPLAY_NAME_LEN}文字で指定してください"
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+    {
+        return Err("表示名に使用できない文字が含まれています".to_string());
+    }
+    Ok(name.to_string())
 }
 
-async fn websocket(socket: WebSocket, state: Arc<AppState>) {
+async fn websocket(
+    socket: WebSocket,
+    state: Arc<AppState>,
+    role: Role,
+    user: Option<String>,
+    rate_key: String,
+    encoding: WireEncoding,
+) {
     let (mut sender, mut receiver) = socket.split();
     let mut rx = state.tx.subscribe();
+    let write_timeout = state.limits.write_timeout();
 
     // Send a welcome message.
-    let welcome_msg = AmbientEvent::System("Ambient Code Watcherに接続しました".to_string());
-    if sender
-        .send(Message::Text(welcome_msg.to_json()))
-        .await
-        .is_err()
-    {
+    let welcome_msg = EventEnvelope::new(AmbientEvent::System(format!(
+        "Ambient Code Watcher v{VERSION}に接続しました"
+    )));
+    if !send_with_timeout(&mut sender, encoding.encode(&welcome_msg), write_timeout).await {
         return; // Client disconnected.
     }
 
     // Send project root path
-    let project_root_msg = AmbientEvent::ProjectRoot(state.project_root.clone());
-    if sender
-        .send(Message::Text(project_root_msg.to_json()))
-        .await
-        .is_err()
+    let project_root_msg =
+        EventEnvelope::new(AmbientEvent::ProjectRoot(state.project_root.clone()));
+    if !send_with_timeout(
+        &mut sender,
+        encoding.encode(&project_root_msg),
+        write_timeout,
+    )
+    .await
     {
         return; // Client disconnected.
     }
 
+    // このクライアントだけに送るメッセージ（拒否通知など）
+    let (direct_tx, mut direct_rx) = mpsc::unbounded_channel::<EventEnvelope>();
+
     // This task will forward broadcast messages to the client.
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            if sender.send(Message::Text(msg.to_json())).await.is_err() {
-                break; // Client disconnected.
+        loop {
+            let msg = tokio::select! {
+                msg = rx.recv() => match msg {
+                    Ok(msg) => msg,
+                    Err(_) => break,
+                },
+                Some(msg) = direct_rx.recv() => msg,
+            };
+            // 書き込めないクライアントのためにイベントを溜めず、切断する
+            if !send_with_timeout(&mut sender, encoding.encode(&msg), write_timeout).await {
+                break; // Client disconnected or too slow.
             }
         }
     });
 
     // This task will receive messages from the client and broadcast them.
     let tx = state.tx.clone();
+    let state = state.clone();
     let mut recv_task = tokio::spawn(async move {
         while let Some(Ok(msg)) = receiver.next().await {
             if let Message::Text(text) = msg {
-                // A message from the client is treated as a user query.
-                let query_event = AmbientEvent::UserQuery(text);
+                // 質問は`chat`、一時停止・再開とチャットのコマンドは`control`スコープが必要
+                let control = serde_json::from_str::<AmbientEvent>(&text)
+                    .ok()
+                    .filter(|event| matches!(event, AmbientEvent::Control(_)));
+                let scope = if control.is_some() || text.trim_start().starts_with('/') {
+                    Scope::Control
+                } else {
+                    Scope::Chat
+                };
+                if !role.allows(scope) {
+                    let _ = direct_tx.send(EventEnvelope::new(AmbientEvent::System(scope_denied(
+                        scope,
+                    ))));
+                    continue;
+                }
+                if !state.rate_limiter.check(&rate_key) {
+                    let _ = direct_tx.send(EventEnvelope::new(AmbientEvent::System(
+                        "質問の送信頻度が上限を超えました。しばらく待ってから再度お試しください"
+                            .to_string(),
+                    )));
+                    continue;
+                }
+                // A message from the client is treated as a user query
+                // （`{"Control": ...}`のJSONだけは監視ループへの指示として扱う）
+                let query_event = control.unwrap_or_else(|| {
+                    AmbientEvent::UserQuery(ChatMessage::new(user.clone(), text))
+                });
                 // The receiver of this event is in the main ambient loop.
-                let _ = tx.send(query_event);
+                let _ = tx.emit(query_event);
             }
         }
     });
@@ -170,3 +800,81 @@ async fn websocket(socket: WebSocket, state: Arc<AppState>) {
         _ = (&mut recv_task) => send_task.abort(),
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_display_names() {
+        assert_eq!(
+            validate_display_name("  山田 太郎 "),
+            Ok("山田 太郎".to_string())
+        );
+        assert!(validate_display_name("").is_err());
+        assert!(validate_display_name("<script>").is_err());
+        assert!(validate_display_name(&"a".repeat(MAX_DISPLAY_NAME_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn analysis_events_carry_run_ids() {
+        let job = RunIds::new_run().new_job();
+        let json: serde_json::Value =
+            serde_json::from_str(&AmbientEvent::analysis(job, "分析中").to_json()).unwrap();
+        assert_eq!(json["Analysis"]["text"], "分析中");
+        assert_eq!(
+            json["Analysis"]["run_id"],
+            job.run_id.unwrap().to_string().as_str()
+        );
+        assert_eq!(
+            json["Analysis"]["job_id"],
+            job.job_id.unwrap().to_string().as_str()
+        );
+
+        let json = AmbientEvent::analysis(RunIds::default(), "x").to_json();
+        assert_eq!(json, r#"{"Analysis":{"text":"x"}}"#);
+    }
+
+    #[test]
+    fn envelopes_add_timestamps_beside_the_event() {
+        let envelope = EventEnvelope::new(AmbientEvent::System("ok".to_string()));
+        let json: serde_json::Value = serde_json::from_str(&envelope.to_json()).unwrap();
+        assert_eq!(json["System"], "ok");
+        assert!(chrono::DateTime::parse_from_rfc3339(json["ts"].as_str().unwrap()).is_ok());
+        assert_eq!(json["utc_offset"], envelope.utc_offset.as_str());
+
+        let parsed: EventEnvelope = serde_json::from_value(json).unwrap();
+        assert!(matches!(parsed.event, AmbientEvent::System(text) if text == "ok"));
+    }
+
+    #[test]
+    fn control_commands_round_trip_as_json() {
+        let json = AmbientEvent::Control(ControlCommand::Pause { minutes: 30 }).to_json();
+        assert_eq!(json, r#"{"Control":{"Pause":{"minutes":30}}}"#);
+        let parsed: AmbientEvent = serde_json::from_str(r#"{"Control":"Resume"}"#).unwrap();
+        assert!(matches!(
+            parsed,
+            AmbientEvent::Control(ControlCommand::Resume)
+        ));
+        assert!(serde_json::from_str::<AmbientEvent>("/pause 30m").is_err());
+    }
+
+    #[test]
+    fn rate_limiter_is_per_client() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.check("token:a"));
+        assert!(limiter.check("token:a"));
+        assert!(!limiter.check("token:a"));
+        assert!(limiter.check("token:b"));
+    }
+
+    #[test]
+    fn rate_limiter_forgets_idle_clients() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(20));
+        assert!(limiter.check("connection:1"));
+        assert!(!limiter.check("connection:1"));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(limiter.check("connection:2"));
+        assert_eq!(limiter.history.lock().unwrap().len(), 1);
+    }
+}
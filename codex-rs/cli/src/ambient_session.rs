@@ -0,0 +1,140 @@
+use anyhow::Context;
+use anyhow::Result;
+use chrono::DateTime;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::ambient_server::EventEnvelope;
+
+/// 再生時に待つ間隔の上限（チェック間隔の待ち時間などで止まって見えないようにする）
+const MAX_REPLAY_GAP: Duration = Duration::from_secs(5);
+
+/// イベントを1行1件のJSONで記録する
+pub async fn record_session(mut rx: broadcast::Receiver<EventEnvelope>, file: File) {
+    let mut out = BufWriter::new(file);
+    loop {
+        match rx.recv().await {
+            Ok(envelope) => {
+                if let Err(e) = write_event(&mut out, &envelope) {
+                    tracing::warn!("failed to record session event: {e}");
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("session recording skipped {skipped} events");
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// 異常終了しても記録が残るよう、1件ごとに書き出す
+fn write_event(out: &mut impl Write, envelope: &EventEnvelope) -> Result<()> {
+    writeln!(out, "{}", serde_json::to_string(envelope)?)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// 記録したセッションを読み込む（壊れた行はエラー）
+pub fn read_session(path: &Path) -> Result<Vec<EventEnvelope>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("{}を読み込めません", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}の{}行目を解析できません", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// `4x`、`0.5x`、`2`形式の再生速度
+pub fn parse_speed(value: &str) -> Result<f64> {
+    let number = value.trim().trim_end_matches(['x', 'X']);
+    match number.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        _ => anyhow::bail!("再生速度を解析できません: {value}（例: 1x、4x、0.5x）"),
+    }
+}
+
+/// 直前のイベントから待つ時間（記録時の間隔を`speed`倍速にし、上限で切り詰める）
+fn replay_delay(previous: &EventEnvelope, next: &EventEnvelope, speed: f64) -> Duration {
+    let (Ok(previous), Ok(next)) = (
+        DateTime::parse_from_rfc3339(&previous.ts),
+        DateTime::parse_from_rfc3339(&next.ts),
+    ) else {
+        return Duration::ZERO;
+    };
+    let gap = (next - previous).to_std().unwrap_or_default();
+    gap.div_f64(speed).min(MAX_REPLAY_GAP)
+}
+
+/// 記録した間隔で（`speed`倍速）イベントを送り直す
+pub async fn replay_session(
+    events: Vec<EventEnvelope>,
+    speed: f64,
+    tx: &broadcast::Sender<EventEnvelope>,
+) {
+    let mut previous: Option<EventEnvelope> = None;
+    for envelope in events {
+        if let Some(previous) = &previous {
+            tokio::time::sleep(replay_delay(previous, &envelope, speed)).await;
+        }
+        let _ = tx.send(envelope.clone());
+        previous = Some(envelope);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::AmbientEvent;
+    use tempfile::tempdir;
+
+    fn event(ts: &str, text: &str) -> EventEnvelope {
+        EventEnvelope {
+            event: AmbientEvent::System(text.to_string()),
+            ts: ts.to_string(),
+            utc_offset: "+00:00".to_string(),
+            project: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn records_and_reads_back_events() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let (tx, _) = broadcast::channel(16);
+        let recorder = tokio::spawn(record_session(tx.subscribe(), File::create(&path).unwrap()));
+        tx.send(event("2026-01-01T00:00:00.000Z", "開始")).unwrap();
+        tx.send(event("2026-01-01T00:00:02.000Z", "終了")).unwrap();
+        drop(tx);
+        recorder.await.unwrap();
+
+        let events = read_session(&path).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].ts, "2026-01-01T00:00:02.000Z");
+    }
+
+    #[test]
+    fn scales_and_caps_replay_delays() {
+        let start = event("2026-01-01T00:00:00.000Z", "");
+        assert_eq!(
+            replay_delay(&start, &event("2026-01-01T00:00:02.000Z", ""), 4.0),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            replay_delay(&start, &event("2026-01-01T00:10:00.000Z", ""), 1.0),
+            MAX_REPLAY_GAP
+        );
+        assert_eq!(parse_speed("4x").unwrap(), 4.0);
+        assert!(parse_speed("0x").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::ambient_encoding::read_text;
+use crate::ambient_git::ChangedFile;
+use crate::ambient_git::run_git_command;
+
+/// 変更箇所の前後に含めるコンテキスト行数
+const CONTEXT_LINES: usize = 3;
+
+/// 1つの抜粋に含める最大行数
+const MAX_EXCERPT_LINES: usize = 80;
+
+/// 1ファイルあたりの最大ハンク数
+const MAX_HUNKS: usize = 5;
+
+/// 行番号付きのコード抜粋
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CodeExcerpt {
+    /// 抜粋の先頭行の行番号（1始まり）
+    pub start_line: usize,
+    pub text: String,
+}
+
+/// 1つのハンクに対応する変更前後のコード抜粋
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Snapshot {
+    /// 比較の基準（通常は`HEAD`）の内容（新規ファイルの場合は`None`）
+    pub before: Option<CodeExcerpt>,
+    /// 作業ツリーの内容（削除されたファイルの場合は`None`）
+    pub after: Option<CodeExcerpt>,
+}
+
+/// diffの各ハンクについて、`git show <比較の基準>:<file>`と作業ツリーから抜粋を作る
+pub fn snapshots_for_file(file: &ChangedFile, diff: &str) -> Vec<Snapshot> {
+    let hunks = parse_hunk_ranges(diff);
+    if hunks.is_empty() {
+        return Vec::new();
+    }
+
+    let before = run_git_command(
+        &["show", &format!("{}:{}", file.base, file.repo_path)],
+        &file.repo_dir,
+    )
+    .ok();
+    let after = read_text(&file.absolute_path()).ok();
+
+    hunks
+        .into_iter()
+        .take(MAX_HUNKS)
+        .map(|hunk| Snapshot {
+            before: before
+                .as_deref()
+                .and_then(|content| excerpt(content, hunk.old_start, hunk.old_len)),
+            after: after
+                .as_deref()
+                .and_then(|content| excerpt(content, hunk.new_start, hunk.new_len)),
+        })
+        .collect()
+}
+
+/// `@@ -a,b +c,d @@`から読み取った行範囲
+#[derive(Debug, PartialEq, Eq)]
+struct HunkRange {
+    old_start: usize,
+    old_len: usize,
+    new_start: usize,
+    new_len: usize,
+}
+
+fn parse_hunk_ranges(diff: &str) -> Vec<HunkRange> {
+    diff.lines()
+        .filter_map(|line| {
+            let ranges = line.strip_prefix("@@ -")?.split(" @@").next()?;
+            let (old, new) = ranges.split_once(" +")?;
+            let (old_start, old_len) = parse_range(old)?;
+            let (new_start, new_len) = parse_range(new)?;
+            Some(HunkRange {
+                old_start,
+                old_len,
+                new_start,
+                new_len,
+            })
+        })
+        .collect()
+}
+
+/// 変更後の`line`行目がdiffのハンクに含まれるか（その行にコメントを付けられるか）
+pub fn in_new_hunk(diff: &str, line: usize) -> bool {
+    parse_hunk_ranges(diff)
+        .iter()
+        .any(|hunk| (hunk.new_start..hunk.new_start + hunk.new_len).contains(&line))
+}
+
+/// diffの各ハンクの変更後の行範囲（先頭行と行数）
+pub fn new_hunk_ranges(diff: &str) -> Vec<(usize, usize)> {
+    parse_hunk_ranges(diff)
+        .into_iter()
+        .map(|hunk| (hunk.new_start, hunk.new_len))
+        .collect()
+}
+
+/// `start,len`または`start`（長さ1）を解析する
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// `start`行目から`len`行を前後のコンテキスト付きで切り出す
+fn excerpt(content: &str, start: usize, len: usize) -> Option<CodeExcerpt> {
+    if len == 0 && start == 0 {
+        return None;
+    }
+    let lines: Vec<&str> = content.lines().collect();
+    let first = start.saturating_sub(CONTEXT_LINES).max(1);
+    let last = (start + len.max(1) - 1 + CONTEXT_LINES)
+        .min(lines.len())
+        .min(first + MAX_EXCERPT_LINES - 1);
+    if first > last {
+        return None;
+    }
+    Some(CodeExcerpt {
+        start_line: first,
+        text: lines[first - 1..last].join("\n"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hunk_headers() {
+        let diff = "@@ -1,3 +1,4 @@ fn main()\n-a\n+b\n@@ -10 +11,0 @@\n";
+        assert_eq!(
+            parse_hunk_ranges(diff),
+            vec![
+                HunkRange {
+                    old_start: 1,
+                    old_len: 3,
+                    new_start: 1,
+                    new_len: 4,
+                },
+                HunkRange {
+                    old_start: 10,
+                    old_len: 1,
+                    new_start: 11,
+                    new_len: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_lines_inside_new_hunks() {
+        let diff = "@@ -1,3 +1,4 @@ fn main()\n-a\n+b\n@@ -10 +11,0 @@\n";
+        assert!(in_new_hunk(diff, 1));
+        assert!(in_new_hunk(diff, 4));
+        assert!(!in_new_hunk(diff, 5));
+        assert!(!in_new_hunk(diff, 11));
+    }
+
+    #[test]
+    fn excerpt_adds_context() {
+        let content = (1..=20)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let excerpt = excerpt(&content, 10, 1).unwrap();
+        assert_eq!(excerpt.start_line, 7);
+        assert_eq!(excerpt.text, "7\n8\n9\n10\n11\n12\n13");
+        assert_eq!(super::excerpt("", 0, 0), None);
+    }
+}
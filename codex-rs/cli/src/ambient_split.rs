@@ -0,0 +1,438 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::ambient_config::RedactionLevel;
+use crate::ambient_git::run_git_command;
+use crate::ambient_redact::redact;
+
+/// モデルに渡す変更の内容の上限（文字数、超えた分は変更の一覧だけを渡す）
+const MAX_SPLIT_DIFF_CHARS: usize = 60_000;
+
+/// 1つの変更からモデルに渡す行数の上限
+const MAX_PREVIEW_LINES: usize = 60;
+
+/// 割り当てられなかった変更をまとめるグループのメッセージ
+const LEFTOVER_MESSAGE: &str = "残りの変更";
+
+/// 作業ツリーの変更の単位（ファイル全体、またはファイル内の1つのハンク）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// `path`または`path#n`（nは1始まりのハンク番号）
+    pub id: String,
+    /// 変更されたファイル（リネームの場合は元のパスも含む）
+    pub paths: Vec<String>,
+    /// diffのファイルヘッダー（ハンクだけをステージするときに使う）
+    header: String,
+    /// ハンクの内容（ファイル全体の場合は`None`）
+    hunk: Option<String>,
+    /// モデルに見せる内容
+    preview: String,
+}
+
+/// 作業ツリーの未コミットの変更（追跡中のファイルのdiffと未追跡のファイル）
+///
+/// 複数のハンクがあるファイルはハンクごと、それ以外（新規・削除・リネーム・バイナリ）はファイルごとに分ける。
+pub fn collect_changes(root: &Path) -> Result<Vec<Change>> {
+    run_git_command(&["rev-parse", "--verify", "-q", "HEAD"], root)
+        .context("最初のコミットの前は変更を分割できません")?;
+    let diff = run_git_command(
+        &[
+            "-c",
+            "core.quotePath=false",
+            "diff",
+            "HEAD",
+            "--no-color",
+            "--no-ext-diff",
+        ],
+        root,
+    )?;
+    let mut changes = parse_diff(&diff);
+
+    let untracked = run_git_command(&["ls-files", "--others", "--exclude-standard", "-z"], root)?;
+    for path in untracked.split('\0').filter(|path| !path.is_empty()) {
+        let preview = match fs::read_to_string(root.join(path)) {
+            Ok(content) => format!("新規ファイル\n{}", head_lines(&content)),
+            Err(_) => "新規ファイル（バイナリ）".to_string(),
+        };
+        changes.push(Change {
+            id: path.to_string(),
+            paths: vec![path.to_string()],
+            header: String::new(),
+            hunk: None,
+            preview,
+        });
+    }
+    Ok(changes)
+}
+
+/// `git diff`の出力を変更の単位に分ける
+fn parse_diff(diff: &str) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for file in diff
+        .split("\ndiff --git ")
+        .filter(|file| !file.trim().is_empty())
+    {
+        let file = if file.starts_with("diff --git ") {
+            file.to_string()
+        } else {
+            format!("diff --git {file}")
+        };
+        let file = if file.ends_with('\n') {
+            file
+        } else {
+            format!("{file}\n")
+        };
+        let (header, hunks) = match file.find("\n@@ ") {
+            Some(index) => file.split_at(index + 1),
+            None => (file.as_str(), ""),
+        };
+        let mut paths = Vec::new();
+        for line in header.lines() {
+            let path = line
+                .strip_prefix("rename from ")
+                .or_else(|| line.strip_prefix("rename to "))
+                .or_else(|| line.strip_prefix("--- a/"))
+                .or_else(|| line.strip_prefix("+++ b/"))
+                // 空白を含むパスの後ろにはタブが付く
+                .map(|path| path.trim_end_matches('\t'));
+            if let Some(path) = path
+                && !paths.iter().any(|known| known == path)
+            {
+                paths.push(path.to_string());
+            }
+        }
+        if paths.is_empty() {
+            // バイナリやモードだけの変更は`diff --git a/x b/x`から取る
+            let Some(path) = header
+                .lines()
+                .next()
+                .and_then(|line| line.rsplit_once(" b/"))
+                .map(|(_, path)| path.to_string())
+            else {
+                continue;
+            };
+            paths.push(path);
+        }
+        let path = paths.last().cloned().unwrap_or_default();
+        let hunks: Vec<String> = split_hunks(hunks);
+        let whole_file = hunks.len() < 2
+            || ["new file mode", "deleted file mode", "rename from"]
+                .iter()
+                .any(|marker| header.contains(marker));
+        if whole_file {
+            let preview = if hunks.is_empty() {
+                header.to_string()
+            } else {
+                hunks
+                    .iter()
+                    .map(|hunk| head_lines(hunk))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            changes.push(Change {
+                id: path,
+                paths,
+                header: header.to_string(),
+                hunk: None,
+                preview,
+            });
+            continue;
+        }
+        for (index, hunk) in hunks.into_iter().enumerate() {
+            changes.push(Change {
+                id: format!("{path}#{}", index + 1),
+                paths: paths.clone(),
+                header: header.to_string(),
+                preview: head_lines(&hunk),
+                hunk: Some(hunk),
+            });
+        }
+    }
+    changes
+}
+
+fn split_hunks(hunks: &str) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for line in hunks.split_inclusive('\n') {
+        match result.last_mut() {
+            Some(hunk) if !line.starts_with("@@ ") => hunk.push_str(line),
+            _ => result.push(line.to_string()),
+        }
+    }
+    result
+}
+
+fn head_lines(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut preview = lines
+        .iter()
+        .take(MAX_PREVIEW_LINES)
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if lines.len() > MAX_PREVIEW_LINES {
+        preview.push_str(&format!("\n…（残り{}行）", lines.len() - MAX_PREVIEW_LINES));
+    }
+    preview
+}
+
+/// 変更のまとめ方をJSONで答えてもらうプロンプト
+pub fn render_prompt(changes: &[Change], redaction: RedactionLevel) -> String {
+    let mut prompt = String::from(
+        "以下は未コミットの変更です。論理的にまとまりのあるコミットの列に分け、それぞれのコミットメッセージの案を作ってください。\n\n\
+         - 各変更は`id`で指定し、どれか1つのコミットにだけ含めてください\n\
+         - 依存される変更を先のコミットにしてください\n\
+         - 次の形式のJSONだけを答えてください: {\"groups\": [{\"message\": \"コミットメッセージ\", \"changes\": [\"id\", ...]}]}\n\n",
+    );
+    let mut budget = MAX_SPLIT_DIFF_CHARS;
+    for change in changes {
+        let preview = redact(&change.preview, redaction);
+        let length = preview.chars().count();
+        if length <= budget {
+            budget -= length;
+            prompt.push_str(&format!(
+                "## id: {}\n\n```diff\n{preview}\n```\n\n",
+                change.id
+            ));
+        } else {
+            prompt.push_str(&format!("## id: {}\n\n（内容は省略）\n\n", change.id));
+        }
+    }
+    prompt
+}
+
+/// 1つのコミットにまとめる変更
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitGroup {
+    pub message: String,
+    /// 変更の`id`
+    pub changes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlanResponse {
+    groups: Vec<CommitGroup>,
+}
+
+/// 回答からまとめ方を取り出す
+///
+/// 知らない`id`と重複は除き、ファイルのパスだけが書かれていればそのファイルのハンクをすべて含める。
+/// どのグループにも入らなかった変更は最後のグループにまとめる。
+pub fn parse_plan(response: &str, changes: &[Change]) -> Result<Vec<CommitGroup>> {
+    let start = response.find('{').context("回答にJSONが含まれていません")?;
+    let end = response
+        .rfind('}')
+        .filter(|end| *end > start)
+        .context("回答にJSONが含まれていません")?;
+    let parsed: PlanResponse =
+        serde_json::from_str(&response[start..=end]).context("回答のJSONを読み取れません")?;
+
+    let mut assigned: Vec<&str> = Vec::new();
+    let mut groups = Vec::new();
+    for group in parsed.groups {
+        let mut ids = Vec::new();
+        for id in &group.changes {
+            let matching = changes.iter().filter(|change| {
+                change.id == *id || (change.hunk.is_some() && change.paths.contains(id))
+            });
+            for change in matching {
+                if !assigned.contains(&change.id.as_str()) {
+                    assigned.push(&change.id);
+                    ids.push(change.id.clone());
+                }
+            }
+        }
+        if !ids.is_empty() {
+            groups.push(CommitGroup {
+                message: group.message.trim().to_string(),
+                changes: ids,
+            });
+        }
+    }
+    let leftover: Vec<String> = changes
+        .iter()
+        .filter(|change| !assigned.contains(&change.id.as_str()))
+        .map(|change| change.id.clone())
+        .collect();
+    if !leftover.is_empty() {
+        groups.push(CommitGroup {
+            message: LEFTOVER_MESSAGE.to_string(),
+            changes: leftover,
+        });
+    }
+    Ok(groups)
+}
+
+/// まとめ方の表示用の文面
+pub fn render_plan(groups: &[CommitGroup]) -> String {
+    let mut text = String::new();
+    for (index, group) in groups.iter().enumerate() {
+        text.push_str(&format!("{}. {}\n", index + 1, group.message));
+        for id in &group.changes {
+            text.push_str(&format!("   - {id}\n"));
+        }
+    }
+    text
+}
+
+/// グループの変更をインデックスに追加する（ハンクは`git apply --cached`で個別に追加する）
+pub fn stage_group(root: &Path, group: &CommitGroup, changes: &[Change]) -> Result<()> {
+    let selected: Vec<&Change> = changes
+        .iter()
+        .filter(|change| group.changes.contains(&change.id))
+        .collect();
+
+    let whole_files: Vec<&str> = selected
+        .iter()
+        .filter(|change| change.hunk.is_none())
+        .flat_map(|change| change.paths.iter().map(String::as_str))
+        .collect();
+    if !whole_files.is_empty() {
+        let mut args = vec!["add", "-A", "--"];
+        args.extend(whole_files);
+        run_git_command(&args, root)?;
+    }
+
+    // 同じファイルのハンクは1つのパッチにまとめる
+    let mut patch = String::new();
+    let mut current_header: Option<&str> = None;
+    for change in &selected {
+        let Some(hunk) = &change.hunk else {
+            continue;
+        };
+        if current_header != Some(change.header.as_str()) {
+            patch.push_str(&change.header);
+            current_header = Some(&change.header);
+        }
+        patch.push_str(hunk);
+    }
+    if patch.is_empty() {
+        return Ok(());
+    }
+    let patch_path = run_git_command(&["rev-parse", "--git-path", "ambient-split.patch"], root)?;
+    let patch_path = root.join(patch_path.trim());
+    fs::write(&patch_path, &patch)
+        .with_context(|| format!("{}に書き込めません", patch_path.display()))?;
+    let applied = run_git_command(
+        &[
+            "apply",
+            "--cached",
+            "--recount",
+            &patch_path.display().to_string(),
+        ],
+        root,
+    );
+    let _ = fs::remove_file(&patch_path);
+    applied.map(|_| ()).context("ハンクをステージできません")
+}
+
+/// ステージ済みの変更があるか
+pub fn has_staged_changes(root: &Path) -> bool {
+    run_git_command(&["diff", "--cached", "--quiet"], root).is_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_test_support::TempRepo;
+
+    fn numbered(count: usize) -> String {
+        (1..=count).map(|n| format!("line {n}\n")).collect()
+    }
+
+    /// 2つのハンクがあるファイル、新規ファイル、未追跡のファイルを持つリポジトリ
+    fn messy_worktree() -> TempRepo {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/lib.rs", &numbered(40)).unwrap();
+        repo.write("README.md", "hello\n").unwrap();
+        repo.add(&["src/lib.rs", "README.md"]).unwrap();
+        repo.commit("initial").unwrap();
+
+        let edited = numbered(40)
+            .replace("line 2\n", "line 2 changed\n")
+            .replace("line 38\n", "line 38 changed\n");
+        repo.write("src/lib.rs", &edited).unwrap();
+        repo.write("README.md", "hello world\n").unwrap();
+        repo.write("docs/guide.md", "# Guide\n").unwrap();
+        repo
+    }
+
+    #[test]
+    fn splits_worktree_into_hunks_and_files() {
+        let repo = messy_worktree();
+        let changes = collect_changes(repo.path()).unwrap();
+        let ids: Vec<&str> = changes.iter().map(|change| change.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["README.md", "src/lib.rs#1", "src/lib.rs#2", "docs/guide.md"]
+        );
+        assert!(changes[1].preview.contains("+line 2 changed"));
+        assert!(!changes[1].preview.contains("line 38 changed"));
+
+        let prompt = render_prompt(&changes, RedactionLevel::default());
+        assert!(prompt.contains("## id: src/lib.rs#2"));
+        assert!(prompt.contains("新規ファイル\n# Guide"));
+    }
+
+    #[test]
+    fn parses_plan_and_collects_leftovers() {
+        let repo = messy_worktree();
+        let changes = collect_changes(repo.path()).unwrap();
+        let response = r#"こう分けられます:
+{"groups": [
+  {"message": "docs: add guide", "changes": ["docs/guide.md", "README.md", "unknown.rs"]},
+  {"message": "fix: adjust lines", "changes": ["src/lib.rs#2", "README.md"]}
+]}"#;
+        let groups = parse_plan(response, &changes).unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                CommitGroup {
+                    message: "docs: add guide".to_string(),
+                    changes: vec!["docs/guide.md".to_string(), "README.md".to_string()],
+                },
+                CommitGroup {
+                    message: "fix: adjust lines".to_string(),
+                    changes: vec!["src/lib.rs#2".to_string()],
+                },
+                CommitGroup {
+                    message: LEFTOVER_MESSAGE.to_string(),
+                    changes: vec!["src/lib.rs#1".to_string()],
+                },
+            ]
+        );
+        assert!(render_plan(&groups).starts_with("1. docs: add guide\n   - docs/guide.md\n"));
+
+        // パスだけの指定はそのファイルのすべてのハンク
+        let groups = parse_plan(
+            r#"{"groups": [{"message": "m", "changes": ["src/lib.rs"]}]}"#,
+            &changes,
+        )
+        .unwrap();
+        assert_eq!(groups[0].changes, vec!["src/lib.rs#1", "src/lib.rs#2"]);
+        assert!(parse_plan("JSONはありません", &changes).is_err());
+    }
+
+    #[test]
+    fn stages_selected_hunks_only() {
+        let repo = messy_worktree();
+        let changes = collect_changes(repo.path()).unwrap();
+        let group = CommitGroup {
+            message: "fix: adjust line 38".to_string(),
+            changes: vec!["src/lib.rs#2".to_string(), "docs/guide.md".to_string()],
+        };
+        assert!(!has_staged_changes(repo.path()));
+        stage_group(repo.path(), &group, &changes).unwrap();
+        assert!(has_staged_changes(repo.path()));
+
+        let staged = run_git_command(&["diff", "--cached"], repo.path()).unwrap();
+        assert!(staged.contains("+line 38 changed"));
+        assert!(!staged.contains("+line 2 changed"));
+        assert!(staged.contains("+# Guide"));
+        assert!(!staged.contains("hello world"));
+    }
+}
@@ -0,0 +1,102 @@
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::ambient_annotations::finding_line;
+use crate::ambient_server::Finding;
+
+/// 指摘の内容の指紋（空白の違いを無視した回答のSHA-256の先頭16桁）
+///
+/// 同じファイル・行・レビューの指摘を、並列のジョブの完了順によらず同じ順に並べるために使う。
+pub fn fingerprint(finding: &Finding) -> String {
+    let normalized = finding
+        .message
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    Sha256::digest(normalized.as_bytes())
+        .iter()
+        .take(8)
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// 指摘を（ファイル、行、レビュー、指紋）の順に並べる（行の分からない指摘はファイルの先頭）
+pub fn sort_findings(findings: &mut [Finding]) {
+    findings.sort_by_cached_key(|finding| {
+        (
+            finding.file_path.clone(),
+            finding_line(finding).unwrap_or(0),
+            finding.review.clone(),
+            fingerprint(finding),
+        )
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::RunIds;
+
+    fn finding(file_path: &str, line: Option<u32>, review: &str, message: &str) -> Finding {
+        Finding {
+            ids: RunIds::new_run().new_job(),
+            file_path: file_path.to_string(),
+            review: review.to_string(),
+            message: message.to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity: None,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line,
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn sorts_by_file_line_review_and_fingerprint() {
+        let expected = vec![
+            finding("src/a.rs", None, "構文", "ファイル全体"),
+            finding(
+                "src/a.rs",
+                Some(3),
+                "セキュリティ",
+                "入力を検証していません",
+            ),
+            finding("src/a.rs", Some(3), "構文", "括弧が閉じていません"),
+            finding("src/a.rs", Some(12), "構文", "未定義の変数"),
+            finding("src/b.rs", Some(1), "構文", "未使用のインポート"),
+        ];
+        let mut findings = expected.clone();
+        findings.reverse();
+        findings.swap(0, 2);
+        sort_findings(&mut findings);
+        let messages = |findings: &[Finding]| {
+            findings
+                .iter()
+                .map(|finding| finding.message.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(messages(&findings), messages(&expected));
+
+        assert_eq!(
+            fingerprint(&finding(
+                "src/a.rs",
+                None,
+                "構文",
+                "括弧が\n  閉じていません "
+            )),
+            fingerprint(&finding(
+                "src/b.rs",
+                Some(1),
+                "構文",
+                "括弧が 閉じていません"
+            ))
+        );
+    }
+}
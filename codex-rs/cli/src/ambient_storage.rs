@@ -0,0 +1,451 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use anyhow::bail;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chacha20poly1305::Key;
+use chacha20poly1305::XChaCha20Poly1305;
+use chacha20poly1305::XNonce;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::aead::AeadCore;
+use chacha20poly1305::aead::KeyInit;
+use chacha20poly1305::aead::OsRng;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::ambient_config::AmbientConfig;
+use crate::ambient_history::ReviewHistory;
+use crate::ambient_review_cache::ReviewCache;
+use crate::ambient_secrets::SecretName;
+use crate::ambient_secrets::get_secret;
+use crate::ambient_secrets::set_secret;
+
+/// 暗号化した内容の先頭に付ける印（付いていない内容は暗号化する前の平文として読む）
+const ENCRYPTED_PREFIX: &str = "ambient-enc:v1:";
+
+/// 鍵の長さ（バイト）
+const KEY_LEN: usize = 32;
+
+/// XChaCha20-Poly1305のnonceの長さ（バイト）
+const NONCE_LEN: usize = 24;
+
+/// OSのキーチェーンに鍵を保存するときのサービス名とアカウント名
+const KEYRING_SERVICE: &str = "ambient";
+const KEYRING_USER: &str = "storage-key";
+
+/// `ambient storage rekey`で置き換えた鍵を残すアカウント名・ファイルの拡張子
+const PREVIOUS_KEY_SUFFIX: &str = "old";
+
+/// `.ambient/config.toml`の`[storage]`（履歴とレビューのキャッシュの保存方法）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct StorageConfig {
+    /// 履歴とレビューのキャッシュをXChaCha20-Poly1305で暗号化して保存する
+    #[serde(default)]
+    pub encrypt: bool,
+
+    /// 鍵の保存先
+    #[serde(default)]
+    pub key: KeySource,
+
+    /// `key = "file"`の鍵ファイル（未指定なら`~/.codex/ambient-storage.key`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+}
+
+/// 暗号化の鍵の保存先
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// 権限を0600にした鍵ファイル
+    #[default]
+    File,
+    /// OSのキーチェーン（macOSのキーチェーン、Windowsの資格情報マネージャー、LinuxのSecret Service）
+    Keychain,
+}
+
+impl KeySource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Keychain => "keychain",
+        }
+    }
+}
+
+impl StorageConfig {
+    /// 履歴とキャッシュの読み書きに使う暗号
+    ///
+    /// 暗号化する設定で鍵がなければ作る。暗号化しない設定でも、既存の鍵があれば
+    /// 暗号化済みの内容を読めるようにする。
+    pub fn cipher(&self) -> Result<StorageCipher> {
+        let store = self.key_store()?;
+        let key = match store.load() {
+            Ok(Some(key)) => Some(key),
+            Ok(None) if self.encrypt => {
+                let key = generate_key();
+                store.save(&key)?;
+                Some(key)
+            }
+            Ok(None) => None,
+            Err(e) if !self.encrypt => {
+                tracing::warn!("{e:#}");
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(StorageCipher::new(key.as_ref(), self.encrypt))
+    }
+
+    fn key_store(&self) -> Result<KeyStore> {
+        Ok(match self.key {
+            KeySource::File => KeyStore::File(match &self.key_file {
+                Some(path) => path.clone(),
+                None => AmbientConfig::storage_key_path()?,
+            }),
+            KeySource::Keychain => KeyStore::Keychain(KEYRING_USER.to_string()),
+        })
+    }
+}
+
+/// 履歴の行やキャッシュのエントリーを暗号化・復号する
+///
+/// 平文の内容はそのまま読むため、暗号化を有効にする前の内容も読める。
+#[derive(Clone, Default)]
+pub struct StorageCipher {
+    /// 書き込む内容を暗号化する
+    encrypt: bool,
+    cipher: Option<XChaCha20Poly1305>,
+}
+
+impl std::fmt::Debug for StorageCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StorageCipher")
+            .field("encrypt", &self.encrypt)
+            .field("has_key", &self.cipher.is_some())
+            .finish()
+    }
+}
+
+impl StorageCipher {
+    pub fn new(key: Option<&[u8; KEY_LEN]>, encrypt: bool) -> Self {
+        Self {
+            encrypt: encrypt && key.is_some(),
+            cipher: key.map(|key| XChaCha20Poly1305::new(Key::from_slice(key))),
+        }
+    }
+
+    /// 保存する内容（暗号化する場合は`ambient-enc:v1:`とnonce・暗号文のbase64）
+    pub fn seal(&self, plaintext: &str) -> Result<String> {
+        let Some(cipher) = self.cipher.as_ref().filter(|_| self.encrypt) else {
+            return Ok(plaintext.to_string());
+        };
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow!("暗号化できません"))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!("{ENCRYPTED_PREFIX}{}", STANDARD.encode(sealed)))
+    }
+
+    /// 保存した内容を読む（平文はそのまま返す）
+    pub fn open(&self, stored: &str) -> Result<String> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let Some(cipher) = &self.cipher else {
+            bail!("暗号化されていますが、`[storage]`の鍵が見つかりません");
+        };
+        let sealed = STANDARD
+            .decode(encoded.trim())
+            .context("暗号化された内容が壊れています")?;
+        if sealed.len() < NONCE_LEN {
+            bail!("暗号化された内容が壊れています");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("復号できません（鍵が違うか、内容が壊れています）"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+/// 鍵の保存先
+enum KeyStore {
+    File(PathBuf),
+    /// キーチェーンのアカウント名
+    Keychain(String),
+}
+
+impl KeyStore {
+    fn load(&self) -> Result<Option<[u8; KEY_LEN]>> {
+        let text = match self {
+            Self::File(path) => match fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("鍵ファイル{}を読めません", path.display()));
+                }
+            },
+            Self::Keychain(user) => match get_secret(&keyring_name(user))? {
+                Some(text) => text,
+                None => return Ok(None),
+            },
+        };
+        decode_key(&text).map(Some)
+    }
+
+    fn save(&self, key: &[u8; KEY_LEN]) -> Result<()> {
+        let text = STANDARD.encode(key);
+        match self {
+            Self::File(path) => write_private(path, &format!("{text}\n"))
+                .with_context(|| format!("鍵ファイル{}に書き込めません", path.display())),
+            Self::Keychain(user) => set_secret(&keyring_name(user), &text),
+        }
+    }
+
+    /// 置き換える前の鍵を残す場所
+    fn previous(&self) -> Self {
+        match self {
+            Self::File(path) => {
+                let mut previous = path.clone().into_os_string();
+                previous.push(format!(".{PREVIOUS_KEY_SUFFIX}"));
+                Self::File(previous.into())
+            }
+            Self::Keychain(user) => Self::Keychain(format!("{user}.{PREVIOUS_KEY_SUFFIX}")),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::File(path) => path.display().to_string(),
+            Self::Keychain(user) => format!("キーチェーンの{KEYRING_SERVICE}/{user}"),
+        }
+    }
+}
+
+fn keyring_name(user: &str) -> SecretName {
+    SecretName {
+        service: KEYRING_SERVICE.to_string(),
+        account: user.to_string(),
+    }
+}
+
+fn generate_key() -> [u8; KEY_LEN] {
+    let mut key = [0; KEY_LEN];
+    key.copy_from_slice(&XChaCha20Poly1305::generate_key(&mut OsRng));
+    key
+}
+
+fn decode_key(text: &str) -> Result<[u8; KEY_LEN]> {
+    let bytes = STANDARD
+        .decode(text.trim())
+        .context("鍵がbase64ではありません")?;
+    <[u8; KEY_LEN]>::try_from(bytes.as_slice())
+        .map_err(|_| anyhow!("鍵の長さが{KEY_LEN}バイトではありません"))
+}
+
+/// 所有者だけが読み書きできるファイルに書き込む
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// `ambient storage rekey`の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RekeySummary {
+    pub history_lines: usize,
+    pub cache_entries: usize,
+    /// 新しい鍵で暗号化した（`false`なら平文に戻した）
+    pub encrypted: bool,
+    /// 新しい鍵の保存先
+    pub key_location: Option<String>,
+}
+
+/// 履歴とレビューのキャッシュを新しい鍵で暗号化し直す（暗号化しない設定なら平文に戻す）
+///
+/// 平文の内容も暗号化するため、暗号化を有効にしたあとの移行にも使う。すべて読めることを
+/// 確かめてから書き換え、置き換える前の鍵は`.old`を付けた場所に残す。履歴はプロジェクトの、
+/// キャッシュはリポジトリルートの`.ambient/`にある。
+pub fn rekey(
+    project_root: &Path,
+    repo_root: &Path,
+    config: &StorageConfig,
+) -> Result<RekeySummary> {
+    let store = config.key_store()?;
+    let current = config.cipher()?;
+    let new_key = config.encrypt.then(generate_key);
+    let next = StorageCipher::new(new_key.as_ref(), config.encrypt);
+
+    let mut rewrites = Vec::new();
+    let history_file = ReviewHistory::file_path(project_root);
+    let mut history_lines = 0;
+    if history_file.exists() {
+        let content = fs::read_to_string(&history_file)
+            .with_context(|| format!("{}を読めません", history_file.display()))?;
+        let mut rewritten = String::new();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let plaintext = current
+                .open(line)
+                .with_context(|| format!("{}", history_file.display()))?;
+            rewritten.push_str(&next.seal(&plaintext)?);
+            rewritten.push('\n');
+            history_lines += 1;
+        }
+        rewrites.push((history_file, rewritten));
+    }
+    let cache_dir = ReviewCache::dir_path(repo_root);
+    let mut cache_entries = 0;
+    if let Ok(entries) = fs::read_dir(&cache_dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let plaintext = current
+                .open(&fs::read_to_string(&path)?)
+                .with_context(|| format!("{}", path.display()))?;
+            rewrites.push((path, next.seal(&plaintext)?));
+            cache_entries += 1;
+        }
+    }
+
+    if let Some(new_key) = &new_key {
+        if let Some(previous) = store.load()? {
+            store.previous().save(&previous)?;
+        }
+        store.save(new_key)?;
+    }
+    for (path, content) in rewrites {
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, content).with_context(|| format!("{}に書き込めません", path.display()))?;
+        fs::rename(&temp, &path)
+            .with_context(|| format!("{}を置き換えられません", path.display()))?;
+    }
+    Ok(RekeySummary {
+        history_lines,
+        cache_entries,
+        encrypted: new_key.is_some(),
+        key_location: new_key.map(|_| store.describe()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_history::FindingRecord;
+    use tempfile::tempdir;
+
+    #[test]
+    fn seals_with_the_key_and_reads_plaintext_as_is() {
+        let key = generate_key();
+        let cipher = StorageCipher::new(Some(&key), true);
+        let sealed = cipher.seal("{\"review\":\"構文\"}").unwrap();
+        assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+        assert!(!sealed.contains("review"));
+        assert_eq!(cipher.open(&sealed).unwrap(), "{\"review\":\"構文\"}");
+        // 暗号化する前の内容はそのまま読む
+        assert_eq!(cipher.open("{}").unwrap(), "{}");
+
+        // 暗号化しない設定でも、鍵があれば読めるが、書くのは平文
+        let reader = StorageCipher::new(Some(&key), false);
+        assert_eq!(reader.open(&sealed).unwrap(), "{\"review\":\"構文\"}");
+        assert_eq!(reader.seal("{}").unwrap(), "{}");
+
+        assert!(StorageCipher::default().open(&sealed).is_err());
+        assert!(
+            StorageCipher::new(Some(&generate_key()), true)
+                .open(&sealed)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rekeys_history_and_cache_and_keeps_the_previous_key() {
+        let project = tempdir().unwrap();
+        let keys = tempdir().unwrap();
+        let key_file = keys.path().join("storage.key");
+        let mut config = StorageConfig {
+            encrypt: false,
+            key: KeySource::File,
+            key_file: Some(key_file.clone()),
+        };
+
+        // 暗号化を有効にする前に書いた平文の履歴
+        let history = ReviewHistory::open(project.path(), config.cipher().unwrap());
+        history.record_finding(
+            "src/lib.rs",
+            FindingRecord {
+                ts: "2026-10-01T00:00:00Z".to_string(),
+                run_id: None,
+                job_id: None,
+                review: "セキュリティ".to_string(),
+                message: "秘密の指摘".to_string(),
+                severity: None,
+                confidence: None,
+                model: None,
+                provider: None,
+                prompt_hash: None,
+                engine_version: None,
+                line: None,
+            },
+        );
+        assert!(!key_file.exists());
+
+        config.encrypt = true;
+        let cache = ReviewCache::open(project.path(), false, config.cipher().unwrap());
+        cache
+            .put("src/lib.rs", "構文", "gpt-5", "diff", "秘密の回答")
+            .unwrap();
+        let first_key = fs::read_to_string(&key_file).unwrap();
+
+        let summary = rekey(project.path(), project.path(), &config).unwrap();
+        assert_eq!(summary.history_lines, 1);
+        assert_eq!(summary.cache_entries, 1);
+        assert!(summary.encrypted);
+        assert_eq!(
+            fs::read_to_string(keys.path().join("storage.key.old")).unwrap(),
+            first_key
+        );
+        assert_ne!(fs::read_to_string(&key_file).unwrap(), first_key);
+        let stored = fs::read_to_string(ReviewHistory::file_path(project.path())).unwrap();
+        assert!(stored.starts_with(ENCRYPTED_PREFIX));
+        assert!(!stored.contains("秘密"));
+
+        let cipher = config.cipher().unwrap();
+        let reopened = ReviewHistory::open(project.path(), cipher.clone());
+        assert_eq!(reopened.files()[0].findings[0].message, "秘密の指摘");
+        let cache = ReviewCache::open(project.path(), false, cipher);
+        assert_eq!(
+            cache.get("src/lib.rs", "構文", "gpt-5", "diff").as_deref(),
+            Some("秘密の回答")
+        );
+
+        // 暗号化しない設定に戻してから実行すると平文に戻す
+        config.encrypt = false;
+        let summary = rekey(project.path(), project.path(), &config).unwrap();
+        assert!(!summary.encrypted);
+        let stored = fs::read_to_string(ReviewHistory::file_path(project.path())).unwrap();
+        assert!(stored.contains("秘密の指摘"));
+    }
+}
@@ -0,0 +1,771 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use tree_sitter::Language;
+use tree_sitter::Node;
+use tree_sitter::Parser;
+use utoipa::ToSchema;
+
+use crate::ambient_git::run_git_command;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EmitEvent;
+use crate::ambient_server::EventEnvelope;
+
+/// 索引に入れるファイルの最大サイズ（これより大きいファイルは生成物などとみなす）
+const MAX_FILE_BYTES: u64 = 512 * 1024;
+
+/// 1つの定義について返す参照箇所の最大数
+const MAX_REFERENCES: usize = 20;
+
+/// 1つの指摘に添える定義の最大数
+const MAX_CROSS_REFERENCES: usize = 5;
+
+/// 定義の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Struct,
+    Enum,
+    Trait,
+    Interface,
+    Class,
+    Type,
+    Constant,
+    Module,
+    Macro,
+}
+
+impl SymbolKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Method => "method",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Interface => "interface",
+            SymbolKind::Class => "class",
+            SymbolKind::Type => "type",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Module => "module",
+            SymbolKind::Macro => "macro",
+        }
+    }
+}
+
+/// 言語ごとの定義と参照のノードの種類
+struct SyntaxRules {
+    /// 定義のノードの種類（名前は`name`フィールド）と、それが表す定義の種類
+    definitions: &'static [(&'static str, SymbolKind)],
+    /// 名前を参照するノードの種類
+    identifiers: &'static [&'static str],
+    /// 公開を表す子ノードの種類（`None`の言語は`_`や`#`で始まらない名前を公開とみなす）
+    visibility: Option<&'static str>,
+}
+
+const RUST: SyntaxRules = SyntaxRules {
+    definitions: &[
+        ("function_item", SymbolKind::Function),
+        ("function_signature_item", SymbolKind::Function),
+        ("struct_item", SymbolKind::Struct),
+        ("union_item", SymbolKind::Struct),
+        ("enum_item", SymbolKind::Enum),
+        ("trait_item", SymbolKind::Trait),
+        ("type_item", SymbolKind::Type),
+        ("const_item", SymbolKind::Constant),
+        ("static_item", SymbolKind::Constant),
+        ("mod_item", SymbolKind::Module),
+        ("macro_definition", SymbolKind::Macro),
+    ],
+    identifiers: &["identifier", "type_identifier", "field_identifier"],
+    visibility: Some("visibility_modifier"),
+};
+
+const PYTHON: SyntaxRules = SyntaxRules {
+    definitions: &[
+        ("function_definition", SymbolKind::Function),
+        ("class_definition", SymbolKind::Class),
+    ],
+    identifiers: &["identifier"],
+    visibility: None,
+};
+
+const JAVASCRIPT: SyntaxRules = SyntaxRules {
+    definitions: &[
+        ("function_declaration", SymbolKind::Function),
+        ("generator_function_declaration", SymbolKind::Function),
+        ("class_declaration", SymbolKind::Class),
+        ("method_definition", SymbolKind::Method),
+        ("variable_declarator", SymbolKind::Function),
+    ],
+    identifiers: &[
+        "identifier",
+        "property_identifier",
+        "shorthand_property_identifier",
+    ],
+    visibility: None,
+};
+
+const TYPESCRIPT: SyntaxRules = SyntaxRules {
+    definitions: &[
+        ("function_declaration", SymbolKind::Function),
+        ("generator_function_declaration", SymbolKind::Function),
+        ("function_signature", SymbolKind::Function),
+        ("class_declaration", SymbolKind::Class),
+        ("abstract_class_declaration", SymbolKind::Class),
+        ("method_definition", SymbolKind::Method),
+        ("method_signature", SymbolKind::Method),
+        ("variable_declarator", SymbolKind::Function),
+        ("interface_declaration", SymbolKind::Interface),
+        ("type_alias_declaration", SymbolKind::Type),
+        ("enum_declaration", SymbolKind::Enum),
+    ],
+    identifiers: &[
+        "identifier",
+        "property_identifier",
+        "shorthand_property_identifier",
+        "type_identifier",
+    ],
+    visibility: None,
+};
+
+/// 拡張子から構文解析に使う文法と規則を選ぶ（対応していない言語は`None`）
+fn grammar_for(path: &str) -> Option<(Language, &'static SyntaxRules)> {
+    let extension = Path::new(path).extension()?.to_str()?;
+    Some(match extension {
+        "rs" => (tree_sitter_rust::LANGUAGE.into(), &RUST),
+        "py" => (tree_sitter_python::LANGUAGE.into(), &PYTHON),
+        "js" | "mjs" | "cjs" | "jsx" => (tree_sitter_javascript::LANGUAGE.into(), &JAVASCRIPT),
+        "ts" => (
+            tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            &TYPESCRIPT,
+        ),
+        "tsx" => (tree_sitter_typescript::LANGUAGE_TSX.into(), &TYPESCRIPT),
+        _ => return None,
+    })
+}
+
+/// ファイル内の位置
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+pub struct SymbolLocation {
+    /// リポジトリルートからの相対パス
+    pub path: String,
+    /// 行番号（1始まり）
+    pub line: u32,
+}
+
+/// 定義と、その名前を参照している箇所
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct CrossReference {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// 定義の位置（開始行）
+    pub defined_in: SymbolLocation,
+    /// 定義の最終行
+    pub end_line: u32,
+    /// 定義の外で同じ名前を参照している箇所（最大20件）
+    pub referenced_from: Vec<SymbolLocation>,
+    /// 参照している箇所の総数
+    pub reference_count: usize,
+}
+
+impl CrossReference {
+    /// 行が定義の範囲に含まれるか
+    pub fn contains(&self, line: u32) -> bool {
+        (self.defined_in.line..=self.end_line).contains(&line)
+    }
+
+    /// 表示用の文面（`` `name`（function）: 定義 path:行、参照 path:行, ... ``）
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "`{}`（{}）: 定義 {}:{}",
+            self.name,
+            self.kind.as_str(),
+            self.defined_in.path,
+            self.defined_in.line
+        );
+        if self.referenced_from.is_empty() {
+            text.push_str("、参照なし");
+            return text;
+        }
+        let references: Vec<String> = self
+            .referenced_from
+            .iter()
+            .map(|location| format!("{}:{}", location.path, location.line))
+            .collect();
+        text.push_str(&format!("、参照 {}", references.join(", ")));
+        let omitted = self.reference_count - self.referenced_from.len();
+        if omitted > 0 {
+            text.push_str(&format!(" ほか{omitted}件"));
+        }
+        text
+    }
+}
+
+/// シグネチャが変更された公開関数と、それを参照しているファイル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureChange {
+    pub symbol: CrossReference,
+    /// 参照しているすべてのファイル（パスの順、定義のファイルを含む場合がある）
+    pub referencing_files: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Definition {
+    name: String,
+    kind: SymbolKind,
+    line: u32,
+    end_line: u32,
+    /// シグネチャの最終行（本体の開始行、本体がなければ定義の最終行）
+    signature_end: u32,
+    /// ほかのモジュールから使える定義か
+    public: bool,
+}
+
+impl Definition {
+    fn contains(&self, line: u32) -> bool {
+        (self.line..=self.end_line).contains(&line)
+    }
+
+    /// 公開関数のシグネチャが変更されたか（定義の直前の行の削除は含めない）
+    fn signature_changed(&self, changed: &ChangedLines) -> bool {
+        matches!(self.kind, SymbolKind::Function | SymbolKind::Method)
+            && self.public
+            && (changed
+                .added
+                .iter()
+                .any(|line| (self.line..=self.signature_end).contains(line))
+                || changed
+                    .removed
+                    .iter()
+                    .any(|line| (self.line + 1..=self.signature_end).contains(line)))
+    }
+
+    /// `other`を内側に含む（同じ範囲は含まない）
+    fn encloses(&self, other: &Definition) -> bool {
+        self.line <= other.line
+            && other.end_line <= self.end_line
+            && (self.line, self.end_line) != (other.line, other.end_line)
+    }
+}
+
+/// 1つのファイルの定義と参照（参照は名前と行番号）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct FileSymbols {
+    definitions: Vec<Definition>,
+    references: Vec<(String, u32)>,
+}
+
+/// リポジトリ内のシンボルの定義と参照の索引（tree-sitterで解析する）
+///
+/// 参照は名前だけで対応付けるため、同じ名前の別の定義への参照も含まれる。
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    files: Mutex<BTreeMap<String, FileSymbols>>,
+}
+
+impl SymbolIndex {
+    /// 索引に入っているファイルの数
+    pub fn len(&self) -> usize {
+        self.files
+            .lock()
+            .map(|files| files.len())
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 1つのファイルを解析し直す（削除された、または解析できないファイルは索引から外す）
+    pub fn update_file(&self, path: &str, full_path: &Path) {
+        let symbols = read_indexable(full_path).and_then(|content| parse_symbols(path, &content));
+        if let Ok(mut files) = self.files.lock() {
+            match symbols {
+                Some(symbols) => {
+                    files.insert(path.to_string(), symbols);
+                }
+                None => {
+                    files.remove(path);
+                }
+            }
+        }
+    }
+
+    /// リポジトリのすべてのファイル（追跡中と、無視されていない未追跡のもの）で索引を作り直す
+    ///
+    /// 解析には時間がかかるため、索引は最後にまとめて置き換える。索引に入れたファイルの数を返す。
+    pub fn sync_repository(&self, root: &Path) -> Result<usize> {
+        let listed = run_git_command(
+            &["ls-files", "--cached", "--others", "--exclude-standard"],
+            root,
+        )?;
+        let parsed: BTreeMap<String, FileSymbols> = listed
+            .lines()
+            .filter(|path| !path.is_empty() && grammar_for(path).is_some())
+            .filter_map(|path| {
+                let content = read_indexable(&root.join(path))?;
+                Some((path.to_string(), parse_symbols(path, &content)?))
+            })
+            .collect();
+        let count = parsed.len();
+        if let Ok(mut files) = self.files.lock() {
+            *files = parsed;
+        }
+        Ok(count)
+    }
+
+    /// 名前が`query`に一致する定義を、完全一致・前方一致・部分一致（大文字小文字を区別しない）の順に返す
+    pub fn search(&self, query: &str, limit: usize) -> Vec<CrossReference> {
+        let query = query.trim();
+        if query.is_empty() {
+            return vec![];
+        }
+        let lowercase = query.to_lowercase();
+        let Ok(files) = self.files.lock() else {
+            return vec![];
+        };
+        let mut matches: Vec<(u8, &str, &Definition)> = files
+            .iter()
+            .flat_map(|(path, symbols)| {
+                symbols
+                    .definitions
+                    .iter()
+                    .map(move |definition| (path.as_str(), definition))
+            })
+            .filter_map(|(path, definition)| {
+                let name = definition.name.to_lowercase();
+                let rank = if definition.name == query {
+                    0
+                } else if name.starts_with(&lowercase) {
+                    1
+                } else if name.contains(&lowercase) {
+                    2
+                } else {
+                    return None;
+                };
+                Some((rank, path, definition))
+            })
+            .collect();
+        matches
+            .sort_by(|a, b| (a.0, &a.2.name, a.1, a.2.line).cmp(&(b.0, &b.2.name, b.1, b.2.line)));
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, path, definition)| cross_reference(&files, path, definition))
+            .collect()
+    }
+
+    /// diffでシグネチャが変更された公開関数と、それを参照しているファイル（参照のない関数は除く）
+    pub fn changed_signatures(&self, path: &str, diff: &str) -> Vec<SignatureChange> {
+        let changed = ChangedLines::parse(diff);
+        let Ok(files) = self.files.lock() else {
+            return vec![];
+        };
+        let Some(symbols) = files.get(path) else {
+            return vec![];
+        };
+        symbols
+            .definitions
+            .iter()
+            .filter(|definition| definition.signature_changed(&changed))
+            .filter_map(|definition| {
+                let mut referencing_files: Vec<String> = references_to(&files, path, definition)
+                    .into_iter()
+                    .map(|location| location.path)
+                    .collect();
+                referencing_files.dedup();
+                (!referencing_files.is_empty()).then(|| SignatureChange {
+                    symbol: cross_reference(&files, path, definition),
+                    referencing_files,
+                })
+            })
+            .collect()
+    }
+
+    /// diffで変更された行を含む定義と、その参照箇所
+    ///
+    /// モジュールやクラス全体ではなく、変更を含む最も内側の定義だけを返す。
+    pub fn cross_references(&self, path: &str, diff: &str) -> Vec<CrossReference> {
+        let changed = ChangedLines::parse(diff);
+        if changed.is_empty() {
+            return vec![];
+        }
+        let Ok(files) = self.files.lock() else {
+            return vec![];
+        };
+        let Some(symbols) = files.get(path) else {
+            return vec![];
+        };
+        let touched: Vec<&Definition> = symbols
+            .definitions
+            .iter()
+            .filter(|definition| changed.touches(definition))
+            .collect();
+        touched
+            .iter()
+            .filter(|definition| !touched.iter().any(|other| definition.encloses(other)))
+            .take(MAX_CROSS_REFERENCES)
+            .map(|definition| cross_reference(&files, path, definition))
+            .collect()
+    }
+}
+
+/// 定義の外で同じ名前を参照している箇所を集める
+fn cross_reference(
+    files: &BTreeMap<String, FileSymbols>,
+    path: &str,
+    definition: &Definition,
+) -> CrossReference {
+    let references = references_to(files, path, definition);
+    CrossReference {
+        name: definition.name.clone(),
+        kind: definition.kind,
+        defined_in: SymbolLocation {
+            path: path.to_string(),
+            line: definition.line,
+        },
+        end_line: definition.end_line,
+        reference_count: references.len(),
+        referenced_from: references.into_iter().take(MAX_REFERENCES).collect(),
+    }
+}
+
+/// 定義の外で同じ名前を参照しているすべての箇所（パスと行の順）
+fn references_to(
+    files: &BTreeMap<String, FileSymbols>,
+    path: &str,
+    definition: &Definition,
+) -> Vec<SymbolLocation> {
+    files
+        .iter()
+        .flat_map(|(reference_path, symbols)| {
+            symbols
+                .references
+                .iter()
+                .filter(move |(name, line)| {
+                    *name == definition.name
+                        && !(reference_path == path && definition.contains(*line))
+                })
+                .map(|(_, line)| SymbolLocation {
+                    path: reference_path.clone(),
+                    line: *line,
+                })
+        })
+        .collect()
+}
+
+/// 索引に入れられるファイルの内容（大きすぎる、UTF-8でない、読めないファイルは`None`）
+fn read_indexable(path: &Path) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    String::from_utf8(fs::read(path).ok()?).ok()
+}
+
+/// ファイルの定義と参照を解析する（対応していない言語は`None`）
+fn parse_symbols(path: &str, content: &str) -> Option<FileSymbols> {
+    let (language, rules) = grammar_for(path)?;
+    let mut parser = Parser::new();
+    parser.set_language(&language).ok()?;
+    let tree = parser.parse(content, None)?;
+    let source = content.as_bytes();
+    let mut symbols = FileSymbols::default();
+    // 定義の名前のノードは参照に含めない
+    let mut definition_names = HashSet::new();
+    let mut cursor = tree.walk();
+    'walk: loop {
+        let node = cursor.node();
+        if let Some((kind, name)) = definition(node, rules)
+            && let Ok(text) = name.utf8_text(source)
+        {
+            definition_names.insert(name.id());
+            let end_line = node.end_position().row as u32 + 1;
+            symbols.definitions.push(Definition {
+                name: text.to_string(),
+                kind,
+                line: node.start_position().row as u32 + 1,
+                end_line,
+                signature_end: node
+                    .child_by_field_name("body")
+                    .map_or(end_line, |body| body.start_position().row as u32 + 1),
+                public: is_public(node, text, rules),
+            });
+        }
+        if rules.identifiers.contains(&node.kind())
+            && !definition_names.contains(&node.id())
+            && let Ok(text) = node.utf8_text(source)
+        {
+            symbols
+                .references
+                .push((text.to_string(), node.start_position().row as u32 + 1));
+        }
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'walk;
+            }
+        }
+    }
+    symbols.references.sort();
+    symbols.references.dedup();
+    Some(symbols)
+}
+
+/// 定義のノードであれば、その種類と名前のノード
+fn definition<'tree>(node: Node<'tree>, rules: &SyntaxRules) -> Option<(SymbolKind, Node<'tree>)> {
+    let (_, kind) = rules
+        .definitions
+        .iter()
+        .find(|(node_kind, _)| *node_kind == node.kind())?;
+    // 変数は関数を代入している場合だけ定義とみなす
+    if node.kind() == "variable_declarator"
+        && !node.child_by_field_name("value").is_some_and(|value| {
+            matches!(
+                value.kind(),
+                "arrow_function" | "function_expression" | "function"
+            )
+        })
+    {
+        return None;
+    }
+    let name = node.child_by_field_name("name")?;
+    rules
+        .identifiers
+        .contains(&name.kind())
+        .then_some((*kind, name))
+}
+
+/// 定義がほかのモジュールから使えるか
+fn is_public(node: Node, name: &str, rules: &SyntaxRules) -> bool {
+    match rules.visibility {
+        Some(visibility) => {
+            let mut cursor = node.walk();
+            node.children(&mut cursor)
+                .any(|child| child.kind() == visibility)
+        }
+        None => !name.starts_with('_') && !name.starts_with('#'),
+    }
+}
+
+/// diffで追加・削除された行の、変更後のファイルでの行番号（削除はその位置の次の行）
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ChangedLines {
+    added: Vec<u32>,
+    removed: Vec<u32>,
+}
+
+impl ChangedLines {
+    fn parse(diff: &str) -> Self {
+        let mut changed = Self::default();
+        let mut next_line: Option<u32> = None;
+        for line in diff.lines() {
+            if line.starts_with("diff --git ") {
+                next_line = None;
+            } else if let Some(header) = line.strip_prefix("@@ ") {
+                next_line = header
+                    .split_whitespace()
+                    .find_map(|range| range.strip_prefix('+'))
+                    .and_then(|range| range.split(',').next()?.parse().ok());
+            } else if let Some(current) = next_line.as_mut() {
+                if line.starts_with('+') {
+                    changed.added.push(*current);
+                    *current += 1;
+                } else if line.starts_with('-') {
+                    changed.removed.push(*current);
+                } else if !line.starts_with('\\') {
+                    *current += 1;
+                }
+            }
+        }
+        changed.removed.dedup();
+        changed
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// 定義の範囲の行が変更されたか
+    fn touches(&self, definition: &Definition) -> bool {
+        self.added
+            .iter()
+            .chain(&self.removed)
+            .any(|line| definition.contains(*line))
+    }
+}
+
+/// リポジトリ全体の索引をバックグラウンドで作り、結果をシステムメッセージで知らせる
+pub fn spawn_repository_sync(
+    index: Arc<SymbolIndex>,
+    root: PathBuf,
+    tx: broadcast::Sender<EventEnvelope>,
+) {
+    tokio::task::spawn_blocking(move || {
+        let message = match index.sync_repository(&root) {
+            Ok(count) => format!("{count}件のファイルのシンボルを索引に入れました"),
+            Err(e) => format!("シンボルの索引を作成できません: {e:#}"),
+        };
+        let _ = tx.emit(AmbientEvent::System(message));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_test_support::TempRepo;
+
+    const LIB_RS: &str = "\
+pub struct Config {
+    pub name: String,
+}
+
+pub fn parse_config(text: &str) -> Config {
+    Config {
+        name: text.to_string(),
+    }
+}
+";
+
+    const MAIN_RS: &str = "\
+mod lib;
+
+fn main() {
+    let config = lib::parse_config(\"app\");
+    println!(\"{}\", config.name);
+}
+";
+
+    #[test]
+    fn parses_definitions_and_references() {
+        let symbols = parse_symbols("src/lib.rs", LIB_RS).unwrap();
+        let definitions: Vec<(&str, SymbolKind, u32, u32)> = symbols
+            .definitions
+            .iter()
+            .map(|d| (d.name.as_str(), d.kind, d.line, d.end_line))
+            .collect();
+        assert_eq!(
+            definitions,
+            vec![
+                ("Config", SymbolKind::Struct, 1, 3),
+                ("parse_config", SymbolKind::Function, 5, 9),
+            ]
+        );
+        // 定義の名前そのものは参照に含めない
+        assert!(symbols.references.contains(&("Config".to_string(), 5)));
+        assert!(
+            !symbols
+                .references
+                .contains(&("parse_config".to_string(), 5))
+        );
+
+        let symbols =
+            parse_symbols("web/app.ts", "const render = () => {};\nconst x = 1;\n").unwrap();
+        assert_eq!(symbols.definitions.len(), 1);
+        assert_eq!(symbols.definitions[0].name, "render");
+        assert!(parse_symbols("README.md", "# readme").is_none());
+    }
+
+    #[test]
+    fn finds_changed_lines_in_diff() {
+        let diff = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -5,4 +5,4 @@ pub struct Config {
+ pub fn parse_config(text: &str) -> Config {
+     Config {
+-        name: text.into(),
++        name: text.to_string(),
+     }
+";
+        assert_eq!(
+            ChangedLines::parse(diff),
+            ChangedLines {
+                added: vec![7],
+                removed: vec![7],
+            }
+        );
+    }
+
+    #[test]
+    fn searches_and_cross_references_the_repository() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/lib.rs", LIB_RS).unwrap();
+        repo.write("src/main.rs", MAIN_RS).unwrap();
+        repo.write("notes.txt", "parse_config").unwrap();
+        repo.add(&["src/lib.rs", "src/main.rs", "notes.txt"])
+            .unwrap();
+
+        let index = SymbolIndex::default();
+        assert_eq!(index.sync_repository(repo.path()).unwrap(), 2);
+
+        let found = index.search("parse", 10);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].defined_in,
+            SymbolLocation {
+                path: "src/lib.rs".to_string(),
+                line: 5,
+            }
+        );
+        assert_eq!(
+            found[0].referenced_from,
+            vec![SymbolLocation {
+                path: "src/main.rs".to_string(),
+                line: 4,
+            }]
+        );
+        // 完全一致を部分一致より先に返す
+        let names: Vec<String> = index
+            .search("config", 10)
+            .into_iter()
+            .map(|symbol| symbol.name)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["Config".to_string(), "parse_config".to_string()]
+        );
+
+        // 変更された行を含む最も内側の定義だけを返す
+        let diff = "@@ -6,3 +6,3 @@\n     Config {\n-        name: text.into(),\n+        name: text.to_string(),\n";
+        let references = index.cross_references("src/lib.rs", diff);
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].name, "parse_config");
+        assert!(references[0].describe().contains("参照 src/main.rs:4"));
+
+        // 本体だけの変更はシグネチャの変更ではない
+        assert!(index.changed_signatures("src/lib.rs", diff).is_empty());
+        let diff = "@@ -5,1 +5,1 @@\n-pub fn parse_config(text: String) -> Config {\n+pub fn parse_config(text: &str) -> Config {\n";
+        let changes = index.changed_signatures("src/lib.rs", diff);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].symbol.name, "parse_config");
+        assert_eq!(
+            changes[0].referencing_files,
+            vec!["src/main.rs".to_string()]
+        );
+
+        // 削除されたファイルは索引から外す
+        fs::remove_file(repo.path().join("src/main.rs")).unwrap();
+        index.update_file("src/main.rs", &repo.path().join("src/main.rs"));
+        assert_eq!(index.len(), 1);
+        assert!(
+            index.search("parse_config", 10)[0]
+                .referenced_from
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_time::now_stamp;
+
+/// 組み込み以外のレビューをまとめて数える名前（プロジェクト固有のレビュー名は記録しない）
+pub const CUSTOM_REVIEW: &str = "custom";
+
+/// 匿名の利用状況（`telemetry = true`のときだけ記録し、送信はしない）
+///
+/// ファイルのパスやレビューの内容は含めず、件数だけを数える。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageStats {
+    /// 集計を始めた時刻（RFC3339、UTC）
+    pub since: String,
+    /// 実行したチェックサイクルの数
+    pub cycles: u64,
+    /// 分析したファイルの数
+    pub files_analyzed: u64,
+    /// レビューごとの結果の数（組み込み以外は`custom`）
+    pub reviews: BTreeMap<String, u64>,
+    /// 種類ごとのエラーの数
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl UsageStats {
+    /// イベントを1件数える
+    pub fn record(&mut self, event: &AmbientEvent, builtin_reviews: &BTreeSet<String>) {
+        match event {
+            AmbientEvent::CycleSummary(summary) => {
+                self.cycles += 1;
+                self.files_analyzed += summary.analyzed.len() as u64;
+            }
+            AmbientEvent::Finding(_) | AmbientEvent::Findings(_) => {
+                for finding in event.findings() {
+                    let review = if builtin_reviews.contains(&finding.review) {
+                        finding.review.as_str()
+                    } else {
+                        CUSTOM_REVIEW
+                    };
+                    *self.reviews.entry(review.to_string()).or_default() += 1;
+                }
+            }
+            AmbientEvent::Analysis(message) => {
+                if let Some(error) = message.text.trim_start().strip_prefix("Error:") {
+                    *self
+                        .errors
+                        .entry(error_class(error).to_string())
+                        .or_default() += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// エラーの種類（メッセージ本文は記録しない）
+fn error_class(message: &str) -> &'static str {
+    let message = message.to_lowercase();
+    if message.contains("timed out") || message.contains("timeout") {
+        "timeout"
+    } else if message.contains("connect") || message.contains("error sending request") {
+        "connection"
+    } else if message.contains("not found") {
+        "configuration"
+    } else if message.contains("stream") {
+        "stream"
+    } else if message.contains("status") || message.contains("http") {
+        "http"
+    } else if message.contains("git") {
+        "git"
+    } else {
+        "other"
+    }
+}
+
+/// 利用状況をファイルに集計する
+#[derive(Debug)]
+pub struct UsageRecorder {
+    path: PathBuf,
+    builtin_reviews: BTreeSet<String>,
+    stats: Mutex<UsageStats>,
+}
+
+impl UsageRecorder {
+    /// 既存の集計があれば引き継ぐ（壊れている場合は集計し直す）
+    pub fn open(path: PathBuf, builtin_reviews: BTreeSet<String>) -> Self {
+        let stats = read_usage(&path).unwrap_or_else(|_| UsageStats {
+            since: now_stamp().0,
+            ..Default::default()
+        });
+        Self {
+            path,
+            builtin_reviews,
+            stats: Mutex::new(stats),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, event: &AmbientEvent) {
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.record(event, &self.builtin_reviews);
+        }
+    }
+
+    pub fn snapshot(&self) -> UsageStats {
+        self.stats
+            .lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(
+            &self.path,
+            serde_json::to_string_pretty(&self.snapshot())? + "\n",
+        )?;
+        Ok(())
+    }
+}
+
+/// 集計したファイルを読み込む
+pub fn read_usage(path: &Path) -> Result<UsageStats> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("{}を読み込めません", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("{}を解析できません", path.display()))
+}
+
+/// イベントを数え、チェックサイクルが終わるたびにファイルへ書き出す
+pub async fn record_usage(
+    mut rx: broadcast::Receiver<EventEnvelope>,
+    recorder: Arc<UsageRecorder>,
+) {
+    loop {
+        match rx.recv().await {
+            Ok(envelope) => {
+                recorder.record(&envelope.event);
+                if matches!(envelope.event, AmbientEvent::CycleSummary(_))
+                    && let Err(e) = recorder.save()
+                {
+                    tracing::warn!("failed to save usage stats: {e}");
+                }
+            }
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+    if let Err(e) = recorder.save() {
+        tracing::warn!("failed to save usage stats: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_cycle::CycleSummary;
+    use crate::ambient_server::AnalysisMessage;
+    use crate::ambient_server::Finding;
+    use crate::ambient_server::RunIds;
+    use tempfile::tempdir;
+
+    fn finding(review: &str) -> AmbientEvent {
+        AmbientEvent::Finding(Finding {
+            ids: RunIds::new_run().new_job(),
+            file_path: "secret/project.rs".to_string(),
+            review: review.to_string(),
+            message: "指摘".to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity: None,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: None,
+            suggestion: None,
+            cross_references: vec![],
+        })
+    }
+
+    #[test]
+    fn counts_without_identifying_details() {
+        let builtin = BTreeSet::from(["セキュリティリスク検出".to_string()]);
+        let mut stats = UsageStats::default();
+        stats.record(&finding("セキュリティリスク検出"), &builtin);
+        stats.record(&finding("社内APIの規約チェック"), &builtin);
+        stats.record(
+            &AmbientEvent::Analysis(AnalysisMessage {
+                ids: RunIds::new_run(),
+                text: "Error: Failed to get AI insight: operation timed out".to_string(),
+                budget: None,
+            }),
+            &builtin,
+        );
+        stats.record(
+            &AmbientEvent::CycleSummary(CycleSummary {
+                analyzed: vec!["a.rs".to_string(), "b.rs".to_string()],
+                ..Default::default()
+            }),
+            &builtin,
+        );
+
+        assert_eq!(stats.cycles, 1);
+        assert_eq!(stats.files_analyzed, 2);
+        assert_eq!(
+            stats.reviews,
+            BTreeMap::from([
+                ("custom".to_string(), 1),
+                ("セキュリティリスク検出".to_string(), 1)
+            ])
+        );
+        assert_eq!(stats.errors, BTreeMap::from([("timeout".to_string(), 1)]));
+        let json = serde_json::to_string(&stats).unwrap();
+        assert!(!json.contains("secret/project.rs"));
+        assert!(!json.contains("社内API"));
+    }
+
+    #[test]
+    fn keeps_counts_across_runs() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("ambient-usage.json");
+        let recorder = UsageRecorder::open(path.clone(), BTreeSet::new());
+        recorder.record(&AmbientEvent::CycleSummary(CycleSummary::default()));
+        recorder.save().unwrap();
+
+        let reopened = UsageRecorder::open(path, BTreeSet::new());
+        reopened.record(&AmbientEvent::CycleSummary(CycleSummary::default()));
+        assert_eq!(reopened.snapshot().cycles, 2);
+        assert_eq!(reopened.snapshot().since, recorder.snapshot().since);
+    }
+}
@@ -0,0 +1,231 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::io::Write;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::ambient_confidence::Severity;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_server::Finding;
+
+/// 通知の本文の最大文字数（長い回答は端末の通知に収まらない）
+const MAX_BODY_CHARS: usize = 200;
+
+/// 通知のタイトル（OSC 777のみ）
+const NOTIFICATION_TITLE: &str = "Ambient Code Watcher";
+
+/// `~/.codex/ambient.toml`の`[terminal_notify]`（端末のエスケープシーケンスでデスクトップ通知を出す）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TerminalNotifyConfig {
+    /// 監視中と`ambient --once`で、指摘が届いたら端末に通知する
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 通知のエスケープシーケンス（kittyやiTerm2は`osc9`、rxvtやWezTermは`osc777`も使える）
+    #[serde(default)]
+    pub protocol: TerminalProtocol,
+
+    /// 通知を出す重大度
+    #[serde(default = "default_notify_severities")]
+    pub notify: Vec<Severity>,
+
+    /// ベルを鳴らす重大度
+    #[serde(default = "default_bell_severities")]
+    pub bell: Vec<Severity>,
+}
+
+fn default_notify_severities() -> Vec<Severity> {
+    vec![Severity::Critical, Severity::High]
+}
+
+fn default_bell_severities() -> Vec<Severity> {
+    vec![Severity::Critical]
+}
+
+impl Default for TerminalNotifyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            protocol: TerminalProtocol::default(),
+            notify: default_notify_severities(),
+            bell: default_bell_severities(),
+        }
+    }
+}
+
+/// 端末の通知のエスケープシーケンス
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminalProtocol {
+    /// `ESC ] 9 ; 本文 BEL`
+    #[default]
+    Osc9,
+    /// `ESC ] 777 ; notify ; タイトル ; 本文 BEL`
+    Osc777,
+}
+
+impl TerminalNotifyConfig {
+    /// 指摘に対して端末に書くシーケンス（通知もベルも不要ならなし）
+    ///
+    /// tmuxの中では、外側の端末に届くようにパススルーで包む。
+    pub fn sequence(&self, finding: &Finding, in_tmux: bool) -> Option<String> {
+        if !self.enabled || finding.needs_human_review {
+            return None;
+        }
+        let severity = finding.severity?;
+        let mut sequence = String::new();
+        if self.notify.contains(&severity) {
+            let body = notification_body(finding, severity);
+            let osc = match self.protocol {
+                TerminalProtocol::Osc9 => format!("\x1b]9;{body}\x07"),
+                TerminalProtocol::Osc777 => {
+                    format!("\x1b]777;notify;{NOTIFICATION_TITLE};{body}\x07")
+                }
+            };
+            sequence.push_str(&if in_tmux { tmux_passthrough(&osc) } else { osc });
+        }
+        if self.bell.contains(&severity) {
+            sequence.push('\x07');
+        }
+        (!sequence.is_empty()).then_some(sequence)
+    }
+
+    /// 指摘の通知を標準エラー出力に書く（端末でなければ何もしない）
+    pub fn notify(&self, finding: &Finding) {
+        let mut stderr = std::io::stderr();
+        if !stderr.is_terminal() {
+            return;
+        }
+        let in_tmux = std::env::var_os("TMUX").is_some();
+        if let Some(sequence) = self.sequence(finding, in_tmux) {
+            let _ = stderr.write_all(sequence.as_bytes());
+            let _ = stderr.flush();
+        }
+    }
+}
+
+/// `[high] src/lib.rs:12 レビュー: 回答の1行目`（制御文字と`;`は空白にする）
+fn notification_body(finding: &Finding, severity: Severity) -> String {
+    let location = match finding.line {
+        Some(line) => format!("{}:{line}", finding.file_path),
+        None => finding.file_path.clone(),
+    };
+    let summary = finding
+        .message
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    format!(
+        "[{}] {location} {}: {summary}",
+        severity.as_str(),
+        finding.review
+    )
+    .chars()
+    .map(|c| if c.is_control() || c == ';' { ' ' } else { c })
+    .take(MAX_BODY_CHARS)
+    .collect()
+}
+
+/// tmuxのパススルー（`ESC P tmux; … ESC \`、中のESCは2つ重ねる）
+fn tmux_passthrough(sequence: &str) -> String {
+    format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+}
+
+/// `enabled`の場合だけ、端末への通知を始める
+pub fn spawn_terminal_notifier(
+    config: &TerminalNotifyConfig,
+    tx: &broadcast::Sender<EventEnvelope>,
+) {
+    if config.enabled {
+        tokio::spawn(notify_terminal(tx.subscribe(), config.clone()));
+    }
+}
+
+/// 監視ループのレビュー結果を端末に通知する
+async fn notify_terminal(mut rx: broadcast::Receiver<EventEnvelope>, config: TerminalNotifyConfig) {
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: event @ (AmbientEvent::Finding(_) | AmbientEvent::Findings(_)),
+                ..
+            }) => {
+                for finding in event.findings() {
+                    config.notify(finding);
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::RunIds;
+
+    fn finding(severity: Severity) -> Finding {
+        Finding {
+            ids: RunIds::default(),
+            file_path: "src/lib.rs".to_string(),
+            review: "セキュリティ".to_string(),
+            message: "\nSQLインジェクション; 入力をエスケープしていません\n詳細".to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: vec![],
+            confidence: None,
+            severity: Some(severity),
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: Some(12),
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn writes_notifications_and_bells_per_severity() {
+        let config = TerminalNotifyConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            config
+                .sequence(&finding(Severity::Critical), false)
+                .as_deref(),
+            Some(
+                "\x1b]9;[critical] src/lib.rs:12 セキュリティ: SQLインジェクション  入力をエスケープしていません\x07\x07"
+            )
+        );
+        assert_eq!(
+            config.sequence(&finding(Severity::High), false).as_deref(),
+            Some(
+                "\x1b]9;[high] src/lib.rs:12 セキュリティ: SQLインジェクション  入力をエスケープしていません\x07"
+            )
+        );
+        assert_eq!(config.sequence(&finding(Severity::Medium), false), None);
+
+        let config = TerminalNotifyConfig {
+            enabled: true,
+            protocol: TerminalProtocol::Osc777,
+            notify: vec![Severity::Medium],
+            bell: vec![],
+        };
+        assert_eq!(
+            config.sequence(&finding(Severity::Medium), true).as_deref(),
+            Some(
+                "\x1bPtmux;\x1b\x1b]777;notify;Ambient Code Watcher;[medium] src/lib.rs:12 セキュリティ: SQLインジェクション  入力をエスケープしていません\x07\x1b\\"
+            )
+        );
+        assert_eq!(
+            TerminalNotifyConfig::default().sequence(&finding(Severity::Critical), false),
+            None
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! 監視ループを組み込む側のテスト用の部品（`test-support`フィーチャー）
+//!
+//! 一時的なGitリポジトリ、チャット補完APIのモックサーバー、イベントを溜めるだけの受け手を提供する。
+
+use anyhow::Context;
+use anyhow::Result;
+use codex_core::BUILT_IN_OSS_MODEL_PROVIDER_ID;
+use codex_core::ModelProviderInfo;
+use codex_core::WireApi;
+use codex_core::config::Config;
+use codex_core::config_types::History;
+use codex_core::config_types::ShellEnvironmentPolicy;
+use codex_core::config_types::Tui;
+use codex_core::config_types::UriBasedFileOpener;
+use codex_core::model_family::find_family_for_model;
+use codex_core::protocol::AskForApproval;
+use codex_core::protocol::SandboxPolicy;
+use codex_protocol::mcp_protocol::AuthMode;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::ResponseTemplate;
+use wiremock::matchers::method;
+use wiremock::matchers::path;
+
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_server::Finding;
+
+/// モックサーバーで使うモデル名（チャット補完APIのモデルファミリーとして解決できるもの）
+pub const TEST_MODEL: &str = "gpt-3.5-turbo";
+
+/// 削除されるまで残る一時的なGitリポジトリ
+pub struct TempRepo {
+    dir: TempDir,
+}
+
+impl TempRepo {
+    /// 空のリポジトリを作る（コミットできるようにユーザー名も設定する）
+    pub fn new() -> Result<Self> {
+        let repo = Self {
+            dir: tempfile::tempdir()?,
+        };
+        repo.git(&["init", "-q"])?;
+        repo.git(&["config", "user.name", "ambient-test"])?;
+        repo.git(&["config", "user.email", "ambient-test@example.com"])?;
+        Ok(repo)
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// ファイルを書き込む（途中のディレクトリも作る）
+    pub fn write(&self, file: &str, content: &str) -> Result<PathBuf> {
+        let path = self.path().join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    /// ファイルをステージする
+    pub fn add(&self, files: &[&str]) -> Result<()> {
+        let mut args = vec!["add", "--"];
+        args.extend_from_slice(files);
+        self.git(&args)
+    }
+
+    /// ステージした変更をコミットする
+    pub fn commit(&self, message: &str) -> Result<()> {
+        self.git(&["commit", "-q", "-m", message])
+    }
+
+    /// リポジトリでgitを実行する
+    pub fn git(&self, args: &[&str]) -> Result<()> {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(self.path())
+            .output()
+            .context("gitを実行できません")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "git {}が失敗しました: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(())
+    }
+}
+
+/// チャット補完APIのストリーミング応答（SSE）を作る
+pub fn sse_body(chunks: &[&str]) -> String {
+    let mut body = String::new();
+    for chunk in chunks {
+        let event = serde_json::json!({"choices": [{"delta": {"content": chunk}}]});
+        body.push_str(&format!("data: {event}\n\n"));
+    }
+    body.push_str("data: [DONE]\n\n");
+    body
+}
+
+/// 決まった応答を返すチャット補完APIのモックサーバー
+pub struct MockModel {
+    server: MockServer,
+}
+
+impl MockModel {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// すべての呼び出しに`text`をストリーミングで返す
+    pub async fn respond_with_text(&self, text: &str) {
+        self.respond_with_chunks(&[text]).await;
+    }
+
+    /// すべての呼び出しに`chunks`を1つずつストリーミングで返す
+    pub async fn respond_with_chunks(&self, chunks: &[&str]) {
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body(chunks)),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// すべての呼び出しに、`delay`だけ待ってから`text`を返す（遅いモデルの代わり）
+    pub async fn respond_with_text_after(&self, text: &str, delay: Duration) {
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(sse_body(&[text]))
+                    .set_delay(delay),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// すべての呼び出しを`status`で失敗させる
+    pub async fn fail_with(&self, status: u16) {
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(status))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// 受け取ったリクエストの数
+    pub async fn request_count(&self) -> usize {
+        self.server
+            .received_requests()
+            .await
+            .map_or(0, |requests| requests.len())
+    }
+
+    /// このサーバーを既定のプロバイダーとして使う設定
+    pub fn config(&self) -> Config {
+        test_config(&self.uri())
+    }
+}
+
+/// `base_url`のチャット補完APIを使う、テスト用の最小限の設定
+pub fn test_config(base_url: &str) -> Config {
+    let model = TEST_MODEL.to_string();
+    let provider_id = BUILT_IN_OSS_MODEL_PROVIDER_ID.to_string();
+    let provider_info = ModelProviderInfo {
+        name: "test-provider".to_string(),
+        base_url: Some(base_url.to_string()),
+        env_key: None,
+        env_key_instructions: None,
+        wire_api: WireApi::Chat,
+        query_params: None,
+        http_headers: None,
+        env_http_headers: None,
+        request_max_retries: Some(1),
+        stream_max_retries: Some(1),
+        stream_idle_timeout_ms: Some(1000),
+        requires_openai_auth: false,
+    };
+
+    Config {
+        model_family: find_family_for_model(&model)
+            .unwrap_or_else(|| panic!("unknown test model: {model}")),
+        model,
+        model_provider_id: provider_id.clone(),
+        // This is deprecated, but required for now.
+        model_provider: provider_info.clone(),
+        model_providers: HashMap::from([(provider_id, provider_info)]),
+        model_context_window: None,
+        model_max_output_tokens: None,
+        approval_policy: AskForApproval::OnRequest,
+        sandbox_policy: SandboxPolicy::ReadOnly,
+        shell_environment_policy: ShellEnvironmentPolicy::default(),
+        hide_agent_reasoning: false,
+        show_raw_agent_reasoning: false,
+        disable_response_storage: false,
+        user_instructions: None,
+        base_instructions: None,
+        notify: None,
+        cwd: PathBuf::new(),
+        mcp_servers: HashMap::new(),
+        project_doc_max_bytes: 0,
+        codex_home: PathBuf::new(),
+        history: History::default(),
+        file_opener: UriBasedFileOpener::VsCode,
+        tui: Tui::default(),
+        codex_linux_sandbox_exe: None,
+        model_reasoning_effort: Default::default(),
+        model_reasoning_summary: Default::default(),
+        model_verbosity: None,
+        chatgpt_base_url: "".to_string(),
+        experimental_resume: None,
+        include_plan_tool: false,
+        include_apply_patch_tool: false,
+        tools_web_search_request: false,
+        responses_originator_header: "".to_string(),
+        preferred_auth_method: AuthMode::ChatGPT,
+        use_experimental_streamable_shell_tool: false,
+        include_view_image_tool: false,
+        disable_paste_burst: false,
+    }
+}
+
+/// 受け取ったイベントをメモリに溜めるだけの受け手
+#[derive(Clone, Default)]
+pub struct EventSink {
+    events: Arc<Mutex<Vec<EventEnvelope>>>,
+}
+
+impl EventSink {
+    /// `tx`のイベントを送信側が閉じられるまで溜める
+    pub fn attach(tx: &broadcast::Sender<EventEnvelope>) -> Self {
+        let sink = Self::default();
+        let mut rx = tx.subscribe();
+        let events = sink.events.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(envelope) => {
+                        if let Ok(mut events) = events.lock() {
+                            events.push(envelope);
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        sink
+    }
+
+    /// これまでに受け取ったイベント
+    pub fn events(&self) -> Vec<EventEnvelope> {
+        self.events
+            .lock()
+            .map(|events| events.clone())
+            .unwrap_or_default()
+    }
+
+    /// これまでに受け取ったレビュー結果
+    pub fn findings(&self) -> Vec<Finding> {
+        self.events()
+            .into_iter()
+            .flat_map(|envelope| envelope.event.into_findings())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::AmbientEvent;
+    use crate::ambient_server::EmitEvent;
+
+    #[tokio::test]
+    async fn sink_collects_emitted_events() {
+        let (tx, _rx) = broadcast::channel(16);
+        let sink = EventSink::attach(&tx);
+        let _ = tx.emit(AmbientEvent::System("起動".to_string()));
+        tokio::task::yield_now().await;
+        for _ in 0..10 {
+            if !sink.events().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(sink.events().len(), 1);
+        assert!(sink.findings().is_empty());
+    }
+
+    #[test]
+    fn temp_repo_commits_files() {
+        let repo = TempRepo::new().unwrap();
+        repo.write("src/lib.rs", "pub fn f() {}").unwrap();
+        repo.add(&["src/lib.rs"]).unwrap();
+        repo.commit("init").unwrap();
+        assert!(repo.git(&["rev-parse", "HEAD"]).is_ok());
+    }
+}
@@ -0,0 +1,114 @@
+use chrono::FixedOffset;
+use chrono::Local;
+use chrono::Offset;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use std::sync::OnceLock;
+
+/// 表示に使うタイムゾーン（`.ambient/config.toml`の`timezone`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisplayTimezone {
+    /// 実行環境のローカルタイムゾーン
+    #[default]
+    Local,
+    /// 固定オフセット（`utc`は`+00:00`）
+    Fixed(FixedOffset),
+}
+
+impl DisplayTimezone {
+    /// `local`、`utc`、または`+09:00`形式のオフセットを解析する
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.trim() {
+            "" | "local" => Ok(DisplayTimezone::Local),
+            "utc" | "UTC" | "Z" => Ok(DisplayTimezone::Fixed(Utc.fix())),
+            offset => parse_offset(offset)
+                .map(DisplayTimezone::Fixed)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "timezoneは local、utc、または +09:00 の形式で指定してください: {value}"
+                    )
+                }),
+        }
+    }
+
+    fn offset(self) -> FixedOffset {
+        match self {
+            DisplayTimezone::Local => Local::now().offset().fix(),
+            DisplayTimezone::Fixed(offset) => offset,
+        }
+    }
+}
+
+static DISPLAY_TIMEZONE: OnceLock<DisplayTimezone> = OnceLock::new();
+
+/// イベントに付ける表示用タイムゾーンを設定する（起動時に1回だけ有効）
+pub fn set_display_timezone(timezone: DisplayTimezone) {
+    let _ = DISPLAY_TIMEZONE.set(timezone);
+}
+
+/// 現在時刻（RFC3339、UTC）と表示用タイムゾーンのオフセット（`+09:00`形式）
+pub fn now_stamp() -> (String, String) {
+    let ts = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    (ts, display_offset().to_string())
+}
+
+/// 表示用タイムゾーンの現在のUTCからのオフセット
+pub fn display_offset() -> FixedOffset {
+    DISPLAY_TIMEZONE.get().copied().unwrap_or_default().offset()
+}
+
+fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let (sign, rest) = match value.split_at_checked(1)? {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':')?;
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timezones() {
+        assert_eq!(
+            DisplayTimezone::parse("local").unwrap(),
+            DisplayTimezone::Local
+        );
+        assert_eq!(
+            DisplayTimezone::parse("utc").unwrap().offset().to_string(),
+            "+00:00"
+        );
+        assert_eq!(
+            DisplayTimezone::parse("+09:00")
+                .unwrap()
+                .offset()
+                .to_string(),
+            "+09:00"
+        );
+        assert_eq!(
+            DisplayTimezone::parse("-05:30")
+                .unwrap()
+                .offset()
+                .to_string(),
+            "-05:30"
+        );
+        assert!(DisplayTimezone::parse("Asia/Tokyo").is_err());
+        assert!(DisplayTimezone::parse("+25:00").is_err());
+    }
+
+    #[test]
+    fn stamps_are_rfc3339_utc() {
+        let (ts, offset) = now_stamp();
+        assert!(ts.ends_with('Z'));
+        assert!(chrono::DateTime::parse_from_rfc3339(&ts).is_ok());
+        assert_eq!(offset.len(), "+00:00".len());
+    }
+}
@@ -12,7 +12,46 @@ const UI_STRINGS = {
     CONNECTION_FINAL_ERROR: 'サーバーへの接続に失敗しました。ページを再読み込みしてください。',
     NOT_CONNECTED: 'サーバーに接続されていません。接続を待っています...',
     PARSE_ERROR: 'サーバーからの不正なメッセージを受信しました',
-    LAST_UPDATE: '最終更新'
+    LAST_UPDATE: '最終更新',
+    SNAPSHOT_TOGGLE: '変更前後のコード',
+    SNAPSHOT_BEFORE: '変更前 (HEAD)',
+    SNAPSHOT_AFTER: '変更後',
+    SNAPSHOT_EMPTY: '(なし)',
+    SHOW_FILE: 'ファイル全体',
+    SHOW_DIFF: 'diff',
+    CONTEXT_FETCH_FAILED: '取得に失敗しました',
+    CYCLE_SUMMARY: 'チェック結果',
+    OWNERS: '担当',
+    LABELS: 'ラベル',
+    SHOW_HISTORY: '履歴',
+    CONFIDENCE: '確信度',
+    SEVERITY: '重大度',
+    REPEATED: '同じ指摘',
+    REMINDER: 'リマインダー',
+    NEEDS_HUMAN_REVIEW: '要確認',
+    MARK_FALSE_POSITIVE: '誤検知',
+    MARKED_FALSE_POSITIVE: '誤検知として記録しました',
+    HISTORY_EMPTY: 'このファイルの履歴はまだありません',
+    HISTORY_REPLAYED: '件の過去の指摘を表示しました',
+    SUGGESTION: '修正案',
+    DEFINED_IN: '定義',
+    REFERENCED_FROM: '参照',
+    NO_REFERENCES: '参照なし',
+    PAUSE: '一時停止',
+    RESUME: '再開',
+    ALL_PROJECTS: 'すべてのプロジェクト',
+    YOU: 'You'
+};
+
+// スキップ理由の表示名（`SkipReason`のJSON表記に対応）
+const SKIP_REASONS = {
+    excluded: '除外パターンに一致',
+    extension: '対象外の拡張子',
+    too_large: 'サイズ上限を超過',
+    binary: 'バイナリファイル',
+    unchanged: '前回の分析から変更なし',
+    budget_exhausted: '1回の上限に到達',
+    submodule: 'サブモジュール'
 };
 
 // CSSクラス名の定数
@@ -28,13 +67,20 @@ const CSS_CLASSES = {
     SYSTEM: 'system',
     INFO: 'info',
     WARNING: 'warning',
-    SUCCESS: 'success'
+    SUCCESS: 'success',
+    FINDING: 'finding',
+    SNAPSHOTS: 'snapshots',
+    SNAPSHOT: 'snapshot',
+    SNAPSHOT_PANE: 'snapshot-pane',
+    JOB_GROUP: 'job-group'
 };
 
 // 設定値の定数
 const CONFIG = {
     MAX_RECONNECT_ATTEMPTS: 5,
     RECONNECT_DELAY_MS: 3000,
+    HISTORY_LIMIT: 50,
+    PAUSE_MINUTES: 60,
     SCROLL_DELAY_MS: 100
 };
 
@@ -42,6 +88,31 @@ document.addEventListener('DOMContentLoaded', () => {
     const logContainer = document.getElementById('log-container');
     const statusDiv = document.getElementById('status');
     const lastUpdateDiv = document.getElementById('last-update');
+    const pauseButton = document.getElementById('pause-toggle');
+    const projectFilter = document.getElementById('project-filter');
+
+    // 共有URL（?token=...）で開かれた場合はそのトークンでサーバーに接続する
+    const pageParams = new URLSearchParams(window.location.search);
+    const accessToken = pageParams.get('token');
+    const withToken = (url) => accessToken
+        ? `${url}${url.includes('?') ? '&' : '?'}token=${encodeURIComponent(accessToken)}`
+        : url;
+    // ?name=... で質問に付与する表示名を指定できる
+    const displayName = pageParams.get('name');
+    // ブラウザが展開に対応していれば、大きなイベントをzlib圧縮で受け取る
+    const wireEncoding = typeof DecompressionStream !== 'undefined' ? 'zlib-json' : 'json';
+    const wsUrl = (base) => {
+        const params = [`encoding=${wireEncoding}`];
+        if (displayName) {
+            params.push(`name=${encodeURIComponent(displayName)}`);
+        }
+        const url = withToken(base);
+        return `${url}${url.includes('?') ? '&' : '?'}${params.join('&')}`;
+    };
+    // テキストフレームはそのまま、バイナリフレームはzlibを展開してJSONを取り出す
+    const decodeFrame = (payload) => typeof payload === 'string'
+        ? Promise.resolve(payload)
+        : new Response(payload.stream().pipeThrough(new DecompressionStream('deflate'))).text();
 
     let socket;
     let reconnectTimeout = null;
@@ -49,6 +120,43 @@ document.addEventListener('DOMContentLoaded', () => {
     let queryCounter = 0; // 質問のカウンター
     let currentQueryId = null; // 現在処理中の質問ID
     
+    // `projects`で追加したリポジトリのイベントを絞り込む（値が空なら起動したプロジェクト）
+    const showsProject = (project) => projectFilter.value === '*' || projectFilter.value === project;
+    const tagProject = (element, project) => {
+        element.dataset.project = project || '';
+        element.hidden = !showsProject(element.dataset.project);
+    };
+    const addProjectOption = (project) => {
+        if (Array.from(projectFilter.options).some((option) => option.value === project)) {
+            return;
+        }
+        const option = document.createElement('option');
+        option.value = project;
+        option.textContent = project;
+        projectFilter.appendChild(option);
+        projectFilter.hidden = false;
+    };
+    projectFilter.options[0].textContent = UI_STRINGS.ALL_PROJECTS;
+    projectFilter.addEventListener('change', () => {
+        logContainer.querySelectorAll('[data-project]').forEach((element) => {
+            element.hidden = !showsProject(element.dataset.project);
+        });
+        logContainer.scrollTop = logContainer.scrollHeight;
+    });
+
+    // 分析ジョブごとのログのまとまり（なければ末尾に作る）
+    const jobGroup = (jobId, project) => {
+        let group = logContainer.querySelector(`[data-job-group="${CSS.escape(jobId)}"]`);
+        if (!group) {
+            group = document.createElement('div');
+            group.classList.add(CSS_CLASSES.JOB_GROUP);
+            group.dataset.jobGroup = jobId;
+            tagProject(group, project);
+            logContainer.appendChild(group);
+        }
+        return group;
+    };
+
     // エラーメッセージをUIに表示する関数
     function showMessage(message, type = CSS_CLASSES.INFO) {
         const logEntry = document.createElement('div');
@@ -62,16 +170,195 @@ document.addEventListener('DOMContentLoaded', () => {
         logContainer.scrollTop = logContainer.scrollHeight;
     }
     
-    function updateLastTime() {
-        const now = new Date();
-        const timeStr = now.toLocaleTimeString('ja-JP', { 
-            hour: '2-digit', 
-            minute: '2-digit', 
-            second: '2-digit' 
+    // 変更前後のコード抜粋を左右に並べて表示する要素を作る
+    function renderSnapshots(snapshots) {
+        const container = document.createElement('div');
+        container.classList.add(CSS_CLASSES.SNAPSHOTS);
+        snapshots.forEach((snapshot) => {
+            const row = document.createElement('div');
+            row.classList.add(CSS_CLASSES.SNAPSHOT);
+            [['before', UI_STRINGS.SNAPSHOT_BEFORE], ['after', UI_STRINGS.SNAPSHOT_AFTER]].forEach(([key, label]) => {
+                const pane = document.createElement('div');
+                pane.classList.add(CSS_CLASSES.SNAPSHOT_PANE, key);
+                const excerpt = snapshot[key];
+                const heading = document.createElement('div');
+                heading.classList.add('snapshot-label');
+                heading.textContent = excerpt ? `${label} (L${excerpt.start_line})` : label;
+                const code = document.createElement('pre');
+                code.textContent = excerpt ? excerpt.text : UI_STRINGS.SNAPSHOT_EMPTY;
+                pane.appendChild(heading);
+                pane.appendChild(code);
+                row.appendChild(pane);
+            });
+            container.appendChild(row);
         });
+        return container;
+    }
+
+    // ファイル全体またはdiffをサーバーから取得して表示する
+    async function showFileContext(entry, filePath, kind) {
+        const url = kind === 'diff'
+            ? `/api/diff?path=${encodeURIComponent(filePath)}`
+            : `/api/file?path=${encodeURIComponent(filePath)}`;
+        let container = entry.querySelector('.file-context');
+        if (!container) {
+            container = document.createElement('pre');
+            container.classList.add('file-context');
+            entry.appendChild(container);
+        }
+        try {
+            const response = await fetch(withToken(url));
+            if (!response.ok) {
+                container.textContent = `${UI_STRINGS.CONTEXT_FETCH_FAILED}: ${await response.text()}`;
+                return;
+            }
+            const data = await response.json();
+            const code = document.createElement('code');
+            code.className = `language-${data.language}`;
+            code.textContent = kind === 'diff' ? data.diff : data.content;
+            container.replaceChildren(code);
+        } catch (e) {
+            container.textContent = `${UI_STRINGS.CONTEXT_FETCH_FAILED}: ${e}`;
+        }
+    }
+
+    // ファイルのパスを`/api/files/{path}/...`のURLにする（`/`はそのまま）
+    const filesUrl = (filePath, suffix) =>
+        `/api/files/${filePath.split('/').map(encodeURIComponent).join('/')}/${suffix}`;
+
+    // ファイルの過去のレビュー結果と対応を集計して表示する
+    async function showFileHistory(entry, filePath) {
+        let container = entry.querySelector('.file-history');
+        if (!container) {
+            container = document.createElement('div');
+            container.classList.add('file-history');
+            entry.appendChild(container);
+        }
+        try {
+            const response = await fetch(withToken(filesUrl(filePath, 'history')));
+            if (!response.ok) {
+                container.textContent = `${UI_STRINGS.CONTEXT_FETCH_FAILED}: ${await response.text()}`;
+                return;
+            }
+            const history = await response.json();
+            if (history.findings.length === 0 && history.analyses.length === 0) {
+                container.textContent = UI_STRINGS.HISTORY_EMPTY;
+                return;
+            }
+            const falsePositives = history.resolutions.filter((r) => r.status === 'false_positive').length;
+            const fixed = history.resolutions.filter((r) => r.status === 'fixed').length;
+            const lines = [
+                `これまでに${history.analyses.length}回分析し、${history.findings.length}件の指摘がありました`,
+                `誤検知: ${falsePositives}件 / 修正済み: ${fixed}件`
+            ];
+            const last = history.findings[history.findings.length - 1];
+            if (last) {
+                lines.push(`最後の指摘: ${last.review}（${last.ts}）`);
+            }
+            container.replaceChildren(...lines.map((line) => {
+                const div = document.createElement('div');
+                div.textContent = line;
+                return div;
+            }));
+        } catch (e) {
+            container.textContent = `${UI_STRINGS.CONTEXT_FETCH_FAILED}: ${e}`;
+        }
+    }
+
+    // レビュー結果を誤検知として記録する（閲覧専用のトークンでは拒否される）
+    async function markFalsePositive(button, finding) {
+        try {
+            const response = await fetch(withToken(filesUrl(finding.file_path, 'resolutions')), {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({
+                    job_id: finding.job_id,
+                    review: finding.review,
+                    status: 'false_positive',
+                    ...(displayName ? { user: displayName } : {})
+                })
+            });
+            if (!response.ok) {
+                button.title = `${UI_STRINGS.CONTEXT_FETCH_FAILED}: ${await response.text()}`;
+                return;
+            }
+            button.disabled = true;
+            button.textContent = UI_STRINGS.MARKED_FALSE_POSITIVE;
+        } catch (e) {
+            button.title = `${UI_STRINGS.CONTEXT_FETCH_FAILED}: ${e}`;
+        }
+    }
+
+    // 一時停止ボタンの表示（ほかのクライアントからの指示でも切り替える）
+    let paused = false;
+    function setPaused(value) {
+        paused = value;
+        pauseButton.textContent = paused ? UI_STRINGS.RESUME : UI_STRINGS.PAUSE;
+        pauseButton.classList.toggle('paused', paused);
+    }
+
+    // 分析を一時停止・再開する（結果はシステムメッセージで届く）
+    async function togglePause() {
+        const url = paused ? '/api/resume' : '/api/pause';
+        try {
+            const response = await fetch(withToken(url), {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: paused ? undefined : JSON.stringify({ minutes: CONFIG.PAUSE_MINUTES })
+            });
+            if (!response.ok) {
+                showMessage(await response.text(), CSS_CLASSES.WARNING);
+                return;
+            }
+            setPaused(!paused);
+        } catch (e) {
+            showMessage(`${e}`, CSS_CLASSES.ERROR);
+        }
+    }
+    pauseButton.addEventListener('click', togglePause);
+
+    // イベントの発生時刻（UTC）を、サーバーが指定したオフセットの時刻で表示する
+    function formatEventTime(ts, utcOffset) {
+        const date = ts ? new Date(ts) : new Date();
+        const match = /^([+-])(\d{2}):(\d{2})$/.exec(utcOffset || '');
+        if (!match) {
+            return date.toLocaleTimeString('ja-JP', {
+                hour: '2-digit',
+                minute: '2-digit',
+                second: '2-digit'
+            });
+        }
+        const sign = match[1] === '-' ? -1 : 1;
+        const offsetMinutes = sign * (Number(match[2]) * 60 + Number(match[3]));
+        return new Date(date.getTime() + offsetMinutes * 60000).toISOString().substring(11, 19);
+    }
+
+    function updateLastTime(ts, utcOffset) {
+        const timeStr = formatEventTime(ts, utcOffset);
         lastUpdateDiv.textContent = `${UI_STRINGS.LAST_UPDATE}: ${timeStr}`;
     }
 
+    // 接続前の指摘を`/api/history`から取得し、まだ表示していないものを指摘として表示する
+    async function replayHistory(handleMessage) {
+        try {
+            const response = await fetch(withToken(`/api/history?limit=${CONFIG.HISTORY_LIMIT}`));
+            if (!response.ok) {
+                return;
+            }
+            const findings = (await response.json()).filter(({ job_id: jobId, review }) =>
+                !Array.from(document.querySelectorAll(`.${CSS_CLASSES.FINDING}`))
+                    .some((entry) => entry.dataset.jobId === jobId && entry.dataset.review === review));
+            findings.forEach(({ path, ts, ...finding }) => {
+                handleMessage(JSON.stringify({ Finding: { ...finding, file_path: path }, ts }));
+            });
+            if (findings.length > 0) {
+                showMessage(`${findings.length}${UI_STRINGS.HISTORY_REPLAYED}`, CSS_CLASSES.INFO);
+            }
+        } catch (e) {
+            console.error('Failed to fetch history:', e);
+        }
+    }
+
     function connect() {
         // 既存の接続とタイムアウトをクリーンアップ
         if (socket) {
@@ -92,7 +379,7 @@ document.addEventListener('DOMContentLoaded', () => {
         // Use the current host and port for the WebSocket connection.
         const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
         const host = window.location.host;
-        socket = new WebSocket(`${protocol}//${host}/ws`);
+        socket = new WebSocket(wsUrl(`${protocol}//${host}/ws`));
 
         socket.onopen = () => {
             statusDiv.textContent = UI_STRINGS.CONNECTED;
@@ -101,19 +388,75 @@ document.addEventListener('DOMContentLoaded', () => {
             if (reconnectAttempts > 0) {
                 showMessage(UI_STRINGS.CONNECTION_RESTORED, CSS_CLASSES.SUCCESS);
             }
+            // 過去の指摘を、接続後に届いたイベントより先に表示する
+            decodeChain = decodeChain.then(() => replayHistory(handleMessage));
         };
 
+        // 展開は非同期のため、到着順に処理されるよう直列化する
+        let decodeChain = Promise.resolve();
         socket.onmessage = (event) => {
+            decodeChain = decodeChain
+                .then(() => decodeFrame(event.data))
+                .then(handleMessage)
+                .catch((e) => {
+                    console.error('Failed to decode WebSocket frame:', e);
+                    showMessage(UI_STRINGS.PARSE_ERROR, CSS_CLASSES.ERROR);
+                });
+        };
+
+        const handleMessage = (raw) => {
             let data;
             try {
-                data = JSON.parse(event.data);
+                data = JSON.parse(raw);
             } catch (e) {
                 console.error('Failed to parse WebSocket message:', e);
-                console.error('Raw message:', event.data);
+                console.error('Raw message:', raw);
                 showMessage(UI_STRINGS.PARSE_ERROR, CSS_CLASSES.ERROR);
                 return;
             }
+            if (data.project) {
+                addProjectOption(data.project);
+            }
             
+            // モデルの回答は届いた分から表示し、回答が終わったら消す（結果は指摘として届く）
+            if (data.AnalysisDelta) {
+                const delta = data.AnalysisDelta;
+                let stream = logContainer.querySelector(`[data-stream-id="${CSS.escape(delta.id)}"]`);
+                if (!stream) {
+                    stream = document.createElement('pre');
+                    stream.classList.add(CSS_CLASSES.LOG_ENTRY, 'analysis-stream');
+                    stream.dataset.streamId = delta.id;
+                    tagProject(stream, data.project);
+                    (delta.job_id ? jobGroup(delta.job_id, data.project) : logContainer).appendChild(stream);
+                }
+                stream.textContent += delta.text;
+                logContainer.scrollTop = logContainer.scrollHeight;
+                return;
+            }
+            if (data.AnalysisComplete) {
+                const stream = logContainer.querySelector(`[data-stream-id="${CSS.escape(data.AnalysisComplete.id)}"]`);
+                if (stream) {
+                    stream.remove();
+                }
+                return;
+            }
+
+            // 指摘の一覧は1件ずつ指摘として表示する（空なら指摘なし）
+            if (data.Findings) {
+                if (data.Findings.length === 0) {
+                    updateLastTime(data.ts, data.utc_offset);
+                }
+                data.Findings.forEach((finding) => {
+                    handleMessage(JSON.stringify({
+                        Finding: finding,
+                        ts: data.ts,
+                        utc_offset: data.utc_offset,
+                        project: data.project
+                    }));
+                });
+                return;
+            }
+
             const logEntry = document.createElement('div');
             logEntry.classList.add('log-entry');
 
@@ -124,56 +467,246 @@ document.addEventListener('DOMContentLoaded', () => {
                     projectRootDiv.textContent = `📁 ${data.ProjectRoot}`;
                     projectRootDiv.title = `監視中のプロジェクト: ${data.ProjectRoot}`;
                 }
+                projectFilter.options[1].textContent = data.ProjectRoot.split(/[\\/]/).filter(Boolean).pop() || data.ProjectRoot;
+                return; // ログには追加しない
+            } else if (data.FindingRepeated) {
+                // 繰り返された指摘は新しく表示せず、最初の指摘に回数を表示する
+                const repeat = data.FindingRepeated;
+                const original = Array.from(document.querySelectorAll(`.${CSS_CLASSES.FINDING}`))
+                    .find((entry) => entry.dataset.jobId === repeat.job_id && entry.dataset.review === repeat.review);
+                if (original) {
+                    let badge = original.querySelector('.finding-repeat');
+                    if (!badge) {
+                        badge = document.createElement('div');
+                        badge.classList.add('finding-repeat');
+                        original.insertBefore(badge, original.firstChild);
+                    }
+                    badge.textContent = `${UI_STRINGS.REPEATED} ×${repeat.count}`;
+                    badge.title = formatEventTime(data.ts, data.utc_offset);
+                }
                 return; // ログには追加しない
+            } else if (data.Control) {
+                // 結果はシステムメッセージで届くので、ボタンの表示だけを合わせる
+                setPaused(data.Control !== 'Resume');
+                return;
+            } else if (data.AnnotationsUpdated) {
+                // エディタのプラグイン向けの通知なので、ログには追加しない
+                return;
+            } else if (data.Reminder) {
+                logEntry.classList.add('reminder');
+                logEntry.textContent = `${UI_STRINGS.REMINDER}: ${data.Reminder.text}`;
             } else if (data.System) {
                 logEntry.classList.add(CSS_CLASSES.SYSTEM);
                 logEntry.textContent = data.System;
             } else if (data.Analysis) {
+                const analysisText = data.Analysis.text;
                 logEntry.classList.add(CSS_CLASSES.ANALYSIS);
                 // 分析データが来たら最終更新時間を更新
-                updateLastTime();
+                updateLastTime(data.ts, data.utc_offset);
                 
                 // Markdownをレンダリング
-                const isMarkdown = data.Analysis.includes('##') || 
-                                 data.Analysis.includes('**') || 
-                                 data.Analysis.includes('```') ||
-                                 data.Analysis.includes('|') ||
-                                 data.Analysis.includes('- ');
+                const isMarkdown = analysisText.includes('##') || 
+                                 analysisText.includes('**') || 
+                                 analysisText.includes('```') ||
+                                 analysisText.includes('|') ||
+                                 analysisText.includes('- ');
                 
                 if (isMarkdown && typeof marked !== 'undefined' && typeof DOMPurify !== 'undefined') {
-                    const rawHtml = marked.parse(data.Analysis);
+                    const rawHtml = marked.parse(analysisText);
                     logEntry.innerHTML = DOMPurify.sanitize(rawHtml);
                 } else {
-                    logEntry.textContent = data.Analysis;
+                    logEntry.textContent = analysisText;
+                }
+            } else if (data.Finding) {
+                const finding = data.Finding;
+                logEntry.classList.add(CSS_CLASSES.ANALYSIS, CSS_CLASSES.FINDING);
+                logEntry.setAttribute('data-file-path', finding.file_path);
+                logEntry.dataset.review = finding.review;
+                updateLastTime(data.ts, data.utc_offset);
+
+                if (finding.owners && finding.owners.length > 0) {
+                    const owners = document.createElement('div');
+                    owners.classList.add('finding-owners');
+                    owners.textContent = `${UI_STRINGS.OWNERS}: ${finding.owners.join(', ')}`;
+                    logEntry.appendChild(owners);
+                }
+
+                if (finding.labels && finding.labels.length > 0) {
+                    const labels = document.createElement('div');
+                    labels.classList.add('finding-labels');
+                    labels.textContent = `${UI_STRINGS.LABELS}: ${finding.labels.join(', ')}`;
+                    logEntry.appendChild(labels);
+                }
+
+                const assessment = [];
+                if (finding.severity) {
+                    assessment.push(`${UI_STRINGS.SEVERITY}: ${finding.severity}`);
                 }
+                if (typeof finding.confidence === 'number') {
+                    assessment.push(`${UI_STRINGS.CONFIDENCE}: ${finding.confidence}`);
+                }
+                if (assessment.length > 0) {
+                    const confidence = document.createElement('div');
+                    confidence.classList.add('finding-confidence');
+                    confidence.textContent = assessment.join(' / ');
+                    logEntry.appendChild(confidence);
+                }
+
+                const message = document.createElement('div');
+                if (typeof marked !== 'undefined' && typeof DOMPurify !== 'undefined') {
+                    message.innerHTML = DOMPurify.sanitize(marked.parse(finding.message));
+                } else {
+                    message.textContent = finding.message;
+                }
+                if (finding.line) {
+                    const location = document.createElement('div');
+                    location.classList.add('finding-location');
+                    location.textContent = `${finding.file_path}:${finding.line}`;
+                    message.insertBefore(location, message.firstChild);
+                }
+                if (finding.suggestion) {
+                    const suggestion = document.createElement('div');
+                    suggestion.classList.add('finding-suggestion');
+                    suggestion.textContent = `${UI_STRINGS.SUGGESTION}: ${finding.suggestion}`;
+                    message.appendChild(suggestion);
+                }
+                // 変更された定義と、その名前を参照している箇所
+                (finding.cross_references || []).forEach((reference) => {
+                    const xref = document.createElement('div');
+                    xref.classList.add('finding-xref');
+                    const defined = `${reference.defined_in.path}:${reference.defined_in.line}`;
+                    let references = reference.referenced_from
+                        .map((location) => `${location.path}:${location.line}`)
+                        .join(', ');
+                    const omitted = reference.reference_count - reference.referenced_from.length;
+                    if (omitted > 0) {
+                        references += ` (+${omitted})`;
+                    }
+                    xref.textContent = `${reference.name}: ${UI_STRINGS.DEFINED_IN} ${defined} / `
+                        + (references ? `${UI_STRINGS.REFERENCED_FROM} ${references}` : UI_STRINGS.NO_REFERENCES);
+                    message.appendChild(xref);
+                });
+                // 確信度の低い指摘は折りたたんで表示する
+                if (finding.needs_human_review) {
+                    logEntry.classList.add('needs-human-review');
+                    const details = document.createElement('details');
+                    const summary = document.createElement('summary');
+                    summary.textContent = `${UI_STRINGS.NEEDS_HUMAN_REVIEW}: ${finding.review}`;
+                    details.appendChild(summary);
+                    details.appendChild(message);
+                    logEntry.appendChild(details);
+                } else {
+                    logEntry.appendChild(message);
+                }
+
+                // 追加したリポジトリのファイルと履歴はAPIから読めないため、操作は起動したプロジェクトの指摘だけ
+                const actions = document.createElement('div');
+                actions.classList.add('finding-actions');
+                [['file', UI_STRINGS.SHOW_FILE], ['diff', UI_STRINGS.SHOW_DIFF]].forEach(([kind, label]) => {
+                    const button = document.createElement('button');
+                    button.textContent = label;
+                    button.addEventListener('click', () => showFileContext(logEntry, finding.file_path, kind));
+                    actions.appendChild(button);
+                });
+                const historyButton = document.createElement('button');
+                historyButton.textContent = UI_STRINGS.SHOW_HISTORY;
+                historyButton.addEventListener('click', () => showFileHistory(logEntry, finding.file_path));
+                actions.appendChild(historyButton);
+                if (finding.job_id) {
+                    const falsePositiveButton = document.createElement('button');
+                    falsePositiveButton.textContent = UI_STRINGS.MARK_FALSE_POSITIVE;
+                    falsePositiveButton.addEventListener('click', () => markFalsePositive(falsePositiveButton, finding));
+                    actions.appendChild(falsePositiveButton);
+                }
+                if (!data.project) {
+                    logEntry.appendChild(actions);
+                }
+
+                if (finding.snapshots && finding.snapshots.length > 0) {
+                    const details = document.createElement('details');
+                    const summary = document.createElement('summary');
+                    summary.textContent = `${UI_STRINGS.SNAPSHOT_TOGGLE}: ${finding.file_path}`;
+                    details.appendChild(summary);
+                    details.appendChild(renderSnapshots(finding.snapshots));
+                    logEntry.appendChild(details);
+                }
+            } else if (data.CycleSummary) {
+                const cycle = data.CycleSummary;
+                // 変更のないサイクルは表示しない
+                const changed = cycle.skipped.filter((s) => s.reason !== 'unchanged');
+                if (cycle.analyzed.length === 0 && changed.length === 0) {
+                    return;
+                }
+                logEntry.classList.add(CSS_CLASSES.SYSTEM);
+                const details = document.createElement('details');
+                const summary = document.createElement('summary');
+                summary.textContent = `${UI_STRINGS.CYCLE_SUMMARY}: ${cycle.text || `分析 ${cycle.analyzed.length}件 / スキップ ${cycle.skipped.length}件`}`;
+                details.appendChild(summary);
+                const list = document.createElement('ul');
+                cycle.skipped.forEach((skipped) => {
+                    const item = document.createElement('li');
+                    item.textContent = `${skipped.path}: ${SKIP_REASONS[skipped.reason] || skipped.reason}`;
+                    list.appendChild(item);
+                });
+                details.appendChild(list);
+                logEntry.appendChild(details);
             } else if (data.UserQuery) {
                 // 新しい質問が来たら、カウンターを増やしてIDを設定
                 queryCounter++;
                 currentQueryId = queryCounter;
                 logEntry.classList.add(CSS_CLASSES.USER_QUERY);
                 logEntry.setAttribute('data-query-id', currentQueryId);
-                const safeQuery = typeof DOMPurify !== 'undefined' 
-                    ? DOMPurify.sanitize(data.UserQuery, {ALLOWED_TAGS: []}) 
-                    : data.UserQuery.replace(/</g, '&lt;').replace(/>/g, '&gt;');
-                logEntry.innerHTML = `<span class="query-badge">Q${currentQueryId}</span> You: ${safeQuery}`;
+                const query = data.UserQuery;
+                const badge = document.createElement('span');
+                badge.classList.add('query-badge');
+                badge.textContent = `Q${currentQueryId}`;
+                logEntry.appendChild(badge);
+                logEntry.appendChild(document.createTextNode(` ${query.user || UI_STRINGS.YOU}: ${query.text}`));
             } else if (data.QueryResponse) {
                 // 質問への回答
                 logEntry.classList.add(CSS_CLASSES.ANALYSIS, CSS_CLASSES.QUERY_RESPONSE);
+                const response = data.QueryResponse;
+                if (response.user) {
+                    logEntry.setAttribute('data-user', response.user);
+                }
                 if (currentQueryId) {
                     logEntry.setAttribute('data-query-id', currentQueryId);
                     const content = (marked && typeof DOMPurify !== 'undefined') 
-                        ? DOMPurify.sanitize(marked.parse(data.QueryResponse)) 
-                        : data.QueryResponse;
+                        ? DOMPurify.sanitize(marked.parse(response.text)) 
+                        : response.text;
                     logEntry.innerHTML = DOMPurify.sanitize(`<span class="query-badge">A${currentQueryId}</span> ${content}`);
                 } else {
                     logEntry.innerHTML = (marked && typeof DOMPurify !== 'undefined')
-                        ? DOMPurify.sanitize(marked.parse(data.QueryResponse))
-                        : data.QueryResponse;
+                        ? DOMPurify.sanitize(marked.parse(response.text))
+                        : response.text;
                 }
-                updateLastTime();
+                updateLastTime(data.ts, data.utc_offset);
+            }
+
+            if (data.project) {
+                const badge = document.createElement('span');
+                badge.classList.add('project-badge');
+                badge.textContent = data.project;
+                logEntry.insertBefore(badge, logEntry.firstChild);
+            }
+            tagProject(logEntry, data.project);
+
+            if (data.ts) {
+                logEntry.dataset.ts = data.ts;
+                logEntry.title = `${formatEventTime(data.ts, data.utc_offset)} (UTC${data.utc_offset})`;
             }
 
-            logContainer.appendChild(logEntry);
+            // 同じ分析ジョブのイベントは、並行実行で前後しても1つのグループにまとめる
+            const ids = data.Analysis || data.Finding || data.CycleSummary || {};
+            if (ids.run_id) {
+                logEntry.dataset.runId = ids.run_id;
+            }
+            if (ids.job_id) {
+                logEntry.dataset.jobId = ids.job_id;
+                jobGroup(ids.job_id, data.project).appendChild(logEntry);
+            } else {
+                logContainer.appendChild(logEntry);
+            }
             logContainer.scrollTop = logContainer.scrollHeight;
         };
 
@@ -0,0 +1,110 @@
+use anyhow::Context;
+use anyhow::Result;
+use axum::http::StatusCode;
+use axum::http::Uri;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// 実行ファイルに埋め込んだWeb UIのファイル（UIのディレクトリからの相対パスと内容）
+///
+/// UIのファイルが見つからない場合（`cargo install`したときなど）はこれを配信する。
+pub const UI_ASSETS: &[(&str, &[u8])] = &[
+    ("index.html", include_bytes!("ambient_ui/index.html")),
+    (
+        "static/main.js",
+        include_bytes!("ambient_ui/static/main.js"),
+    ),
+    (
+        "static/styles.css",
+        include_bytes!("ambient_ui/static/styles.css"),
+    ),
+];
+
+/// `ambient install-ui`の既定のインストール先（サーバーが探す場所の1つ）
+pub fn default_ui_dir() -> Result<PathBuf> {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .map_err(|_| anyhow::anyhow!("ホームディレクトリが見つかりません"))?;
+    Ok(PathBuf::from(home)
+        .join(".config")
+        .join("ambient")
+        .join("ui"))
+}
+
+/// 埋め込んだUIのファイルを`dir`に書き出す（既存のファイルは上書きする）
+pub fn install_ui(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::with_capacity(UI_ASSETS.len());
+    for (name, content) in UI_ASSETS {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("{}を作成できません", parent.display()))?;
+        }
+        fs::write(&path, content).with_context(|| format!("{}に書き込めません", path.display()))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// URLのパスに対応する埋め込みファイルと、その`Content-Type`
+pub fn embedded_asset(path: &str) -> Option<(&'static [u8], &'static str)> {
+    let name = match path.trim_start_matches('/') {
+        "" => "index.html",
+        name => name,
+    };
+    let content = UI_ASSETS
+        .iter()
+        .find(|(asset, _)| *asset == name)
+        .map(|(_, content)| *content)?;
+    let content_type = match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        _ => "application/octet-stream",
+    };
+    Some((content, content_type))
+}
+
+/// UIのディレクトリがないときに埋め込んだファイルを配信するハンドラー
+pub(crate) async fn embedded_ui_handler(uri: Uri) -> Response {
+    match embedded_asset(uri.path()) {
+        Some((content, content_type)) => {
+            ([(header::CONTENT_TYPE, content_type)], content).into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn installs_every_embedded_asset() {
+        let dir = tempdir().unwrap();
+        let written = install_ui(&dir.path().join("ui")).unwrap();
+        assert_eq!(written.len(), UI_ASSETS.len());
+        let index = fs::read_to_string(dir.path().join("ui/index.html")).unwrap();
+        assert!(index.contains("/static/main.js"));
+        assert!(dir.path().join("ui/static/styles.css").is_file());
+    }
+
+    #[test]
+    fn resolves_embedded_assets_by_url() {
+        let (index, content_type) = embedded_asset("/").unwrap();
+        assert!(content_type.starts_with("text/html"));
+        assert_eq!(Some(index), embedded_asset("/index.html").map(|(c, _)| c));
+        assert!(
+            embedded_asset("/static/main.js")
+                .unwrap()
+                .1
+                .starts_with("text/javascript")
+        );
+        assert!(embedded_asset("/../Cargo.toml").is_none());
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::Context;
+use anyhow::Result;
+use axum::Router;
+use hyper_util::rt::TokioExecutor;
+use hyper_util::rt::TokioIo;
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tokio::net::UnixListener;
+
+/// Unixドメインソケットで待ち受ける
+///
+/// ソケットファイルは所有者のみ読み書きできる権限（0600）で作成し、
+/// ファイルシステムの権限でアクセスを制御する。終了時にソケットファイルを削除する。
+pub(crate) async fn serve_unix(
+    socket_path: &Path,
+    app: Router,
+    shutdown_signal: impl std::future::Future<Output = ()> + Send + 'static,
+) {
+    let listener = match bind_socket(socket_path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{e:#}");
+            return;
+        }
+    };
+    println!(
+        "Ambient Code Watcherが unix:{} で動作中です",
+        socket_path.display()
+    );
+
+    tokio::pin!(shutdown_signal);
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("接続の受け付けに失敗しました: {e}");
+                    continue;
+                }
+            },
+            _ = &mut shutdown_signal => break,
+        };
+
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            if let Err(e) = Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("unix socket connection error: {e}");
+            }
+        });
+    }
+
+    let _ = fs::remove_file(socket_path);
+}
+
+/// ソケットファイルを作って待ち受ける
+///
+/// 他のプロセスが権限を設定する前に接続できないよう、所有者だけが入れる一時ディレクトリ（0700）の中で
+/// バインドして0600にしてから`socket_path`へ移動する。
+fn bind_socket(socket_path: &Path) -> Result<UnixListener> {
+    // 前回の異常終了で残ったソケットファイルだけを削除し、通常のファイルは上書きしない
+    match fs::symlink_metadata(socket_path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            fs::remove_file(socket_path).with_context(|| {
+                format!(
+                    "既存のソケットファイルを削除できません {}",
+                    socket_path.display()
+                )
+            })?;
+        }
+        Ok(_) => anyhow::bail!(
+            "{}はソケットではないため使用できません",
+            socket_path.display()
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(e).with_context(|| format!("{}を確認できません", socket_path.display()));
+        }
+    }
+
+    let parent = socket_path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let staging = tempfile::Builder::new()
+        .prefix(".ambient-socket")
+        .tempdir_in(parent)
+        .with_context(|| format!("{}に一時ディレクトリを作成できません", parent.display()))?;
+    let staged = staging.path().join("socket");
+    let listener = UnixListener::bind(&staged).with_context(|| {
+        format!(
+            "ソケット{}へのバインドに失敗しました",
+            socket_path.display()
+        )
+    })?;
+    fs::set_permissions(&staged, fs::Permissions::from_mode(0o600))
+        .context("ソケットの権限を設定できません")?;
+    fs::rename(&staged, socket_path).with_context(|| {
+        format!(
+            "ソケット{}へのバインドに失敗しました",
+            socket_path.display()
+        )
+    })?;
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn binds_owner_only_socket_and_keeps_other_files() {
+        let dir = tempdir().unwrap();
+        let socket_path = dir.path().join("ambient.sock");
+
+        let listener = bind_socket(&socket_path).unwrap();
+        let metadata = fs::symlink_metadata(&socket_path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert!(tokio::net::UnixStream::connect(&socket_path).await.is_ok());
+        drop(listener);
+
+        // 残ったソケットは置き換え、ソケットでないファイルは削除しない
+        assert!(bind_socket(&socket_path).is_ok());
+        let file_path = dir.path().join("notes.txt");
+        fs::write(&file_path, "大事なメモ").unwrap();
+        assert!(bind_socket(&file_path).is_err());
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "大事なメモ");
+        // 一時ディレクトリは残らない
+        let mut names: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["ambient.sock", "notes.txt"]);
+    }
+}
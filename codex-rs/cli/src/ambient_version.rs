@@ -0,0 +1,161 @@
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use utoipa::ToSchema;
+
+/// 実行中のバージョン（ビルド時のgitのタグ、詳しくは`build.rs`）
+pub const VERSION: &str = env!("AMBIENT_VERSION");
+
+/// リリースを公開しているリポジトリ
+pub const REPOSITORY: &str = "hama-jp/ambient_code_watcher";
+
+/// リポジトリのルートからのインストールスクリプトのパス
+const INSTALL_SCRIPT: &str = "codex-rs/install.sh";
+
+/// `GET /api/version`の応答
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct VersionInfo {
+    /// 実行中のバージョン（例: `0.3.0`）
+    pub version: String,
+}
+
+impl VersionInfo {
+    pub fn current() -> Self {
+        Self {
+            version: VERSION.to_string(),
+        }
+    }
+}
+
+/// GitHubのリリース（必要な項目だけ）
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub html_url: String,
+}
+
+/// GitHubから最新のリリースを取得する
+pub async fn fetch_latest_release(client: &reqwest::Client, api_base: &str) -> Result<Release> {
+    let url = format!("{api_base}/repos/{REPOSITORY}/releases/latest");
+    let response = client
+        .get(&url)
+        .header(reqwest::header::USER_AGENT, format!("ambient/{VERSION}"))
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await
+        .with_context(|| format!("{url}に接続できません"))?;
+    if !response.status().is_success() {
+        anyhow::bail!("最新のリリースを取得できません: {}", response.status());
+    }
+    Ok(response.json().await?)
+}
+
+/// `latest`が`current`より新しいか（`v`の接頭辞と`-rc.1`などの後ろの部分は無視する）
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    match (parse_version(latest), parse_version(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => false,
+    }
+}
+
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let core = version.trim().trim_start_matches('v');
+    let core = core.split(['-', '+']).next()?;
+    core.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// `install.sh`でインストールした実行ファイル（`~/.local/bin/`）かどうか
+pub fn installed_by_script(exe: &Path) -> bool {
+    let Ok(home) = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")) else {
+        return false;
+    };
+    exe.parent() == Some(PathBuf::from(home).join(".local").join("bin").as_path())
+}
+
+/// 新しいリリースへの更新手順
+pub fn upgrade_instructions(release: &Release, by_script: bool) -> String {
+    let tag = &release.tag_name;
+    let mut instructions = format!("リリースノート: {}\n\n", release.html_url);
+    if by_script {
+        instructions
+            .push_str("`ambient self-update --install`で更新できます。手動で更新する場合:\n\n");
+    } else {
+        instructions.push_str("更新するには:\n\n");
+    }
+    instructions.push_str(&format!(
+        "  git clone --depth 1 --branch {tag} https://github.com/{REPOSITORY}.git\n  cd ambient_code_watcher\n  ./{INSTALL_SCRIPT}\n"
+    ));
+    instructions
+}
+
+/// リリースのソースを取得し、`install.sh`で上書きインストールする
+///
+/// ソースは他のユーザーが先に作れない一時ディレクトリに取得し、終わったら削除する。
+pub fn install_release(release: &Release) -> Result<()> {
+    let dir = tempfile::Builder::new()
+        .prefix("ambient-update-")
+        .tempdir()
+        .context("一時ディレクトリを作成できません")?;
+    let url = format!("https://github.com/{REPOSITORY}.git");
+    run(Command::new("git")
+        .args(["clone", "--depth", "1", "--branch", &release.tag_name, &url])
+        .arg(dir.path()))?;
+    run(Command::new(dir.path().join(INSTALL_SCRIPT)).current_dir(dir.path()))
+}
+
+fn run(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let status = command
+        .status()
+        .with_context(|| format!("{program}を実行できません"))?;
+    if !status.success() {
+        anyhow::bail!("{program}が失敗しました: {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    #[test]
+    fn compares_release_versions() {
+        assert!(is_newer("v0.4.0", "0.3.9"));
+        assert!(is_newer("v1.10.0", "1.9.3"));
+        assert!(!is_newer("v0.3.0", "0.3.0"));
+        assert!(!is_newer("v0.3.0-rc.1", "0.3.0"));
+        assert!(!is_newer("nightly", "0.3.0"));
+    }
+
+    #[tokio::test]
+    async fn fetches_latest_release() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/repos/{REPOSITORY}/releases/latest")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "tag_name": "v9.9.9",
+                "html_url": "https://github.com/hama-jp/ambient_code_watcher/releases/tag/v9.9.9",
+                "name": "v9.9.9",
+            })))
+            .mount(&server)
+            .await;
+
+        let release = fetch_latest_release(&reqwest::Client::new(), &server.uri())
+            .await
+            .unwrap();
+        assert_eq!(release.tag_name, "v9.9.9");
+        assert!(is_newer(&release.tag_name, VERSION));
+        let instructions = upgrade_instructions(&release, true);
+        assert!(instructions.contains("--branch v9.9.9"));
+        assert!(instructions.contains("./codex-rs/install.sh"));
+    }
+}
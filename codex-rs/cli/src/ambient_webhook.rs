@@ -0,0 +1,311 @@
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::ambient_confidence::Severity;
+use crate::ambient_secrets::resolve_secret;
+use crate::ambient_server::AmbientEvent;
+use crate::ambient_server::EventEnvelope;
+use crate::ambient_server::Finding;
+
+/// Slackに送る回答の最大文字数（長い回答はメッセージが読みにくくなる）
+const MAX_SLACK_MESSAGE_CHARS: usize = 1500;
+
+/// `.ambient/config.toml`の`[notifications]`（指摘をチームのチャンネルなどに送る）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct NotificationsConfig {
+    /// 指摘を送るWebhook（`[[notifications.webhooks]]`）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+/// 指摘を送るWebhook
+///
+/// 指定した条件にすべて一致した指摘を`url`にPOSTする（未指定の条件は常に一致）。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct WebhookConfig {
+    /// 送信先のURL（`keyring:ambient/slack`ならOSのキーチェーンから読む）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// 送信先のURLを読む環境変数（SlackのURLのように秘密を含む場合、`url`の代わりに使う）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_env: Option<String>,
+
+    /// 本文の形式
+    #[serde(default)]
+    pub format: WebhookFormat,
+
+    /// この重大度以上の指摘だけを送る（重大度のない指摘は送らない）
+    #[serde(default = "default_min_severity")]
+    pub min_severity: Severity,
+
+    /// 対象のレビュー（名前の一部で指定）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reviews: Vec<String>,
+
+    /// 対象のポリシーのラベル（いずれかが付いた指摘）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+}
+
+fn default_min_severity() -> Severity {
+    Severity::High
+}
+
+/// Webhookの本文の形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// 指摘のJSON
+    #[default]
+    Json,
+    /// Slackの Incoming Webhook の`text`
+    Slack,
+}
+
+impl WebhookFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookFormat::Json => "json",
+            WebhookFormat::Slack => "slack",
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// 送信先のURL（`url_env`が設定されていればその環境変数、`url`がキーチェーンの項目ならその値）
+    pub fn resolve_url(&self) -> Option<String> {
+        match &self.url_env {
+            Some(var) => std::env::var(var)
+                .ok()
+                .map(|url| url.trim().to_string())
+                .filter(|url| !url.is_empty()),
+            None => match resolve_secret(self.url.as_deref()?) {
+                Ok(url) => url,
+                Err(e) => {
+                    tracing::warn!("{e:#}");
+                    None
+                }
+            },
+        }
+    }
+
+    /// 指摘が送る条件にすべて一致するか（確信度が低く人の確認が必要な指摘は送らない）
+    pub fn matches(&self, finding: &Finding) -> bool {
+        let severity_matches = finding
+            .severity
+            .is_some_and(|severity| severity >= self.min_severity);
+        let review_matches = self.reviews.is_empty()
+            || self
+                .reviews
+                .iter()
+                .any(|review| finding.review.contains(review.as_str()));
+        let label_matches =
+            self.labels.is_empty() || self.labels.iter().any(|l| finding.labels.contains(l));
+        !finding.needs_human_review && severity_matches && review_matches && label_matches
+    }
+
+    /// 送る本文
+    pub fn payload(&self, finding: &Finding, ts: &str) -> serde_json::Value {
+        match self.format {
+            WebhookFormat::Json => serde_json::json!({
+                "type": "ambient-finding",
+                "ts": ts,
+                "run_id": finding.ids.run_id,
+                "job_id": finding.ids.job_id,
+                "file_path": finding.file_path,
+                "line": finding.line,
+                "review": finding.review,
+                "severity": finding.severity,
+                "labels": finding.labels,
+                "owners": finding.owners,
+                "message": finding.message,
+                "suggestion": finding.suggestion,
+            }),
+            WebhookFormat::Slack => serde_json::json!({ "text": slack_text(finding) }),
+        }
+    }
+}
+
+/// `*[high] セキュリティ* `src/lib.rs:12`` の見出しと、回答の本文
+fn slack_text(finding: &Finding) -> String {
+    let severity = finding.severity.map_or("-", Severity::as_str);
+    let location = match finding.line {
+        Some(line) => format!("{}:{line}", finding.file_path),
+        None => finding.file_path.clone(),
+    };
+    let message = finding.message.trim();
+    let mut body: String = message.chars().take(MAX_SLACK_MESSAGE_CHARS).collect();
+    if body.len() < message.len() {
+        body.push('…');
+    }
+    format!("*[{severity}] {}* `{location}`\n{body}", finding.review)
+}
+
+/// Webhookが設定されている場合だけ、指摘の配送を始める
+pub fn spawn_webhooks(
+    webhooks: &[WebhookConfig],
+    client: reqwest::Client,
+    tx: &broadcast::Sender<EventEnvelope>,
+) {
+    if !webhooks.is_empty() {
+        tokio::spawn(deliver_webhooks(tx.subscribe(), webhooks.to_vec(), client));
+    }
+}
+
+/// 監視ループのレビュー結果を、条件に一致するWebhookに送る
+async fn deliver_webhooks(
+    mut rx: broadcast::Receiver<EventEnvelope>,
+    webhooks: Vec<WebhookConfig>,
+    client: reqwest::Client,
+) {
+    loop {
+        match rx.recv().await {
+            Ok(EventEnvelope {
+                event: event @ (AmbientEvent::Finding(_) | AmbientEvent::Findings(_)),
+                project: None,
+                ts,
+                ..
+            }) => {
+                for finding in event.findings() {
+                    for webhook in webhooks.iter().filter(|webhook| webhook.matches(finding)) {
+                        let Some(url) = webhook.resolve_url() else {
+                            continue;
+                        };
+                        // 送信を待つ間に次の指摘を取りこぼさないように、別タスクで送る
+                        let request = post_webhook(&client, &url, webhook.payload(finding, &ts));
+                        tokio::spawn(async move {
+                            if let Err(e) = request.await {
+                                eprintln!("{e:#}");
+                            }
+                        });
+                    }
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// 本文をPOSTする
+pub fn post_webhook(
+    client: &reqwest::Client,
+    url: &str,
+    payload: serde_json::Value,
+) -> impl std::future::Future<Output = Result<()>> + Send + 'static {
+    let request = client.post(url).json(&payload);
+    let url = url.to_string();
+    async move {
+        // URLに秘密が含まれる場合があるため、エラーにはホストまでしか含めない
+        let host = url.split('/').take(3).collect::<Vec<_>>().join("/");
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Webhook（{host}）に接続できません"))?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("Webhook（{host}）への送信に失敗しました: {status}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::RunIds;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+    use wiremock::matchers::body_json;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+
+    fn finding(review: &str, severity: Option<Severity>, labels: &[&str]) -> Finding {
+        Finding {
+            ids: RunIds::default(),
+            file_path: "src/db.rs".to_string(),
+            review: review.to_string(),
+            message: "SQLを文字列で組み立てています\n".to_string(),
+            snapshots: vec![],
+            owners: vec![],
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            confidence: None,
+            severity,
+            needs_human_review: false,
+            model: None,
+            provider: None,
+            prompt_hash: None,
+            line: Some(12),
+            suggestion: None,
+            cross_references: vec![],
+        }
+    }
+
+    #[test]
+    fn matches_findings_by_severity_review_and_label() {
+        let webhook: WebhookConfig =
+            toml::from_str("url = \"https://example.com/hook\"\nreviews = [\"セキュリティ\"]")
+                .unwrap();
+        assert_eq!(webhook.format, WebhookFormat::Json);
+        assert!(webhook.matches(&finding("セキュリティ", Some(Severity::High), &[])));
+        assert!(webhook.matches(&finding("セキュリティ", Some(Severity::Critical), &[])));
+        assert!(!webhook.matches(&finding("セキュリティ", Some(Severity::Medium), &[])));
+        assert!(!webhook.matches(&finding("セキュリティ", None, &[])));
+        assert!(!webhook.matches(&finding("構文", Some(Severity::High), &[])));
+
+        let webhook = WebhookConfig {
+            reviews: vec![],
+            labels: vec!["security".to_string()],
+            ..webhook
+        };
+        assert!(webhook.matches(&finding("構文", Some(Severity::High), &["security"])));
+        assert!(!webhook.matches(&finding("構文", Some(Severity::High), &["perf"])));
+    }
+
+    #[tokio::test]
+    async fn posts_slack_payloads() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/services/T000/B000"))
+            .and(body_json(serde_json::json!({
+                "text": "*[high] セキュリティ* `src/db.rs:12`\nSQLを文字列で組み立てています",
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let webhook = WebhookConfig {
+            url: Some(format!("{}/services/T000/B000", server.uri())),
+            url_env: None,
+            format: WebhookFormat::Slack,
+            min_severity: Severity::High,
+            reviews: vec![],
+            labels: vec![],
+        };
+        let payload = webhook.payload(
+            &finding("セキュリティ", Some(Severity::High), &[]),
+            "2026-10-16T03:12:45Z",
+        );
+        let url = webhook.resolve_url().unwrap();
+        post_webhook(&reqwest::Client::new(), &url, payload)
+            .await
+            .unwrap();
+
+        let error = post_webhook(
+            &reqwest::Client::new(),
+            &format!("{}/missing", server.uri()),
+            serde_json::json!({}),
+        )
+        .await
+        .unwrap_err();
+        assert!(!error.to_string().contains("/missing"));
+    }
+}
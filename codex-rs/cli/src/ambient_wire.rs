@@ -0,0 +1,132 @@
+use axum::extract::ws::Message;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use serde::Deserialize;
+use std::io::Write;
+
+use crate::ambient_server::EventEnvelope;
+
+/// この長さを超えるJSONだけを圧縮する（小さなイベントは圧縮しても効果が薄い）
+const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// WebSocketで送るイベントの符号化方式（接続時に`?encoding=`で指定）
+///
+/// `ZlibJson`はアプリケーション側の独自フレーミングで、WebSocketの
+/// permessage-deflate拡張（RFC 7692）とは異なる。クライアントはフレームの種類で展開の要否を判断する。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WireEncoding {
+    /// すべてJSONのテキストフレーム
+    #[default]
+    Json,
+    /// 大きなイベントだけをzlib形式（RFC 1950）で圧縮したJSONのバイナリフレーム、それ以外はJSONのテキストフレームで送る
+    ZlibJson,
+    /// すべてMessagePackのバイナリフレーム
+    Msgpack,
+}
+
+impl WireEncoding {
+    /// イベントを送信用のフレームに変換する
+    ///
+    /// 符号化に失敗した場合はJSONのテキストフレームにフォールバックする。
+    pub fn encode(self, event: &EventEnvelope) -> Message {
+        match self {
+            WireEncoding::Json => Message::Text(event.to_json()),
+            WireEncoding::ZlibJson => {
+                let json = event.to_json();
+                if json.len() <= COMPRESSION_THRESHOLD {
+                    return Message::Text(json);
+                }
+                match deflate(json.as_bytes()) {
+                    Ok(bytes) => Message::Binary(bytes),
+                    Err(_) => Message::Text(json),
+                }
+            }
+            WireEncoding::Msgpack => match rmp_serde::to_vec_named(event) {
+                Ok(bytes) => Message::Binary(bytes),
+                Err(_) => Message::Text(event.to_json()),
+            },
+        }
+    }
+}
+
+fn deflate(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambient_server::AmbientEvent;
+    use crate::ambient_server::RunIds;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn deflates_only_large_events() {
+        let small = EventEnvelope::new(AmbientEvent::System("ok".to_string()));
+        assert!(matches!(
+            WireEncoding::ZlibJson.encode(&small),
+            Message::Text(_)
+        ));
+
+        let large = EventEnvelope::new(AmbientEvent::analysis(
+            RunIds::default(),
+            "x".repeat(COMPRESSION_THRESHOLD * 4),
+        ));
+        let Message::Binary(bytes) = WireEncoding::ZlibJson.encode(&large) else {
+            panic!("large events should be sent as binary frames");
+        };
+        assert!(bytes.len() < COMPRESSION_THRESHOLD);
+        let mut json = String::new();
+        ZlibDecoder::new(bytes.as_slice())
+            .read_to_string(&mut json)
+            .unwrap();
+        assert_eq!(json, large.to_json());
+    }
+
+    /// `main.js`の`decodeFrame`と同じく、テキストはそのまま、バイナリはzlibを展開してJSONを得る
+    fn client_decode(message: Message) -> String {
+        match message {
+            Message::Text(json) => json,
+            Message::Binary(bytes) => {
+                let mut json = String::new();
+                ZlibDecoder::new(bytes.as_slice())
+                    .read_to_string(&mut json)
+                    .unwrap();
+                json
+            }
+            other => panic!("unexpected frame: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn clients_decode_every_zlib_json_frame() {
+        let events = [
+            EventEnvelope::new(AmbientEvent::System("ok".to_string())),
+            EventEnvelope::new(AmbientEvent::analysis(
+                RunIds::default(),
+                "日本語の指摘".repeat(COMPRESSION_THRESHOLD),
+            )),
+        ];
+        for event in events {
+            let json = client_decode(WireEncoding::ZlibJson.encode(&event));
+            let decoded: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded["ts"], event.ts.as_str());
+            assert_eq!(json, event.to_json());
+        }
+    }
+
+    #[test]
+    fn encodes_msgpack_as_binary() {
+        let event = EventEnvelope::new(AmbientEvent::System("ok".to_string()));
+        let Message::Binary(bytes) = WireEncoding::Msgpack.encode(&event) else {
+            panic!("msgpack should be sent as a binary frame");
+        };
+        let decoded: serde_json::Value = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(decoded["System"], "ok");
+        assert_eq!(decoded["ts"], event.ts.as_str());
+    }
+}
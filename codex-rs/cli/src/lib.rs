@@ -1,7 +1,86 @@
 pub mod ambient;
+pub mod ambient_annotations;
+mod ambient_api;
+pub mod ambient_api_client;
+pub mod ambient_architecture;
+pub mod ambient_auth;
+pub mod ambient_azure_devops;
+pub mod ambient_bitbucket;
+pub mod ambient_budget;
+pub mod ambient_chat_command;
+pub mod ambient_check_run;
+pub mod ambient_chunk;
+pub mod ambient_ci;
+pub mod ambient_circuit;
+pub mod ambient_codeowners;
+pub mod ambient_commit_lint;
+pub mod ambient_confidence;
 pub mod ambient_config;
+pub mod ambient_context;
+pub mod ambient_cycle;
+pub mod ambient_desktop_notify;
+pub mod ambient_digest;
+pub mod ambient_editor;
+pub mod ambient_embedding;
+pub mod ambient_encoding;
+pub mod ambient_endpoint;
+pub mod ambient_file_metadata;
+pub mod ambient_file_watch;
+pub mod ambient_focus;
+pub mod ambient_gerrit;
+pub mod ambient_git;
+pub mod ambient_grpc;
+pub mod ambient_history;
+pub mod ambient_housekeeping;
+pub mod ambient_http;
+pub mod ambient_impact;
+pub mod ambient_index;
+pub mod ambient_integration;
+pub mod ambient_language;
+pub mod ambient_large_file;
+pub mod ambient_limits;
+pub mod ambient_locale;
+pub mod ambient_mention;
+pub mod ambient_mock_provider;
+pub mod ambient_notify;
+pub mod ambient_output_format;
+pub mod ambient_panic;
+pub mod ambient_plan;
+pub mod ambient_policy;
 pub mod ambient_project_config;
+pub mod ambient_projects;
+pub mod ambient_pull_request;
+pub mod ambient_quickfix;
+pub mod ambient_rebase_plan;
+pub mod ambient_redact;
+pub mod ambient_repeat;
+pub mod ambient_report;
+pub mod ambient_review_cache;
+pub mod ambient_safe_path;
+pub mod ambient_sarif;
+pub mod ambient_scaffold;
+pub mod ambient_scheduler;
+pub mod ambient_schema;
+pub mod ambient_score;
+pub mod ambient_secrets;
 pub mod ambient_server;
+pub mod ambient_session;
+pub mod ambient_snapshot;
+pub mod ambient_split;
+pub mod ambient_stable_order;
+pub mod ambient_storage;
+pub mod ambient_symbols;
+pub mod ambient_telemetry;
+pub mod ambient_terminal_notify;
+#[cfg(any(test, feature = "test-support"))]
+pub mod ambient_test_support;
+pub mod ambient_time;
+pub mod ambient_ui_assets;
+#[cfg(unix)]
+mod ambient_unix_socket;
+pub mod ambient_version;
+pub mod ambient_webhook;
+pub mod ambient_wire;
 pub mod debug_sandbox;
 mod exit_status;
 pub mod login;
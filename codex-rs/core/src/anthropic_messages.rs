@@ -0,0 +1,425 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use eventsource_stream::Eventsource;
+use futures::Stream;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use reqwest::StatusCode;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tracing::debug;
+use tracing::trace;
+
+use crate::ModelProviderInfo;
+use crate::client_common::Prompt;
+use crate::client_common::ResponseEvent;
+use crate::client_common::ResponseStream;
+use crate::error::CodexErr;
+use crate::error::Result;
+use crate::model_family::ModelFamily;
+use crate::util::backoff;
+use codex_protocol::models::ContentItem;
+use codex_protocol::models::ReasoningItemContent;
+use codex_protocol::models::ResponseItem;
+use codex_protocol::protocol::TokenUsage;
+
+/// Value sent in the `anthropic-version` header.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// The Messages API requires an explicit output budget on every request.
+const DEFAULT_MAX_TOKENS: u64 = 8192;
+
+/// Implementation for the Anthropic Messages API (`/v1/messages`).
+///
+/// Only the conversation text is translated: tool definitions and tool calls
+/// are not forwarded, so this wire is meant for plain prompt/answer use such as
+/// `codex ambient` reviews.
+pub async fn stream_anthropic_messages(
+    prompt: &Prompt,
+    model_family: &ModelFamily,
+    client: &reqwest::Client,
+    provider: &ModelProviderInfo,
+) -> Result<ResponseStream> {
+    let system = prompt.get_full_instructions(model_family);
+    let messages = messages_from_input(&prompt.get_formatted_input());
+
+    let payload = json!({
+        "model": model_family.slug,
+        "max_tokens": DEFAULT_MAX_TOKENS,
+        "system": system,
+        "messages": messages,
+        "stream": true,
+    });
+
+    let url = provider.get_full_url(&None);
+    debug!(
+        "POST to {url}: {}",
+        serde_json::to_string_pretty(&payload).unwrap_or_default()
+    );
+
+    let mut attempt = 0;
+    let max_retries = provider.request_max_retries();
+    loop {
+        attempt += 1;
+
+        let mut req_builder = client
+            .post(&url)
+            .header("anthropic-version", ANTHROPIC_VERSION);
+        if let Some(api_key) = provider.api_key()? {
+            req_builder = req_builder.header("x-api-key", api_key);
+        }
+        let req_builder = provider.apply_http_headers(req_builder);
+
+        let res = req_builder
+            .header(reqwest::header::ACCEPT, "text/event-stream")
+            .json(&payload)
+            .send()
+            .await;
+
+        match res {
+            Ok(resp) if resp.status().is_success() => {
+                let (tx_event, rx_event) = mpsc::channel::<Result<ResponseEvent>>(1600);
+                let stream = resp.bytes_stream().map_err(CodexErr::Reqwest);
+                tokio::spawn(process_anthropic_sse(
+                    stream,
+                    tx_event,
+                    provider.stream_idle_timeout(),
+                ));
+                return Ok(ResponseStream { rx_event });
+            }
+            Ok(res) => {
+                let status = res.status();
+                // 529 is Anthropic's "overloaded" status and is worth retrying.
+                let overloaded = status.as_u16() == 529;
+                if !(status == StatusCode::TOO_MANY_REQUESTS
+                    || status.is_server_error()
+                    || overloaded)
+                {
+                    let body = (res.text().await).unwrap_or_default();
+                    return Err(CodexErr::UnexpectedStatus(status, body));
+                }
+
+                if attempt > max_retries {
+                    return Err(CodexErr::RetryLimit(status));
+                }
+
+                let retry_after_secs = res
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|s| s.parse::<u64>().ok());
+
+                let delay = retry_after_secs
+                    .map(|s| Duration::from_millis(s * 1_000))
+                    .unwrap_or_else(|| backoff(attempt));
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => {
+                if attempt > max_retries {
+                    return Err(e.into());
+                }
+                let delay = backoff(attempt);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Convert the conversation into Messages API turns. The API only accepts
+/// alternating `user`/`assistant` turns, so consecutive items with the same
+/// role are merged and anything that is not a text message is dropped.
+fn messages_from_input(input: &[ResponseItem]) -> Vec<serde_json::Value> {
+    let mut turns: Vec<(&'static str, String)> = Vec::new();
+    for item in input {
+        let ResponseItem::Message { role, content, .. } = item else {
+            continue;
+        };
+        let role = if role == "assistant" {
+            "assistant"
+        } else {
+            "user"
+        };
+        let mut text = String::new();
+        for c in content {
+            match c {
+                ContentItem::InputText { text: t } | ContentItem::OutputText { text: t } => {
+                    text.push_str(t);
+                }
+                _ => {}
+            }
+        }
+        if text.is_empty() {
+            continue;
+        }
+        match turns.last_mut() {
+            Some((last_role, last_text)) if *last_role == role => {
+                last_text.push_str("\n\n");
+                last_text.push_str(&text);
+            }
+            _ => turns.push((role, text)),
+        }
+    }
+
+    turns
+        .into_iter()
+        .map(|(role, text)| json!({"role": role, "content": text}))
+        .collect()
+}
+
+/// SSE processor for the Anthropic Messages streaming format. Text and
+/// thinking deltas are forwarded as they arrive; the assembled assistant
+/// message and the token usage are emitted once `message_stop` is received.
+async fn process_anthropic_sse<S>(
+    stream: S,
+    tx_event: mpsc::Sender<Result<ResponseEvent>>,
+    idle_timeout: Duration,
+) where
+    S: Stream<Item = Result<Bytes>> + Unpin,
+{
+    let mut stream = stream.eventsource();
+
+    let mut response_id = String::new();
+    let mut input_tokens = 0;
+    let mut cached_input_tokens = None;
+    let mut output_tokens = 0;
+    let mut assistant_text = String::new();
+    let mut reasoning_text = String::new();
+
+    loop {
+        let sse = match timeout(idle_timeout, stream.next()).await {
+            Ok(Some(Ok(ev))) => ev,
+            Ok(Some(Err(e))) => {
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(e.to_string(), None)))
+                    .await;
+                return;
+            }
+            Ok(None) => {
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(
+                        "stream closed before message_stop".into(),
+                        None,
+                    )))
+                    .await;
+                return;
+            }
+            Err(_) => {
+                let _ = tx_event
+                    .send(Err(CodexErr::Stream(
+                        "idle timeout waiting for SSE".into(),
+                        None,
+                    )))
+                    .await;
+                return;
+            }
+        };
+
+        trace!("Anthropic SSE event: {}", sse.data);
+
+        let event: serde_json::Value = match serde_json::from_str(&sse.data) {
+            Ok(v) => v,
+            Err(e) => {
+                debug!(
+                    "Failed to parse Anthropic SSE event: {e}, data: {}",
+                    &sse.data
+                );
+                continue;
+            }
+        };
+
+        match event.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+            "message_start" => {
+                let message = &event["message"];
+                if let Some(id) = message.get("id").and_then(|v| v.as_str()) {
+                    response_id = id.to_string();
+                }
+                let usage = &message["usage"];
+                input_tokens = usage["input_tokens"].as_u64().unwrap_or(0);
+                cached_input_tokens = usage["cache_read_input_tokens"].as_u64();
+                output_tokens = usage["output_tokens"].as_u64().unwrap_or(0);
+                if tx_event.send(Ok(ResponseEvent::Created)).await.is_err() {
+                    return;
+                }
+            }
+            "content_block_delta" => {
+                let delta = &event["delta"];
+                let ev = match delta.get("type").and_then(|v| v.as_str()) {
+                    Some("text_delta") => {
+                        let text = delta["text"].as_str().unwrap_or_default();
+                        assistant_text.push_str(text);
+                        ResponseEvent::OutputTextDelta(text.to_string())
+                    }
+                    Some("thinking_delta") => {
+                        let text = delta["thinking"].as_str().unwrap_or_default();
+                        reasoning_text.push_str(text);
+                        ResponseEvent::ReasoningContentDelta(text.to_string())
+                    }
+                    _ => continue,
+                };
+                if tx_event.send(Ok(ev)).await.is_err() {
+                    return;
+                }
+            }
+            "message_delta" => {
+                if let Some(tokens) = event["usage"]["output_tokens"].as_u64() {
+                    output_tokens = tokens;
+                }
+            }
+            "message_stop" => {
+                if !reasoning_text.is_empty() {
+                    let item = ResponseItem::Reasoning {
+                        id: String::new(),
+                        summary: Vec::new(),
+                        content: Some(vec![ReasoningItemContent::ReasoningText {
+                            text: std::mem::take(&mut reasoning_text),
+                        }]),
+                        encrypted_content: None,
+                    };
+                    let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+                }
+
+                if !assistant_text.is_empty() {
+                    let item = ResponseItem::Message {
+                        role: "assistant".to_string(),
+                        content: vec![ContentItem::OutputText {
+                            text: std::mem::take(&mut assistant_text),
+                        }],
+                        id: None,
+                    };
+                    let _ = tx_event.send(Ok(ResponseEvent::OutputItemDone(item))).await;
+                }
+
+                let _ = tx_event
+                    .send(Ok(ResponseEvent::Completed {
+                        response_id: std::mem::take(&mut response_id),
+                        token_usage: Some(TokenUsage {
+                            input_tokens,
+                            cached_input_tokens,
+                            output_tokens,
+                            reasoning_output_tokens: None,
+                            total_tokens: input_tokens + output_tokens,
+                        }),
+                    }))
+                    .await;
+                return;
+            }
+            "error" => {
+                let message = event["error"]["message"]
+                    .as_str()
+                    .unwrap_or("unknown error")
+                    .to_string();
+                let _ = tx_event.send(Err(CodexErr::Stream(message, None))).await;
+                return;
+            }
+            // `ping`, `content_block_start` and `content_block_stop` carry
+            // nothing we need.
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_test::io::Builder as IoBuilder;
+    use tokio_util::io::ReaderStream;
+
+    async fn run_sse(body: &str) -> Vec<Result<ResponseEvent>> {
+        let reader = IoBuilder::new().read(body.as_bytes()).build();
+        let stream = ReaderStream::new(reader).map_err(CodexErr::Io);
+        let (tx, mut rx) = mpsc::channel::<Result<ResponseEvent>>(16);
+        tokio::spawn(process_anthropic_sse(stream, tx, Duration::from_secs(5)));
+
+        let mut events = Vec::new();
+        while let Some(ev) = rx.recv().await {
+            events.push(ev);
+        }
+        events
+    }
+
+    fn message(role: &str, text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: None,
+            role: role.to_string(),
+            content: vec![ContentItem::InputText {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn merges_consecutive_turns_with_the_same_role() {
+        let input = vec![
+            message("user", "context"),
+            message("user", "question"),
+            message("assistant", "answer"),
+        ];
+        assert_eq!(
+            messages_from_input(&input),
+            vec![
+                json!({"role": "user", "content": "context\n\nquestion"}),
+                json!({"role": "assistant", "content": "answer"}),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn translates_text_deltas_and_usage() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n",
+            "event: ping\n",
+            "data: {\"type\":\"ping\"}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\" world\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":5}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        let events = run_sse(body).await;
+        let events: Vec<ResponseEvent> = events.into_iter().map(|e| e.unwrap()).collect();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[0], ResponseEvent::Created));
+        assert!(matches!(&events[1], ResponseEvent::OutputTextDelta(t) if t == "Hello"));
+        assert!(matches!(&events[2], ResponseEvent::OutputTextDelta(t) if t == " world"));
+        match &events[3] {
+            ResponseEvent::OutputItemDone(ResponseItem::Message { role, content, .. }) => {
+                assert_eq!(role, "assistant");
+                assert!(
+                    matches!(&content[0], ContentItem::OutputText { text } if text == "Hello world")
+                );
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+        match &events[4] {
+            ResponseEvent::Completed {
+                response_id,
+                token_usage: Some(usage),
+            } => {
+                assert_eq!(response_id, "msg_1");
+                assert_eq!(usage.input_tokens, 12);
+                assert_eq!(usage.output_tokens, 5);
+                assert_eq!(usage.total_tokens, 17);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn surfaces_error_events() {
+        let body = concat!(
+            "event: error\n",
+            "data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+        );
+
+        let events = run_sse(body).await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], Err(CodexErr::Stream(msg, None)) if msg == "Overloaded"));
+    }
+}
@@ -19,6 +19,7 @@ use tracing::trace;
 use tracing::warn;
 use uuid::Uuid;
 
+use crate::anthropic_messages::stream_anthropic_messages;
 use crate::chat_completions::AggregateStreamExt;
 use crate::chat_completions::stream_chat_completions;
 use crate::client_common::Prompt;
@@ -97,8 +98,8 @@ impl ModelClient {
             .or_else(|| get_model_info(&self.config.model_family).map(|info| info.context_window))
     }
 
-    /// Dispatches to either the Responses or Chat implementation depending on
-    /// the provider config.  Public callers always invoke `stream()` – the
+    /// Dispatches to the Responses, Chat or Anthropic Messages implementation
+    /// depending on the provider config.  Public callers always invoke `stream()` – the
     /// specialised helpers are private to avoid accidental misuse.
     pub async fn stream(&self, prompt: &Prompt) -> Result<ResponseStream> {
         match self.provider.wire_api {
@@ -138,6 +139,15 @@ impl ModelClient {
 
                 Ok(ResponseStream { rx_event: rx })
             }
+            WireApi::Anthropic => {
+                stream_anthropic_messages(
+                    prompt,
+                    &self.config.model_family,
+                    &self.client,
+                    &self.provider,
+                )
+                .await
+            }
         }
     }
 
@@ -102,6 +102,8 @@ pub fn find_family_for_model(slug: &str) -> Option<ModelFamily> {
         simple_model_family!(slug, "gpt-4o")
     } else if slug.starts_with("gpt-3.5") {
         simple_model_family!(slug, "gpt-3.5")
+    } else if slug.starts_with("claude") {
+        simple_model_family!(slug, "claude")
     } else if slug.starts_with("gpt-5") {
         model_family!(
             slug, "gpt-5",
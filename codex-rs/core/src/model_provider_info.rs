@@ -25,9 +25,10 @@ const MAX_REQUEST_MAX_RETRIES: u64 = 100;
 /// Wire protocol that the provider speaks. Most third-party services only
 /// implement the classic OpenAI Chat Completions JSON schema, whereas OpenAI
 /// itself (and a handful of others) additionally expose the more modern
-/// *Responses* API. The two protocols use different request/response shapes
-/// and *cannot* be auto-detected at runtime, therefore each provider entry
-/// must declare which one it expects.
+/// *Responses* API, and Anthropic exposes its own *Messages* API. The
+/// protocols use different request/response shapes and *cannot* be
+/// auto-detected at runtime, therefore each provider entry must declare which
+/// one it expects.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum WireApi {
@@ -37,6 +38,9 @@ pub enum WireApi {
     /// Regular Chat Completions compatible with `/v1/chat/completions`.
     #[default]
     Chat,
+
+    /// The Anthropic Messages API at `/v1/messages`.
+    Anthropic,
 }
 
 /// Serializable representation of a provider definition.
@@ -136,7 +140,9 @@ impl ModelProviderInfo {
     }
 
     pub(crate) fn get_full_url(&self, auth: &Option<CodexAuth>) -> String {
-        let default_base_url = if matches!(
+        let default_base_url = if self.wire_api == WireApi::Anthropic {
+            "https://api.anthropic.com/v1"
+        } else if matches!(
             auth,
             Some(CodexAuth {
                 mode: AuthMode::ChatGPT,
@@ -156,13 +162,17 @@ impl ModelProviderInfo {
         match self.wire_api {
             WireApi::Responses => format!("{base_url}/responses{query_string}"),
             WireApi::Chat => format!("{base_url}/chat/completions{query_string}"),
+            WireApi::Anthropic => format!("{base_url}/messages{query_string}"),
         }
     }
 
     /// Apply provider-specific HTTP headers (both static and environment-based)
     /// onto an existing `reqwest::RequestBuilder` and return the updated
     /// builder.
-    fn apply_http_headers(&self, mut builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    pub(crate) fn apply_http_headers(
+        &self,
+        mut builder: reqwest::RequestBuilder,
+    ) -> reqwest::RequestBuilder {
         if let Some(extra) = &self.http_headers {
             for (k, v) in extra {
                 builder = builder.header(k, v);